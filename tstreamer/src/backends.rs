@@ -6,7 +6,16 @@ use serde::{Deserialize, Serialize};
 // Also it's yet another trait and probably a generic parameter on all of the
 // things.
 #[derive(
-    Debug, Clone, Copy, Serialize, Deserialize, derive_more::IsVariant,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    schemars::JsonSchema,
+    derive_more::IsVariant,
 )]
 pub enum Backend {
     /// Backend independent.
@@ -24,3 +33,21 @@ impl Backend {
         Backend::Misanthropic,
     ];
 }
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl Default for Backend {
+    /// The first backend other than [`Independent`] that is enabled, if any.
+    ///
+    /// [`Independent`]: Backend::Independent
+    fn default() -> Self {
+        #[cfg(feature = "misanthropic")]
+        return Backend::Misanthropic;
+        #[cfg(not(feature = "misanthropic"))]
+        return Backend::Independent;
+    }
+}
@@ -7,15 +7,19 @@ mod html;
 mod image;
 mod markdown;
 pub mod message;
+pub mod meta;
 #[cfg(feature = "misanthropic")]
 pub mod misanthropic;
-mod prompt;
+pub mod prompt;
+#[cfg(test)]
+pub(crate) mod test;
 pub mod tool;
 
 pub use html::{Html, ToHtml};
 pub use image::Image;
 pub use markdown::{Markdown, ToMarkdown};
 pub use message::{AgentMessage, Message, SystemMessage, UserMessage};
+pub use meta::{Meta, Tagged};
 pub use prompt::Prompt;
 
 /// A `Buffer` is a piece of data that can be written to a stream.
@@ -32,6 +36,28 @@ pub trait Buffer: Send + Info {
     {
         Box::new(Clone::clone(self))
     }
+    /// Clone the buffer through a `dyn Buffer`. Returns [`None`] if it can't
+    /// be cloned, which is the default. Types implementing [`Clone`] should
+    /// override this with `Some(Buffer::clone(self))`.
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        None
+    }
+    /// Approximate size of the buffer in bytes, used to bound queues. The
+    /// default is the size of the value itself, so types holding data on the
+    /// heap should override this.
+    fn size(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+    /// [`Meta`]data attached to the buffer. [`None`] unless it is
+    /// [`Tagged`], which is the default.
+    fn meta(&self) -> Option<&Meta> {
+        None
+    }
+    /// [`Meta`]data attached to the buffer, mutably. [`None`] unless it is
+    /// [`Tagged`], which is the default.
+    fn meta_mut(&mut self) -> Option<&mut Meta> {
+        None
+    }
 }
 static_assertions::assert_impl_all!(dyn Buffer: Send);
 static_assertions::assert_obj_safe!(Buffer);
@@ -43,6 +69,18 @@ impl<T: Buffer + ?Sized> Buffer for Box<T> {
     fn into_owned(self: Box<Self>) -> any::Owned {
         (*self).into_owned()
     }
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        (**self).try_clone()
+    }
+    fn size(&self) -> usize {
+        (**self).size()
+    }
+    fn meta(&self) -> Option<&Meta> {
+        (**self).meta()
+    }
+    fn meta_mut(&mut self) -> Option<&mut Meta> {
+        (**self).meta_mut()
+    }
 }
 
 impl<T: Buffer + ?Sized> Info for Box<T> {
@@ -55,9 +93,9 @@ impl<T: Buffer + ?Sized> Info for Box<T> {
     }
 }
 
-impl Into<any::Owned> for Box<dyn Buffer> {
-    fn into(self) -> any::Owned {
-        self.into_owned()
+impl From<Box<dyn Buffer>> for any::Owned {
+    fn from(buffer: Box<dyn Buffer>) -> Self {
+        buffer.into_owned()
     }
 }
 
@@ -81,7 +119,7 @@ static_assertions::assert_obj_safe!(Error);
 
 #[derive(Debug, Clone, Copy, thiserror::Error)]
 #[error("{}", inner)]
-struct ErrorStaticString {
+pub(crate) struct ErrorStaticString {
     inner: &'static str,
 }
 
@@ -95,6 +133,10 @@ impl Buffer for ErrorStaticString {
     fn into_owned(self: Box<Self>) -> any::Owned {
         any::Owned::Error(self)
     }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
 }
 
 impl Info for ErrorStaticString {
@@ -158,50 +200,73 @@ where
 /// [`Source`]s of [`Buffer`]s.
 pub mod source {
     use super::*;
-    use crate::{element::prompt::PromptSource, info::Info, pad::Source};
+    use crate::{
+        element::prompt::PromptSource,
+        info::Info,
+        pad::{Caps, Pull},
+    };
+    // Only used in doc links.
+    #[allow(unused_imports)]
+    use crate::pad::Source;
 
     /// [`Message`] [`Source`]
-    pub trait MessageSource: Source<Box<dyn Message>> + Info {}
+    pub trait MessageSource: Pull<Box<dyn Message>> + Info + Send {}
     impl<T> MessageSource for T where
-        T: Source<Box<dyn crate::buffer::Message>> + Info
+        T: Pull<Box<dyn crate::buffer::Message>> + Info + Send
     {
     }
 
     /// [`AgentMessage`] [`Source`]
-    pub trait AgentMessageSource: Source<Box<dyn AgentMessage>> + Info {}
+    pub trait AgentMessageSource:
+        Pull<Box<dyn AgentMessage>> + Info + Send
+    {
+    }
     impl<T> AgentMessageSource for T where
-        T: Source<Box<dyn crate::buffer::message::AgentMessage>> + Info
+        T: Pull<Box<dyn crate::buffer::message::AgentMessage>> + Info + Send
     {
     }
 
     /// [`UserMessage`] [`Source`]
-    pub trait UserMessageSource: Source<Box<dyn UserMessage>> + Info {}
+    pub trait UserMessageSource:
+        Pull<Box<dyn UserMessage>> + Info + Send
+    {
+    }
     impl<T> UserMessageSource for T where
-        T: Source<Box<dyn crate::buffer::message::UserMessage>> + Info
+        T: Pull<Box<dyn crate::buffer::message::UserMessage>> + Info + Send
     {
     }
 
     /// [`tool::Schema`] [`Source`]
-    pub trait ToolSchemaSource: Source<Box<dyn tool::Schema>> + Info {}
+    pub trait ToolSchemaSource:
+        Pull<Box<dyn tool::Schema>> + Info + Send
+    {
+    }
     impl<T> ToolSchemaSource for T where
-        T: Source<Box<dyn crate::buffer::tool::Schema>> + Info
+        T: Pull<Box<dyn crate::buffer::tool::Schema>> + Info + Send
     {
     }
 
     /// [`tool::Use`] [`Source`]
-    pub trait ToolUseSource: Source<Box<dyn tool::Use>> + Info {}
+    pub trait ToolUseSource: Pull<Box<dyn tool::Use>> + Info + Send {}
     impl<T> ToolUseSource for T where
-        T: Source<Box<dyn crate::buffer::tool::Use>> + Info
+        T: Pull<Box<dyn crate::buffer::tool::Use>> + Info + Send
     {
     }
 
     /// [`tool::Result`] [`Source`]
-    pub trait ToolResultSource: Source<Box<dyn tool::Result>> + Info {}
+    pub trait ToolResultSource:
+        Pull<Box<dyn tool::Result>> + Info + Send
+    {
+    }
     impl<T> ToolResultSource for T where
-        T: Source<Box<dyn crate::buffer::tool::Result>> + Info
+        T: Pull<Box<dyn crate::buffer::tool::Result>> + Info + Send
     {
     }
 
+    /// [`Error`] [`Source`]
+    pub trait ErrorSource: Pull<Box<dyn Error>> + Info + Send {}
+    impl<T> ErrorSource for T where T: Pull<Box<dyn Error>> + Info + Send {}
+
     /// [`Source`] capabilities of an [`Element`] (borrowed).
     ///
     /// [`Element`]: crate::element::Element
@@ -220,75 +285,164 @@ pub mod source {
         ToolUse(&'a dyn ToolUseSource),
         /// Yields [`tool::Result`]s
         ToolResult(&'a dyn ToolResultSource),
+        /// Yields [`Error`]s.
+        Error(&'a dyn ErrorSource),
     }
 
     impl Info for Any<'_> {
         fn name<'a>(&'a self) -> std::borrow::Cow<'a, str> {
             match self {
-                Self::Prompt(e)
-                | Self::Message(e)
-                | Self::AgentMessage(e)
-                | Self::UserMessage(e)
-                | Self::ToolSchema(e)
-                | Self::ToolUse(e)
-                | Self::ToolResult(e) => e.name(),
+                Self::Prompt(e) => e.name(),
+                Self::Message(e) => e.name(),
+                Self::AgentMessage(e) => e.name(),
+                Self::UserMessage(e) => e.name(),
+                Self::ToolSchema(e) => e.name(),
+                Self::ToolUse(e) => e.name(),
+                Self::ToolResult(e) => e.name(),
+                Self::Error(e) => e.name(),
             }
         }
 
         fn description<'a>(&'a self) -> std::borrow::Cow<'a, str> {
             match self {
-                Self::Prompt(e)
-                | Self::Message(e)
-                | Self::AgentMessage(e)
-                | Self::UserMessage(e)
-                | Self::ToolSchema(e)
-                | Self::ToolUse(e)
-                | Self::ToolResult(e) => e.description(),
+                Self::Prompt(e) => e.description(),
+                Self::Message(e) => e.description(),
+                Self::AgentMessage(e) => e.description(),
+                Self::UserMessage(e) => e.description(),
+                Self::ToolSchema(e) => e.description(),
+                Self::ToolUse(e) => e.description(),
+                Self::ToolResult(e) => e.description(),
+                Self::Error(e) => e.description(),
+            }
+        }
+    }
+
+    impl Any<'_> {
+        /// [`Caps`] of the [`Source`].
+        pub fn caps(&self) -> Caps {
+            match self {
+                Self::Prompt(_) => Caps::Prompt,
+                Self::Message(_) => Caps::Message,
+                Self::AgentMessage(_) => Caps::AgentMessage,
+                Self::UserMessage(_) => Caps::UserMessage,
+                Self::ToolSchema(_) => Caps::ToolSchema,
+                Self::ToolUse(_) => Caps::ToolUse,
+                Self::ToolResult(_) => Caps::ToolResult,
+                Self::Error(_) => Caps::Error,
             }
         }
     }
 
     /// [`Source`] capabilities of an [`Element`] (mutable).
+    ///
+    /// [`Element`]: crate::element::Element
     pub enum AnyMut<'a> {
         /// Mutable [`PromptSource`].
-        PromptSource(&'a mut dyn PromptSource),
+        Prompt(&'a mut dyn PromptSource),
         /// Mutable [`MessageSource`].
-        MessageSource(&'a mut dyn MessageSource),
+        Message(&'a mut dyn MessageSource),
         /// Mutable [`AgentMessageSource`].
-        AgentMessageSource(&'a mut dyn AgentMessageSource),
+        AgentMessage(&'a mut dyn AgentMessageSource),
         /// Mutable [`UserMessageSource`].
-        UserMessageSource(&'a mut dyn MessageSource),
+        UserMessage(&'a mut dyn UserMessageSource),
         /// Mutable [`ToolSchemaSource`].
-        ToolSchemaSource(&'a mut dyn ToolSchemaSource),
+        ToolSchema(&'a mut dyn ToolSchemaSource),
         /// Mutable [`ToolUseSource`].
-        ToolUseSource(&'a mut dyn ToolUseSource),
+        ToolUse(&'a mut dyn ToolUseSource),
         /// Mutable [`ToolResultSource`].
-        ToolResultSource(&'a mut dyn ToolResultSource),
+        ToolResult(&'a mut dyn ToolResultSource),
+        /// Mutable [`ErrorSource`].
+        Error(&'a mut dyn ErrorSource),
+    }
+
+    impl AnyMut<'_> {
+        /// [`Pull`] a [`Buffer`] from the [`Source`], whatever kind it yields.
+        /// This is how the [`Pipeline`] moves buffers without knowing their
+        /// types.
+        ///
+        /// [`Pipeline`]: crate::pipeline::Pipeline
+        pub async fn pull(
+            &mut self,
+        ) -> Result<Box<dyn Buffer>, Box<dyn Error>> {
+            Ok(match self {
+                Self::Prompt(source) => source.pull().await?,
+                Self::Message(source) => source.pull().await?,
+                Self::AgentMessage(source) => source.pull().await?,
+                Self::UserMessage(source) => source.pull().await?,
+                Self::ToolSchema(source) => source.pull().await?,
+                Self::ToolUse(source) => source.pull().await?,
+                Self::ToolResult(source) => source.pull().await?,
+                Self::Error(source) => source.pull().await?,
+            })
+        }
     }
 }
 
+/// [`Sink`]s of [`Buffer`]s.
+///
+/// [`Sink`]: crate::pad::Sink
 pub mod sink {
     use super::*;
+    use crate::pad::{Caps, Push};
+    // Only used in doc links.
+    #[allow(unused_imports)]
     use crate::pad::Sink;
 
     /// [`Prompt`] [`Sink`]
-    pub trait PromptSink: Sink<Box<dyn Prompt>> + Info {}
-    impl<T> PromptSink for T where T: Sink<Box<dyn Prompt>> + Info {}
+    pub trait PromptSink: Push<Box<dyn Prompt>> + Info + Send {}
+    impl<T> PromptSink for T where T: Push<Box<dyn Prompt>> + Info + Send {}
 
     /// [`Message`] [`Sink`]
-    pub trait MessageSink: Sink<Box<dyn Message>> + Info {}
-    impl<T> MessageSink for T where T: Sink<Box<dyn Message>> + Info {}
+    pub trait MessageSink: Push<Box<dyn Message>> + Info + Send {}
+    impl<T> MessageSink for T where T: Push<Box<dyn Message>> + Info + Send {}
 
     /// [`AgentMessage`] [`Sink`]
-    pub trait AgentMessageSink: Sink<Box<dyn AgentMessage>> + Info {}
-    impl<T> AgentMessageSink for T where T: Sink<Box<dyn AgentMessage>> + Info {}
+    pub trait AgentMessageSink:
+        Push<Box<dyn AgentMessage>> + Info + Send
+    {
+    }
+    impl<T> AgentMessageSink for T where
+        T: Push<Box<dyn crate::buffer::message::AgentMessage>> + Info + Send
+    {
+    }
 
     /// [`UserMessage`] [`Sink`]
-    pub trait UserMessageSink: Sink<Box<dyn UserMessage>> + Info {}
-    impl<T> UserMessageSink for T where T: Sink<Box<dyn UserMessage>> + Info {}
+    pub trait UserMessageSink:
+        Push<Box<dyn UserMessage>> + Info + Send
+    {
+    }
+    impl<T> UserMessageSink for T where
+        T: Push<Box<dyn crate::buffer::message::UserMessage>> + Info + Send
+    {
+    }
+
+    /// [`tool::Schema`] [`Sink`]
+    pub trait ToolSchemaSink:
+        Push<Box<dyn tool::Schema>> + Info + Send
+    {
+    }
+    impl<T> ToolSchemaSink for T where
+        T: Push<Box<dyn crate::buffer::tool::Schema>> + Info + Send
+    {
+    }
 
     /// [`tool::Use`] [`Sink`]
-    pub trait ToolUseSink: Sink<Box<dyn tool::Use>> + Info {}
+    pub trait ToolUseSink: Push<Box<dyn tool::Use>> + Info + Send {}
+    impl<T> ToolUseSink for T where T: Push<Box<dyn tool::Use>> + Info + Send {}
+
+    /// [`tool::Result`] [`Sink`]
+    pub trait ToolResultSink:
+        Push<Box<dyn tool::Result>> + Info + Send
+    {
+    }
+    impl<T> ToolResultSink for T where
+        T: Push<Box<dyn crate::buffer::tool::Result>> + Info + Send
+    {
+    }
+
+    /// [`Error`] [`Sink`]
+    pub trait ErrorSink: Push<Box<dyn Error>> + Info + Send {}
+    impl<T> ErrorSink for T where T: Push<Box<dyn Error>> + Info + Send {}
 
     /// All possible types of [`Sink`] elements (borrowed).
     ///
@@ -302,13 +456,107 @@ pub mod sink {
         AgentMessage(&'a dyn AgentMessageSink),
         /// Accepts [`UserMessage`]s.
         UserMessage(&'a dyn UserMessageSink),
-        /// Accepts [`tool::Result`]s.
+        /// Accepts [`tool::Schema`]s.
+        ToolSchema(&'a dyn ToolSchemaSink),
+        /// Accepts [`tool::Use`]s.
         ToolUse(&'a dyn ToolUseSink),
+        /// Accepts [`tool::Result`]s.
+        ToolResult(&'a dyn ToolResultSink),
+        /// Accepts [`Error`]s.
+        Error(&'a dyn ErrorSink),
+    }
+
+    impl Any<'_> {
+        /// [`Caps`] of the [`Sink`].
+        pub fn caps(&self) -> Caps {
+            match self {
+                Self::Prompt(_) => Caps::Prompt,
+                Self::Message(_) => Caps::Message,
+                Self::AgentMessage(_) => Caps::AgentMessage,
+                Self::UserMessage(_) => Caps::UserMessage,
+                Self::ToolSchema(_) => Caps::ToolSchema,
+                Self::ToolUse(_) => Caps::ToolUse,
+                Self::ToolResult(_) => Caps::ToolResult,
+                Self::Error(_) => Caps::Error,
+            }
+        }
     }
 
     /// All possible types of [`Sink`] elements (mutable).
     pub enum AnyMut<'a> {
-        /// A mutable [`PromptSource`] element.
+        /// Mutable [`PromptSink`].
+        Prompt(&'a mut dyn PromptSink),
+        /// Mutable [`MessageSink`].
         Message(&'a mut dyn MessageSink),
+        /// Mutable [`AgentMessageSink`].
+        AgentMessage(&'a mut dyn AgentMessageSink),
+        /// Mutable [`UserMessageSink`].
+        UserMessage(&'a mut dyn UserMessageSink),
+        /// Mutable [`ToolSchemaSink`].
+        ToolSchema(&'a mut dyn ToolSchemaSink),
+        /// Mutable [`ToolUseSink`].
+        ToolUse(&'a mut dyn ToolUseSink),
+        /// Mutable [`ToolResultSink`].
+        ToolResult(&'a mut dyn ToolResultSink),
+        /// Mutable [`ErrorSink`].
+        Error(&'a mut dyn ErrorSink),
+    }
+
+    impl AnyMut<'_> {
+        /// [`Push`] a type-erased [`Buffer`] to the [`Sink`], converting it to
+        /// the kind the sink accepts. This is how the [`Pipeline`] moves
+        /// buffers without knowing their types.
+        ///
+        /// # Errors
+        /// - If the buffer is not of a kind this sink accepts.
+        /// - Any error returned by the sink itself.
+        ///
+        /// [`Pipeline`]: crate::pipeline::Pipeline
+        pub async fn push(
+            &mut self,
+            buffer: Box<dyn Buffer>,
+        ) -> Result<(), Box<dyn Error>> {
+            let buffer = any::Typed::new(self.caps(), buffer)?;
+            match (self, buffer) {
+                (Self::Prompt(sink), any::Typed::Prompt(b)) => {
+                    sink.push(b).await
+                }
+                (Self::Message(sink), any::Typed::Message(b)) => {
+                    sink.push(b).await
+                }
+                (Self::AgentMessage(sink), any::Typed::AgentMessage(b)) => {
+                    sink.push(b).await
+                }
+                (Self::UserMessage(sink), any::Typed::UserMessage(b)) => {
+                    sink.push(b).await
+                }
+                (Self::ToolSchema(sink), any::Typed::ToolSchema(b)) => {
+                    sink.push(b).await
+                }
+                (Self::ToolUse(sink), any::Typed::ToolUse(b)) => {
+                    sink.push(b).await
+                }
+                (Self::ToolResult(sink), any::Typed::ToolResult(b)) => {
+                    sink.push(b).await
+                }
+                (Self::Error(sink), any::Typed::Error(b)) => sink.push(b).await,
+                // `Typed::new` returns the kind matching the caps.
+                _ => unreachable!(),
+            }
+        }
+
+        /// [`Caps`] of the [`Sink`].
+        pub fn caps(&self) -> Caps {
+            match self {
+                Self::Prompt(_) => Caps::Prompt,
+                Self::Message(_) => Caps::Message,
+                Self::AgentMessage(_) => Caps::AgentMessage,
+                Self::UserMessage(_) => Caps::UserMessage,
+                Self::ToolSchema(_) => Caps::ToolSchema,
+                Self::ToolUse(_) => Caps::ToolUse,
+                Self::ToolResult(_) => Caps::ToolResult,
+                Self::Error(_) => Caps::Error,
+            }
+        }
     }
 }
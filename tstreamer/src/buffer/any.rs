@@ -3,9 +3,10 @@ use std::borrow::Cow;
 use html::ToHtml;
 use markdown::ToMarkdown;
 use message::Content;
-use tool::ToolOk;
+use tool::{ToolError, ToolOk};
 
 use super::*;
+use crate::pad::Caps;
 
 pub type CowBuffer<'a> = Cow<'a, Borrowed<'a>>;
 
@@ -19,8 +20,10 @@ pub enum Owned {
     Message(Box<dyn Message>),
     Content(Box<dyn Content>),
     Image(Box<dyn Image>),
-    ToolCall(Box<dyn ToolCall>),
+    Schema(Box<dyn tool::Schema>),
+    ToolCall(Box<dyn tool::Use>),
     ToolOk(Box<dyn ToolOk>),
+    ToolError(Box<dyn ToolError>),
     Error(Box<dyn Error>),
 }
 
@@ -34,7 +37,143 @@ pub enum Borrowed<'a> {
     Message(&'a dyn Message),
     Content(&'a dyn Content),
     Image(&'a dyn Image),
-    ToolCall(&'a dyn ToolCall),
+    Schema(&'a dyn tool::Schema),
+    ToolCall(&'a dyn tool::Use),
     ToolOk(&'a dyn ToolOk),
+    ToolError(&'a dyn ToolError),
     Error(&'a dyn Error),
 }
+
+/// An owned buffer of the kind a pad with certain [`Caps`] carries.
+pub enum Typed {
+    Prompt(Box<dyn Prompt>),
+    Message(Box<dyn Message>),
+    AgentMessage(Box<dyn AgentMessage>),
+    UserMessage(Box<dyn UserMessage>),
+    ToolSchema(Box<dyn tool::Schema>),
+    ToolUse(Box<dyn tool::Use>),
+    ToolResult(Box<dyn tool::Result>),
+    Error(Box<dyn Error>),
+}
+
+impl Typed {
+    /// Convert a type-erased [`Buffer`] to the kind described by `caps`. A
+    /// [`Message`] can be any kind of [`Message`], including a
+    /// [`tool::Result`]. The other kinds must match exactly. This is what
+    /// [`Caps::accepts`] checks when linking.
+    ///
+    /// # Errors
+    /// - If the buffer is not of that kind.
+    pub fn new(
+        caps: Caps,
+        buffer: Box<dyn Buffer>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mismatch =
+            || ErrorStaticString::from("Buffer does not match the caps.");
+
+        Ok(match (caps, buffer.into_owned()) {
+            (Caps::Prompt, Owned::Prompt(prompt)) => Typed::Prompt(prompt),
+            (Caps::Message, Owned::Message(message)) => Typed::Message(message),
+            (Caps::Message, Owned::ToolOk(ok)) => Typed::Message(ok),
+            (Caps::Message, Owned::ToolError(error)) => Typed::Message(error),
+            (Caps::AgentMessage, Owned::Message(message)) => {
+                match message.into_any()? {
+                    message::Any::Agent(message) => {
+                        Typed::AgentMessage(message)
+                    }
+                    _ => return Err(mismatch().into()),
+                }
+            }
+            (Caps::UserMessage, Owned::Message(message)) => {
+                match message.into_any()? {
+                    message::Any::User(message) => Typed::UserMessage(message),
+                    _ => return Err(mismatch().into()),
+                }
+            }
+            (Caps::ToolSchema, Owned::Schema(schema)) => {
+                Typed::ToolSchema(schema)
+            }
+            (Caps::ToolUse, Owned::ToolCall(call)) => Typed::ToolUse(call),
+            // Some backends, like Anthropic, have tool use in a `Message`.
+            (Caps::ToolUse, Owned::Message(message)) => {
+                match message.into_any()? {
+                    message::Any::ToolUse(call) => Typed::ToolUse(call),
+                    _ => return Err(mismatch().into()),
+                }
+            }
+            (Caps::ToolResult, Owned::ToolOk(ok)) => Typed::ToolResult(ok),
+            (Caps::ToolResult, Owned::ToolError(error)) => {
+                Typed::ToolResult(error)
+            }
+            (Caps::ToolResult, Owned::Message(message)) => {
+                match message.into_any()? {
+                    message::Any::ToolReturn(result) => {
+                        Typed::ToolResult(result)
+                    }
+                    _ => return Err(mismatch().into()),
+                }
+            }
+            (Caps::Error, Owned::Error(error)) => Typed::Error(error),
+            _ => return Err(mismatch().into()),
+        })
+    }
+
+    /// [`Caps`] of the buffer.
+    pub fn caps(&self) -> Caps {
+        match self {
+            Self::Prompt(_) => Caps::Prompt,
+            Self::Message(_) => Caps::Message,
+            Self::AgentMessage(_) => Caps::AgentMessage,
+            Self::UserMessage(_) => Caps::UserMessage,
+            Self::ToolSchema(_) => Caps::ToolSchema,
+            Self::ToolUse(_) => Caps::ToolUse,
+            Self::ToolResult(_) => Caps::ToolResult,
+            Self::Error(_) => Caps::Error,
+        }
+    }
+
+    /// [`Buffer::size`] of the buffer.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Prompt(buffer) => buffer.size(),
+            Self::Message(buffer) => buffer.size(),
+            Self::AgentMessage(buffer) => buffer.size(),
+            Self::UserMessage(buffer) => buffer.size(),
+            Self::ToolSchema(buffer) => buffer.size(),
+            Self::ToolUse(buffer) => buffer.size(),
+            Self::ToolResult(buffer) => buffer.size(),
+            Self::Error(buffer) => buffer.size(),
+        }
+    }
+
+    /// Erase the type again.
+    pub fn into_buffer(self) -> Box<dyn Buffer> {
+        match self {
+            Self::Prompt(buffer) => Box::new(buffer),
+            Self::Message(buffer) => Box::new(buffer),
+            Self::AgentMessage(buffer) => Box::new(buffer),
+            Self::UserMessage(buffer) => Box::new(buffer),
+            Self::ToolSchema(buffer) => Box::new(buffer),
+            Self::ToolUse(buffer) => Box::new(buffer),
+            Self::ToolResult(buffer) => Box::new(buffer),
+            Self::Error(buffer) => Box::new(buffer),
+        }
+    }
+
+    /// Clone the buffer with [`Buffer::try_clone`]. Returns [`None`] if it
+    /// can't be cloned.
+    pub fn try_clone(&self) -> Option<Self> {
+        let buffer = match self {
+            Self::Prompt(buffer) => buffer.try_clone(),
+            Self::Message(buffer) => buffer.try_clone(),
+            Self::AgentMessage(buffer) => buffer.try_clone(),
+            Self::UserMessage(buffer) => buffer.try_clone(),
+            Self::ToolSchema(buffer) => buffer.try_clone(),
+            Self::ToolUse(buffer) => buffer.try_clone(),
+            Self::ToolResult(buffer) => buffer.try_clone(),
+            Self::Error(buffer) => buffer.try_clone(),
+        }?;
+
+        Self::new(self.caps(), buffer).ok()
+    }
+}
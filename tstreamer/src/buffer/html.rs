@@ -133,7 +133,7 @@ pub trait ToHtml: ToMarkdown {
     fn html(&self) -> Html {
         let mut opts = DEFAULT_OPTIONS;
         opts.attrs = true;
-        self.html_custom(DEFAULT_OPTIONS)
+        self.html_custom(opts)
     }
 
     /// Render the type to an HTML string with maximum verbosity.
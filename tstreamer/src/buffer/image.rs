@@ -26,7 +26,22 @@ pub trait Image: Buffer {
     }
 
     /// Convert into an [`image::RgbaImage`].
-    fn into_image(self) -> Result<image::RgbaImage, Box<dyn Error>>;
+    fn into_image(self: Box<Self>) -> Result<image::RgbaImage, Box<dyn Error>>;
 }
 static_assertions::assert_impl_all!(dyn Image: Buffer);
 static_assertions::assert_obj_safe!(Image);
+
+impl<T: Image + ?Sized> Image for Box<T> {
+    fn format(&self) -> image::ImageFormat {
+        (**self).format()
+    }
+    fn base64<'a>(&'a self) -> Cow<'a, str> {
+        (**self).base64()
+    }
+    fn html(&self) -> String {
+        (**self).html()
+    }
+    fn into_image(self: Box<Self>) -> Result<image::RgbaImage, Box<dyn Error>> {
+        T::into_image(*self)
+    }
+}
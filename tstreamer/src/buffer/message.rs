@@ -2,7 +2,7 @@ pub mod content;
 pub use content::{Block, Content};
 use serde::{Deserialize, Serialize};
 
-use crate::buffer::{tool, Buffer};
+use crate::buffer::{tool, Buffer, Error};
 
 /// `Role` of a [`Message`]. On backends where all roles are not supported, the
 /// role will be converted to the closest supported role. All backends do
@@ -56,28 +56,50 @@ pub trait Message: Buffer + std::fmt::Display {
     /// The [`Role`] of the message.
     fn role(&self) -> Role;
     /// The [`Content`] of the message in the form of one or more [`Block`]s.
-    fn content<'a>(&'a self) -> &'a dyn Content;
+    fn content(&self) -> &dyn Content;
     /// Discard the [`Role`] and return the [`Content`].
-    fn into_content(self) -> Box<dyn Content>;
+    fn into_content(self: Box<Self>) -> Box<dyn Content>;
     /// Convert into the native [`Kind`] of message (of a specific backend).
     fn into_concrete(self: Box<Self>) -> Kind;
     /// Converted into a boxed [`Any`] [`Message`] to be routed to the correct
     /// handler. Classifies the message based on the role or other properties.
     ///
     /// ## Note:
-    /// - In the case of Anthropic, any [`Message`] containing tool use blocks
-    ///   will be considered [`ToolUse`] messages.
+    /// - In the case of Anthropic, a [`Message`] starting with a tool use
+    ///   block is a [`ToolUse`] message, which must have no other block.
     /// - In the case of Anthropic, there is no system role, so system messages
     ///   handled by the [`Prompt`] element replace the `system` field with
     ///   the [`Content`] of the [`Message`].
     ///
+    /// # Errors
+    /// - If the [`Message`] can't be one kind without losing [`Content`],
+    ///   like several tool uses in one Anthropic [`Message`].
+    ///
     /// [`Prompt`]: crate::element::prompt::Prompt
     /// [`ToolUse`]: Role::ToolUse
-    fn into_any(self: Box<Self>) -> Any;
+    fn into_any(self: Box<Self>) -> Result<Any, Box<dyn Error>>;
 }
 static_assertions::assert_impl_all!(dyn Message: Buffer, std::fmt::Display);
 static_assertions::assert_obj_safe!(Message);
 
+impl<T: Message + ?Sized> Message for Box<T> {
+    fn role(&self) -> Role {
+        (**self).role()
+    }
+    fn content(&self) -> &dyn Content {
+        (**self).content()
+    }
+    fn into_content(self: Box<Self>) -> Box<dyn Content> {
+        (*self).into_content()
+    }
+    fn into_concrete(self: Box<Self>) -> Kind {
+        (*self).into_concrete()
+    }
+    fn into_any(self: Box<Self>) -> Result<Any, Box<dyn Error>> {
+        (*self).into_any()
+    }
+}
+
 /// [`Message`] created by a [`User`].
 ///
 /// [`User`]: Role::User
@@ -90,6 +112,12 @@ pub trait UserMessage: Message {
 static_assertions::assert_impl_all!(dyn UserMessage: Buffer, std::fmt::Display);
 static_assertions::assert_obj_safe!(UserMessage);
 
+impl<T: UserMessage + ?Sized> UserMessage for Box<T> {
+    fn role(&self) -> Role {
+        UserMessage::role(&**self)
+    }
+}
+
 /// Guaranted [`Agent`] role [`Message`].
 pub trait AgentMessage: Message {
     #[inline]
@@ -98,6 +126,12 @@ pub trait AgentMessage: Message {
     }
 }
 
+impl<T: AgentMessage + ?Sized> AgentMessage for Box<T> {
+    fn role(&self) -> Role {
+        AgentMessage::role(&**self)
+    }
+}
+
 /// Guaranted [`System`] role [`Message`]. Generally this should only be set
 /// once when the [`Prompt`] because of prompt caching with many backends.
 pub trait SystemMessage: Message {
@@ -106,6 +140,12 @@ pub trait SystemMessage: Message {
     }
 }
 
+impl<T: SystemMessage + ?Sized> SystemMessage for Box<T> {
+    fn role(&self) -> Role {
+        SystemMessage::role(&**self)
+    }
+}
+
 // See the `crate::buffer::tool` module for the `ToolUse` and `ToolReturn` which
 // are also `Message`s.
 
@@ -1,6 +1,6 @@
 use crate::buffer::{
-    tool::{ToolError, ToolOk},
-    Buffer, Image, Result, ToolCall,
+    tool::{self, ToolError, ToolOk},
+    Buffer, Image,
 };
 
 /// [`Content`] of a [`Message`] as one or more [`Block`]s of a specific
@@ -14,6 +14,15 @@ pub trait Content: Buffer + std::fmt::Display {
 static_assertions::assert_impl_all!(dyn Content: Buffer, std::fmt::Display);
 static_assertions::assert_obj_safe!(Content);
 
+impl<T: Content + ?Sized> Content for Box<T> {
+    fn blocks<'a>(&'a self) -> Box<dyn Iterator<Item = Block<'a>> + 'a> {
+        (**self).blocks()
+    }
+    fn into_native(self: Box<Self>) -> NativeKind {
+        (*self).into_native()
+    }
+}
+
 /// `NativeKind` of a [`Content`] (OpenAI, Misanthropic, etc.).
 pub enum NativeKind {
     #[cfg(feature = "misanthropic")]
@@ -28,7 +37,7 @@ pub enum Block<'a> {
     /// Image block.
     Image { image: &'a dyn Image },
     /// Tool use
-    ToolCall { call: &'a dyn ToolCall },
+    ToolCall { call: &'a dyn tool::Use },
     /// Sucessful tool result
     ToolOk { ok: &'a dyn ToolOk },
     /// Error tool result
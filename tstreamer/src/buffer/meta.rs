@@ -0,0 +1,615 @@
+//! Metadata attached to [`Buffer`]s, like `GstMeta` in GStreamer.
+//!
+//! [`Meta`] is a map of [`Entry`]s keyed by their type, so any element can
+//! attach its own without coordinating with the others. Entries for when a
+//! buffer was [`Created`], the [`SessionId`] it belongs to, its [`Sequence`]
+//! number and a [`TraceId`] are built in.
+//!
+//! Buffers are mostly backend types that have nowhere to keep it, so the
+//! [`Meta`] travels in a [`Tagged`] wrapper instead. Read it through
+//! [`Buffer::meta`] on any buffer. It is kept by [`Buffer::clone`],
+//! [`Buffer::try_clone`] and [`Buffer::into_owned`], and so through pads,
+//! [`Queue`]s and [`Tee`]s.
+//!
+//! ```
+//! use tstreamer::buffer::meta::{Meta, Sequence, SessionId};
+//!
+//! let mut meta = Meta::now();
+//! meta.insert(SessionId("alice".into()));
+//! meta.insert(Sequence(0));
+//!
+//! // Elements deriving one buffer from another carry the `Meta` over.
+//! let mut next = Meta::now();
+//! next.merge(&meta);
+//! next.get_mut::<Sequence>().unwrap().0 += 1;
+//! assert_eq!(next.get::<SessionId>(), Some(&SessionId("alice".into())));
+//! assert_eq!(next.get::<Sequence>(), Some(&Sequence(1)));
+//! ```
+//!
+//! [`Queue`]: crate::element::queue::Queue
+//! [`Tee`]: crate::element::tee::Tee
+
+use std::{
+    any::{Any, TypeId},
+    borrow::Cow,
+    collections::HashMap,
+    time::SystemTime,
+};
+
+use super::{any, message, tool, Buffer, Error, Image, Message, Prompt};
+use crate::info::Info;
+
+/// An `Entry` of [`Meta`]. Implemented for every type that is [`Clone`],
+/// [`Debug`] and can be sent between threads.
+///
+/// [`Debug`]: std::fmt::Debug
+pub trait Entry: Any + Send + Sync + std::fmt::Debug {
+    /// Clone the `Entry` through a `dyn Entry`.
+    fn clone_entry(&self) -> Box<dyn Entry>;
+    /// Upcast to [`Any`] for downcasting.
+    fn as_any(&self) -> &dyn Any;
+    /// Upcast to [`Any`] for downcasting.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Upcast to [`Any`] for downcasting.
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+static_assertions::assert_obj_safe!(Entry);
+
+impl<T> Entry for T
+where
+    T: Any + Clone + Send + Sync + std::fmt::Debug,
+{
+    fn clone_entry(&self) -> Box<dyn Entry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// `Meta`data of a [`Buffer`]. Holds at most one [`Entry`] of each type.
+#[derive(Debug, Default)]
+pub struct Meta {
+    entries: HashMap<TypeId, Box<dyn Entry>>,
+}
+
+impl Clone for Meta {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self
+                .entries
+                .iter()
+                .map(|(id, entry)| (*id, (**entry).clone_entry()))
+                .collect(),
+        }
+    }
+}
+
+impl Meta {
+    /// Empty `Meta`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `Meta` with only a [`Created`] entry for the current time.
+    pub fn now() -> Self {
+        let mut meta = Self::new();
+        meta.insert(Created::now());
+        meta
+    }
+
+    /// Insert an [`Entry`], returning the previous one of the same type.
+    pub fn insert<T: Entry>(&mut self, entry: T) -> Option<T> {
+        self.entries
+            .insert(TypeId::of::<T>(), Box::new(entry))
+            .map(|old| *old.into_any().downcast().unwrap())
+    }
+
+    /// Get the [`Entry`] of type `T`.
+    pub fn get<T: Entry>(&self) -> Option<&T> {
+        self.entries
+            .get(&TypeId::of::<T>())
+            .and_then(|entry| (**entry).as_any().downcast_ref())
+    }
+
+    /// Get the [`Entry`] of type `T` mutably.
+    pub fn get_mut<T: Entry>(&mut self) -> Option<&mut T> {
+        self.entries
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|entry| (**entry).as_any_mut().downcast_mut())
+    }
+
+    /// Remove the [`Entry`] of type `T`.
+    pub fn remove<T: Entry>(&mut self) -> Option<T> {
+        self.entries
+            .remove(&TypeId::of::<T>())
+            .map(|entry| *entry.into_any().downcast().unwrap())
+    }
+
+    /// Whether there is an [`Entry`] of type `T`.
+    pub fn contains<T: Entry>(&self) -> bool {
+        self.entries.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Number of [`Entry`]s.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no [`Entry`]s.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the [`Entry`]s, in no particular order.
+    pub fn entries(&self) -> impl Iterator<Item = &dyn Entry> {
+        self.entries.values().map(|entry| &**entry)
+    }
+
+    /// `Meta` for a buffer derived from one with this `Meta`: [`Meta::now`]
+    /// with the same [`SessionId`] and [`TraceId`], if any.
+    pub fn derived(&self) -> Meta {
+        let mut meta = Meta::now();
+        if let Some(session) = self.get::<SessionId>() {
+            meta.insert(session.clone());
+        }
+        if let Some(trace) = self.get::<TraceId>() {
+            meta.insert(*trace);
+        }
+        meta
+    }
+
+    /// Insert clones of all `other`'s [`Entry`]s, replacing those of the same
+    /// type. For elements deriving a buffer from another.
+    pub fn merge(&mut self, other: &Meta) {
+        for (id, entry) in &other.entries {
+            self.entries.insert(*id, (**entry).clone_entry());
+        }
+    }
+}
+
+/// When the buffer was `Created`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Created(pub SystemTime);
+
+impl Created {
+    /// The current time.
+    pub fn now() -> Self {
+        Self(SystemTime::now())
+    }
+}
+
+/// ID of the user session the buffer belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionId(pub String);
+
+/// `Sequence` number of the buffer in its stream, counted from zero by
+/// whatever produced it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sequence(pub u64);
+
+/// ID of the request the buffer traces back to, as in W3C Trace Context.
+/// Displayed as 32 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId(pub u128);
+
+impl std::fmt::Display for TraceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+/// A [`Buffer`] `Tagged` with [`Meta`]. Implements the same buffer traits as
+/// the buffer itself, so it can go anywhere that can.
+#[derive(Debug, Clone)]
+pub struct Tagged<B> {
+    buffer: B,
+    meta: Meta,
+}
+
+impl<B> Tagged<B> {
+    /// Tag `buffer` with [`Meta::now`].
+    pub fn new(buffer: B) -> Self {
+        Self::with_meta(buffer, Meta::now())
+    }
+
+    /// Tag `buffer` with `meta`.
+    pub fn with_meta(buffer: B, meta: Meta) -> Self {
+        Self { buffer, meta }
+    }
+
+    /// The buffer.
+    pub fn buffer(&self) -> &B {
+        &self.buffer
+    }
+
+    /// The buffer, mutably.
+    pub fn buffer_mut(&mut self) -> &mut B {
+        &mut self.buffer
+    }
+
+    /// Split into the buffer and its [`Meta`].
+    pub fn into_parts(self) -> (B, Meta) {
+        (self.buffer, self.meta)
+    }
+}
+
+impl<B: Info> Info for Tagged<B> {
+    fn name(&self) -> Cow<'_, str> {
+        self.buffer.name()
+    }
+
+    fn description(&self) -> Cow<'_, str> {
+        self.buffer.description()
+    }
+}
+
+impl<B: std::fmt::Display> std::fmt::Display for Tagged<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.buffer.fmt(f)
+    }
+}
+
+impl<B: Buffer + 'static> Buffer for Tagged<B> {
+    fn as_borrowed<'a>(&'a self) -> any::Borrowed<'a> {
+        self.buffer.as_borrowed()
+    }
+
+    fn into_owned(self: Box<Self>) -> any::Owned {
+        let Tagged { buffer, meta } = *self;
+        match Buffer::into_owned(Box::new(buffer)) {
+            any::Owned::Prompt(prompt) => {
+                any::Owned::Prompt(Box::new(Tagged::with_meta(prompt, meta)))
+            }
+            any::Owned::Message(message) => {
+                any::Owned::Message(Box::new(Tagged::with_meta(message, meta)))
+            }
+            any::Owned::Schema(schema) => {
+                any::Owned::Schema(Box::new(Tagged::with_meta(schema, meta)))
+            }
+            any::Owned::ToolCall(call) => {
+                any::Owned::ToolCall(Box::new(Tagged::with_meta(call, meta)))
+            }
+            any::Owned::ToolOk(ok) => {
+                any::Owned::ToolOk(Box::new(Tagged::with_meta(ok, meta)))
+            }
+            any::Owned::ToolError(error) => {
+                any::Owned::ToolError(Box::new(Tagged::with_meta(error, meta)))
+            }
+            any::Owned::Content(content) => {
+                any::Owned::Content(Box::new(Tagged::with_meta(content, meta)))
+            }
+            any::Owned::Image(image) => {
+                any::Owned::Image(Box::new(Tagged::with_meta(image, meta)))
+            }
+            any::Owned::Error(error) => {
+                any::Owned::Error(Box::new(Tagged::with_meta(error, meta)))
+            }
+            // Rendered text isn't a buffer, so it has nowhere to keep the
+            // `Meta`.
+            owned @ (any::Owned::ToMarkdown(_)
+            | any::Owned::Markdown(_)
+            | any::Owned::ToHtml(_)
+            | any::Owned::Html(_)) => owned,
+        }
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        let buffer = self.buffer.try_clone()?;
+        Some(Box::new(Tagged::with_meta(buffer, self.meta.clone())))
+    }
+
+    fn size(&self) -> usize {
+        self.buffer.size()
+    }
+
+    fn meta(&self) -> Option<&Meta> {
+        Some(&self.meta)
+    }
+
+    fn meta_mut(&mut self) -> Option<&mut Meta> {
+        Some(&mut self.meta)
+    }
+}
+
+impl<B: Prompt + 'static> Prompt for Tagged<B> {
+    fn set_system(
+        self: Box<Self>,
+        content: Option<Box<dyn message::Content>>,
+    ) -> Box<dyn Prompt> {
+        let Tagged { buffer, meta } = *self;
+        let prompt = Prompt::set_system(Box::new(buffer), content);
+        Box::new(Tagged::with_meta(prompt, meta))
+    }
+
+    fn append_system(
+        self: Box<Self>,
+        content: Box<dyn message::Content>,
+    ) -> Box<dyn Prompt> {
+        let Tagged { buffer, meta } = *self;
+        let prompt = Prompt::append_system(Box::new(buffer), content);
+        Box::new(Tagged::with_meta(prompt, meta))
+    }
+
+    fn system(&self) -> Option<&dyn message::Content> {
+        self.buffer.system()
+    }
+
+    fn add_message(
+        self: Box<Self>,
+        message: Box<dyn Message>,
+    ) -> Result<Box<dyn Prompt>, Box<dyn Error>> {
+        let Tagged { buffer, meta } = *self;
+        let prompt = Prompt::add_message(Box::new(buffer), message)?;
+        Ok(Box::new(Tagged::with_meta(prompt, meta)))
+    }
+
+    fn extend_messages(
+        self: Box<Self>,
+        messages: Box<dyn Iterator<Item = Box<dyn Message>>>,
+    ) -> Result<Box<dyn Prompt>, Box<dyn Error>> {
+        let Tagged { buffer, meta } = *self;
+        let prompt = Prompt::extend_messages(Box::new(buffer), messages)?;
+        Ok(Box::new(Tagged::with_meta(prompt, meta)))
+    }
+
+    fn messages<'a>(
+        &'a self,
+    ) -> Box<dyn ExactSizeIterator<Item = &'a dyn Message> + 'a> {
+        self.buffer.messages()
+    }
+
+    /// The backend's own prompt has nowhere to keep the [`Meta`], see
+    /// [`Message::into_concrete`].
+    fn into_concrete(self: Box<Self>) -> super::prompt::Kind {
+        Prompt::into_concrete(Box::new(self.buffer))
+    }
+}
+
+impl<B: Message + 'static> Message for Tagged<B> {
+    fn role(&self) -> message::Role {
+        Message::role(&self.buffer)
+    }
+
+    fn content(&self) -> &dyn message::Content {
+        self.buffer.content()
+    }
+
+    fn into_content(self: Box<Self>) -> Box<dyn message::Content> {
+        let Tagged { buffer, meta } = *self;
+        let content = Message::into_content(Box::new(buffer));
+        Box::new(Tagged::with_meta(content, meta))
+    }
+
+    /// The backend's own message has nowhere to keep the [`Meta`]. Elements
+    /// handing a message to a backend keep it themselves, see
+    /// [`Meta::derived`].
+    fn into_concrete(self: Box<Self>) -> message::Kind {
+        Message::into_concrete(Box::new(self.buffer))
+    }
+
+    fn into_any(self: Box<Self>) -> Result<message::Any, Box<dyn Error>> {
+        use message::Any;
+
+        let Tagged { buffer, meta } = *self;
+        Ok(match Message::into_any(Box::new(buffer))? {
+            Any::Agent(m) => Any::Agent(Box::new(Tagged::with_meta(m, meta))),
+            Any::User(m) => Any::User(Box::new(Tagged::with_meta(m, meta))),
+            Any::System(m) => Any::System(Box::new(Tagged::with_meta(m, meta))),
+            Any::ToolUse(m) => {
+                Any::ToolUse(Box::new(Tagged::with_meta(m, meta)))
+            }
+            Any::ToolReturn(m) => {
+                Any::ToolReturn(Box::new(Tagged::with_meta(m, meta)))
+            }
+        })
+    }
+}
+
+impl<B: message::Content + 'static> message::Content for Tagged<B> {
+    fn blocks<'a>(
+        &'a self,
+    ) -> Box<dyn Iterator<Item = message::content::Block<'a>> + 'a> {
+        self.buffer.blocks()
+    }
+
+    fn into_native(self: Box<Self>) -> message::content::NativeKind {
+        message::Content::into_native(Box::new(self.buffer))
+    }
+}
+
+impl<B: Image + 'static> Image for Tagged<B> {
+    fn format(&self) -> image::ImageFormat {
+        self.buffer.format()
+    }
+
+    fn base64<'a>(&'a self) -> Cow<'a, str> {
+        self.buffer.base64()
+    }
+
+    fn into_image(self: Box<Self>) -> Result<image::RgbaImage, Box<dyn Error>> {
+        Image::into_image(Box::new(self.buffer))
+    }
+}
+
+impl<B: Error> Error for Tagged<B> {}
+
+// `Box<dyn Error>` can't be an `Error` itself, see `From<T: Error>`.
+impl Error for Tagged<Box<dyn Error>> {}
+
+impl<B: message::AgentMessage + 'static> message::AgentMessage for Tagged<B> {
+    fn role(&self) -> message::Role {
+        message::AgentMessage::role(&self.buffer)
+    }
+}
+
+impl<B: message::UserMessage + 'static> message::UserMessage for Tagged<B> {
+    fn role(&self) -> message::Role {
+        message::UserMessage::role(&self.buffer)
+    }
+}
+
+impl<B: message::SystemMessage + 'static> message::SystemMessage for Tagged<B> {
+    fn role(&self) -> message::Role {
+        message::SystemMessage::role(&self.buffer)
+    }
+}
+
+impl<B: tool::Use + 'static> tool::Use for Tagged<B> {
+    fn id(&self) -> &str {
+        self.buffer.id()
+    }
+
+    fn args(&self) -> &serde_json::Value {
+        self.buffer.args()
+    }
+}
+
+impl<B: tool::Result + 'static> tool::Result for Tagged<B> {
+    fn role(&self) -> message::Role {
+        tool::Result::role(&self.buffer)
+    }
+
+    fn id(&self) -> &str {
+        tool::Result::id(&self.buffer)
+    }
+
+    fn value(&self) -> &dyn message::Content {
+        self.buffer.value()
+    }
+
+    fn is_error(&self) -> bool {
+        self.buffer.is_error()
+    }
+}
+
+impl<B: tool::ToolOk + 'static> tool::ToolOk for Tagged<B> {}
+
+impl<B: tool::ToolError + 'static> tool::ToolError for Tagged<B> {
+    fn message(&self) -> &str {
+        self.buffer.message()
+    }
+}
+
+impl<B: tool::Schema + 'static> tool::Schema for Tagged<B> {
+    fn schema(&self) -> &serde_json::Value {
+        self.buffer.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::ErrorStaticString;
+
+    #[test]
+    fn test_meta() {
+        let mut meta = Meta::new();
+        assert!(meta.is_empty());
+        assert_eq!(meta.insert(Sequence(1)), None);
+        assert_eq!(meta.insert(Sequence(2)), Some(Sequence(1)));
+        meta.insert(SessionId("alice".into()));
+        assert_eq!(meta.len(), 2);
+
+        meta.get_mut::<Sequence>().unwrap().0 += 1;
+        assert_eq!(meta.get::<Sequence>(), Some(&Sequence(3)));
+        assert!(!meta.contains::<TraceId>());
+
+        let clone = meta.clone();
+        assert_eq!(meta.remove::<Sequence>(), Some(Sequence(3)));
+        assert_eq!(meta.get::<Sequence>(), None);
+        assert_eq!(clone.get::<Sequence>(), Some(&Sequence(3)));
+
+        let mut other = Meta::now();
+        other.merge(&clone);
+        assert!(other.contains::<Created>());
+        assert_eq!(other.get::<SessionId>(), clone.get::<SessionId>());
+
+        assert_eq!(
+            TraceId(0xabc).to_string(),
+            "00000000000000000000000000000abc"
+        );
+    }
+
+    #[test]
+    fn test_tagged() {
+        let error = ErrorStaticString::from("test");
+        assert!(Buffer::meta(&error).is_none());
+
+        let mut meta = Meta::now();
+        meta.insert(TraceId(7));
+        let mut tagged = Tagged::with_meta(error, meta);
+        tagged.meta_mut().unwrap().insert(Sequence(0));
+
+        let cloned = Buffer::clone(&tagged);
+        let meta = cloned.meta().unwrap();
+        assert_eq!(meta.get::<TraceId>(), Some(&TraceId(7)));
+        assert_eq!(meta.get::<Sequence>(), Some(&Sequence(0)));
+        assert!(meta.contains::<Created>());
+
+        // Through `dyn Buffer` and the `Box` impl.
+        let boxed: Box<dyn Buffer> = Box::new(tagged);
+        let cloned = boxed.try_clone().unwrap();
+        assert_eq!(cloned.meta().unwrap().get(), Some(&TraceId(7)));
+    }
+
+    #[test]
+    fn test_tagged_into_owned() {
+        use crate::buffer::test::{Text, TextMessage};
+
+        let mut meta = Meta::now();
+        meta.insert(SessionId("alice".into()));
+        meta.insert(TraceId(7));
+        let traced = |buffer: &dyn Buffer| {
+            buffer
+                .meta()
+                .and_then(|meta| meta.get::<TraceId>().copied())
+        };
+
+        let message = TextMessage::new(message::Role::User, "Hi.");
+        let tagged = Box::new(Tagged::with_meta(message, meta.clone()));
+        let any::Owned::Message(message) = Buffer::into_owned(tagged) else {
+            panic!("not a message");
+        };
+        assert_eq!(traced(&message), Some(TraceId(7)));
+        assert_eq!(traced(&message.into_content()), Some(TraceId(7)));
+
+        let text =
+            Box::new(Tagged::with_meta(Text("Hi.".into()), meta.clone()));
+        let any::Owned::Content(content) = Buffer::into_owned(text) else {
+            panic!("not content");
+        };
+        assert_eq!(traced(&content), Some(TraceId(7)));
+
+        let error = Box::new(Tagged::with_meta(
+            ErrorStaticString::from("oops"),
+            meta.clone(),
+        ));
+        let any::Owned::Error(error) = Buffer::into_owned(error) else {
+            panic!("not an error");
+        };
+        assert_eq!(traced(&error), Some(TraceId(7)));
+        // And it still goes wherever an error does.
+        let error: Box<dyn Error> = Box::new(Tagged::with_meta(error, meta));
+        assert_eq!(error.to_string(), "oops");
+
+        let mut meta = Meta::new();
+        meta.insert(SessionId("alice".into()));
+        meta.insert(TraceId(7));
+        meta.insert(Sequence(3));
+        let derived = meta.derived();
+        assert_eq!(derived.get::<SessionId>(), meta.get::<SessionId>());
+        assert_eq!(derived.get::<TraceId>(), Some(&TraceId(7)));
+        assert!(derived.contains::<Created>());
+        assert!(!derived.contains::<Sequence>());
+    }
+}
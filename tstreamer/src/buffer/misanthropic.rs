@@ -3,6 +3,12 @@ use tool::{ToolError, ToolOk};
 
 use super::*;
 
+/// Size of the JSON serialization, a good enough estimate for
+/// [`Buffer::size`].
+fn serialized_size(value: &impl serde::Serialize) -> usize {
+    serde_json::to_vec(value).map_or(0, |json| json.len())
+}
+
 // Prompt
 
 impl Buffer for ::misanthropic::prompt::Prompt<'static> {
@@ -13,6 +19,14 @@ impl Buffer for ::misanthropic::prompt::Prompt<'static> {
     fn into_owned(self: Box<Self>) -> any::Owned {
         any::Owned::Prompt(self)
     }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+
+    fn size(&self) -> usize {
+        serialized_size(self)
+    }
 }
 
 impl Info for ::misanthropic::prompt::Prompt<'static> {
@@ -35,6 +49,14 @@ impl Buffer for ::misanthropic::prompt::Message<'static> {
     fn into_owned(self: Box<Self>) -> any::Owned {
         any::Owned::Message(self)
     }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+
+    fn size(&self) -> usize {
+        serialized_size(self)
+    }
 }
 
 impl Info for ::misanthropic::prompt::Message<'static> {
@@ -57,6 +79,14 @@ impl Buffer for ::misanthropic::prompt::message::Content<'static> {
     fn into_owned(self: Box<Self>) -> any::Owned {
         any::Owned::Content(self)
     }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+
+    fn size(&self) -> usize {
+        serialized_size(self)
+    }
 }
 
 impl Info for ::misanthropic::prompt::message::Content<'static> {
@@ -79,6 +109,14 @@ impl Buffer for ::misanthropic::prompt::message::Image<'static> {
     fn into_owned(self: Box<Self>) -> any::Owned {
         any::Owned::Image(self)
     }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+
+    fn size(&self) -> usize {
+        serialized_size(self)
+    }
 }
 
 impl Info for ::misanthropic::prompt::message::Image<'static> {
@@ -222,6 +260,14 @@ impl Buffer for ::misanthropic::tool::Use<'static> {
     fn into_owned(self: Box<Self>) -> any::Owned {
         any::Owned::ToolCall(self)
     }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+
+    fn size(&self) -> usize {
+        serialized_size(self)
+    }
 }
 
 impl Info for ::misanthropic::tool::Use<'static> {
@@ -234,10 +280,10 @@ impl Info for ::misanthropic::tool::Use<'static> {
     }
 }
 
-// Tool Call (by the Agent)
+// Tool Use (by the Agent)
 
-impl ToolCall for ::misanthropic::tool::Use<'static> {
-    /// ID of the [`ToolCall`].
+impl tool::Use for ::misanthropic::tool::Use<'static> {
+    /// ID of the [`tool::Use`].
     fn id(&self) -> &str {
         &self.id
     }
@@ -276,19 +322,29 @@ impl ToolError for ::misanthropic::tool::Result<'static> {
 impl Buffer for ::misanthropic::tool::Result<'static> {
     fn as_borrowed<'a>(&'a self) -> any::Borrowed<'a> {
         if self.is_error {
-            any::Borrowed::Error(self as &dyn Error)
+            any::Borrowed::ToolError(self as &dyn ToolError)
         } else {
             any::Borrowed::ToolOk(self as &dyn ToolOk)
         }
     }
 
+    // A failed tool is for the agent to handle, not the pipeline, so it is
+    // a `ToolError` rather than an `Error`.
     fn into_owned(self: Box<Self>) -> any::Owned {
         if self.is_error {
-            any::Owned::Error(self)
+            any::Owned::ToolError(self)
         } else {
             any::Owned::ToolOk(self)
         }
     }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+
+    fn size(&self) -> usize {
+        serialized_size(self)
+    }
 }
 
 impl Info for ::misanthropic::tool::Result<'static> {
@@ -426,6 +482,18 @@ impl Prompt for ::misanthropic::Prompt<'static> {
     ) -> Box<dyn ExactSizeIterator<Item = &'a dyn Message> + 'a> {
         Box::new(self.messages.iter().map(|message| message as &dyn Message))
     }
+
+    fn into_concrete(self: Box<Self>) -> prompt::Kind {
+        prompt::Kind::MisanthropicPrompt(*self)
+    }
+}
+
+impl From<prompt::Kind> for ::misanthropic::Prompt<'static> {
+    fn from(kind: prompt::Kind) -> Self {
+        match kind {
+            prompt::Kind::MisanthropicPrompt(prompt) => prompt,
+        }
+    }
 }
 
 // Message
@@ -434,9 +502,6 @@ impl Into<::misanthropic::prompt::Message<'static>> for Box<dyn Message> {
     fn into(self) -> ::misanthropic::prompt::Message<'static> {
         match self.into_concrete() {
             message::Kind::MisanthropicPromptMessage(message) => message,
-            message::Kind::MisanthropicResponseMessage(message) => {
-                message.message
-            }
         }
     }
 }
@@ -480,18 +545,193 @@ impl Message for ::misanthropic::prompt::Message<'static> {
         crate::buffer::message::Kind::MisanthropicPromptMessage(*self)
     }
 
-    fn into_content(self) -> Box<dyn Content> {
+    fn into_content(self: Box<Self>) -> Box<dyn Content> {
         Box::new(self.content)
     }
-}
+
+    fn into_any(self: Box<Self>) -> Result<message::Any, Box<dyn Error>> {
+        use ::misanthropic::prompt::message as native;
+
+        // A tool use or result is taken out of the message, which would lose
+        // any other block.
+        let only = |message: Self| match message.content {
+            native::Content::MultiPart(blocks) if blocks.len() == 1 => {
+                blocks.into_iter().next()
+            }
+            _ => None,
+        };
+        let split = || {
+            ErrorStaticString::from(
+                "A tool use or result message has more than one block.",
+            )
+        };
+
+        Ok(match Message::role(&*self) {
+            Role::ToolUse => match only(*self) {
+                Some(native::Block::ToolUse { call }) => {
+                    message::Any::ToolUse(Box::new(call))
+                }
+                _ => return Err(split().into()),
+            },
+            Role::ToolResult => match only(*self) {
+                Some(native::Block::ToolResult { result }) => {
+                    message::Any::ToolReturn(Box::new(result))
+                }
+                _ => return Err(split().into()),
+            },
+            Role::User => message::Any::User(Box::new(User(*self))),
+            Role::Agent => message::Any::Agent(Box::new(Assistant(*self))),
+            Role::System => {
+                return Err(ErrorStaticString::from(
+                    "Anthropic has no system role messages.",
+                )
+                .into())
+            }
+        })
+    }
+}
+
+// Assistant and User Messages
+
+/// A [`::misanthropic::prompt::Message`] known to have the role `$role`, so
+/// it can be a `$kind`.
+macro_rules! role_message {
+    ($(#[$attr:meta])* $name:ident, $role:ident, $kind:ident, $any:ident) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone)]
+        pub struct $name(::misanthropic::prompt::Message<'static>);
+
+        impl $name {
+            #[doc = concat!(
+                "A message of `content` with the `", stringify!($role),
+                "` role."
+            )]
+            pub fn new(
+                content: ::misanthropic::prompt::message::Content<'static>,
+            ) -> Self {
+                Self(::misanthropic::prompt::Message {
+                    role: ::misanthropic::prompt::message::Role::$role,
+                    content,
+                })
+            }
+
+            /// The native message.
+            pub fn into_inner(
+                self,
+            ) -> ::misanthropic::prompt::Message<'static> {
+                self.0
+            }
+        }
+
+        impl TryFrom<::misanthropic::prompt::Message<'static>> for $name {
+            type Error = Box<dyn Error>;
+
+            /// Check the role of `message`.
+            fn try_from(
+                message: ::misanthropic::prompt::Message<'static>,
+            ) -> Result<Self, Self::Error> {
+                use ::misanthropic::prompt::message::Role;
+
+                if message.role == Role::$role {
+                    Ok(Self(message))
+                } else {
+                    Err(ErrorStaticString::from(concat!(
+                        "Not a message with the `",
+                        stringify!($role),
+                        "` role."
+                    ))
+                    .into())
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                std::fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        impl Buffer for $name {
+            fn as_borrowed<'a>(&'a self) -> any::Borrowed<'a> {
+                any::Borrowed::Message(self)
+            }
+
+            fn into_owned(self: Box<Self>) -> any::Owned {
+                any::Owned::Message(self)
+            }
+
+            fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+                Some(Buffer::clone(self))
+            }
+
+            fn size(&self) -> usize {
+                serialized_size(&self.0)
+            }
+        }
+
+        impl Info for $name {
+            fn name(&self) -> Cow<'static, str> {
+                Cow::Borrowed(stringify!($kind))
+            }
+
+            fn description(&self) -> Cow<'static, str> {
+                Cow::Borrowed(concat!(stringify!($kind), " (misanthropic)"))
+            }
+        }
+
+        impl Message for $name {
+            fn role(&self) -> Role {
+                Message::role(&self.0)
+            }
+
+            fn content<'a>(&'a self) -> &'a dyn Content {
+                &self.0.content
+            }
+
+            fn into_concrete(self: Box<Self>) -> message::Kind {
+                message::Kind::MisanthropicPromptMessage(self.0)
+            }
+
+            fn into_content(self: Box<Self>) -> Box<dyn Content> {
+                Box::new(self.0.content)
+            }
+
+            // The role is checked, whatever the blocks are.
+            fn into_any(
+                self: Box<Self>,
+            ) -> Result<message::Any, Box<dyn Error>> {
+                Ok(message::Any::$any(self))
+            }
+        }
+
+        impl $kind for $name {}
+    };
+}
+
+role_message!(
+    /// An [`AgentMessage`] on the Misanthropic backend, like a reply of an
+    /// [`Inference`] or an [`ErrorSink`].
+    ///
+    /// [`Inference`]: crate::element::inference::Inference
+    /// [`ErrorSink`]: crate::element::error_sink::ErrorSink
+    Assistant,
+    Assistant,
+    AgentMessage,
+    Agent
+);
+role_message!(
+    /// A [`UserMessage`] on the Misanthropic backend, like what the end user
+    /// typed.
+    User,
+    User,
+    UserMessage,
+    User
+);
 
 impl Into<::misanthropic::prompt::Message<'static>> for message::Kind {
     fn into(self) -> ::misanthropic::prompt::Message<'static> {
         match self {
             message::Kind::MisanthropicPromptMessage(message) => message,
-            message::Kind::MisanthropicResponseMessage(message) => {
-                message.message
-            }
         }
     }
 }
@@ -594,8 +834,10 @@ impl Image for ::misanthropic::prompt::message::Image<'static> {
         }
     }
 
-    fn into_image(self) -> Result<::image::RgbaImage, Box<dyn Error>> {
-        match self.decode() {
+    fn into_image(
+        self: Box<Self>,
+    ) -> Result<::image::RgbaImage, Box<dyn Error>> {
+        match (*self).decode() {
             Ok(image) => Ok(image),
             // For some reason both ? and .map_err(Box::new) don't work
             // here.
@@ -603,3 +845,63 @@ impl Image for ::misanthropic::prompt::message::Image<'static> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer::any::Typed, pad::Caps};
+
+    fn native(
+        json: serde_json::Value,
+    ) -> ::misanthropic::prompt::Message<'static> {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_message_roles() {
+        let user = native(serde_json::json!({
+            "role": "user",
+            "content": "Hi",
+        }));
+        assert!(Assistant::try_from(user.clone()).is_err());
+        let agent = Typed::new(Caps::AgentMessage, Box::new(user.clone()));
+        assert!(agent.is_err());
+        assert!(matches!(
+            Typed::new(Caps::UserMessage, Box::new(user.clone())),
+            Ok(Typed::UserMessage(_))
+        ));
+        let user = User::try_from(user).unwrap();
+        assert_eq!(UserMessage::role(&user), Role::User);
+
+        // Text before a tool use is kept along with it.
+        let reply = native(serde_json::json!({
+            "role": "assistant",
+            "content": [
+                { "type": "text", "text": "Searching." },
+                call("1"),
+            ],
+        }));
+        let Ok(Typed::AgentMessage(reply)) =
+            Typed::new(Caps::AgentMessage, Box::new(reply))
+        else {
+            panic!("an assistant message is an agent message");
+        };
+        assert_eq!(reply.content().blocks().count(), 2);
+
+        // Parallel tool uses can't be taken out one at a time.
+        let calls = native(serde_json::json!({
+            "role": "assistant",
+            "content": [call("1"), call("2")],
+        }));
+        assert!(Box::new(calls.clone()).into_any().is_err());
+        assert!(Typed::new(Caps::ToolUse, Box::new(calls)).is_err());
+        let call = native(serde_json::json!({
+            "role": "assistant",
+            "content": [call("1")],
+        }));
+        assert!(matches!(
+            Typed::new(Caps::ToolUse, Box::new(call)),
+            Ok(Typed::ToolUse(_))
+        ));
+    }
+}
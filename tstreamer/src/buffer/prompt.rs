@@ -19,7 +19,7 @@ pub trait Prompt: Buffer {
         content: Box<dyn Content>,
     ) -> Box<dyn Prompt>;
     /// Get system prompt [`Content`].
-    fn system<'a>(&'a self) -> Option<&'a dyn Content>;
+    fn system(&self) -> Option<&dyn Content>;
     /// Add a message to the prompt.
     fn add_message(
         self: Box<Self>,
@@ -34,6 +34,53 @@ pub trait Prompt: Buffer {
     fn messages<'a>(
         &'a self,
     ) -> Box<dyn ExactSizeIterator<Item = &'a dyn Message> + 'a>;
+    /// Convert into the native [`Kind`] of prompt (of a specific backend).
+    fn into_concrete(self: Box<Self>) -> Kind;
 }
 static_assertions::assert_impl_all!(dyn Prompt: Buffer);
 static_assertions::assert_obj_safe!(Prompt);
+
+impl<T: Prompt + ?Sized> Prompt for Box<T> {
+    fn set_system(
+        self: Box<Self>,
+        content: Option<Box<dyn Content>>,
+    ) -> Box<dyn Prompt> {
+        (*self).set_system(content)
+    }
+    fn append_system(
+        self: Box<Self>,
+        content: Box<dyn Content>,
+    ) -> Box<dyn Prompt> {
+        (*self).append_system(content)
+    }
+    fn system(&self) -> Option<&dyn Content> {
+        (**self).system()
+    }
+    fn add_message(
+        self: Box<Self>,
+        message: Box<dyn Message>,
+    ) -> Result<Box<dyn Prompt>, Box<dyn Error>> {
+        (*self).add_message(message)
+    }
+    fn extend_messages(
+        self: Box<Self>,
+        messages: Box<dyn Iterator<Item = Box<dyn Message>>>,
+    ) -> Result<Box<dyn Prompt>, Box<dyn Error>> {
+        (*self).extend_messages(messages)
+    }
+    fn messages<'a>(
+        &'a self,
+    ) -> Box<dyn ExactSizeIterator<Item = &'a dyn Message> + 'a> {
+        (**self).messages()
+    }
+    fn into_concrete(self: Box<Self>) -> Kind {
+        (*self).into_concrete()
+    }
+}
+
+/// `Kind` of [`Prompt`] (for a specific backend).
+pub enum Kind {
+    /// A [`misanthropic::Prompt`].
+    #[cfg(feature = "misanthropic")]
+    MisanthropicPrompt(misanthropic::Prompt<'static>),
+}
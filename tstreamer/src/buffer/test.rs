@@ -0,0 +1,457 @@
+//! Backend independent [`Buffer`]s of every kind, so tests can move real
+//! buffers through pads without a backend. They have no native kind, so
+//! pushing them to a backend's [`Element`] panics.
+//!
+//! [`Element`]: crate::element::Element
+
+use std::borrow::Cow;
+
+use super::{
+    any,
+    message::{self, Block, Content, Role},
+    tool, AgentMessage, Buffer, Error, ErrorStaticString, Message, Prompt,
+    UserMessage,
+};
+use crate::{info::Info, pad::Caps};
+
+/// Plain text [`Content`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text(pub String);
+
+/// A [`Message`] of plain [`Text`] with any [`Role`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMessage {
+    pub role: Role,
+    pub content: Text,
+}
+
+impl TextMessage {
+    pub fn new(role: Role, text: &str) -> Self {
+        Self {
+            role,
+            content: Text(text.into()),
+        }
+    }
+}
+
+/// A [`tool::Schema`] that can't be cloned, unlike every other test buffer.
+#[derive(Debug)]
+pub struct Unique(pub serde_json::Value);
+
+/// A [`Prompt`] of [`TextMessage`]s.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextPrompt {
+    pub system: Option<Text>,
+    pub messages: Vec<TextMessage>,
+}
+
+/// A [`tool::Use`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub id: String,
+    pub args: serde_json::Value,
+}
+
+/// A [`tool::Result`], successful or not.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Return {
+    pub id: String,
+    pub content: Text,
+    pub is_error: bool,
+}
+
+/// A buffer of each kind that can be pushed to a sink with `caps`.
+pub fn buffer(caps: Caps) -> Box<dyn Buffer> {
+    match caps {
+        Caps::Prompt => Box::new(TextPrompt {
+            system: Some(Text("Be brief.".into())),
+            messages: vec![TextMessage::new(Role::User, "Hi")],
+        }),
+        Caps::Message | Caps::AgentMessage => {
+            Box::new(TextMessage::new(Role::Agent, "Hello"))
+        }
+        Caps::UserMessage => Box::new(TextMessage::new(Role::User, "Hi")),
+        Caps::ToolSchema => Box::new(serde_json::json!({"name": "tool"})),
+        Caps::ToolUse => Box::new(Call {
+            id: "call".into(),
+            args: serde_json::json!({"x": 1}),
+        }),
+        Caps::ToolResult => Box::new(Return {
+            id: "call".into(),
+            content: Text("1".into()),
+            is_error: false,
+        }),
+        Caps::Error => Box::new(ErrorStaticString::from("failed")),
+    }
+}
+
+/// A buffer of every shape a source with `caps` can yield. A [`Message`]
+/// can be any kind of [`Message`], and a [`tool::Result`] can have failed.
+pub fn buffers(caps: Caps) -> Vec<Box<dyn Buffer>> {
+    let ok = Return {
+        id: "call".into(),
+        content: Text("1".into()),
+        is_error: false,
+    };
+    let error = Return {
+        is_error: true,
+        ..Clone::clone(&ok)
+    };
+    match caps {
+        Caps::Message => vec![
+            Box::new(TextMessage::new(Role::Agent, "Hello")),
+            Box::new(TextMessage::new(Role::User, "Hi")),
+            Box::new(ok),
+            Box::new(error),
+        ],
+        Caps::ToolResult => vec![Box::new(ok), Box::new(error)],
+        caps => vec![buffer(caps)],
+    }
+}
+
+/// Describe any `buffer` as text, so what arrives can be compared.
+pub fn text(buffer: &dyn Buffer) -> String {
+    match buffer.as_borrowed() {
+        any::Borrowed::Prompt(prompt) => prompt
+            .messages()
+            .map(|message| message.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        any::Borrowed::Message(message) => message.to_string(),
+        any::Borrowed::Schema(schema) => schema.schema().to_string(),
+        any::Borrowed::ToolCall(call) => {
+            format!("{} {}", call.id(), call.args())
+        }
+        any::Borrowed::ToolOk(ok) => format!("{} {}", ok.id(), ok.value()),
+        any::Borrowed::ToolError(error) => {
+            format!("{} {}", error.id(), error.message())
+        }
+        any::Borrowed::Error(error) => error.to_string(),
+        _ => buffer.name().into_owned(),
+    }
+}
+
+impl std::fmt::Display for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Info for Text {
+    fn name(&self) -> Cow<'_, str> {
+        "Text".into()
+    }
+
+    fn description(&self) -> Cow<'_, str> {
+        "Plain text.".into()
+    }
+}
+
+impl Buffer for Text {
+    fn as_borrowed<'a>(&'a self) -> any::Borrowed<'a> {
+        any::Borrowed::Content(self)
+    }
+
+    fn into_owned(self: Box<Self>) -> any::Owned {
+        any::Owned::Content(self)
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl Content for Text {
+    fn blocks<'a>(&'a self) -> Box<dyn Iterator<Item = Block<'a>> + 'a> {
+        Box::new(std::iter::once(Block::Text { text: &self.0 }))
+    }
+
+    fn into_native(self: Box<Self>) -> message::content::NativeKind {
+        unimplemented!("test buffers have no native kind")
+    }
+}
+
+impl std::fmt::Display for TextMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.content.fmt(f)
+    }
+}
+
+impl Info for TextMessage {
+    fn name(&self) -> Cow<'_, str> {
+        "Text Message".into()
+    }
+
+    fn description(&self) -> Cow<'_, str> {
+        "Plain text with a role.".into()
+    }
+}
+
+impl Buffer for TextMessage {
+    fn as_borrowed<'a>(&'a self) -> any::Borrowed<'a> {
+        any::Borrowed::Message(self)
+    }
+
+    fn into_owned(self: Box<Self>) -> any::Owned {
+        any::Owned::Message(self)
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+
+    fn size(&self) -> usize {
+        self.content.size()
+    }
+}
+
+impl Message for TextMessage {
+    fn role(&self) -> Role {
+        self.role
+    }
+
+    fn content(&self) -> &dyn Content {
+        &self.content
+    }
+
+    fn into_content(self: Box<Self>) -> Box<dyn Content> {
+        Box::new(self.content)
+    }
+
+    fn into_concrete(self: Box<Self>) -> message::Kind {
+        unimplemented!("test buffers have no native kind")
+    }
+
+    fn into_any(self: Box<Self>) -> Result<message::Any, Box<dyn Error>> {
+        Ok(match self.role {
+            Role::User => message::Any::User(self),
+            _ => message::Any::Agent(self),
+        })
+    }
+}
+
+impl AgentMessage for TextMessage {}
+impl UserMessage for TextMessage {}
+
+impl Info for TextPrompt {
+    fn name(&self) -> Cow<'_, str> {
+        "Text Prompt".into()
+    }
+
+    fn description(&self) -> Cow<'_, str> {
+        "A prompt of plain text messages.".into()
+    }
+}
+
+impl Buffer for TextPrompt {
+    fn as_borrowed<'a>(&'a self) -> any::Borrowed<'a> {
+        any::Borrowed::Prompt(self)
+    }
+
+    fn into_owned(self: Box<Self>) -> any::Owned {
+        any::Owned::Prompt(self)
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+}
+
+impl Prompt for TextPrompt {
+    fn set_system(
+        mut self: Box<Self>,
+        content: Option<Box<dyn Content>>,
+    ) -> Box<dyn Prompt> {
+        self.system = content.map(|content| Text(content.to_string()));
+        self
+    }
+
+    fn append_system(
+        mut self: Box<Self>,
+        content: Box<dyn Content>,
+    ) -> Box<dyn Prompt> {
+        let system = self.system.get_or_insert_with(|| Text(String::new()));
+        system.0.push_str(&content.to_string());
+        self
+    }
+
+    fn system(&self) -> Option<&dyn Content> {
+        self.system.as_ref().map(|system| system as &dyn Content)
+    }
+
+    fn add_message(
+        mut self: Box<Self>,
+        message: Box<dyn Message>,
+    ) -> Result<Box<dyn Prompt>, Box<dyn Error>> {
+        self.messages
+            .push(TextMessage::new(message.role(), &message.to_string()));
+        Ok(self)
+    }
+
+    fn extend_messages(
+        self: Box<Self>,
+        messages: Box<dyn Iterator<Item = Box<dyn Message>>>,
+    ) -> Result<Box<dyn Prompt>, Box<dyn Error>> {
+        let mut prompt: Box<dyn Prompt> = self;
+        for message in messages {
+            prompt = prompt.add_message(message)?;
+        }
+        Ok(prompt)
+    }
+
+    fn messages<'a>(
+        &'a self,
+    ) -> Box<dyn ExactSizeIterator<Item = &'a dyn Message> + 'a> {
+        Box::new(self.messages.iter().map(|message| message as &dyn Message))
+    }
+
+    fn into_concrete(self: Box<Self>) -> super::prompt::Kind {
+        unimplemented!("test buffers have no native kind")
+    }
+}
+
+impl Info for Call {
+    fn name(&self) -> Cow<'_, str> {
+        "Call".into()
+    }
+
+    fn description(&self) -> Cow<'_, str> {
+        "A tool call.".into()
+    }
+}
+
+impl Buffer for Call {
+    fn as_borrowed<'a>(&'a self) -> any::Borrowed<'a> {
+        any::Borrowed::ToolCall(self)
+    }
+
+    fn into_owned(self: Box<Self>) -> any::Owned {
+        any::Owned::ToolCall(self)
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+}
+
+impl tool::Use for Call {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn args(&self) -> &serde_json::Value {
+        &self.args
+    }
+}
+
+impl std::fmt::Display for Return {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.content.fmt(f)
+    }
+}
+
+impl Info for Return {
+    fn name(&self) -> Cow<'_, str> {
+        "Return".into()
+    }
+
+    fn description(&self) -> Cow<'_, str> {
+        "The result of a tool call.".into()
+    }
+}
+
+impl Buffer for Return {
+    fn as_borrowed<'a>(&'a self) -> any::Borrowed<'a> {
+        if self.is_error {
+            any::Borrowed::ToolError(self)
+        } else {
+            any::Borrowed::ToolOk(self)
+        }
+    }
+
+    fn into_owned(self: Box<Self>) -> any::Owned {
+        if self.is_error {
+            any::Owned::ToolError(self)
+        } else {
+            any::Owned::ToolOk(self)
+        }
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+}
+
+impl Message for Return {
+    fn role(&self) -> Role {
+        Role::ToolResult
+    }
+
+    fn content(&self) -> &dyn Content {
+        &self.content
+    }
+
+    fn into_content(self: Box<Self>) -> Box<dyn Content> {
+        Box::new(self.content)
+    }
+
+    fn into_concrete(self: Box<Self>) -> message::Kind {
+        unimplemented!("test buffers have no native kind")
+    }
+
+    fn into_any(self: Box<Self>) -> Result<message::Any, Box<dyn Error>> {
+        Ok(message::Any::ToolReturn(self))
+    }
+}
+
+impl tool::Result for Return {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn value(&self) -> &dyn Content {
+        &self.content
+    }
+
+    fn is_error(&self) -> bool {
+        self.is_error
+    }
+}
+
+impl tool::ToolOk for Return {}
+
+impl tool::ToolError for Return {
+    fn message(&self) -> &str {
+        &self.content.0
+    }
+}
+
+impl Info for Unique {
+    fn name(&self) -> Cow<'_, str> {
+        "Unique".into()
+    }
+
+    fn description(&self) -> Cow<'_, str> {
+        "A tool schema that can't be cloned.".into()
+    }
+}
+
+impl Buffer for Unique {
+    fn as_borrowed<'a>(&'a self) -> any::Borrowed<'a> {
+        any::Borrowed::Schema(self)
+    }
+
+    fn into_owned(self: Box<Self>) -> any::Owned {
+        any::Owned::Schema(self)
+    }
+}
+
+impl tool::Schema for Unique {
+    fn schema(&self) -> &serde_json::Value {
+        &self.0
+    }
+}
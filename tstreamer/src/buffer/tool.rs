@@ -18,7 +18,16 @@ pub trait Use: Buffer {
 static_assertions::assert_impl_all!(dyn Use: Buffer);
 static_assertions::assert_obj_safe!(Use);
 
-/// `Return` is a value returned by a [`ToolCall`]. It may be successful or an
+impl<T: Use + ?Sized> Use for Box<T> {
+    fn id(&self) -> &str {
+        (**self).id()
+    }
+    fn args(&self) -> &serde_json::Value {
+        (**self).args()
+    }
+}
+
+/// `Return` is a value returned by a tool for a [`Use`]. It may be successful or an
 /// error.
 pub trait Result: Message {
     /// Role of the message.
@@ -35,7 +44,7 @@ pub trait Result: Message {
     // result.
 
     /// The sucessful return value of the tool.
-    fn value<'a>(&'a self) -> &'a dyn Content;
+    fn value(&self) -> &dyn Content;
 
     /// Whether the return is an error.
     fn is_error(&self) -> bool;
@@ -43,12 +52,29 @@ pub trait Result: Message {
 static_assertions::assert_impl_all!(dyn Result: Buffer);
 static_assertions::assert_obj_safe!(Result);
 
+impl<T: Result + ?Sized> Result for Box<T> {
+    fn role(&self) -> Role {
+        Result::role(&**self)
+    }
+    fn id(&self) -> &str {
+        Result::id(&**self)
+    }
+    fn value(&self) -> &dyn Content {
+        (**self).value()
+    }
+    fn is_error(&self) -> bool {
+        (**self).is_error()
+    }
+}
+
 /// `ToolOk` is a successful [`Result`].
 pub trait ToolOk: Result {}
 static_assertions::assert_impl_all!(dyn ToolOk: Buffer);
 static_assertions::assert_obj_safe!(ToolOk);
 
-/// `ToolError` is a result of a failed [`ToolCall`]. Intended for the
+impl<T: ToolOk + ?Sized> ToolOk for Box<T> {}
+
+/// `ToolError` is a result of a failed [`Use`]. Intended for the
 /// [`Agent`].
 // This is not a buffer error because that is handled by the pipeline and this
 // is intended for the Agent to handle.
@@ -60,6 +86,12 @@ pub trait ToolError: Result {
 static_assertions::assert_impl_all!(dyn ToolError: Buffer);
 static_assertions::assert_obj_safe!(ToolError);
 
+impl<T: ToolError + ?Sized> ToolError for Box<T> {
+    fn message(&self) -> &str {
+        (**self).message()
+    }
+}
+
 impl std::fmt::Debug for Box<dyn ToolError> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct(stringify!(ToolError))
@@ -74,12 +106,18 @@ impl Error for Box<dyn ToolError> {}
 /// `ToolSchema` provides the schema for a [`Tool`]. [`Schema`] is already
 /// implemented for [`serde_json::Value`].
 pub trait Schema: Buffer {
-    /// The schema for the tool.
-    fn schema(&self) -> &dyn serde::Serialize;
+    /// The schema for the tool, as JSON.
+    fn schema(&self) -> &serde_json::Value;
 }
 static_assertions::assert_impl_all!(dyn Schema: Buffer);
 static_assertions::assert_obj_safe!(Schema);
 
+impl<T: Schema + ?Sized> Schema for Box<T> {
+    fn schema(&self) -> &serde_json::Value {
+        (**self).schema()
+    }
+}
+
 impl Buffer for serde_json::Value {
     fn as_borrowed<'a>(&'a self) -> any::Borrowed<'a> {
         any::Borrowed::Schema(self)
@@ -88,6 +126,14 @@ impl Buffer for serde_json::Value {
     fn into_owned(self: Box<Self>) -> any::Owned {
         any::Owned::Schema(self)
     }
+
+    fn try_clone(&self) -> Option<Box<dyn Buffer>> {
+        Some(Buffer::clone(self))
+    }
+
+    fn size(&self) -> usize {
+        self.to_string().len()
+    }
 }
 
 impl Info for serde_json::Value {
@@ -101,7 +147,7 @@ impl Info for serde_json::Value {
 }
 
 impl Schema for serde_json::Value {
-    fn schema(&self) -> &dyn serde::Serialize {
+    fn schema(&self) -> &serde_json::Value {
         self
     }
 }
@@ -1,13 +1,34 @@
 /// Emumerations of the different types of elements in a pipeline.
 pub mod any;
+/// [`Bin`](bin::Bin) running a whole [`Pipeline`] as one element.
+///
+/// [`Pipeline`]: crate::pipeline::Pipeline
+pub mod bin;
+/// [`ErrorSink`](error_sink::ErrorSink) logging, counting or replying to the
+/// errors of other elements.
+pub mod error_sink;
 /// [`Inference`] [`Element`]s.
 pub mod inference;
 /// [`Prompt`] containing all messages and metadata needed to prompt the model.
 pub mod prompt;
+/// Typed, named runtime properties of elements.
+pub mod property;
+/// [`Queue`](queue::Queue) absorbing bursts between elements.
+pub mod queue;
+/// Open [`registry`] of [`Element`] factories, so downstream crates can add
+/// their own for configuration to construct.
+pub mod registry;
+/// [`Tee`](tee::Tee) sending copies of buffers to several pads.
+pub mod tee;
+/// [`TeleportSink`](teleport::TeleportSink) and
+/// [`TeleportSource`](teleport::TeleportSource) linking pipelines through
+/// named channels.
+pub mod teleport;
 
 use crate::backends::Backend;
 use crate::buffer;
 use crate::info::Info;
+use crate::pad::Event;
 
 /// A trait for elements in a [`Pipeline`].
 #[async_trait::async_trait]
@@ -23,6 +44,193 @@ pub trait Element: Info + Send + 'static {
         Ok(())
     }
 
+    /// Handle an [`Event`] arriving on the sink pad at index `pad`. Return the
+    /// [`Event`] to forward downstream on every linked source pad, or [`None`]
+    /// to drop it. By default every [`Event`] is forwarded.
+    ///
+    /// [`Event::Eos`] arrives once, after every upstream [`Element`] has
+    /// ended, and is always forwarded. [`Buffer`]s arriving between
+    /// [`Event::FlushStart`] and [`Event::FlushStop`] are discarded without
+    /// being pushed.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    async fn event(
+        &mut self,
+        pad: usize,
+        event: Event,
+    ) -> Result<Option<Event>, Box<dyn buffer::Error>> {
+        let _ = pad;
+        Ok(Some(event))
+    }
+
+    /// An [`Event`] the `Element` wants to send downstream on its own, such
+    /// as [`Event::Eos`] from a source that has run dry. Checked after every
+    /// [`Buffer`] the `Element` pushes or pulls. After [`Event::Eos`] the
+    /// `Element` is not pushed or pulled again.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    fn take_event(&mut self) -> Option<Event> {
+        None
+    }
+
+    /// Whether the source pad at index `pad` has a [`Buffer`] ready to pull.
+    /// Pads that are not ready are skipped instead of pulled. By default
+    /// every pad is always ready.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    fn can_pull(&self, pad: usize) -> bool {
+        let _ = pad;
+        true
+    }
+
+    /// Whether the `Element` has room for another [`Buffer`] on its sink
+    /// pads. Only checked for [`decoupled`] `Element`s, which are not pushed
+    /// while this is `false`. By default there is always room.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`decoupled`]: Element::decoupled
+    fn can_push(&self) -> bool {
+        true
+    }
+
+    /// Whether the sink and source pads of the `Element` are decoupled, as in
+    /// a [`Queue`]. Instead of being pulled once per [`Buffer`] pushed, its
+    /// source pads are pulled whenever [`can_pull`] and downstream has room,
+    /// and it is pushed whenever [`can_push`]. By default `Element`s are not
+    /// decoupled.
+    ///
+    /// [`Queue`]: queue::Queue
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`can_pull`]: Element::can_pull
+    /// [`can_push`]: Element::can_push
+    fn decoupled(&self) -> bool {
+        false
+    }
+
+    /// Wait until the `Element` may have something new to pull, or room for
+    /// another [`Buffer`], because it works in the background like a
+    /// [`Bin`]. Only awaited while the `Element` is [`busy`], and only for
+    /// sources and [`decoupled`] `Element`s. [`can_pull`] and [`can_push`]
+    /// are checked again afterwards. Must be cancel safe. By default it never
+    /// completes.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`Bin`]: bin::Bin
+    /// [`busy`]: Element::busy
+    /// [`decoupled`]: Element::decoupled
+    /// [`can_pull`]: Element::can_pull
+    /// [`can_push`]: Element::can_push
+    async fn ready(&mut self) {
+        std::future::pending().await
+    }
+
+    /// Whether the `Element` is still working in the background, see
+    /// [`ready`]. A source, or a [`decoupled`] `Element` past [`Event::Eos`],
+    /// is not finished while it is busy, even with nothing to pull. By
+    /// default it is not.
+    ///
+    /// [`ready`]: Element::ready
+    /// [`decoupled`]: Element::decoupled
+    fn busy(&self) -> bool {
+        false
+    }
+
+    /// Whether the sink pad at index `pad` must be linked for the `Element`
+    /// to do anything, like the [`Prompt`] sink of an [`Inference`]
+    /// `Element`. Checked by [`Pipeline::validate`]. By default no pad is
+    /// required.
+    ///
+    /// [`Prompt`]: crate::buffer::Prompt
+    /// [`Inference`]: inference::Inference
+    /// [`Pipeline::validate`]: crate::pipeline::Pipeline::validate
+    fn required(&self, pad: usize) -> bool {
+        let _ = pad;
+        false
+    }
+
+    /// The runtime [`Property`]s of the `Element`. By default there are none.
+    ///
+    /// [`Property`]: property::Property
+    fn properties(&self) -> &'static [property::Property] {
+        &[]
+    }
+
+    /// Read a [`Property`] listed by [`Element::properties`]. Use
+    /// [`property::get`] instead, which checks the name.
+    ///
+    /// [`Property`]: property::Property
+    fn property(&self, name: &str) -> Option<property::Value> {
+        let _ = name;
+        None
+    }
+
+    /// Write a [`Property`] listed by [`Element::properties`]. Only called
+    /// between [`Buffer`]s, with a `value` of the right [`Type`], by
+    /// [`property::set`].
+    ///
+    /// # Errors
+    /// - [`PropertyError::Invalid`] if the `Element` rejects `value`.
+    ///
+    /// [`Property`]: property::Property
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`Type`]: property::Type
+    /// [`PropertyError::Invalid`]: property::PropertyError::Invalid
+    fn set_property(
+        &mut self,
+        name: &str,
+        value: property::Value,
+    ) -> Result<(), property::PropertyError> {
+        let _ = value;
+        Err(property::PropertyError::Unknown {
+            name: name.to_owned(),
+        })
+    }
+
+    /// Monotonic [`Counter`]s of the `Element` broken down by labels, like the
+    /// tokens an [`Inference`] used per model. A running [`Pipeline`] reads
+    /// them along with the read-only [`Property`]s, for
+    /// [`Tracer::counters`]. By default there are none.
+    ///
+    /// [`Counter`]: property::Counter
+    /// [`Inference`]: inference::Inference
+    /// [`Pipeline`]: crate::pipeline::Pipeline
+    /// [`Property`]: property::Property
+    /// [`Tracer::counters`]: crate::pipeline::Tracer::counters
+    fn counters(&self) -> Vec<property::Counter> {
+        Vec::new()
+    }
+
+    /// State of the `Element` worth keeping across restarts of the process,
+    /// like the history of a [`Prompt`], for a [`Checkpoint`]. Only called
+    /// between [`Buffer`]s. By default there is none.
+    ///
+    /// # Errors
+    /// - If the state can't be serialized.
+    ///
+    /// [`Prompt`]: crate::buffer::Prompt
+    /// [`Checkpoint`]: crate::pipeline::checkpoint::Checkpoint
+    /// [`Buffer`]: crate::buffer::Buffer
+    fn checkpoint(
+        &self,
+    ) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    /// Restore `state` saved by [`Element::checkpoint`]. Called before
+    /// [`Element::init`] by [`Pipeline::restore`].
+    ///
+    /// # Errors
+    /// - If `state` is invalid. By default always, since there is no state.
+    ///
+    /// [`Pipeline::restore`]: crate::pipeline::Pipeline::restore
+    fn restore(
+        &mut self,
+        state: serde_json::Value,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let _ = state;
+        Err(format!("`{}` has no state to restore.", self.name()).into())
+    }
+
     /// Backend used by the `Element`.
     fn backend(&self) -> Backend;
 
@@ -47,3 +255,84 @@ pub trait Element: Info + Send + 'static {
     ) -> Box<dyn Iterator<Item = buffer::sink::AnyMut<'a>> + 'a>;
 }
 static_assertions::assert_obj_safe!(Element);
+
+/// Implement [`Push`] for every kind of boxed buffer on an [`Element`] that
+/// handles them all alike. The buffer is wrapped in a [`Typed`] and passed to
+/// `$method`, an `async fn(&mut self, Typed) -> Result<(), Box<dyn Error>>`.
+///
+/// [`Push`]: crate::pad::Push
+/// [`Typed`]: crate::buffer::any::Typed
+macro_rules! impl_push_typed {
+    ($element:ty, $method:ident) => {
+        $crate::element::impl_push_typed!(
+            @each $element, $method;
+            Prompt(dyn $crate::buffer::Prompt),
+            Message(dyn $crate::buffer::Message),
+            AgentMessage(dyn $crate::buffer::AgentMessage),
+            UserMessage(dyn $crate::buffer::UserMessage),
+            ToolSchema(dyn $crate::buffer::tool::Schema),
+            ToolUse(dyn $crate::buffer::tool::Use),
+            ToolResult(dyn $crate::buffer::tool::Result),
+            Error(dyn $crate::buffer::Error)
+        );
+    };
+    (@each $element:ty, $method:ident; $($variant:ident($buffer:ty)),*) => {
+        $(
+            #[async_trait::async_trait]
+            impl $crate::pad::Push<Box<$buffer>> for $element {
+                async fn push(
+                    &mut self,
+                    buffer: Box<$buffer>,
+                ) -> Result<(), Box<dyn $crate::buffer::Error>> {
+                    self.$method($crate::buffer::any::Typed::$variant(buffer))
+                        .await
+                }
+            }
+        )*
+    };
+}
+pub(crate) use impl_push_typed;
+
+/// Implement [`Pull`] for every kind of boxed buffer on a source pad that
+/// yields them all alike. `$method` is an
+/// `async fn(&mut self) -> Result<Typed, Box<dyn Error>>` and its [`Typed`]
+/// must match the kind being pulled.
+///
+/// [`Pull`]: crate::pad::Pull
+/// [`Typed`]: crate::buffer::any::Typed
+macro_rules! impl_pull_typed {
+    ($pad:ty, $method:ident) => {
+        $crate::element::impl_pull_typed!(
+            @each $pad, $method;
+            Prompt(dyn $crate::buffer::Prompt),
+            Message(dyn $crate::buffer::Message),
+            AgentMessage(dyn $crate::buffer::AgentMessage),
+            UserMessage(dyn $crate::buffer::UserMessage),
+            ToolSchema(dyn $crate::buffer::tool::Schema),
+            ToolUse(dyn $crate::buffer::tool::Use),
+            ToolResult(dyn $crate::buffer::tool::Result),
+            Error(dyn $crate::buffer::Error)
+        );
+    };
+    (@each $pad:ty, $method:ident; $($variant:ident($buffer:ty)),*) => {
+        $(
+            #[async_trait::async_trait]
+            impl $crate::pad::Pull<Box<$buffer>> for $pad {
+                async fn pull(
+                    &mut self,
+                ) -> Result<Box<$buffer>, Box<dyn $crate::buffer::Error>> {
+                    match self.$method().await? {
+                        $crate::buffer::any::Typed::$variant(buffer) => {
+                            Ok(buffer)
+                        }
+                        _ => Err($crate::buffer::ErrorStaticString::from(
+                            "Buffer does not match the caps of the pad.",
+                        )
+                        .into()),
+                    }
+                }
+            }
+        )*
+    };
+}
+pub(crate) use impl_pull_typed;
@@ -1,15 +1,38 @@
+use std::borrow::Cow;
+
 use serde::{Deserialize, Serialize};
 
-use crate::backends;
+use crate::{
+    backends,
+    element::{registry, Element},
+};
+
+/// A `Kind` of [`Element`], by the name it is [`registered`] under. The
+/// built-in `Kind`s are associated constants.
+///
+/// [`registered`]: crate::element::registry::register
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(transparent)]
+pub struct Kind(Cow<'static, str>);
 
-/// A `Kind` of [`Element`].
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Kind {
+impl Kind {
     /// A [`Prompt` `Element`]. Provides a [`Prompt`].
     ///
     /// Accepts:
     /// - [`Prompt`]s
     /// - [`Message`]s (appending to the prompt)
+    ///
     /// TODO:
     /// - [`ToolSchema`]s (replacing the prompt's tool schemas)
     ///
@@ -17,19 +40,80 @@ pub enum Kind {
     /// - [`Prompt`]s (copies of the prompt)
     ///
     /// [`Prompt`] [`Element`]: crate::element::prompt::Prompt
-    Prompt,
-    /// A [`Inference`] [`Element`], accepting [`Prompt`]s and yielding [`Agent`]
-    /// [`Role`] [`Message`]s. Can also connect to a [`ToolBox`] for tool use.
-    Inference,
+    pub const PROMPT: Kind = Kind::from_static("Prompt");
+    /// A [`Inference`] [`Element`], accepting [`Prompt`]s and yielding
+    /// [`Agent`] [`Role`] [`Message`]s. Can also connect to a [`ToolBox`] for
+    /// tool use.
+    pub const INFERENCE: Kind = Kind::from_static("Inference");
+    /// A [`Tee`] sending copies of every buffer to each of its source pads.
+    /// Backend independent, so available for every [`Backend`].
+    ///
+    /// [`Tee`]: crate::element::tee::Tee
+    /// [`Backend`]: backends::Backend
+    pub const TEE: Kind = Kind::from_static("Tee");
+    /// A [`TeleportSink`] sending buffers to another [`Pipeline`] through a
+    /// named channel. Backend independent.
+    ///
+    /// [`TeleportSink`]: crate::element::teleport::TeleportSink
+    /// [`Pipeline`]: crate::pipeline::Pipeline
+    pub const TELEPORT_SINK: Kind = Kind::from_static("TeleportSink");
+    /// A [`TeleportSource`] receiving buffers from another [`Pipeline`]
+    /// through a named channel. Backend independent.
+    ///
+    /// [`TeleportSource`]: crate::element::teleport::TeleportSource
+    /// [`Pipeline`]: crate::pipeline::Pipeline
+    pub const TELEPORT_SOURCE: Kind = Kind::from_static("TeleportSource");
+    /// A [`Queue`] holding buffers until downstream is ready for them.
+    /// Backend independent.
+    ///
+    /// [`Queue`]: crate::element::queue::Queue
+    pub const QUEUE: Kind = Kind::from_static("Queue");
+    /// A [`Bin`] running a nested [`Pipeline`] as one element. Backend
+    /// independent, although what it contains need not be.
+    ///
+    /// [`Bin`]: crate::element::bin::Bin
+    /// [`Pipeline`]: crate::pipeline::Pipeline
+    pub const BIN: Kind = Kind::from_static("Bin");
+    /// An [`ErrorSink`] logging, counting or replying to [`Error`]s, such as
+    /// those from error pads. Available for every [`Backend`], but only
+    /// replies on those with messages.
+    ///
+    /// [`ErrorSink`]: crate::element::error_sink::ErrorSink
+    /// [`Error`]: crate::buffer::Error
+    /// [`Backend`]: backends::Backend
+    pub const ERROR_SINK: Kind = Kind::from_static("ErrorSink");
+
+    /// A `Kind` named `name`, usable in `const` context.
+    pub const fn from_static(name: &'static str) -> Self {
+        Self(Cow::Borrowed(name))
+    }
+
+    /// The name of this `Kind`.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
-/// An `Owned` [`Element`].
-pub enum Owned {
-    Prompt(Box<dyn crate::element::prompt::Prompt>),
-    Inference(Box<dyn crate::element::inference::Inference>),
+impl From<&'static str> for Kind {
+    fn from(name: &'static str) -> Self {
+        Self::from_static(name)
+    }
 }
 
-/// An error indicating that an [`Element`] is unavailable for a given backend.
+impl From<String> for Kind {
+    fn from(name: String) -> Self {
+        Self(Cow::Owned(name))
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// An error indicating that an [`Element`] is unavailable for a given backend,
+/// or not registered at all.
 #[derive(Debug, thiserror::Error, Serialize, Deserialize)]
 #[error(
     "UNAVAILABLE: Element `{}` is unavailable for backend `{}`.",
@@ -51,6 +135,47 @@ pub struct ConfigError {
     pub message: String,
 }
 
+/// A `SchemaProblem` with the options for an [`Element`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaProblem {
+    /// JSON Pointer to the offending value within the options. Empty for the
+    /// options themselves.
+    pub path: String,
+    /// What is wrong with it.
+    pub message: String,
+}
+
+impl std::fmt::Display for SchemaProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.path.as_str() {
+            "" => write!(f, "{}", self.message),
+            path => write!(f, "`{path}`: {}", self.message),
+        }
+    }
+}
+
+/// Error when options do not match the [`Kind::schema`] of an [`Element`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaError {
+    /// The [`Kind`] of [`Element`] the options are for.
+    pub kind: Kind,
+    /// Every [`SchemaProblem`] found.
+    pub problems: Vec<SchemaProblem>,
+}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SCHEMA: Invalid options for `{}`:", self.kind)?;
+        for problem in self.problems.iter() {
+            write!(f, "\n- {problem}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
 /// Error when constructing a new [`Element`].
 #[derive(
     Debug, thiserror::Error, Serialize, Deserialize, derive_more::IsVariant,
@@ -59,38 +184,66 @@ pub enum NewError {
     /// The [`Element`] is unavailable for the backend.
     #[error(transparent)]
     Unavailable(#[from] UnavailableError),
+    /// The options do not match the [`Kind::schema`].
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
     /// There was a configuration error.
     #[error(transparent)]
     Config(#[from] ConfigError),
 }
 
 impl Kind {
+    /// Every registered `Kind`, sorted by name. See [`registry::kinds`].
+    pub fn all() -> Vec<Kind> {
+        registry::kinds()
+    }
+
+    /// Whether this `Kind` of [`Element`] is registered and available for
+    /// `backend`. If not, [`Kind::new`] fails with an [`UnavailableError`].
+    pub fn available(&self, backend: backends::Backend) -> bool {
+        registry::backends(self)
+            .is_some_and(|backends| backends.contains(&backend))
+    }
+
+    /// JSON Schema for the options of this `Kind` of [`Element`], or `None`
+    /// if it is not registered.
+    pub fn schema(&self) -> Option<schemars::Schema> {
+        registry::schema(self)
+    }
+
+    /// Check `options` against the [`Kind::schema`] without constructing
+    /// anything. Null `options` are treated as an empty object.
+    ///
+    /// # Errors
+    /// - [`UnavailableError`] if this `Kind` is not registered.
+    /// - [`SchemaError`] listing every [`SchemaProblem`] found.
+    pub fn validate(
+        &self,
+        options: &serde_json::Value,
+    ) -> Result<(), NewError> {
+        registry::validate(self, &or_empty(options.clone()))
+    }
+
     /// Construct a new [`Element`] of this kind for a particular backend with
-    /// the given options.
+    /// the given options, using the [`Factory`] registered for it. Null
+    /// `options` are treated as an empty object. They are checked with
+    /// [`Kind::validate`] first.
+    ///
+    /// [`Factory`]: registry::Factory
+    // It makes an `Element` of this `Kind`, not a `Kind`.
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(
-        self,
+        &self,
         backend: backends::Backend,
         options: serde_json::Value,
-    ) -> Result<Owned, NewError> {
-        match self {
-            Kind::Prompt => match backend {
-                // TODO: A backend independent prompt
-                backends::Backend::Independent => Err(UnavailableError {
-                    kind: Kind::Prompt,
-                    backend,
-                }
-                .into()),
-                #[cfg(feature = "misanthropic")]
-                backends::Backend::Misanthropic => {
-                    let prompt: ::misanthropic::Prompt =
-                        serde_json::from_value(options).map_err(|e| {
-                            ConfigError {
-                                message: e.to_string(),
-                            }
-                        })?;
-                    Ok(Owned::Prompt(Box::new(prompt)))
-                }
-            },
-        }
+    ) -> Result<Box<dyn Element>, NewError> {
+        registry::new(self, backend, or_empty(options))
+    }
+}
+
+fn or_empty(options: serde_json::Value) -> serde_json::Value {
+    match options {
+        serde_json::Value::Null => serde_json::json!({}),
+        options => options,
     }
 }
@@ -0,0 +1,966 @@
+//! A [`Bin`] runs a whole [`Pipeline`] as a single [`Element`], like a
+//! GStreamer bin. Chosen pads of the inner [`Pipeline`] are exposed as ghost
+//! pads of the [`Bin`], so a cluster of elements such as a prompt, inference
+//! and tool use loop can be reused as one node.
+//!
+//! Serialized, a [`Bin`] is one node whose [`Options`] hold the inner
+//! [`pipeline::Config`]:
+//!
+//! ```json
+//! {
+//!   "name": "agent",
+//!   "element": "Bin",
+//!   "backend": "Independent",
+//!   "config": {
+//!     "pipeline": { "nodes": [], "edges": [] },
+//!     "sinks": [{ "node": "prompt", "pad": 0 }],
+//!     "sources": [{ "node": "inference", "pad": 0 }]
+//!   }
+//! }
+//! ```
+
+use std::collections::VecDeque;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{
+    backends::Backend,
+    buffer::{any::Typed, sink, source, Error, ErrorStaticString},
+    element::{impl_pull_typed, impl_push_typed, Element},
+    info::Info,
+    pad::{Caps, Event},
+    pipeline::{
+        self, BuildError, Builder, LinkError, NodeIndex, Pipeline, RunError,
+        Shutdown, CHANNEL_CAPACITY,
+    },
+};
+
+/// Prefix of the names of the [`Node`]s a [`Bin`] adds to its [`Pipeline`]
+/// while it runs, to connect the ghost pads.
+///
+/// [`Node`]: crate::pipeline::Node
+pub const GHOST_PREFIX: &str = "__ghost_";
+
+/// A pad of a [`Node`] in the inner [`Pipeline`], by name.
+///
+/// [`Node`]: crate::pipeline::Node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Ghost {
+    /// Name of the [`Node`].
+    ///
+    /// [`Node`]: crate::pipeline::Node
+    pub node: String,
+    /// Index of the pad.
+    pub pad: usize,
+}
+
+/// Options for a [`Bin`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Options {
+    /// The inner [`Pipeline`].
+    pub pipeline: pipeline::Config,
+    /// Sink pads of the inner [`Pipeline`] to expose, in order.
+    pub sinks: Vec<Ghost>,
+    /// Source pads of the inner [`Pipeline`] to expose, in order.
+    pub sources: Vec<Ghost>,
+}
+
+/// A `Bin` [`Element`] running an inner [`Pipeline`]. Backend independent,
+/// although the inner [`Element`]s need not be.
+///
+/// - [`Element::init`] builds, initializes and starts the inner [`Pipeline`].
+///   [`Pipeline::validate`] runs first, with the ghost pads linked.
+/// - [`Buffer`]s pushed to a ghost sink pad go to the inner pad it exposes.
+///   What the inner [`Pipeline`] yields on a ghosted source pad is pulled
+///   from the matching ghost source pad, whenever it is ready. The `Bin` is
+///   [`decoupled`], like a [`Queue`].
+/// - [`Event::Eos`] ends the inner [`Pipeline`]. The `Bin` finishes once the
+///   inner [`Pipeline`] has and everything it yielded has been pulled.
+/// - [`Event::Eos`], [`Event::FlushStart`], [`Event::FlushStop`] and
+///   [`Event::Cancel`] go into the inner [`Pipeline`] through the ghost sink
+///   pad they arrive on, in order with the buffers. [`Event::FlushStart`]
+///   also drops what the inner [`Pipeline`] yielded and was not pulled yet.
+///   Every [`Event`] passes the `Bin` by as well.
+/// - [`Element::stop`] ends the inner [`Pipeline`] if it is still running,
+///   and shuts it down so the `Bin` can be initialized again. If it failed,
+///   the error is returned and the inner [`Pipeline`] is lost.
+///
+/// [`Buffer`]: crate::buffer::Buffer
+/// [`decoupled`]: Element::decoupled
+/// [`Queue`]: crate::element::queue::Queue
+pub struct Bin {
+    /// The inner [`Pipeline`], while it is not running.
+    pipeline: Option<Pipeline<Builder>>,
+    /// Inner pads behind the ghost sink and source pads.
+    sink_targets: Vec<(NodeIndex, usize)>,
+    source_targets: Vec<(NodeIndex, usize)>,
+    /// Whether the inner sink pads are [`Element::required`].
+    required: Vec<bool>,
+    sinks: Vec<SendPad>,
+    sources: Vec<RecvPad>,
+    /// Inner [`Node`]s connecting the ghost pads, while running.
+    ///
+    /// [`Node`]: crate::pipeline::Node
+    ghosts: Vec<NodeIndex>,
+    running: Option<JoinHandle<Ran>>,
+    /// How the inner [`Pipeline`] ended, until the `Bin` is stopped.
+    finished: Option<Ran>,
+}
+
+/// How the inner [`Pipeline`] ended.
+enum Ran {
+    /// It finished cleanly and still has to be shut down.
+    Finished(Pipeline<Shutdown>),
+    /// It failed and its [`Element`]s are stopped.
+    Failed(Pipeline<Builder>, RunError),
+    /// The task running it panicked.
+    Lost(RunError),
+}
+
+/// What goes through the channel behind a ghost pad.
+enum Item {
+    Buffer(Typed),
+    /// Forwarded by a [`Bin`] into its [`Pipeline`].
+    Event(Event),
+}
+
+/// Pad sending [`Typed`] buffers into a channel. Ghost sink pads of a [`Bin`]
+/// and the sink pad of an [`Exit`].
+struct SendPad {
+    caps: Caps,
+    tx: Option<mpsc::Sender<Item>>,
+}
+
+/// Pad yielding [`Typed`] buffers received from a channel. Ghost source pads
+/// of a [`Bin`] and the source pad of an [`Enter`].
+struct RecvPad {
+    caps: Caps,
+    rx: Option<mpsc::Receiver<Item>>,
+    /// Received, not yet pulled.
+    queue: VecDeque<Item>,
+}
+
+/// Inner source [`Element`] yielding what is pushed to a ghost sink pad, and
+/// sending the [`Event`]s the [`Bin`] forwards.
+struct Enter(RecvPad);
+
+/// Inner sink [`Element`] sending what it is pushed to a ghost source pad.
+struct Exit(SendPad);
+
+impl Bin {
+    /// Wrap a `pipeline`. The `Bin` has no pads until some are exposed with
+    /// [`Bin::ghost_sink`] and [`Bin::ghost_source`].
+    pub fn new(pipeline: Pipeline<Builder>) -> Self {
+        Self {
+            pipeline: Some(pipeline),
+            sink_targets: vec![],
+            source_targets: vec![],
+            required: vec![],
+            sinks: vec![],
+            sources: vec![],
+            ghosts: vec![],
+            running: None,
+            finished: None,
+        }
+    }
+
+    /// Create a `Bin` from [`Options`].
+    ///
+    /// # Errors
+    /// - Anything [`Pipeline::from_config`] can return.
+    /// - [`BuildError::UnknownNode`] if a [`Ghost`] names a missing node.
+    /// - [`BuildError::Link`] if a [`Ghost`] pad does not exist.
+    pub fn from_options(options: &Options) -> Result<Self, BuildError> {
+        let pipeline = Pipeline::from_config(&options.pipeline)?;
+        let find = |ghost: &Ghost| {
+            pipeline
+                .find(&ghost.node)
+                .map(|node| (node, ghost.pad))
+                .ok_or_else(|| BuildError::UnknownNode {
+                    name: ghost.node.clone(),
+                })
+        };
+        let sinks: Vec<_> =
+            options.sinks.iter().map(find).collect::<Result<_, _>>()?;
+        let sources: Vec<_> =
+            options.sources.iter().map(find).collect::<Result<_, _>>()?;
+
+        let mut bin = Self::new(pipeline);
+        for (node, pad) in sinks {
+            bin.ghost_sink(node, pad)?;
+        }
+        for (node, pad) in sources {
+            bin.ghost_source(node, pad)?;
+        }
+
+        Ok(bin)
+    }
+
+    /// The [`Options`] this `Bin` can be recreated from. Returns [`None`] if
+    /// the inner [`Pipeline`] can't be serialized (see [`Pipeline::config`])
+    /// or is running.
+    pub fn options(&self) -> Option<Options> {
+        let pipeline = self.pipeline.as_ref()?.config()?;
+        let ghost = |&(node, pad): &(NodeIndex, usize)| Ghost {
+            node: pipeline.nodes[node.index()].name.clone(),
+            pad,
+        };
+
+        let sinks = self.sink_targets.iter().map(ghost).collect();
+        let sources = self.source_targets.iter().map(ghost).collect();
+
+        Some(Options {
+            pipeline,
+            sinks,
+            sources,
+        })
+    }
+
+    /// Expose the sink pad at `pad` of the inner `node` as the next sink pad
+    /// of the `Bin`. Returns the index of the ghost pad.
+    ///
+    /// # Errors
+    /// - [`LinkError::NoSuchNode`] or [`LinkError::NoSuchSinkPad`] if the pad
+    ///   does not exist, or the inner [`Pipeline`] is running.
+    pub fn ghost_sink(
+        &mut self,
+        node: NodeIndex,
+        pad: usize,
+    ) -> Result<usize, LinkError> {
+        let pipeline = self.inner(node)?;
+        let caps = pipeline.sink_caps(node, pad)?;
+        let required = pipeline
+            .element(node)
+            .is_some_and(|node| node.required(pad));
+
+        self.sink_targets.push((node, pad));
+        self.required.push(required);
+        self.sinks.push(SendPad { caps, tx: None });
+
+        Ok(self.sinks.len() - 1)
+    }
+
+    /// Expose the source pad at `pad` of the inner `node` as the next source
+    /// pad of the `Bin`. Returns the index of the ghost pad.
+    ///
+    /// # Errors
+    /// - [`LinkError::NoSuchNode`] or [`LinkError::NoSuchSourcePad`] if the
+    ///   pad does not exist, or the inner [`Pipeline`] is running.
+    pub fn ghost_source(
+        &mut self,
+        node: NodeIndex,
+        pad: usize,
+    ) -> Result<usize, LinkError> {
+        let caps = self.inner(node)?.source_caps(node, pad)?;
+
+        self.source_targets.push((node, pad));
+        self.sources.push(RecvPad::new(caps));
+
+        Ok(self.sources.len() - 1)
+    }
+
+    /// The inner [`Pipeline`], if it is not running.
+    fn inner(&self, node: NodeIndex) -> Result<&Pipeline<Builder>, LinkError> {
+        self.pipeline
+            .as_ref()
+            .ok_or(LinkError::NoSuchNode { node: node.index() })
+    }
+
+    /// Add an [`Enter`] or [`Exit`] for every ghost pad, with fresh channels.
+    fn connect(
+        &mut self,
+        pipeline: &mut Pipeline<Builder>,
+    ) -> Result<(), LinkError> {
+        for (index, &(node, pad)) in self.sink_targets.iter().enumerate() {
+            let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+            let sink = &mut self.sinks[index];
+            sink.tx = Some(tx);
+            let mut enter = RecvPad::new(sink.caps);
+            enter.rx = Some(rx);
+
+            let enter = pipeline.add(
+                format!("{GHOST_PREFIX}sink{index}"),
+                Box::new(Enter(enter)),
+            );
+            self.ghosts.push(enter);
+            pipeline.link(enter, 0, node, pad)?;
+        }
+
+        for (index, &(node, pad)) in self.source_targets.iter().enumerate() {
+            let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+            let source = &mut self.sources[index];
+            source.rx = Some(rx);
+            source.queue.clear();
+            let exit = SendPad {
+                caps: source.caps,
+                tx: Some(tx),
+            };
+
+            let exit = pipeline.add(
+                format!("{GHOST_PREFIX}source{index}"),
+                Box::new(Exit(exit)),
+            );
+            self.ghosts.push(exit);
+            pipeline.link(node, pad, exit, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove what [`Bin::connect`] added, last first so other indices are
+    /// kept.
+    fn disconnect(&mut self, pipeline: &mut Pipeline<Builder>) {
+        for node in self.ghosts.drain(..).rev() {
+            pipeline.remove(node);
+        }
+        for sink in self.sinks.iter_mut() {
+            sink.tx = None;
+        }
+        for source in self.sources.iter_mut() {
+            source.rx = None;
+        }
+    }
+
+    /// Send `event` into the inner [`Pipeline`] through the ghost sink `pad`.
+    /// What the inner [`Pipeline`] yields meanwhile is kept, so it never
+    /// waits for the `Bin` while the `Bin` waits for it.
+    async fn forward(&mut self, pad: usize, event: Event) {
+        let Some(tx) = self.sinks.get(pad).and_then(|sink| sink.tx.clone())
+        else {
+            return;
+        };
+        let sent = tx.send(Item::Event(event));
+        if self.sources.is_empty() {
+            // Gone if the inner pipeline has stopped, which is fine.
+            sent.await.ok();
+            return;
+        }
+
+        tokio::pin!(sent);
+        loop {
+            let received = futures::future::select_all(
+                self.sources
+                    .iter_mut()
+                    .map(|source| Box::pin(source.receive())),
+            );
+            tokio::select! {
+                _ = &mut sent => return,
+                _ = received => {}
+            }
+        }
+    }
+
+    /// Keep what the inner [`Pipeline`] yielded after it finished, and stop
+    /// taking more.
+    fn finish(&mut self, result: Ran) {
+        self.running = None;
+        self.finished = Some(result);
+        for sink in self.sinks.iter_mut() {
+            sink.tx = None;
+        }
+        for source in self.sources.iter_mut() {
+            if let Some(mut rx) = source.rx.take() {
+                while let Ok(buffer) = rx.try_recv() {
+                    source.queue.push_back(buffer);
+                }
+            }
+        }
+    }
+}
+
+impl SendPad {
+    /// Send a buffer, waiting if the channel is full.
+    async fn send(&mut self, buffer: Typed) -> Result<(), Box<dyn Error>> {
+        if buffer.caps() != self.caps {
+            return Err(ErrorStaticString::from(
+                "Buffer does not match the caps of the ghost pad.",
+            )
+            .into());
+        }
+
+        let tx = self.tx.as_ref().ok_or_else(|| {
+            ErrorStaticString::from("The bin is not running.")
+        })?;
+        tx.send(Item::Buffer(buffer)).await.map_err(|_| {
+            ErrorStaticString::from("The pipeline of the bin has stopped.")
+                .into()
+        })
+    }
+
+    /// Whether [`SendPad::send`] would not wait.
+    fn has_room(&self) -> bool {
+        self.tx.as_ref().is_none_or(|tx| tx.capacity() > 0)
+    }
+
+    fn as_sink(&self) -> sink::Any<'_> {
+        match self.caps {
+            Caps::Prompt => sink::Any::Prompt(self),
+            Caps::Message => sink::Any::Message(self),
+            Caps::AgentMessage => sink::Any::AgentMessage(self),
+            Caps::UserMessage => sink::Any::UserMessage(self),
+            Caps::ToolSchema => sink::Any::ToolSchema(self),
+            Caps::ToolUse => sink::Any::ToolUse(self),
+            Caps::ToolResult => sink::Any::ToolResult(self),
+            Caps::Error => sink::Any::Error(self),
+        }
+    }
+
+    fn as_sink_mut(&mut self) -> sink::AnyMut<'_> {
+        match self.caps {
+            Caps::Prompt => sink::AnyMut::Prompt(self),
+            Caps::Message => sink::AnyMut::Message(self),
+            Caps::AgentMessage => sink::AnyMut::AgentMessage(self),
+            Caps::UserMessage => sink::AnyMut::UserMessage(self),
+            Caps::ToolSchema => sink::AnyMut::ToolSchema(self),
+            Caps::ToolUse => sink::AnyMut::ToolUse(self),
+            Caps::ToolResult => sink::AnyMut::ToolResult(self),
+            Caps::Error => sink::AnyMut::Error(self),
+        }
+    }
+}
+
+impl RecvPad {
+    fn new(caps: Caps) -> Self {
+        Self {
+            caps,
+            rx: None,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Take the oldest received [`Typed`] buffer.
+    async fn take(&mut self) -> Result<Typed, Box<dyn Error>> {
+        match self.queue.pop_front() {
+            Some(Item::Buffer(buffer)) => Ok(buffer),
+            item => {
+                // An event is taken with `take_event` instead.
+                if let Some(item) = item {
+                    self.queue.push_front(item);
+                }
+                Err(ErrorStaticString::from(
+                    "Nothing to pull from the ghost pad.",
+                )
+                .into())
+            }
+        }
+    }
+
+    /// Whether the oldest received item is a buffer.
+    fn can_pull(&self) -> bool {
+        matches!(self.queue.front(), Some(Item::Buffer(_)))
+    }
+
+    /// Take the oldest received item if it is an [`Event`].
+    fn take_event(&mut self) -> Option<Event> {
+        match self.queue.pop_front()? {
+            Item::Event(event) => Some(event),
+            item => {
+                self.queue.push_front(item);
+                None
+            }
+        }
+    }
+
+    /// Wait for an item if there is none yet. Never completes if there is
+    /// one, or the channel is closed. Cancel safe.
+    async fn fill(&mut self) {
+        if self.queue.is_empty() {
+            self.receive().await
+        } else {
+            std::future::pending().await
+        }
+    }
+
+    /// Wait for an item, even if some were not taken yet. Never completes if
+    /// the channel is closed. Cancel safe.
+    async fn receive(&mut self) {
+        match self.rx.as_mut() {
+            Some(rx) => match rx.recv().await {
+                Some(item) => self.queue.push_back(item),
+                None => self.rx = None,
+            },
+            None => std::future::pending().await,
+        }
+    }
+
+    fn as_source(&self) -> source::Any<'_> {
+        match self.caps {
+            Caps::Prompt => source::Any::Prompt(self),
+            Caps::Message => source::Any::Message(self),
+            Caps::AgentMessage => source::Any::AgentMessage(self),
+            Caps::UserMessage => source::Any::UserMessage(self),
+            Caps::ToolSchema => source::Any::ToolSchema(self),
+            Caps::ToolUse => source::Any::ToolUse(self),
+            Caps::ToolResult => source::Any::ToolResult(self),
+            Caps::Error => source::Any::Error(self),
+        }
+    }
+
+    fn as_source_mut(&mut self) -> source::AnyMut<'_> {
+        match self.caps {
+            Caps::Prompt => source::AnyMut::Prompt(self),
+            Caps::Message => source::AnyMut::Message(self),
+            Caps::AgentMessage => source::AnyMut::AgentMessage(self),
+            Caps::UserMessage => source::AnyMut::UserMessage(self),
+            Caps::ToolSchema => source::AnyMut::ToolSchema(self),
+            Caps::ToolUse => source::AnyMut::ToolUse(self),
+            Caps::ToolResult => source::AnyMut::ToolResult(self),
+            Caps::Error => source::AnyMut::Error(self),
+        }
+    }
+}
+
+impl_push_typed!(SendPad, send);
+impl_pull_typed!(RecvPad, take);
+
+impl Info for Bin {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "Bin".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Runs a whole pipeline as one element.".into()
+    }
+}
+
+impl Info for SendPad {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "Ghost Sink".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Sends buffers into the pipeline of a bin.".into()
+    }
+}
+
+impl Info for RecvPad {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "Ghost Source".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Receives buffers from the pipeline of a bin.".into()
+    }
+}
+
+impl Info for Enter {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "Bin Enter".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Yields what is pushed to a ghost sink pad of a bin.".into()
+    }
+}
+
+impl Info for Exit {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "Bin Exit".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Sends what it is pushed to a ghost source pad of a bin.".into()
+    }
+}
+
+#[async_trait::async_trait]
+impl Element for Bin {
+    fn backend(&self) -> Backend {
+        Backend::Independent
+    }
+
+    async fn init(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut pipeline = self.pipeline.take().ok_or_else(|| {
+            ErrorStaticString::from("The pipeline of the bin was lost.")
+        })?;
+
+        if let Err(err) = self.connect(&mut pipeline) {
+            self.disconnect(&mut pipeline);
+            self.pipeline = Some(pipeline);
+            return Err(BuildError::from(err).into());
+        }
+        let pipeline = match pipeline.build() {
+            Ok(pipeline) => pipeline,
+            Err((mut pipeline, err)) => {
+                self.disconnect(&mut pipeline);
+                self.pipeline = Some(pipeline);
+                return Err(err.into());
+            }
+        };
+
+        let pipeline = match pipeline.init().await {
+            Ok(pipeline) => pipeline,
+            Err((mut pipeline, err)) => {
+                self.disconnect(&mut pipeline);
+                self.pipeline = Some(pipeline);
+                return Err(err.into());
+            }
+        };
+        self.running = Some(tokio::spawn(async move {
+            match pipeline.run().await {
+                Ok(pipeline) => Ran::Finished(pipeline),
+                Err((pipeline, err)) => Ran::Failed(pipeline, err),
+            }
+        }));
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pipeline.is_some() {
+            return Ok(());
+        }
+
+        // End the inner pipeline, discarding whatever it still yields.
+        for sink in self.sinks.iter_mut() {
+            sink.tx = None;
+        }
+        while self.running.is_some() {
+            for source in self.sources.iter_mut() {
+                source.queue.clear();
+            }
+            self.ready().await;
+        }
+        for source in self.sources.iter_mut() {
+            source.queue.clear();
+        }
+
+        let finished = self.finished.take().ok_or_else(|| {
+            ErrorStaticString::from("The pipeline of the bin was lost.")
+        })?;
+        let (mut pipeline, result) = match finished {
+            Ran::Finished(pipeline) => (pipeline.shutdown().await?, Ok(())),
+            Ran::Failed(pipeline, err) => (pipeline, Err(err.into())),
+            Ran::Lost(err) => return Err(err.into()),
+        };
+        self.disconnect(&mut pipeline);
+        self.pipeline = Some(pipeline);
+
+        result
+    }
+
+    async fn event(
+        &mut self,
+        pad: usize,
+        event: Event,
+    ) -> Result<Option<Event>, Box<dyn Error>> {
+        match event {
+            Event::Eos => {
+                self.forward(pad, Event::Eos).await;
+                // The inner pipeline ends once everything sent is through.
+                for sink in self.sinks.iter_mut() {
+                    sink.tx = None;
+                }
+            }
+            Event::FlushStart => {
+                for source in self.sources.iter_mut() {
+                    source.queue.clear();
+                }
+                self.forward(pad, Event::FlushStart).await;
+            }
+            Event::FlushStop | Event::Cancel => {
+                self.forward(pad, event.clone()).await
+            }
+            _ => {}
+        }
+
+        Ok(Some(event))
+    }
+
+    async fn ready(&mut self) {
+        let Some(running) = self.running.as_mut() else {
+            return std::future::pending().await;
+        };
+
+        let mut wakers: Vec<
+            std::pin::Pin<
+                Box<dyn std::future::Future<Output = ()> + Send + '_>,
+            >,
+        > = vec![];
+        for source in self.sources.iter_mut() {
+            wakers.push(Box::pin(source.fill()));
+        }
+        for tx in self.sinks.iter().filter_map(|sink| sink.tx.as_ref()) {
+            if tx.capacity() == 0 {
+                wakers.push(Box::pin(async move {
+                    // The permit is dropped at once. There is room now.
+                    tx.reserve().await.ok();
+                }));
+            }
+        }
+        let woken = async move {
+            if wakers.is_empty() {
+                std::future::pending().await
+            } else {
+                futures::future::select_all(wakers).await;
+            }
+        };
+
+        let finished = tokio::select! {
+            result = running => Some(result),
+            () = woken => None,
+        };
+        if let Some(result) = finished {
+            // A panic inside the inner pipeline is reported as an error.
+            self.finish(result.unwrap_or_else(|err| {
+                Ran::Lost(RunError::Custom(err.to_string()))
+            }));
+        }
+    }
+
+    fn busy(&self) -> bool {
+        self.running.is_some()
+    }
+
+    fn can_pull(&self, pad: usize) -> bool {
+        self.sources.get(pad).is_some_and(RecvPad::can_pull)
+    }
+
+    fn can_push(&self) -> bool {
+        self.sinks.iter().all(SendPad::has_room)
+    }
+
+    fn decoupled(&self) -> bool {
+        true
+    }
+
+    fn required(&self, pad: usize) -> bool {
+        self.required.get(pad).copied().unwrap_or(false)
+    }
+
+    fn sources<'a>(&'a self) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+        Box::new(self.sources.iter().map(RecvPad::as_source))
+    }
+
+    fn sources_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+        Box::new(self.sources.iter_mut().map(RecvPad::as_source_mut))
+    }
+
+    fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+        Box::new(self.sinks.iter().map(SendPad::as_sink))
+    }
+
+    fn sinks_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+        Box::new(self.sinks.iter_mut().map(SendPad::as_sink_mut))
+    }
+}
+
+#[async_trait::async_trait]
+impl Element for Enter {
+    fn backend(&self) -> Backend {
+        Backend::Independent
+    }
+
+    async fn ready(&mut self) {
+        self.0.fill().await
+    }
+
+    fn take_event(&mut self) -> Option<Event> {
+        self.0.take_event()
+    }
+
+    fn busy(&self) -> bool {
+        self.0.rx.is_some()
+    }
+
+    fn can_pull(&self, _pad: usize) -> bool {
+        self.0.can_pull()
+    }
+
+    fn sources<'a>(&'a self) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+        Box::new(std::iter::once(self.0.as_source()))
+    }
+
+    fn sources_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::once(self.0.as_source_mut()))
+    }
+
+    fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+        Box::new(std::iter::empty())
+    }
+
+    fn sinks_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::empty())
+    }
+}
+
+#[async_trait::async_trait]
+impl Element for Exit {
+    fn backend(&self) -> Backend {
+        Backend::Independent
+    }
+
+    async fn event(
+        &mut self,
+        _pad: usize,
+        _event: Event,
+    ) -> Result<Option<Event>, Box<dyn Error>> {
+        // There is nothing downstream inside the bin.
+        Ok(None)
+    }
+
+    fn required(&self, _pad: usize) -> bool {
+        true
+    }
+
+    fn sources<'a>(&'a self) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+        Box::new(std::iter::empty())
+    }
+
+    fn sources_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::empty())
+    }
+
+    fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+        Box::new(std::iter::once(self.0.as_sink()))
+    }
+
+    fn sinks_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::once(self.0.as_sink_mut()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::queue;
+
+    fn options() -> Options {
+        serde_json::from_value(serde_json::json!({
+            "pipeline": {
+                "nodes": [
+                    {
+                        "name": "queue",
+                        "element": "Queue",
+                        "backend": "Independent",
+                        "config": { "caps": "ToolSchema" }
+                    },
+                    {
+                        "name": "tee",
+                        "element": "Tee",
+                        "backend": "Independent",
+                        "config": { "caps": "ToolSchema", "pads": 2 }
+                    }
+                ],
+                "edges": [{ "source": "queue", "sink": "tee" }]
+            },
+            "sinks": [{ "node": "queue", "pad": 0 }],
+            "sources": [
+                { "node": "tee", "pad": 0 },
+                { "node": "tee", "pad": 1 }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_bin_options() {
+        let bin = Bin::from_options(&options()).unwrap();
+        assert_eq!(bin.sinks().count(), 1);
+        assert_eq!(bin.sources().count(), 2);
+        assert!(bin.sources().all(|pad| pad.caps() == Caps::ToolSchema));
+        assert!(bin.sinks().all(|pad| pad.caps() == Caps::ToolSchema));
+        assert!(bin.required(0));
+
+        let again = bin.options().unwrap();
+        assert_eq!(again.sinks, options().sinks);
+        assert_eq!(again.sources, options().sources);
+        assert_eq!(again.pipeline.edges.len(), 1);
+
+        let mut missing = options();
+        missing.sources[0].node = "nope".into();
+        assert!(matches!(
+            Bin::from_options(&missing),
+            Err(BuildError::UnknownNode { name }) if name == "nope"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bin_lifecycle() {
+        // A bin with nothing to pull is a valid source in an outer pipeline.
+        let mut pipeline = Pipeline::new();
+        let queue = pipeline.add(
+            "queue",
+            Box::new(queue::Queue::new(queue::Options {
+                caps: Caps::ToolSchema,
+                ..Default::default()
+            })),
+        );
+        let mut bin = Bin::new(pipeline);
+        assert_eq!(bin.ghost_sink(queue, 0).unwrap(), 0);
+        assert_eq!(bin.ghost_source(queue, 0).unwrap(), 0);
+        assert!(matches!(
+            bin.ghost_sink(queue, 1),
+            Err(LinkError::NoSuchSinkPad { pad: 1, .. })
+        ));
+
+        for _ in 0..2 {
+            bin.init().await.unwrap();
+            assert!(bin.busy());
+            assert!(bin.can_push());
+            assert!(bin.ghost_sink(queue, 0).is_err());
+
+            bin.event(0, Event::Eos).await.unwrap();
+            while bin.busy() {
+                bin.ready().await;
+            }
+            assert!(!bin.can_pull(0));
+            bin.stop().await.unwrap();
+        }
+
+        // The ghost nodes are gone again.
+        let options = bin.options();
+        assert!(options.is_none(), "the queue was added without a config");
+        assert_eq!(bin.pipeline.as_ref().unwrap().find("queue"), Some(queue));
+        assert!(bin
+            .pipeline
+            .as_ref()
+            .unwrap()
+            .find(&format!("{GHOST_PREFIX}sink0"))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bin_in_pipeline() {
+        let mut outer = Pipeline::new();
+        let bin = outer
+            .add_config(
+                "bin",
+                pipeline::node::Config {
+                    element: crate::element::any::Kind::BIN,
+                    backend: Backend::Independent,
+                    config: serde_json::to_value(options()).unwrap(),
+                },
+            )
+            .unwrap();
+
+        // The ghost sink is required.
+        assert!(matches!(
+            outer.validate().unwrap_err().problems.as_slice(),
+            [pipeline::Problem::Unconnected { pad: 0, .. }]
+        ));
+
+        let config = outer.config().unwrap();
+        assert_eq!(config.nodes.len(), 1);
+        let nested: Options =
+            serde_json::from_value(config.nodes[0].config.config.clone())
+                .unwrap();
+        assert_eq!(nested.pipeline.nodes.len(), 2);
+        assert_eq!(outer.find("bin"), Some(bin));
+    }
+}
@@ -0,0 +1,395 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backends::Backend,
+    buffer::{sink, source, AgentMessage, Error, ErrorStaticString},
+    element::{
+        any::ConfigError,
+        property::{Property, PropertyError, Type, Value},
+        Element,
+    },
+    info::Info,
+    pad::{Pull, Push},
+};
+
+/// What an [`ErrorSink`] does with an [`Error`]. Every [`Error`] is counted
+/// either way.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+pub enum Action {
+    /// Write it to standard error.
+    #[default]
+    Log,
+    /// Only count it.
+    Count,
+    /// Yield an [`AgentMessage`] with [`Options::text`] for the user to see,
+    /// instead of the [`Error`] itself. Needs a [`Backend`] that can make
+    /// one, or [`ErrorSink::with_reply`].
+    Reply,
+}
+
+/// Options for an [`ErrorSink`].
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Options {
+    /// What to do with each [`Error`].
+    pub action: Action,
+    /// Text of the [`AgentMessage`] for [`Action::Reply`].
+    pub text: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            action: Action::Log,
+            text: "Sorry, something went wrong.".to_owned(),
+        }
+    }
+}
+
+/// What an [`ErrorSink`] keeps in a [`Checkpoint`].
+///
+/// [`Checkpoint`]: crate::pipeline::checkpoint::Checkpoint
+#[derive(Serialize, Deserialize)]
+struct State {
+    errors: u64,
+    last: Option<String>,
+}
+
+/// Makes the [`AgentMessage`] an [`ErrorSink`] replies with, from
+/// [`Options::text`] and the [`Error`].
+pub type Reply =
+    Box<dyn Fn(&str, &dyn Error) -> Box<dyn AgentMessage> + Send + 'static>;
+
+/// An `ErrorSink` [`Element`]. Logs, counts or replies to the [`Error`]s
+/// pushed to its sink pad, usually from the error pads of other [`Node`]s,
+/// see [`Pipeline::error_pad`]. Replies are yielded on its source pad, which
+/// can be linked where the user will see them, like a chat UI.
+///
+/// [`Node`]: crate::pipeline::Node
+/// [`Pipeline::error_pad`]: crate::pipeline::Pipeline::error_pad
+pub struct ErrorSink {
+    options: Options,
+    backend: Backend,
+    reply: Option<Reply>,
+    pending: Option<Box<dyn AgentMessage>>,
+    errors: u64,
+    last: Option<String>,
+}
+
+impl ErrorSink {
+    /// Create a new `ErrorSink` from [`Options`], replying with messages for
+    /// the `backend`.
+    ///
+    /// # Errors
+    /// - [`ConfigError`] if the [`Action`] is [`Action::Reply`] and the
+    ///   `backend` has no messages to reply with. Use
+    ///   [`ErrorSink::with_reply`] then.
+    pub fn new(
+        options: Options,
+        backend: Backend,
+    ) -> Result<Self, ConfigError> {
+        let reply = self::reply(backend);
+        if options.action == Action::Reply && reply.is_none() {
+            return Err(ConfigError {
+                message: format!(
+                    "ErrorSink can't reply with {backend:?} messages. Use \
+                    `ErrorSink::with_reply`."
+                ),
+            });
+        }
+
+        Ok(Self::make(options, backend, reply))
+    }
+
+    /// Create a new `ErrorSink` from [`Options`], making replies with
+    /// `reply`, such as messages for another [`Backend`] or saying more
+    /// about the [`Error`].
+    pub fn with_reply<F>(options: Options, backend: Backend, reply: F) -> Self
+    where
+        F: Fn(&str, &dyn Error) -> Box<dyn AgentMessage> + Send + 'static,
+    {
+        Self::make(options, backend, Some(Box::new(reply)))
+    }
+
+    fn make(options: Options, backend: Backend, reply: Option<Reply>) -> Self {
+        Self {
+            options,
+            backend,
+            reply,
+            pending: None,
+            errors: 0,
+            last: None,
+        }
+    }
+
+    /// Number of [`Error`]s pushed so far.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// The last [`Error`] pushed, as text.
+    pub fn last(&self) -> Option<&str> {
+        self.last.as_deref()
+    }
+}
+
+/// The [`Reply`] for a [`Backend`], if it has [`AgentMessage`]s to make.
+fn reply(backend: Backend) -> Option<Reply> {
+    match backend {
+        Backend::Independent => None,
+        #[cfg(feature = "misanthropic")]
+        Backend::Misanthropic => Some(Box::new(|text, _| {
+            use ::misanthropic::prompt::message::Content;
+
+            Box::new(crate::buffer::misanthropic::Assistant::new(
+                Content::SinglePart(text.to_owned().into()),
+            ))
+        })),
+    }
+}
+
+#[async_trait::async_trait]
+impl Push<Box<dyn Error>> for ErrorSink {
+    async fn push(
+        &mut self,
+        error: Box<dyn Error>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.errors += 1;
+        let message = error.to_string();
+        match self.options.action {
+            Action::Log => eprintln!("Error: {message}"),
+            Action::Count => {}
+            Action::Reply => {
+                // There is one, see `ErrorSink::new` and `set_property`.
+                if let Some(reply) = &self.reply {
+                    self.pending = Some(reply(&self.options.text, &*error))
+                }
+            }
+        }
+        self.last = Some(message);
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Pull<Box<dyn AgentMessage>> for ErrorSink {
+    async fn pull(&mut self) -> Result<Box<dyn AgentMessage>, Box<dyn Error>> {
+        self.pending
+            .take()
+            .ok_or(ErrorStaticString::from("No reply to pull.").into())
+    }
+}
+
+impl Info for ErrorSink {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "ErrorSink".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Logs, counts or replies to errors.".into()
+    }
+}
+
+#[async_trait::async_trait]
+impl Element for ErrorSink {
+    fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    fn can_pull(&self, _pad: usize) -> bool {
+        self.pending.is_some()
+    }
+
+    fn required(&self, _pad: usize) -> bool {
+        true
+    }
+
+    fn properties(&self) -> &'static [Property] {
+        const PROPERTIES: &[Property] = &[
+            Property::new("action", Type::String, "What to do with errors."),
+            Property::new("text", Type::String, "Text of the reply."),
+            Property::new("errors", Type::Int, "Number of errors so far.")
+                .read_only(),
+            Property::new("last", Type::String, "The last error.")
+                .optional()
+                .read_only(),
+        ];
+
+        PROPERTIES
+    }
+
+    fn property(&self, name: &str) -> Option<Value> {
+        match name {
+            "action" => serde_json::from_value(
+                serde_json::to_value(self.options.action).ok()?,
+            )
+            .ok(),
+            "text" => Some(Value::String(self.options.text.clone())),
+            "errors" => Some(Value::Int(self.errors as i64)),
+            "last" => Some(self.last.clone().into()),
+            _ => None,
+        }
+    }
+
+    fn set_property(
+        &mut self,
+        name: &str,
+        value: Value,
+    ) -> Result<(), PropertyError> {
+        match name {
+            "action" => {
+                let action = serde_json::to_value(&value)
+                    .and_then(serde_json::from_value)
+                    .map_err(|e| PropertyError::invalid(name, e.to_string()))?;
+                if action == Action::Reply && self.reply.is_none() {
+                    return Err(PropertyError::invalid(
+                        name,
+                        "no messages to reply with",
+                    ));
+                }
+                self.options.action = action;
+            }
+            "text" => {
+                self.options.text =
+                    value.as_str().unwrap_or_default().to_owned()
+            }
+            _ => {
+                return Err(PropertyError::Unknown {
+                    name: name.to_owned(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn checkpoint(
+        &self,
+    ) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>> {
+        let state = State {
+            errors: self.errors,
+            last: self.last.clone(),
+        };
+
+        Ok(Some(serde_json::to_value(state)?))
+    }
+
+    fn restore(
+        &mut self,
+        state: serde_json::Value,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let State { errors, last } = serde_json::from_value(state)?;
+        self.errors = errors;
+        self.last = last;
+
+        Ok(())
+    }
+
+    fn sources<'a>(&'a self) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+        Box::new(std::iter::once(source::Any::AgentMessage(self)))
+    }
+
+    fn sources_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::once(source::AnyMut::AgentMessage(self)))
+    }
+
+    fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+        Box::new(std::iter::once(sink::Any::Error(self)))
+    }
+
+    fn sinks_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::once(sink::AnyMut::Error(self)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer::test, element::property};
+
+    #[tokio::test]
+    async fn test_error_sink() {
+        let mut sink = ErrorSink::new(
+            Options {
+                action: Action::Count,
+                ..Default::default()
+            },
+            Backend::Independent,
+        )
+        .unwrap();
+        for error in ["Overloaded.", "Rate limited."] {
+            sink.push(ErrorStaticString::from(error).into())
+                .await
+                .unwrap();
+        }
+        assert_eq!(sink.errors(), 2);
+        assert_eq!(sink.last(), Some("Rate limited."));
+        assert!(!sink.can_pull(0));
+        assert_eq!(property::get(&sink, "errors").unwrap(), Value::Int(2));
+        assert_eq!(
+            property::get(&sink, "action").unwrap(),
+            Value::String("Count".into())
+        );
+
+        // No messages to reply with.
+        assert!(matches!(
+            property::set(&mut sink, "action", Value::String("Reply".into())),
+            Err(PropertyError::Invalid { .. })
+        ));
+        let reply = Options {
+            action: Action::Reply,
+            ..Default::default()
+        };
+        assert!(ErrorSink::new(reply.clone(), Backend::Independent).is_err());
+        property::set(&mut sink, "action", Value::String("Log".into()))
+            .unwrap();
+        sink.push(ErrorStaticString::from("Timed out.").into())
+            .await
+            .unwrap();
+        assert!(!sink.can_pull(0));
+        assert_eq!(sink.errors(), 3);
+        assert!(property::set(&mut sink, "errors", Value::Int(0)).is_err());
+
+        let mut replying =
+            ErrorSink::with_reply(reply, Backend::Independent, |text, _| {
+                Box::new(test::TextMessage::new(
+                    crate::buffer::message::Role::Agent,
+                    text,
+                ))
+            });
+        replying
+            .push(ErrorStaticString::from("Timed out.").into())
+            .await
+            .unwrap();
+        assert!(replying.can_pull(0));
+        let message = Pull::<Box<dyn AgentMessage>>::pull(&mut replying)
+            .await
+            .unwrap();
+        assert_eq!(message.to_string(), "Sorry, something went wrong.");
+
+        let mut restored =
+            ErrorSink::new(Options::default(), sink.backend()).unwrap();
+        restored
+            .restore(sink.checkpoint().unwrap().unwrap())
+            .unwrap();
+        assert_eq!(restored.errors(), 3);
+        assert_eq!(restored.last(), Some("Timed out."));
+        assert!(restored.restore(serde_json::json!("nope")).is_err());
+    }
+}
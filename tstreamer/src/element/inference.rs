@@ -1,6 +1,14 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
 use crate::{
     buffer::{message::AgentMessage, message::UserMessage, tool, Prompt},
-    element::Element,
+    element::{
+        property::{Counter, Property, PropertyError, Type, Value},
+        Element,
+    },
     pad::{Sink, Source},
 };
 
@@ -14,7 +22,6 @@ use crate::{
 ///
 /// Yields:
 /// - [`AgentMessage`]s (agent role).
-
 pub trait Inference:
     Sink<Box<dyn UserMessage>>
     + Sink<Box<dyn tool::Schema>>
@@ -26,20 +33,457 @@ pub trait Inference:
 {
 }
 
+/// The [`Property`]s of an [`Inference`] [`Element`]: its [`Settings`] and,
+/// read-only, its total [`Usage`].
+pub const PROPERTIES: &[Property] = &[
+    Property::new("model", Type::String, "Model to use.").optional(),
+    Property::new(
+        "max_tokens",
+        Type::Int,
+        "Maximum number of tokens to generate.",
+    )
+    .optional(),
+    Property::new(
+        "temperature",
+        Type::Float,
+        "Sampling temperature, from 0 to 1.",
+    )
+    .optional(),
+    Property::new("requests", Type::Int, "Number of requests made.")
+        .read_only(),
+    Property::new("input_tokens", Type::Int, "Input tokens used.").read_only(),
+    Property::new("output_tokens", Type::Int, "Output tokens generated.")
+        .read_only(),
+];
+
+/// Options for an [`Inference`] made by the [`registry`].
+///
+/// [`registry`]: crate::element::registry
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Options {
+    /// API key for the backend. On [`Misanthropic`] it is read from the
+    /// `ANTHROPIC_API_KEY` environment variable if not set. Prefer that over
+    /// storing keys in configuration.
+    ///
+    /// [`Misanthropic`]: crate::backends::Backend::Misanthropic
+    pub key: Option<String>,
+    /// Initial [`Settings`], changeable at runtime as [`Property`]s.
+    #[serde(flatten)]
+    pub settings: Settings,
+}
+
+/// Generation `Settings` of an [`Inference`] [`Element`], overriding those of
+/// the [`Prompt`]s it is given. Exposed as runtime [`Property`]s, so they can
+/// be changed between requests. [`None`] keeps the [`Prompt`]'s own.
+#[derive(
+    Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(default)]
+pub struct Settings {
+    /// Model to use.
+    pub model: Option<String>,
+    /// Maximum number of tokens to generate.
+    pub max_tokens: Option<u32>,
+    /// Sampling temperature, from 0 to 1.
+    pub temperature: Option<f64>,
+}
+
+impl Settings {
+    /// Read the [`Property`] called `name`. See [`Element::property`].
+    pub fn property(&self, name: &str) -> Option<Value> {
+        match name {
+            "model" => Some(self.model.clone().into()),
+            "max_tokens" => Some(self.max_tokens.map(i64::from).into()),
+            "temperature" => Some(self.temperature.into()),
+            _ => None,
+        }
+    }
+
+    /// Write the [`Property`] called `name`. See [`Element::set_property`].
+    ///
+    /// # Errors
+    /// - [`PropertyError::Invalid`] if `value` is out of range.
+    /// - [`PropertyError::Unknown`] if there is no such [`Property`].
+    pub fn set_property(
+        &mut self,
+        name: &str,
+        value: Value,
+    ) -> Result<(), PropertyError> {
+        match name {
+            "model" => self.model = value.as_str().map(str::to_owned),
+            "max_tokens" => {
+                self.max_tokens = match value.as_int() {
+                    None => None,
+                    Some(max) => Some(
+                        u32::try_from(max)
+                            .ok()
+                            .filter(|max| *max > 0)
+                            .ok_or_else(|| {
+                                PropertyError::invalid(name, "must be positive")
+                            })?,
+                    ),
+                }
+            }
+            "temperature" => {
+                self.temperature = match value.as_float() {
+                    Some(t) if !(0.0..=1.0).contains(&t) => {
+                        return Err(PropertyError::invalid(
+                            name,
+                            "must be from 0 to 1",
+                        ))
+                    }
+                    t => t,
+                }
+            }
+            _ => {
+                return Err(PropertyError::Unknown {
+                    name: name.to_owned(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Override the fields of a JSON `request` in the Anthropic Messages API
+    /// format with the `Settings` that are set.
+    pub fn apply(&self, request: &mut serde_json::Value) {
+        if let Some(model) = &self.model {
+            request["model"] = model.clone().into();
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            request["max_tokens"] = max_tokens.into();
+        }
+        if let Some(temperature) = self.temperature {
+            request["temperature"] = temperature.into();
+        }
+    }
+}
+
+/// [`Tokens`] used by an [`Inference`] [`Element`] for one model.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct Tokens {
+    /// Number of requests made.
+    pub requests: u64,
+    /// Input (prompt) tokens.
+    pub input: u64,
+    /// Output (generated) tokens.
+    pub output: u64,
+}
+
+/// The [`Tokens`] used by an [`Inference`] [`Element`], per model. The
+/// [`Element`] records every response and exposes the totals as read-only
+/// [`PROPERTIES`] and those per model as [`Counter`]s, which [`Tracer`]s
+/// like [`Metrics`] read as it runs.
+///
+/// [`Tracer`]: crate::pipeline::trace::Tracer
+/// [`Metrics`]: crate::pipeline::metrics::Metrics
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    models: BTreeMap<String, Tokens>,
+}
+
+impl Usage {
+    /// Record a request to `model` that used `input` and `output` tokens.
+    pub fn record(&mut self, model: &str, input: u64, output: u64) {
+        let tokens = self.models.entry(model.to_owned()).or_default();
+        tokens.requests += 1;
+        tokens.input += input;
+        tokens.output += output;
+    }
+
+    /// [`Tokens`] by model.
+    pub fn models(&self) -> &BTreeMap<String, Tokens> {
+        &self.models
+    }
+
+    /// [`Tokens`] of every model together.
+    pub fn total(&self) -> Tokens {
+        self.models
+            .values()
+            .fold(Tokens::default(), |total, tokens| Tokens {
+                requests: total.requests + tokens.requests,
+                input: total.input + tokens.input,
+                output: total.output + tokens.output,
+            })
+    }
+
+    /// Read the [`Property`] called `name`. See [`Element::property`].
+    pub fn property(&self, name: &str) -> Option<Value> {
+        let total = self.total();
+        match name {
+            "requests" => Some(Value::Int(total.requests as i64)),
+            "input_tokens" => Some(Value::Int(total.input as i64)),
+            "output_tokens" => Some(Value::Int(total.output as i64)),
+            _ => None,
+        }
+    }
+
+    /// `inference_input_tokens` and `inference_output_tokens` per model, for
+    /// [`Element::counters`].
+    pub fn counters(&self) -> Vec<Counter> {
+        self.models
+            .iter()
+            .flat_map(|(model, tokens)| {
+                let labels = vec![("model", model.clone())];
+                [
+                    Counter {
+                        name: "inference_input_tokens",
+                        description: "Input tokens used, per model.",
+                        labels: labels.clone(),
+                        value: tokens.input,
+                    },
+                    Counter {
+                        name: "inference_output_tokens",
+                        description: "Output tokens generated, per model.",
+                        labels,
+                        value: tokens.output,
+                    },
+                ]
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "misanthropic")]
 pub mod misanthropic {
-    use std::borrow::Cow;
+    use std::{borrow::Cow, collections::VecDeque, sync::Arc};
 
-    use ::misanthropic::Client;
+    use ::misanthropic::{prompt, Client};
+    use tokio::task::{JoinError, JoinHandle};
 
     use crate::{
-        backends::Backend, buffer, element::prompt::PromptSource, info::Info,
+        backends::Backend,
+        buffer::{self, Error, ErrorStaticString, Meta, Tagged},
+        info::Info,
+        pad::{Caps, Event, Pull, Push},
     };
 
     use super::*;
 
-    impl Inference for Client {}
+    /// What a [`Misanthropic`] keeps in a [`Checkpoint`]: the conversation
+    /// and whatever was not pulled yet. Its [`Settings`] are properties, kept
+    /// in the config.
+    ///
+    /// [`Checkpoint`]: crate::pipeline::checkpoint::Checkpoint
+    #[derive(Serialize, Deserialize)]
+    struct State {
+        usage: Usage,
+        prompt: Option<::misanthropic::Prompt<'static>>,
+        tools: Vec<serde_json::Value>,
+        waiting: usize,
+        replies: VecDeque<prompt::Message<'static>>,
+        calls: VecDeque<::misanthropic::tool::Use<'static>>,
+        /// A request was in flight. It is made again once restored.
+        asked: bool,
+    }
+
+    /// [`Inference`] on the Misanthropic backend. A [`Client`] and the
+    /// [`Settings`] to use with it.
+    ///
+    /// The conversation starts with the [`Prompt`] pushed to it. Every
+    /// [`UserMessage`] is appended and answered. [`tool::Result`]s are
+    /// appended and answered once every [`tool::Use`] of the last reply has
+    /// one. Replies are yielded as [`AgentMessage`]s and their tool calls as
+    /// [`tool::Use`]s, and both are appended to the conversation.
+    ///
+    /// Requests are made in the background, so [`Event::Cancel`] can abort
+    /// one in flight. Anything pushed meanwhile waits for it.
+    ///
+    /// [`Event::Cancel`]: crate::pad::Event::Cancel
+    pub struct Misanthropic {
+        client: Arc<Client>,
+        settings: Settings,
+        usage: Usage,
+        prompt: Option<::misanthropic::Prompt<'static>>,
+        tools: Vec<serde_json::Value>,
+        /// Tool calls of the last reply still waiting for a result.
+        waiting: usize,
+        replies: VecDeque<prompt::Message<'static>>,
+        calls: VecDeque<::misanthropic::tool::Use<'static>>,
+        /// The request in flight.
+        pending: Option<JoinHandle<Result<Response, Box<dyn Error>>>>,
+        /// Why the last request failed, yielded by the next pull.
+        failed: Option<Box<dyn Error>>,
+        /// A request was in flight when the state was restored. It is made
+        /// again by [`Element::init`].
+        asked: bool,
+        /// Session and trace of the conversation, for what it yields.
+        meta: Option<Meta>,
+    }
+
+    /// What a request to the model got back.
+    struct Response {
+        /// The model as the backend names it, like the API.
+        model: String,
+        input_tokens: u64,
+        output_tokens: u64,
+        message: prompt::Message<'static>,
+    }
+
+    impl Misanthropic {
+        /// Use `client` with `settings`.
+        pub fn new(client: Client, settings: Settings) -> Self {
+            Self {
+                client: Arc::new(client),
+                settings,
+                usage: Usage::default(),
+                prompt: None,
+                tools: Vec::new(),
+                waiting: 0,
+                replies: VecDeque::new(),
+                calls: VecDeque::new(),
+                pending: None,
+                failed: None,
+                asked: false,
+                meta: None,
+            }
+        }
+
+        /// The [`Client`].
+        pub fn client(&self) -> &Client {
+            &self.client
+        }
+
+        /// The current [`Settings`].
+        pub fn settings(&self) -> &Settings {
+            &self.settings
+        }
+
+        /// The [`Usage`] so far.
+        pub fn usage(&self) -> &Usage {
+            &self.usage
+        }
+
+        /// Append `message` to the conversation.
+        fn append(
+            &mut self,
+            message: Box<dyn buffer::Message>,
+        ) -> Result<(), Box<dyn Error>> {
+            let prompt = self.prompt.as_mut().ok_or_else(|| {
+                ErrorStaticString::from(
+                    "Inference has no `Prompt` to continue. Push one first.",
+                )
+            })?;
+            if let Some(meta) = buffer::Buffer::meta(&message) {
+                self.meta = Some(meta.derived());
+            }
+            prompt.push_message(message.into_concrete())?;
 
-    impl Info for Client {
+            Ok(())
+        }
+
+        /// The [`Prompt`] to send, with the tool [`Schema`]s and the
+        /// [`Settings`] applied.
+        ///
+        /// [`Prompt`]: ::misanthropic::Prompt
+        /// [`Schema`]: tool::Schema
+        fn request(
+            &self,
+        ) -> Result<::misanthropic::Prompt<'static>, Box<dyn Error>> {
+            let prompt = self.prompt.as_ref().ok_or_else(|| {
+                ErrorStaticString::from(
+                    "Inference has no `Prompt` to continue. Push one first.",
+                )
+            })?;
+            if self.tools.is_empty() && self.settings == Settings::default() {
+                return Ok(prompt.clone());
+            }
+
+            let invalid = |_| {
+                ErrorStaticString::from(
+                    "Settings or tool schemas are not valid for the Misanthropic backend.",
+                )
+            };
+            let mut request = serde_json::to_value(prompt).map_err(invalid)?;
+            self.settings.apply(&mut request);
+            if !self.tools.is_empty() {
+                request["tools"] = self.tools.clone().into();
+            }
+            Ok(serde_json::from_value(request).map_err(invalid)?)
+        }
+
+        /// Send the conversation in the background. See [`Self::answered`].
+        fn respond(&mut self) -> Result<(), Box<dyn Error>> {
+            let request = self.request()?;
+            let client = Arc::clone(&self.client);
+            self.pending = Some(tokio::spawn(async move {
+                let response = client.message(&request).await?;
+                let model = serde_json::to_value(&response.model)
+                    .ok()
+                    .and_then(|model| model.as_str().map(str::to_owned))
+                    .unwrap_or_default();
+                Ok::<_, Box<dyn Error>>(Response {
+                    model,
+                    input_tokens: response.usage.input_tokens,
+                    output_tokens: response.usage.output_tokens,
+                    message: response.message,
+                })
+            }));
+
+            Ok(())
+        }
+
+        /// Wait for the request in flight, if any.
+        async fn settle(&mut self) -> Result<(), Box<dyn Error>> {
+            match self.pending.as_mut() {
+                Some(pending) => {
+                    let result = pending.await;
+                    self.answered(result)
+                }
+                None => Ok(()),
+            }
+        }
+
+        /// The request in flight finished. Queue the reply and its tool calls.
+        fn answered(
+            &mut self,
+            result: Result<Result<Response, Box<dyn Error>>, JoinError>,
+        ) -> Result<(), Box<dyn Error>> {
+            self.pending = None;
+            let Response {
+                model,
+                input_tokens,
+                output_tokens,
+                message: reply,
+            } = result.map_err(|_| {
+                ErrorStaticString::from("The request to the model panicked.")
+            })??;
+            self.usage.record(&model, input_tokens, output_tokens);
+
+            if let prompt::message::Content::MultiPart(blocks) = &reply.content
+            {
+                for block in blocks {
+                    if let prompt::message::Block::ToolUse { call } = block {
+                        self.calls.push_back(call.clone());
+                        self.waiting += 1;
+                    }
+                }
+            }
+            self.append(Box::new(reply.clone()))?;
+            self.replies.push_back(reply);
+
+            Ok(())
+        }
+
+        /// Abandon the request in flight and whatever was not pulled yet.
+        fn cancel(&mut self) {
+            if let Some(pending) = self.pending.take() {
+                pending.abort();
+            }
+            self.failed = None;
+            self.waiting = 0;
+            self.replies.clear();
+            self.calls.clear();
+        }
+    }
+
+    impl Inference for Misanthropic {}
+
+    impl Info for Misanthropic {
         fn name<'a>(&'a self) -> std::borrow::Cow<'a, str> {
             Cow::Borrowed(concat!(
                 stringify!(Inference),
@@ -58,12 +502,137 @@ pub mod misanthropic {
     }
 
     #[async_trait::async_trait]
-    impl Element for Client {
+    impl Element for Misanthropic {
         #[inline]
         fn backend(&self) -> Backend {
             Backend::Misanthropic
         }
 
+        fn properties(&self) -> &'static [Property] {
+            PROPERTIES
+        }
+
+        fn property(&self, name: &str) -> Option<Value> {
+            self.settings
+                .property(name)
+                .or_else(|| self.usage.property(name))
+        }
+
+        fn set_property(
+            &mut self,
+            name: &str,
+            value: Value,
+        ) -> Result<(), PropertyError> {
+            self.settings.set_property(name, value)
+        }
+
+        fn counters(&self) -> Vec<Counter> {
+            self.usage.counters()
+        }
+
+        async fn init(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+            if std::mem::take(&mut self.asked) {
+                self.respond().map_err(|e| e.to_string())?;
+            }
+
+            Ok(())
+        }
+
+        async fn stop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+            if let Some(pending) = self.pending.take() {
+                pending.abort();
+            }
+
+            Ok(())
+        }
+
+        async fn event(
+            &mut self,
+            _pad: usize,
+            event: Event,
+        ) -> Result<Option<Event>, Box<dyn Error>> {
+            if event == Event::Cancel {
+                self.cancel();
+            }
+
+            Ok(Some(event))
+        }
+
+        fn decoupled(&self) -> bool {
+            // Replies are pulled once they arrive.
+            true
+        }
+
+        async fn ready(&mut self) {
+            let Some(pending) = self.pending.as_mut() else {
+                return std::future::pending().await;
+            };
+            let result = pending.await;
+            if let Err(err) = self.answered(result) {
+                self.failed = Some(err);
+            }
+        }
+
+        fn busy(&self) -> bool {
+            self.pending.is_some()
+        }
+
+        fn checkpoint(
+            &self,
+        ) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>>
+        {
+            let state = State {
+                usage: self.usage.clone(),
+                prompt: self.prompt.clone(),
+                tools: self.tools.clone(),
+                waiting: self.waiting,
+                replies: self.replies.clone(),
+                calls: self.calls.clone(),
+                asked: self.pending.is_some(),
+            };
+
+            Ok(Some(serde_json::to_value(state)?))
+        }
+
+        fn restore(
+            &mut self,
+            state: serde_json::Value,
+        ) -> Result<(), Box<dyn std::error::Error>> {
+            let State {
+                usage,
+                prompt,
+                tools,
+                waiting,
+                replies,
+                calls,
+                asked,
+            } = serde_json::from_value(state)?;
+            self.usage = usage;
+            self.prompt = prompt;
+            self.tools = tools;
+            self.waiting = waiting;
+            self.replies = replies;
+            self.calls = calls;
+            self.asked = asked;
+
+            Ok(())
+        }
+
+        fn required(&self, pad: usize) -> bool {
+            // Nothing happens without a `Prompt`.
+            self.sinks()
+                .nth(pad)
+                .is_some_and(|sink| sink.caps() == Caps::Prompt)
+        }
+
+        fn can_pull(&self, pad: usize) -> bool {
+            self.failed.is_some()
+                || match pad {
+                    0 => !self.replies.is_empty(),
+                    _ => !self.calls.is_empty(),
+                }
+        }
+
         fn sources<'a>(
             &'a self,
         ) -> Box<dyn Iterator<Item = buffer::source::Any<'a>> + 'a> {
@@ -81,8 +650,8 @@ pub mod misanthropic {
         ) -> Box<dyn Iterator<Item = buffer::source::AnyMut<'a>> + 'a> {
             Box::new(
                 [
-                    buffer::source::Any::AgentMessage(self),
-                    buffer::source::Any::ToolUse(self),
+                    buffer::source::AnyMut::AgentMessage(self),
+                    buffer::source::AnyMut::ToolUse(self),
                 ]
                 .into_iter(),
             )
@@ -107,10 +676,10 @@ pub mod misanthropic {
         ) -> Box<dyn Iterator<Item = buffer::sink::AnyMut<'a>> + 'a> {
             Box::new(
                 [
-                    buffer::sink::Any::UserMessage(self),
-                    buffer::sink::Any::ToolSchema(self),
-                    buffer::sink::Any::ToolResult(self),
-                    buffer::sink::Any::Prompt(self),
+                    buffer::sink::AnyMut::UserMessage(self),
+                    buffer::sink::AnyMut::ToolSchema(self),
+                    buffer::sink::AnyMut::ToolResult(self),
+                    buffer::sink::AnyMut::Prompt(self),
                 ]
                 .into_iter(),
             )
@@ -118,14 +687,296 @@ pub mod misanthropic {
     }
 
     #[async_trait::async_trait]
-    impl Sink<Box<dyn UserMessage>> for Client {
+    impl Push<Box<dyn buffer::Prompt>> for Misanthropic {
+        /// Start the conversation over with `prompt`, answering it if the
+        /// user has the last word.
+        async fn push(
+            &mut self,
+            prompt: Box<dyn buffer::Prompt>,
+        ) -> Result<(), Box<dyn Error>> {
+            self.settle().await?;
+            self.meta = buffer::Buffer::meta(&prompt).map(Meta::derived);
+            let prompt: ::misanthropic::Prompt<'static> =
+                prompt.into_concrete().into();
+            let answer = prompt.messages.last().is_some_and(|message| {
+                message.role == prompt::message::Role::User
+            });
+            self.prompt = Some(prompt);
+            self.waiting = 0;
+
+            if answer {
+                self.respond()?;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Push<Box<dyn UserMessage>> for Misanthropic {
+        /// Append `message` and answer it.
+        async fn push(
+            &mut self,
+            message: Box<dyn UserMessage>,
+        ) -> Result<(), Box<dyn Error>> {
+            self.settle().await?;
+            self.append(message)?;
+            self.respond()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Push<Box<dyn tool::Schema>> for Misanthropic {
+        /// Offer the tool described by `schema` in every request.
         async fn push(
             &mut self,
-            buffer: Box<dyn UserMessage>,
+            schema: Box<dyn tool::Schema>,
         ) -> Result<(), Box<dyn Error>> {
-            let native: ::misanthropic::prompt::Message<'static> =
-                buffer.into_concrete().into();
-            let prompt = (self as &dyn PromptSource).pull();
+            self.tools.push(schema.schema().clone());
+
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Push<Box<dyn tool::Result>> for Misanthropic {
+        /// Append `result`, answering once every call has a result.
+        async fn push(
+            &mut self,
+            result: Box<dyn tool::Result>,
+        ) -> Result<(), Box<dyn Error>> {
+            self.settle().await?;
+            self.append(result)?;
+            self.waiting = self.waiting.saturating_sub(1);
+
+            if self.waiting == 0 {
+                self.respond()?;
+            }
+
+            Ok(())
         }
     }
+
+    #[async_trait::async_trait]
+    impl Pull<Box<dyn AgentMessage>> for Misanthropic {
+        /// The next reply.
+        ///
+        /// # Errors
+        /// - If the last request failed.
+        /// - If there is none, see [`Element::can_pull`].
+        async fn pull(
+            &mut self,
+        ) -> Result<Box<dyn AgentMessage>, Box<dyn Error>> {
+            if let Some(err) = self.failed.take() {
+                return Err(err);
+            }
+            match self.replies.pop_front() {
+                Some(reply) => {
+                    let reply =
+                        buffer::misanthropic::Assistant::try_from(reply)?;
+                    let reply: Box<dyn AgentMessage> = match &self.meta {
+                        Some(meta) => {
+                            Box::new(Tagged::with_meta(reply, meta.derived()))
+                        }
+                        None => Box::new(reply),
+                    };
+                    Ok(reply)
+                }
+                None => {
+                    Err(ErrorStaticString::from("No reply to pull.").into())
+                }
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Pull<Box<dyn tool::Use>> for Misanthropic {
+        /// The next tool call.
+        ///
+        /// # Errors
+        /// - If the last request failed.
+        /// - If there is none, see [`Element::can_pull`].
+        async fn pull(&mut self) -> Result<Box<dyn tool::Use>, Box<dyn Error>> {
+            if let Some(err) = self.failed.take() {
+                return Err(err);
+            }
+            match self.calls.pop_front() {
+                Some(call) => {
+                    let call: Box<dyn tool::Use> = match &self.meta {
+                        Some(meta) => {
+                            Box::new(Tagged::with_meta(call, meta.derived()))
+                        }
+                        None => Box::new(call),
+                    };
+                    Ok(call)
+                }
+                None => {
+                    Err(ErrorStaticString::from("No tool call to pull.").into())
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_inference_checkpoint() {
+            let client = || Client::new("test".to_owned()).unwrap();
+            let mut inference =
+                Misanthropic::new(client(), Settings::default());
+            inference.usage.record("claude", 10, 5);
+            inference.prompt = Some(::misanthropic::Prompt::default());
+            inference
+                .tools
+                .push(serde_json::json!({ "name": "search" }));
+            inference.waiting = 1;
+            inference.replies.push_back(
+                (prompt::message::Role::Assistant, "Searching.").into(),
+            );
+            let state = inference.checkpoint().unwrap().unwrap();
+
+            let mut restored = Misanthropic::new(client(), Settings::default());
+            restored.restore(state.clone()).unwrap();
+            assert_eq!(restored.usage, inference.usage);
+            assert_eq!(restored.waiting, 1);
+            assert!(restored.can_pull(0));
+            assert_eq!(restored.checkpoint().unwrap().unwrap(), state);
+            assert!(restored.restore(serde_json::json!(42)).is_err());
+        }
+
+        #[tokio::test]
+        async fn test_inference_cancel() {
+            let client = Client::new("test".to_owned()).unwrap();
+            let mut inference = Misanthropic::new(client, Settings::default());
+            // Dropped once the request is aborted.
+            let (tx, dropped) = tokio::sync::oneshot::channel::<()>();
+            inference.pending = Some(tokio::spawn(async move {
+                let _tx = tx;
+                std::future::pending().await
+            }));
+            inference.waiting = 1;
+            inference.replies.push_back(
+                (prompt::message::Role::Assistant, "Searching.").into(),
+            );
+            assert!(inference.busy());
+
+            assert_eq!(
+                inference.event(0, Event::Cancel).await.unwrap(),
+                Some(Event::Cancel)
+            );
+            assert!(!inference.busy());
+            assert!(!inference.can_pull(0));
+            assert_eq!(inference.waiting, 0);
+            let aborted = tokio::time::timeout(
+                std::time::Duration::from_secs(10),
+                dropped,
+            )
+            .await
+            .expect("Timed out waiting for the request to be aborted.");
+            assert!(aborted.is_err());
+        }
+
+        #[tokio::test]
+        async fn test_inference_meta() {
+            use crate::buffer::meta::{Sequence, TraceId};
+
+            let client = Client::new("test".to_owned()).unwrap();
+            let mut inference = Misanthropic::new(client, Settings::default());
+            let mut meta = Meta::now();
+            meta.insert(TraceId(7));
+            meta.insert(Sequence(0));
+            let prompt = ::misanthropic::Prompt::default();
+            let prompt: Box<dyn buffer::Prompt> =
+                Box::new(Tagged::with_meta(prompt, meta));
+            inference.push(prompt).await.unwrap();
+            inference
+                .replies
+                .push_back((prompt::message::Role::Assistant, "Hello.").into());
+
+            let reply: Box<dyn AgentMessage> = inference.pull().await.unwrap();
+            let meta = buffer::Buffer::meta(&reply).unwrap();
+            assert_eq!(meta.get::<TraceId>(), Some(&TraceId(7)));
+            assert!(!meta.contains::<Sequence>());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.property("model"), Some(Value::Null));
+
+        settings.set_property("model", "claude".into()).unwrap();
+        settings.set_property("max_tokens", 256.into()).unwrap();
+        settings.set_property("temperature", 0.5.into()).unwrap();
+        assert_eq!(settings.property("max_tokens"), Some(Value::Int(256)));
+        assert!(settings.set_property("max_tokens", 0.into()).is_err());
+        assert!(settings.set_property("temperature", 1.5.into()).is_err());
+        assert_eq!(
+            settings,
+            Settings {
+                model: Some("claude".into()),
+                max_tokens: Some(256),
+                temperature: Some(0.5),
+            }
+        );
+
+        settings.set_property("temperature", Value::Null).unwrap();
+        assert_eq!(settings.temperature, None);
+
+        let mut request = serde_json::json!({
+            "model": "other",
+            "max_tokens": 1024,
+            "temperature": 1.0,
+            "messages": [],
+        });
+        settings.apply(&mut request);
+        assert_eq!(
+            request,
+            serde_json::json!({
+                "model": "claude",
+                "max_tokens": 256,
+                "temperature": 1.0,
+                "messages": [],
+            })
+        );
+    }
+
+    #[test]
+    fn test_usage() {
+        let mut usage = Usage::default();
+        usage.record("claude", 10, 5);
+        usage.record("claude", 20, 7);
+        usage.record("other", 1, 1);
+
+        let models = usage.models();
+        assert_eq!(
+            models["claude"],
+            Tokens {
+                requests: 2,
+                input: 30,
+                output: 12,
+            }
+        );
+        assert_eq!(models["other"].requests, 1);
+        assert_eq!(usage.total().input, 31);
+        assert_eq!(usage.property("requests"), Some(Value::Int(3)));
+        assert_eq!(usage.property("output_tokens"), Some(Value::Int(13)));
+        assert_eq!(usage.property("model"), None);
+
+        let json = serde_json::to_value(&usage).unwrap();
+        assert_eq!(serde_json::from_value::<Usage>(json).unwrap(), usage);
+
+        let counters = usage.counters();
+        assert_eq!(counters.len(), 4);
+        assert_eq!(counters[1].name, "inference_output_tokens");
+        assert_eq!(counters[1].labels, [("model", "claude".to_owned())]);
+        assert_eq!(counters[1].value, 12);
+    }
 }
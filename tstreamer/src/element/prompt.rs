@@ -1,29 +1,25 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    buffer::{self, Error},
-    element::Element,
+    buffer,
+    element::{inference::Settings, Element},
     info::Info,
-    new::New,
-    pad::{
-        direction::{Direction, Pulls},
-        Pull, Push, Sink, Source,
-    },
+    pad::{Pull, Push},
 };
 
 /// [`Prompt`] [`Source`]. Yields copies of a [`Prompt`].
 ///
-/// [`Message`]: crate::buffer::Message
-pub trait PromptSource<D: Direction>:
-    Source<Box<dyn buffer::Prompt>, D> + Info
-{
-}
-static_assertions::assert_obj_safe!(PromptSource<Pulls>);
+/// [`Source`]: crate::pad::Source
+pub trait PromptSource: Pull<Box<dyn buffer::Prompt>> + Info + Send {}
+static_assertions::assert_obj_safe!(PromptSource);
 
-impl<T, D: Direction> PromptSource<D> for T where
-    T: Source<Box<dyn buffer::Prompt>, D> + Element
+impl<T> PromptSource for T where
+    T: Pull<Box<dyn crate::buffer::Prompt>> + Info + Send
 {
 }
 
-impl<D> Info for Box<dyn PromptSource<D>> {
+impl Info for Box<dyn PromptSource> {
     fn name(&self) -> std::borrow::Cow<'_, str> {
         "Prompt Source".into()
     }
@@ -43,48 +39,247 @@ pub trait Prompt:
 {
 }
 
+/// Options for a [`Prompt`] made by the [`registry`]. Each backend builds its
+/// own prompt from them.
+///
+/// [`registry`]: crate::element::registry
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Options {
+    /// System prompt, as plain text.
+    pub system: Option<String>,
+    /// The conversation so far, oldest first.
+    pub messages: Vec<Turn>,
+    /// Generation [`Settings`]. Those of an [`Inference`] take precedence.
+    ///
+    /// [`Inference`]: crate::element::inference::Inference
+    #[serde(flatten)]
+    pub settings: Settings,
+}
+
+/// A single message in the [`Options`] of a [`Prompt`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Turn {
+    /// Who said it.
+    pub role: Role,
+    /// What was said, as plain text.
+    pub content: String,
+}
+
+/// [`Role`] of a [`Turn`]. The system prompt is [`Options::system`].
+///
+/// [`Role`]: crate::buffer::message::Role
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// The end user.
+    User,
+    /// The agent, usually from an earlier [`Inference`].
+    ///
+    /// [`Inference`]: crate::element::inference::Inference
+    Agent,
+}
+
 #[cfg(feature = "misanthropic")]
-mod misanthropic {
+pub mod misanthropic {
     use crate::backends::Backend;
-    use crate::buffer::{sink, source, Message};
+    use crate::buffer::{sink, source, Error, Message, Meta, Tagged};
+    use crate::element::property::{Property, PropertyError, Type, Value};
 
     use super::*;
 
+    /// [`Prompt`] on the Misanthropic backend.
+    ///
+    /// The copies it yields carry the [`SessionId`] and [`TraceId`] of the
+    /// last buffer pushed to it that had any.
+    ///
+    /// [`SessionId`]: crate::buffer::meta::SessionId
+    /// [`TraceId`]: crate::buffer::meta::TraceId
+    #[derive(Debug, Clone, Default)]
+    pub struct Misanthropic {
+        prompt: ::misanthropic::Prompt<'static>,
+        meta: Option<Meta>,
+    }
+
+    impl Misanthropic {
+        /// The [`Prompt`](::misanthropic::Prompt) so far.
+        pub fn prompt(&self) -> &::misanthropic::Prompt<'static> {
+            &self.prompt
+        }
+
+        /// Follow the session and trace of a buffer pushed, if it has any.
+        fn follow(&mut self, meta: Option<&Meta>) {
+            if let Some(meta) = meta {
+                self.meta = Some(meta.derived());
+            }
+        }
+    }
+
+    impl From<::misanthropic::Prompt<'static>> for Misanthropic {
+        fn from(prompt: ::misanthropic::Prompt<'static>) -> Self {
+            Self { prompt, meta: None }
+        }
+    }
+
+    impl TryFrom<super::Options> for Misanthropic {
+        type Error = serde_json::Error;
+
+        /// Build the [`Prompt`](::misanthropic::Prompt) through its
+        /// serialized form, leaving out anything not set.
+        fn try_from(options: super::Options) -> Result<Self, Self::Error> {
+            let messages: Vec<serde_json::Value> = options
+                .messages
+                .into_iter()
+                .map(|turn| {
+                    let role = match turn.role {
+                        super::Role::User => "user",
+                        super::Role::Agent => "assistant",
+                    };
+                    serde_json::json!({ "role": role, "content": turn.content })
+                })
+                .collect();
+
+            let mut prompt = serde_json::Map::new();
+            prompt.insert("messages".into(), messages.into());
+            let settings = serde_json::to_value(options.settings)?;
+            let settings = settings.as_object().into_iter().flatten();
+            for (key, value) in settings.filter(|(_, v)| !v.is_null()) {
+                prompt.insert(key.clone(), value.clone());
+            }
+            if let Some(system) = options.system {
+                prompt.insert("system".into(), system.into());
+            }
+
+            let prompt: ::misanthropic::Prompt<'static> =
+                serde_json::from_value(prompt.into())?;
+            Ok(prompt.into())
+        }
+    }
+
+    impl Info for Misanthropic {
+        fn name(&self) -> std::borrow::Cow<'_, str> {
+            Info::name(&self.prompt)
+        }
+
+        fn description(&self) -> std::borrow::Cow<'_, str> {
+            Info::description(&self.prompt)
+        }
+    }
+
     #[async_trait::async_trait]
-    impl Element for ::misanthropic::Prompt<'static> {
+    impl Element for Misanthropic {
         fn sources<'a>(
             &'a self,
         ) -> Box<dyn Iterator<Item = source::Borrowed<'a>> + 'a> {
             // This prompt source only has one source: itself.
-            Box::new(std::iter::once(source::Borrowed::Prompt(self)))
+            Box::new(std::iter::once(source::Any::Prompt(self)))
         }
 
         fn sources_mut<'a>(
             &'a mut self,
-        ) -> Box<dyn Iterator<Item = source::Mut<'a>> + 'a> {
-            Box::new(std::iter::once(source::Mut::PromptSource(self)))
+        ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+            Box::new(std::iter::once(source::AnyMut::Prompt(self)))
         }
 
-        fn sinks<'a>(
-            &'a self,
-        ) -> Box<dyn Iterator<Item = sink::Borrowed<'a>> + 'a> {
-            Box::new(std::iter::once(sink::Borrowed::MessageSink(self)))
+        fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+            Box::new(std::iter::once(sink::Any::Message(self)))
         }
 
         fn sinks_mut<'a>(
             &'a mut self,
-        ) -> Box<dyn Iterator<Item = sink::Mut<'a>> + 'a> {
-            Box::new(std::iter::once(sink::Mut::MessageSink(self)))
+        ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+            Box::new(std::iter::once(sink::AnyMut::Message(self)))
         }
 
         fn backend(&self) -> Backend {
             Backend::Misanthropic
         }
+
+        fn properties(&self) -> &'static [Property] {
+            const PROPERTIES: &[Property] = &[Property::new(
+                "system",
+                Type::String,
+                "System prompt, as plain text.",
+            )
+            .optional()];
+            PROPERTIES
+        }
+
+        fn property(&self, name: &str) -> Option<Value> {
+            if name != "system" {
+                return None;
+            }
+
+            // Go through the serialized form, which is stable, rather than
+            // the content blocks.
+            let prompt = serde_json::to_value(&self.prompt).ok()?;
+            Some(match prompt.get("system") {
+                Some(serde_json::Value::String(system)) => {
+                    system.clone().into()
+                }
+                Some(serde_json::Value::Array(blocks)) => blocks
+                    .iter()
+                    .filter_map(|block| block.get("text")?.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+                    .into(),
+                _ => Value::Null,
+            })
+        }
+
+        fn set_property(
+            &mut self,
+            name: &str,
+            value: Value,
+        ) -> Result<(), PropertyError> {
+            if name != "system" {
+                return Err(PropertyError::Unknown {
+                    name: name.to_owned(),
+                });
+            }
+
+            let invalid = |e: serde_json::Error| {
+                PropertyError::invalid(name, e.to_string())
+            };
+            let mut prompt =
+                serde_json::to_value(&self.prompt).map_err(invalid)?;
+            if let Some(prompt) = prompt.as_object_mut() {
+                match value.as_str() {
+                    Some(system) => {
+                        prompt.insert("system".into(), system.into())
+                    }
+                    None => prompt.remove("system"),
+                };
+            }
+            self.prompt = serde_json::from_value(prompt).map_err(invalid)?;
+
+            Ok(())
+        }
+
+        fn checkpoint(
+            &self,
+        ) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>>
+        {
+            // The whole prompt, including the history and any tool calls
+            // still waiting for a result.
+            Ok(Some(serde_json::to_value(&self.prompt)?))
+        }
+
+        fn restore(
+            &mut self,
+            state: serde_json::Value,
+        ) -> Result<(), Box<dyn std::error::Error>> {
+            self.prompt = serde_json::from_value(state)?;
+
+            Ok(())
+        }
     }
 
     // It's possible to pull a prompt from the prompt source.
     #[async_trait::async_trait]
-    impl Pull<Box<dyn buffer::Prompt>> for ::misanthropic::Prompt<'static> {
+    impl Pull<Box<dyn buffer::Prompt>> for Misanthropic {
         /// Pulls a [`Box<dyn Prompt>`] copy of the [`Prompt`].
         ///
         /// # Errors
@@ -94,13 +289,21 @@ mod misanthropic {
         async fn pull(
             &mut self,
         ) -> Result<Box<dyn buffer::Prompt>, Box<dyn Error>> {
-            Ok(Box::new(self.clone()))
+            let prompt = self.prompt.clone();
+            let prompt: Box<dyn buffer::Prompt> = match &self.meta {
+                Some(meta) => {
+                    Box::new(Tagged::with_meta(prompt, meta.derived()))
+                }
+                None => Box::new(prompt),
+            };
+
+            Ok(prompt)
         }
     }
 
     // It's possible to push a message to the prompt.
     #[async_trait::async_trait]
-    impl Push<Box<dyn Message>> for ::misanthropic::Prompt<'static> {
+    impl Push<Box<dyn Message>> for Misanthropic {
         /// Append a [`Box<dyn Message>`] to the [`Prompt`].
         ///
         /// [`Prompt`]: crate::buffer::Prompt
@@ -112,31 +315,34 @@ mod misanthropic {
             &mut self,
             message: Box<dyn Message>,
         ) -> Result<(), Box<dyn Error>> {
-            Ok(self.push_message(message.into_concrete())?)
+            self.follow(buffer::Buffer::meta(&message));
+            Ok(self.prompt.push_message(message.into_concrete())?)
         }
     }
 
     // It's possible to replace the prompt with a new prompt.
     #[async_trait::async_trait]
-    impl Push<Box<dyn buffer::Prompt>> for ::misanthropic::Prompt<'static> {
+    impl Push<Box<dyn buffer::Prompt>> for Misanthropic {
         /// Replace the [`Prompt`] with a new [`Box<dyn Prompt>`].
         ///
         /// [`Prompt`]: crate::buffer::Prompt
         ///
         /// # Errors
-        /// - If the new [`Prompt`] cannot be set (for example, if the turn
-        ///   order is incorrect).
+        /// - Cannot fail.
         async fn push(
             &mut self,
             prompt: Box<dyn buffer::Prompt>,
         ) -> Result<(), Box<dyn Error>> {
-            Ok(self.replace(prompt.into_concrete())?)
+            self.meta = buffer::Buffer::meta(&prompt).map(Meta::derived);
+            self.prompt = prompt.into_concrete().into();
+
+            Ok(())
         }
     }
 
-    impl Prompt for ::misanthropic::Prompt<'static> {}
+    impl Prompt for Misanthropic {}
 
-    static_assertions::assert_impl_all!(::misanthropic::Prompt<'static>: PromptSource<Pulls>);
+    static_assertions::assert_impl_all!(Misanthropic: PromptSource);
 
     #[cfg(test)]
     mod tests {
@@ -148,7 +354,7 @@ mod misanthropic {
                 (::misanthropic::prompt::message::Role::User, "Test Message")
                     .into();
 
-            let mut source = Box::new(::misanthropic::Prompt::default());
+            let mut source = Box::new(Misanthropic::default());
 
             source.push(Box::new(message)).await.unwrap();
             let prompt = source.pull().await.unwrap();
@@ -159,5 +365,74 @@ mod misanthropic {
             ));
             assert_eq!(format!("{}", message.content()), "Test Message");
         }
+
+        #[tokio::test]
+        async fn test_prompt_meta() {
+            use crate::buffer::{
+                meta::{Sequence, TraceId},
+                Buffer,
+            };
+
+            let message: ::misanthropic::prompt::Message =
+                (::misanthropic::prompt::message::Role::User, "Test Message")
+                    .into();
+            let mut meta = Meta::now();
+            meta.insert(TraceId(7));
+            meta.insert(Sequence(0));
+
+            let mut source = Misanthropic::default();
+            assert!(source.pull().await.unwrap().meta().is_none());
+            source
+                .push(Box::new(Tagged::with_meta(message, meta)))
+                .await
+                .unwrap();
+            let prompt = source.pull().await.unwrap();
+            let meta = prompt.meta().unwrap();
+            assert_eq!(meta.get::<TraceId>(), Some(&TraceId(7)));
+            assert!(!meta.contains::<Sequence>());
+        }
+
+        #[test]
+        fn test_prompt_options() {
+            let options: Options = serde_json::from_value(serde_json::json!({
+                "system": "Be brief.",
+                "messages": [
+                    { "role": "user", "content": "Hi" },
+                    { "role": "agent", "content": "Hello" },
+                ],
+                "max_tokens": 64,
+            }))
+            .unwrap();
+
+            let prompt = Misanthropic::try_from(options).unwrap();
+            assert_eq!(prompt.property("system"), Some("Be brief.".into()));
+            let roles: Vec<_> = crate::buffer::Prompt::messages(&prompt.prompt)
+                .map(|message| message.role())
+                .collect();
+            assert!(matches!(
+                roles[..],
+                [
+                    crate::buffer::message::Role::User,
+                    crate::buffer::message::Role::Agent
+                ]
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_prompt_checkpoint() {
+            let message: ::misanthropic::prompt::Message =
+                (::misanthropic::prompt::message::Role::User, "Test Message")
+                    .into();
+
+            let mut source = Misanthropic::default();
+            source.push(Box::new(message)).await.unwrap();
+            let state = source.checkpoint().unwrap().unwrap();
+
+            let mut restored = Misanthropic::default();
+            restored.restore(state).unwrap();
+            let prompt = restored.pull().await.unwrap();
+            assert_eq!(prompt.messages().count(), 1);
+            assert!(restored.restore(serde_json::json!(42)).is_err());
+        }
     }
 }
@@ -0,0 +1,411 @@
+//! Typed, named runtime [`Property`]s of [`Element`]s, like GObject
+//! properties in GStreamer.
+//!
+//! An [`Element`] lists its [`Property`]s with [`Element::properties`] and
+//! implements [`Element::property`] and [`Element::set_property`]. Use
+//! [`get`] and [`set`] rather than calling those directly, so names, types
+//! and writability are checked in one place.
+//!
+//! A [`Pipeline`] that is not running reads and writes them with
+//! [`Pipeline::property`] and [`Pipeline::set_property`]. A running one does
+//! so through its [`Control`]. Either way every change is posted on the
+//! [`Bus`] as a [`PropertyChanged`] message.
+//!
+//! Read-only [`Property`]s report the state of an [`Element`], like the fill
+//! level of a [`Queue`]. A running [`Pipeline`] reads them with [`readings`]
+//! as every [`Element`] starts and after every push and pull, for
+//! [`Tracer::readings`] and [`Control::to_dot`]. [`Counter`]s broken down by
+//! more than the [`Element`] are read alongside, for [`Tracer::counters`].
+//!
+//! [`Pipeline`]: crate::pipeline::Pipeline
+//! [`Pipeline::property`]: crate::pipeline::Pipeline::property
+//! [`Pipeline::set_property`]: crate::pipeline::Pipeline::set_property
+//! [`Control`]: crate::pipeline::Control
+//! [`Bus`]: crate::pipeline::Bus
+//! [`PropertyChanged`]: crate::pipeline::bus::Message::PropertyChanged
+//! [`Queue`]: crate::element::queue::Queue
+//! [`Tracer::readings`]: crate::pipeline::Tracer::readings
+//! [`Tracer::counters`]: crate::pipeline::Tracer::counters
+//! [`Control::to_dot`]: crate::pipeline::Control::to_dot
+
+use serde::{Deserialize, Serialize};
+
+use super::Element;
+
+/// The `Type` of a [`Property`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Type {
+    Bool,
+    Int,
+    Float,
+    String,
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Type::Bool => "bool",
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::String => "string",
+        })
+    }
+}
+
+/// The `Value` of a [`Property`]. Serialized as the plain JSON value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    /// Not set. Only for [`Property::optional`] properties.
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Value {
+    /// The [`Type`] of the `Value`, or [`None`] for [`Value::Null`].
+    pub fn kind(&self) -> Option<Type> {
+        match self {
+            Value::Null => None,
+            Value::Bool(_) => Some(Type::Bool),
+            Value::Int(_) => Some(Type::Int),
+            Value::Float(_) => Some(Type::Float),
+            Value::String(_) => Some(Type::String),
+        }
+    }
+
+    /// The `bool`, if it is one.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The integer, if it is one.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The number, if it is one. Integers are converted.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(value) => Some(*value),
+            Value::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    /// The string, if it is one.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Int(value) => write!(f, "{value}"),
+            Value::Float(value) => write!(f, "{value}"),
+            Value::String(value) => write!(f, "{value:?}"),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_owned())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// Description of a `Property` of an [`Element`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Property {
+    /// Name of the `Property`, unique within the [`Element`].
+    pub name: &'static str,
+    /// What the `Property` does.
+    pub description: &'static str,
+    /// [`Type`] of its [`Value`]s.
+    pub kind: Type,
+    /// Whether it may be [`Value::Null`].
+    pub optional: bool,
+    /// Whether it can be [`set`].
+    pub writable: bool,
+}
+
+impl Property {
+    /// A writable, non-optional `Property`.
+    pub const fn new(
+        name: &'static str,
+        kind: Type,
+        description: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            kind,
+            optional: false,
+            writable: true,
+        }
+    }
+
+    /// Allow [`Value::Null`].
+    pub const fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    /// Forbid [`set`]ting it.
+    pub const fn read_only(mut self) -> Self {
+        self.writable = false;
+        self
+    }
+
+    /// Check `value` against the `Property`, converting integers for
+    /// [`Type::Float`] properties.
+    ///
+    /// # Errors
+    /// - [`PropertyError::Type`] if `value` has the wrong [`Type`].
+    pub fn check(&self, value: Value) -> Result<Value, PropertyError> {
+        match (self.kind, value) {
+            (_, Value::Null) if self.optional => Ok(Value::Null),
+            (Type::Float, Value::Int(value)) => Ok(Value::Float(value as f64)),
+            (kind, value) if value.kind() == Some(kind) => Ok(value),
+            (_, value) => Err(PropertyError::Type {
+                name: self.name.to_owned(),
+                expected: self.kind,
+                found: value,
+            }),
+        }
+    }
+}
+
+/// Error when reading or writing a [`Property`].
+#[derive(Debug, thiserror::Error)]
+pub enum PropertyError {
+    /// There is no [`Property`] with this name.
+    #[error("No property named `{name}`.")]
+    Unknown {
+        /// The name asked for.
+        name: String,
+    },
+    /// The [`Property`] can't be [`set`].
+    #[error("Property `{name}` is read only.")]
+    ReadOnly {
+        /// Name of the [`Property`].
+        name: String,
+    },
+    /// The [`Value`] has the wrong [`Type`].
+    #[error("Property `{name}` takes a {expected}, not `{found}`.")]
+    Type {
+        /// Name of the [`Property`].
+        name: String,
+        /// [`Type`] of the [`Property`].
+        expected: Type,
+        /// The [`Value`] given.
+        found: Value,
+    },
+    /// The [`Element`] rejected the [`Value`], for example because it is out
+    /// of range.
+    #[error("Invalid value for property `{name}`: {message}")]
+    Invalid {
+        /// Name of the [`Property`].
+        name: String,
+        /// Why it was rejected.
+        message: String,
+    },
+}
+
+impl PropertyError {
+    /// A [`PropertyError::Invalid`].
+    pub fn invalid(name: &str, message: impl Into<String>) -> Self {
+        Self::Invalid {
+            name: name.to_owned(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Find the [`Property`] of `element` called `name`.
+///
+/// # Errors
+/// - [`PropertyError::Unknown`] if there is none.
+pub fn find(
+    element: &dyn Element,
+    name: &str,
+) -> Result<Property, PropertyError> {
+    element
+        .properties()
+        .iter()
+        .find(|property| property.name == name)
+        .copied()
+        .ok_or_else(|| PropertyError::Unknown {
+            name: name.to_owned(),
+        })
+}
+
+/// Read the [`Property`] of `element` called `name`.
+///
+/// # Errors
+/// - [`PropertyError::Unknown`] if there is none.
+pub fn get(element: &dyn Element, name: &str) -> Result<Value, PropertyError> {
+    find(element, name)?;
+    element
+        .property(name)
+        .ok_or_else(|| PropertyError::Unknown {
+            name: name.to_owned(),
+        })
+}
+
+/// Read every read-only [`Property`] of `element`, in the order they are
+/// listed.
+pub fn readings(element: &dyn Element) -> Vec<(&'static str, Value)> {
+    element
+        .properties()
+        .iter()
+        .filter(|property| !property.writable)
+        .filter_map(|property| {
+            Some((property.name, element.property(property.name)?))
+        })
+        .collect()
+}
+
+/// A monotonic `Counter` of an [`Element`], labeled by more than the [`Node`]
+/// it is in, like the tokens used per model. See [`Element::counters`].
+///
+/// [`Node`]: crate::pipeline::Node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counter {
+    /// Name, like `inference_input_tokens`. Every `Counter` with the same
+    /// name has the same label names.
+    pub name: &'static str,
+    /// What it counts.
+    pub description: &'static str,
+    /// Label names and values, like `("model", "claude-3-5-sonnet")`.
+    pub labels: Vec<(&'static str, String)>,
+    /// Count so far.
+    pub value: u64,
+}
+
+/// Write the [`Property`] of `element` called `name`. Returns the [`Value`]
+/// it has afterwards, which the [`Element`] may have adjusted.
+///
+/// # Errors
+/// - [`PropertyError::Unknown`] if there is none.
+/// - [`PropertyError::ReadOnly`] if it can't be written.
+/// - [`PropertyError::Type`] if `value` has the wrong [`Type`].
+/// - [`PropertyError::Invalid`] if the [`Element`] rejects `value`.
+pub fn set(
+    element: &mut dyn Element,
+    name: &str,
+    value: Value,
+) -> Result<Value, PropertyError> {
+    let property = find(element, name)?;
+    if !property.writable {
+        return Err(PropertyError::ReadOnly {
+            name: name.to_owned(),
+        });
+    }
+
+    element.set_property(name, property.check(value)?)?;
+    get(element, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{element::queue, pad::Caps};
+
+    #[test]
+    fn test_check() {
+        let property = Property::new("t", Type::Float, "").optional();
+        assert_eq!(property.check(1.into()).unwrap(), Value::Float(1.0));
+        assert_eq!(property.check(Value::Null).unwrap(), Value::Null);
+        assert!(matches!(
+            property.check("hot".into()),
+            Err(PropertyError::Type {
+                expected: Type::Float,
+                ..
+            })
+        ));
+
+        let property = Property::new("n", Type::Int, "");
+        assert!(property.check(Value::Null).is_err());
+        assert!(property.check(1.5.into()).is_err());
+    }
+
+    #[test]
+    fn test_get_set() {
+        let mut queue = queue::Queue::new(queue::Options {
+            caps: Caps::ToolSchema,
+            ..Default::default()
+        });
+
+        assert_eq!(get(&queue, "max_buffers").unwrap(), Value::Int(64));
+        assert_eq!(
+            set(&mut queue, "max_buffers", 2.into()).unwrap(),
+            Value::Int(2)
+        );
+        assert_eq!(
+            set(&mut queue, "leak", "DropOldest".into()).unwrap(),
+            Value::String("DropOldest".into())
+        );
+        assert!(matches!(
+            set(&mut queue, "max_buffers", 0.into()),
+            Err(PropertyError::Invalid { .. })
+        ));
+        assert!(matches!(
+            set(&mut queue, "leak", "Sideways".into()),
+            Err(PropertyError::Invalid { .. })
+        ));
+        assert!(matches!(
+            set(&mut queue, "buffers", 1.into()),
+            Err(PropertyError::ReadOnly { .. })
+        ));
+        assert!(matches!(
+            get(&queue, "volume"),
+            Err(PropertyError::Unknown { .. })
+        ));
+        assert_eq!(get(&queue, "max_buffers").unwrap(), Value::Int(2));
+    }
+}
@@ -0,0 +1,590 @@
+use std::{collections::VecDeque, num::NonZeroUsize};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backends::Backend,
+    buffer::{any::Typed, sink, source, Error, ErrorStaticString},
+    element::{
+        impl_pull_typed, impl_push_typed,
+        property::{Property, PropertyError, Type, Value},
+        Element,
+    },
+    info::Info,
+    pad::{Caps, Event},
+};
+
+/// What a full [`Queue`] does with another [`Buffer`].
+///
+/// [`Buffer`]: crate::buffer::Buffer
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+pub enum Leak {
+    /// Wait for room, holding back upstream.
+    #[default]
+    Block,
+    /// Drop the oldest queued [`Buffer`] to make room.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    DropOldest,
+    /// Drop the new [`Buffer`].
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    DropNewest,
+}
+
+/// Options for a [`Queue`].
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Options {
+    /// [`Caps`] of the sink and source pads.
+    pub caps: Caps,
+    /// Maximum number of queued [`Buffer`]s, if any.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub max_buffers: Option<NonZeroUsize>,
+    /// Maximum total [`Buffer::size`] of queued [`Buffer`]s, if any. A
+    /// single [`Buffer`] larger than this is only queued by a blocking
+    /// `Queue`, and only when it is empty.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`Buffer::size`]: crate::buffer::Buffer::size
+    pub max_bytes: Option<NonZeroUsize>,
+    /// What to do when the `Queue` is full.
+    pub leak: Leak,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            caps: Caps::Message,
+            max_buffers: NonZeroUsize::new(64),
+            max_bytes: None,
+            leak: Leak::Block,
+        }
+    }
+}
+
+/// Snapshot of the fill level of a [`Queue`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    /// Number of queued [`Buffer`]s.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub buffers: usize,
+    /// Total size of queued [`Buffer`]s in bytes.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub bytes: usize,
+    /// Most [`Buffer`]s ever queued at once.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub peak_buffers: usize,
+    /// Number of [`Buffer`]s pushed, including dropped ones.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub pushed: u64,
+    /// Number of [`Buffer`]s pulled.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub pulled: u64,
+    /// Number of [`Buffer`]s dropped because the `Queue` was full or
+    /// flushed.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub dropped: u64,
+}
+
+/// A `Queue` [`Element`]. Holds [`Buffer`]s pushed to its sink pad until its
+/// source pad is pulled, absorbing bursts such as a fast token stream going
+/// to a slow websocket. It is [`decoupled`], so upstream and downstream run
+/// at their own pace. Backend independent.
+///
+/// [`Buffer`]: crate::buffer::Buffer
+/// [`decoupled`]: Element::decoupled
+pub struct Queue {
+    options: Options,
+    queue: VecDeque<(Typed, usize)>,
+    bytes: usize,
+    stats: Stats,
+}
+
+impl Queue {
+    /// Create a new, empty, `Queue` from [`Options`].
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            queue: VecDeque::new(),
+            bytes: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Current [`Stats`]. While the `Queue` runs in a [`Pipeline`], they are
+    /// its read-only [`Property`]s.
+    ///
+    /// [`Pipeline`]: crate::pipeline::Pipeline
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Whether another [`Buffer`] would exceed a limit.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub fn is_full(&self) -> bool {
+        self.options
+            .max_buffers
+            .is_some_and(|max| self.queue.len() >= max.get())
+            || self
+                .options
+                .max_bytes
+                .is_some_and(|max| self.bytes >= max.get())
+    }
+
+    /// Whether a [`Buffer`] of `size` bytes can be queued without exceeding
+    /// a limit.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    fn fits(&self, size: usize) -> bool {
+        self.options
+            .max_buffers
+            .is_none_or(|max| self.queue.len() < max.get())
+            && self
+                .options
+                .max_bytes
+                .is_none_or(|max| self.bytes + size <= max.get())
+    }
+
+    /// Drop every queued [`Buffer`].
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub fn clear(&mut self) {
+        self.stats.dropped += self.queue.len() as u64;
+        self.queue.clear();
+        self.bytes = 0;
+        self.update();
+    }
+
+    /// Update the fill level in the [`Stats`].
+    fn update(&mut self) {
+        self.stats.buffers = self.queue.len();
+        self.stats.bytes = self.bytes;
+        self.stats.peak_buffers = self.stats.peak_buffers.max(self.queue.len());
+    }
+
+    /// Queue a buffer, applying the [`Leak`] policy if full.
+    ///
+    /// # Errors
+    /// - If the buffer doesn't match the [`Caps`] of the `Queue`.
+    /// - If the `Queue` is full and the policy is [`Leak::Block`]. The
+    ///   [`Pipeline`] doesn't push to a full blocking `Queue`.
+    ///
+    /// [`Pipeline`]: crate::pipeline::Pipeline
+    async fn enqueue(&mut self, buffer: Typed) -> Result<(), Box<dyn Error>> {
+        if buffer.caps() != self.options.caps {
+            return Err(ErrorStaticString::from(
+                "Buffer does not match the caps of the queue.",
+            )
+            .into());
+        }
+
+        self.stats.pushed += 1;
+        let size = buffer.size();
+        match self.options.leak {
+            Leak::Block if self.is_full() => {
+                return Err(ErrorStaticString::from("Queue is full.").into())
+            }
+            Leak::Block => {}
+            Leak::DropNewest if !self.fits(size) => {
+                self.stats.dropped += 1;
+                return Ok(());
+            }
+            Leak::DropNewest => {}
+            Leak::DropOldest => {
+                // Nothing would make room for it, so keep what is queued.
+                if self.options.max_bytes.is_some_and(|max| size > max.get()) {
+                    self.stats.dropped += 1;
+                    return Ok(());
+                }
+                while !self.fits(size) {
+                    let Some((_, size)) = self.queue.pop_front() else {
+                        break;
+                    };
+                    self.bytes -= size;
+                    self.stats.dropped += 1;
+                }
+            }
+        }
+
+        self.bytes += size;
+        self.queue.push_back((buffer, size));
+        self.update();
+
+        Ok(())
+    }
+
+    /// Take the oldest queued buffer.
+    async fn dequeue(&mut self) -> Result<Typed, Box<dyn Error>> {
+        let (buffer, size) = self
+            .queue
+            .pop_front()
+            .ok_or(ErrorStaticString::from("Queue is empty."))?;
+        self.bytes -= size;
+        self.stats.pulled += 1;
+        self.update();
+
+        Ok(buffer)
+    }
+}
+
+impl crate::new::New for Queue {
+    type Options = Options;
+
+    fn new(options: Options, _backend: Backend) -> Self {
+        Self::new(options)
+    }
+}
+
+impl crate::new::Options for Options {
+    type New = Queue;
+}
+
+impl_push_typed!(Queue, enqueue);
+impl_pull_typed!(Queue, dequeue);
+
+impl Info for Queue {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "Queue".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Holds buffers until downstream is ready for them.".into()
+    }
+}
+
+#[async_trait::async_trait]
+impl Element for Queue {
+    fn backend(&self) -> Backend {
+        Backend::Independent
+    }
+
+    async fn event(
+        &mut self,
+        _pad: usize,
+        event: Event,
+    ) -> Result<Option<Event>, Box<dyn Error>> {
+        if event == Event::FlushStart {
+            self.clear();
+        }
+
+        Ok(Some(event))
+    }
+
+    fn can_pull(&self, _pad: usize) -> bool {
+        !self.queue.is_empty()
+    }
+
+    fn can_push(&self) -> bool {
+        self.options.leak != Leak::Block || !self.is_full()
+    }
+
+    fn decoupled(&self) -> bool {
+        true
+    }
+
+    fn required(&self, _pad: usize) -> bool {
+        true
+    }
+
+    fn properties(&self) -> &'static [Property] {
+        const PROPERTIES: &[Property] = &[
+            Property::new(
+                "max_buffers",
+                Type::Int,
+                "Maximum number of queued buffers. Null for no limit.",
+            )
+            .optional(),
+            Property::new(
+                "max_bytes",
+                Type::Int,
+                "Maximum total size of queued buffers. Null for no limit.",
+            )
+            .optional(),
+            Property::new("leak", Type::String, "What to do when full."),
+            Property::new("buffers", Type::Int, "Number of queued buffers.")
+                .read_only(),
+            Property::new("bytes", Type::Int, "Total size of queued buffers.")
+                .read_only(),
+            Property::new(
+                "peak_buffers",
+                Type::Int,
+                "Most buffers ever queued at once.",
+            )
+            .read_only(),
+            Property::new("pushed", Type::Int, "Number of buffers pushed.")
+                .read_only(),
+            Property::new("pulled", Type::Int, "Number of buffers pulled.")
+                .read_only(),
+            Property::new(
+                "dropped",
+                Type::Int,
+                "Number of buffers dropped because the queue was full or \
+                flushed.",
+            )
+            .read_only(),
+        ];
+
+        PROPERTIES
+    }
+
+    fn property(&self, name: &str) -> Option<Value> {
+        let limit =
+            |max: Option<NonZeroUsize>| max.map(|max| max.get() as i64).into();
+        match name {
+            "max_buffers" => Some(limit(self.options.max_buffers)),
+            "max_bytes" => Some(limit(self.options.max_bytes)),
+            "leak" => serde_json::from_value(
+                serde_json::to_value(self.options.leak).ok()?,
+            )
+            .ok(),
+            "buffers" => Some(Value::Int(self.stats.buffers as i64)),
+            "bytes" => Some(Value::Int(self.stats.bytes as i64)),
+            "peak_buffers" => Some(Value::Int(self.stats.peak_buffers as i64)),
+            "pushed" => Some(Value::Int(self.stats.pushed as i64)),
+            "pulled" => Some(Value::Int(self.stats.pulled as i64)),
+            "dropped" => Some(Value::Int(self.stats.dropped as i64)),
+            _ => None,
+        }
+    }
+
+    fn set_property(
+        &mut self,
+        name: &str,
+        value: Value,
+    ) -> Result<(), PropertyError> {
+        // Queued buffers over a new limit stay until pulled.
+        let limit = |value: Value| match value.as_int() {
+            None => Ok(None),
+            Some(max) if max > 0 => Ok(NonZeroUsize::new(max as usize)),
+            Some(_) => Err(PropertyError::invalid(name, "must be positive")),
+        };
+        match name {
+            "max_buffers" => self.options.max_buffers = limit(value)?,
+            "max_bytes" => self.options.max_bytes = limit(value)?,
+            "leak" => {
+                self.options.leak = serde_json::to_value(&value)
+                    .and_then(serde_json::from_value)
+                    .map_err(|e| PropertyError::invalid(name, e.to_string()))?
+            }
+            _ => {
+                return Err(PropertyError::Unknown {
+                    name: name.to_owned(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn sources<'a>(&'a self) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+        Box::new(std::iter::once(match self.options.caps {
+            Caps::Prompt => source::Any::Prompt(self),
+            Caps::Message => source::Any::Message(self),
+            Caps::AgentMessage => source::Any::AgentMessage(self),
+            Caps::UserMessage => source::Any::UserMessage(self),
+            Caps::ToolSchema => source::Any::ToolSchema(self),
+            Caps::ToolUse => source::Any::ToolUse(self),
+            Caps::ToolResult => source::Any::ToolResult(self),
+            Caps::Error => source::Any::Error(self),
+        }))
+    }
+
+    fn sources_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::once(match self.options.caps {
+            Caps::Prompt => source::AnyMut::Prompt(self),
+            Caps::Message => source::AnyMut::Message(self),
+            Caps::AgentMessage => source::AnyMut::AgentMessage(self),
+            Caps::UserMessage => source::AnyMut::UserMessage(self),
+            Caps::ToolSchema => source::AnyMut::ToolSchema(self),
+            Caps::ToolUse => source::AnyMut::ToolUse(self),
+            Caps::ToolResult => source::AnyMut::ToolResult(self),
+            Caps::Error => source::AnyMut::Error(self),
+        }))
+    }
+
+    fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+        Box::new(std::iter::once(match self.options.caps {
+            Caps::Prompt => sink::Any::Prompt(self),
+            Caps::Message => sink::Any::Message(self),
+            Caps::AgentMessage => sink::Any::AgentMessage(self),
+            Caps::UserMessage => sink::Any::UserMessage(self),
+            Caps::ToolSchema => sink::Any::ToolSchema(self),
+            Caps::ToolUse => sink::Any::ToolUse(self),
+            Caps::ToolResult => sink::Any::ToolResult(self),
+            Caps::Error => sink::Any::Error(self),
+        }))
+    }
+
+    fn sinks_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::once(match self.options.caps {
+            Caps::Prompt => sink::AnyMut::Prompt(self),
+            Caps::Message => sink::AnyMut::Message(self),
+            Caps::AgentMessage => sink::AnyMut::AgentMessage(self),
+            Caps::UserMessage => sink::AnyMut::UserMessage(self),
+            Caps::ToolSchema => sink::AnyMut::ToolSchema(self),
+            Caps::ToolUse => sink::AnyMut::ToolUse(self),
+            Caps::ToolResult => sink::AnyMut::ToolResult(self),
+            Caps::Error => sink::AnyMut::Error(self),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::test;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queue_leak() {
+        let mut queue = Queue::new(Options {
+            max_buffers: NonZeroUsize::new(2),
+            leak: Leak::DropOldest,
+            ..Default::default()
+        });
+        assert!(!queue.can_pull(0));
+
+        for text in ["one", "two", "three"] {
+            assert!(queue.can_push());
+            let message = test::TextMessage::new(
+                crate::buffer::message::Role::User,
+                text,
+            );
+            let mut sink = queue.sinks_mut().next().unwrap();
+            sink.push(Box::new(message)).await.unwrap();
+        }
+
+        let stats = queue.stats();
+        assert_eq!(stats.buffers, 2);
+        assert_eq!(stats.pushed, 3);
+        assert_eq!(stats.dropped, 1);
+        assert!(stats.bytes > 0);
+        assert_eq!(queue.property("dropped"), Some(Value::Int(1)));
+
+        let mut source = queue.sources_mut().next().unwrap();
+        let first = source.pull().await.unwrap();
+        assert_eq!(test::text(&*first), "two");
+        assert_eq!(queue.stats().pulled, 1);
+    }
+
+    /// Push a [`TextMessage`](test::TextMessage) for each of `texts`.
+    async fn push(queue: &mut Queue, texts: &[&str]) {
+        for text in texts {
+            let message = test::TextMessage::new(
+                crate::buffer::message::Role::User,
+                text,
+            );
+            let mut sink = queue.sinks_mut().next().unwrap();
+            sink.push(Box::new(message)).await.unwrap();
+        }
+    }
+
+    /// Pull everything queued, as text.
+    async fn pull(queue: &mut Queue) -> Vec<String> {
+        let mut pulled = Vec::new();
+        while queue.can_pull(0) {
+            let mut source = queue.sources_mut().next().unwrap();
+            pulled.push(test::text(&*source.pull().await.unwrap()));
+        }
+        pulled
+    }
+
+    #[tokio::test]
+    async fn test_queue_drop_newest() {
+        let mut queue = Queue::new(Options {
+            max_buffers: NonZeroUsize::new(2),
+            leak: Leak::DropNewest,
+            ..Default::default()
+        });
+        push(&mut queue, &["one", "two", "three"]).await;
+        assert!(queue.can_push());
+        assert_eq!(queue.stats().dropped, 1);
+        assert_eq!(pull(&mut queue).await, ["one", "two"]);
+
+        // There is room again.
+        push(&mut queue, &["four"]).await;
+        assert_eq!(pull(&mut queue).await, ["four"]);
+        assert_eq!(queue.stats().pulled, 3);
+    }
+
+    #[tokio::test]
+    async fn test_queue_block() {
+        let mut queue = Queue::new(Options {
+            max_buffers: NonZeroUsize::new(2),
+            ..Default::default()
+        });
+        push(&mut queue, &["one", "two"]).await;
+        assert!(queue.is_full());
+        assert!(!queue.can_push());
+        let mut sink = queue.sinks_mut().next().unwrap();
+        assert!(sink.push(test::buffer(Caps::Message)).await.is_err());
+
+        // Pulling makes room. Nothing was dropped.
+        let mut source = queue.sources_mut().next().unwrap();
+        assert_eq!(test::text(&*source.pull().await.unwrap()), "one");
+        assert!(queue.can_push());
+        push(&mut queue, &["three"]).await;
+        assert_eq!(pull(&mut queue).await, ["two", "three"]);
+        assert_eq!(queue.stats().dropped, 0);
+    }
+
+    #[tokio::test]
+    async fn test_queue_max_bytes() {
+        // Larger than the limit on its own, so it can't make room.
+        let mut queue = Queue::new(Options {
+            max_buffers: None,
+            max_bytes: NonZeroUsize::new(8),
+            leak: Leak::DropOldest,
+            ..Default::default()
+        });
+        push(&mut queue, &["one", "two", "a buffer over the limit"]).await;
+        assert_eq!(queue.stats().dropped, 1);
+        assert!(queue.stats().bytes <= 8);
+        assert_eq!(pull(&mut queue).await, ["one", "two"]);
+
+        // A blocking queue takes it when empty, or it would never pass.
+        let mut queue = Queue::new(Options {
+            max_bytes: NonZeroUsize::new(8),
+            ..Default::default()
+        });
+        push(&mut queue, &["a buffer over the limit"]).await;
+        assert!(!queue.can_push());
+        assert_eq!(pull(&mut queue).await, ["a buffer over the limit"]);
+    }
+
+    #[test]
+    fn test_queue_options() {
+        for limit in ["max_buffers", "max_bytes"] {
+            let options = serde_json::json!({ limit: 0 });
+            assert!(serde_json::from_value::<Options>(options).is_err());
+            let schema = schemars::schema_for!(Options);
+            let minimum =
+                schema.pointer(&format!("/properties/{limit}/minimum"));
+            assert_eq!(minimum, Some(&serde_json::json!(1)), "{schema:?}");
+        }
+    }
+}
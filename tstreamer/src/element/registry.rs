@@ -0,0 +1,599 @@
+//! Process wide `Registry` of [`Element`] factories, so configuration can
+//! construct [`Element`]s defined outside this crate.
+//!
+//! A factory is registered under a [`Kind`] along with the [`Backend`]s it
+//! supports and a JSON Schema for its options. [`node::Config`] and
+//! [`Kind::new`] look the [`Kind`] up here, and options are checked against
+//! the schema before the factory sees them. The built-in [`Element`]s are
+//! registered before anything else can be, so their names are taken.
+//!
+//! An [`Element`] implementing [`New`] needs no factory. Its schema is
+//! derived from its [`new::Options`]:
+//!
+//! ```
+//! use tstreamer::{
+//!     backends::Backend,
+//!     element::{any::Kind, registry, tee::Tee},
+//! };
+//!
+//! let fork = Kind::from("Fork");
+//! registry::register_new::<Tee>(fork.clone(), &[Backend::Independent])
+//!     .unwrap();
+//!
+//! assert!(fork.available(Backend::Independent));
+//! assert!(fork.validate(&serde_json::json!({ "pads": -1 })).is_err());
+//! ```
+//!
+//! [`New`]: crate::new::New
+//! [`new::Options`]: crate::new::Options
+//!
+//! [`node::Config`]: crate::pipeline::node::Config
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use schemars::Schema;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    backends::Backend,
+    new::{New, Options as _},
+};
+
+use super::{
+    any::{
+        ConfigError, Kind, NewError, SchemaError, SchemaProblem,
+        UnavailableError,
+    },
+    Element,
+};
+
+/// A `Factory` constructing an [`Element`] from a [`Backend`] and options.
+/// Options are never null. See [`Kind::new`].
+pub type Factory = Arc<
+    dyn Fn(Backend, serde_json::Value) -> Result<Box<dyn Element>, NewError>
+        + Send
+        + Sync,
+>;
+
+/// Error when registering a [`Factory`].
+#[derive(Debug, thiserror::Error)]
+pub enum RegisterError {
+    /// A [`Factory`] is already registered for the [`Kind`].
+    #[error("Element `{kind}` is already registered.")]
+    Duplicate {
+        /// The [`Kind`] already taken.
+        kind: Kind,
+    },
+    /// The schema for the options is not a valid JSON Schema.
+    #[error("Element `{kind}` has an invalid schema: {message}")]
+    Schema {
+        /// The [`Kind`] being registered.
+        kind: Kind,
+        /// Why the schema is invalid.
+        message: String,
+    },
+}
+
+/// A registered [`Factory`], the [`Backend`]s it supports and the schema for
+/// its options.
+struct Entry {
+    backends: Vec<Backend>,
+    schema: Schema,
+    validator: jsonschema::Validator,
+    factory: Factory,
+}
+
+impl Entry {
+    fn new(
+        kind: &Kind,
+        backends: &[Backend],
+        schema: Schema,
+        factory: Factory,
+    ) -> Result<Self, RegisterError> {
+        let validator =
+            jsonschema::validator_for(schema.as_value()).map_err(|e| {
+                RegisterError::Schema {
+                    kind: kind.clone(),
+                    message: e.to_string(),
+                }
+            })?;
+
+        Ok(Self {
+            backends: backends.to_vec(),
+            schema,
+            validator,
+            factory,
+        })
+    }
+
+    /// Every [`SchemaProblem`] with `options`.
+    fn problems(&self, options: &serde_json::Value) -> Vec<SchemaProblem> {
+        self.validator
+            .iter_errors(options)
+            .map(|e| SchemaProblem {
+                path: e.instance_path.to_string(),
+                message: e.to_string(),
+            })
+            .collect()
+    }
+}
+
+type Registry = RwLock<HashMap<Kind, Arc<Entry>>>;
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| RwLock::new(builtins()))
+}
+
+/// Get the [`Entry`] for `kind`, if any. The lock is not held afterwards, so
+/// factories may construct other [`Element`]s, as a [`Bin`] does.
+///
+/// [`Bin`]: super::bin::Bin
+fn entry(kind: &Kind) -> Option<Arc<Entry>> {
+    registry()
+        .read()
+        // Entries are inserted whole, so a poisoned lock is fine.
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(kind)
+        .cloned()
+}
+
+/// Register a `factory` for `kind`, supporting `backends`. Options are
+/// checked against `schema` before they reach the `factory`.
+///
+/// # Errors
+/// - [`RegisterError::Duplicate`] if `kind` is already registered. Built-in
+///   [`Kind`]s can't be replaced.
+/// - [`RegisterError::Schema`] if `schema` is not a valid JSON Schema.
+pub fn register<F>(
+    kind: Kind,
+    backends: &[Backend],
+    schema: Schema,
+    factory: F,
+) -> Result<(), RegisterError>
+where
+    F: Fn(Backend, serde_json::Value) -> Result<Box<dyn Element>, NewError>
+        + Send
+        + Sync
+        + 'static,
+{
+    let entry = Entry::new(&kind, backends, schema, Arc::new(factory))?;
+    let mut registry = registry()
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if registry.contains_key(&kind) {
+        return Err(RegisterError::Duplicate { kind });
+    }
+    registry.insert(kind, Arc::new(entry));
+
+    Ok(())
+}
+
+/// Register an [`Element`] implementing [`New`] for `kind`, supporting
+/// `backends`. The schema is [`new::Options::schema`].
+///
+/// # Errors
+/// - [`RegisterError`] as for [`register`].
+///
+/// [`new::Options::schema`]: crate::new::Options::schema
+pub fn register_new<E>(
+    kind: Kind,
+    backends: &[Backend],
+) -> Result<(), RegisterError>
+where
+    E: New + Element,
+{
+    register(kind, backends, E::Options::schema(), new_factory::<E>)
+}
+
+/// [`Factory`] for an [`Element`] implementing [`New`].
+fn new_factory<E: New + Element>(
+    backend: Backend,
+    options: serde_json::Value,
+) -> Result<Box<dyn Element>, NewError> {
+    Ok(Box::new(E::new(self::options(options)?, backend)))
+}
+
+/// Every registered [`Kind`], sorted by name.
+pub fn kinds() -> Vec<Kind> {
+    let mut kinds: Vec<Kind> = registry()
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .keys()
+        .cloned()
+        .collect();
+    kinds.sort();
+
+    kinds
+}
+
+/// Whether `kind` is registered at all.
+pub fn contains(kind: &Kind) -> bool {
+    entry(kind).is_some()
+}
+
+/// The [`Backend`]s `kind` supports, or `None` if it is not registered.
+pub fn backends(kind: &Kind) -> Option<Vec<Backend>> {
+    entry(kind).map(|entry| entry.backends.clone())
+}
+
+/// The JSON Schema for the options of `kind`, or `None` if it is not
+/// registered. Prefer [`Kind::schema`].
+pub fn schema(kind: &Kind) -> Option<Schema> {
+    entry(kind).map(|entry| entry.schema.clone())
+}
+
+/// Check `options` for `kind` against its schema. Prefer [`Kind::validate`].
+///
+/// # Errors
+/// - [`UnavailableError`] if `kind` is not registered, for the default
+///   [`Backend`].
+/// - [`SchemaError`] listing every [`SchemaProblem`] found.
+pub(crate) fn validate(
+    kind: &Kind,
+    options: &serde_json::Value,
+) -> Result<(), NewError> {
+    let entry = entry(kind).ok_or_else(|| UnavailableError {
+        kind: kind.clone(),
+        backend: Backend::default(),
+    })?;
+    check(kind, &entry, options)
+}
+
+fn check(
+    kind: &Kind,
+    entry: &Entry,
+    options: &serde_json::Value,
+) -> Result<(), NewError> {
+    let problems = entry.problems(options);
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(SchemaError {
+            kind: kind.clone(),
+            problems,
+        }
+        .into())
+    }
+}
+
+/// Construct an [`Element`] of `kind`. Prefer [`Kind::new`].
+///
+/// # Errors
+/// - [`UnavailableError`] if `kind` is not registered or does not support
+///   `backend`.
+/// - [`SchemaError`] if `options` do not match the schema.
+/// - Whatever the [`Factory`] returns.
+pub(crate) fn new(
+    kind: &Kind,
+    backend: Backend,
+    options: serde_json::Value,
+) -> Result<Box<dyn Element>, NewError> {
+    let unavailable = || UnavailableError {
+        kind: kind.clone(),
+        backend,
+    };
+    let entry = entry(kind).ok_or_else(unavailable)?;
+    if !entry.backends.contains(&backend) {
+        return Err(unavailable().into());
+    }
+    check(kind, &entry, &options)?;
+
+    (entry.factory)(backend, options)
+}
+
+/// Deserialize `options` for a [`Factory`], mapping errors to
+/// [`ConfigError`].
+pub fn options<T: DeserializeOwned>(
+    options: serde_json::Value,
+) -> Result<T, ConfigError> {
+    serde_json::from_value(options).map_err(|e| ConfigError {
+        message: e.to_string(),
+    })
+}
+
+/// The built-in [`Element`]s.
+fn builtins() -> HashMap<Kind, Arc<Entry>> {
+    use super::{
+        bin,
+        error_sink::{self, ErrorSink},
+        inference, prompt,
+        queue::Queue,
+        tee::Tee,
+        teleport,
+    };
+
+    fn entry<F>(
+        kind: &Kind,
+        backends: &[Backend],
+        schema: Schema,
+        factory: F,
+    ) -> (Kind, Arc<Entry>)
+    where
+        F: Fn(Backend, serde_json::Value) -> Result<Box<dyn Element>, NewError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let entry = Entry::new(kind, backends, schema, Arc::new(factory))
+            .expect("built-in schemas are valid");
+        (kind.clone(), Arc::new(entry))
+    }
+
+    fn new<E: New + Element>(kind: &Kind) -> (Kind, Arc<Entry>) {
+        entry(kind, Backend::ALL, E::Options::schema(), new_factory::<E>)
+    }
+
+    // Prompts and inference are backend specific.
+    let backends: Vec<Backend> = Backend::ALL
+        .iter()
+        .copied()
+        .filter(|backend| !backend.is_independent())
+        .collect();
+
+    HashMap::from([
+        entry(
+            &Kind::PROMPT,
+            &backends,
+            schemars::schema_for!(prompt::Options),
+            self::prompt,
+        ),
+        entry(
+            &Kind::INFERENCE,
+            &backends,
+            schemars::schema_for!(inference::Options),
+            self::inference,
+        ),
+        new::<Tee>(&Kind::TEE),
+        new::<Queue>(&Kind::QUEUE),
+        entry(
+            &Kind::ERROR_SINK,
+            Backend::ALL,
+            schemars::schema_for!(error_sink::Options),
+            |backend, options| {
+                Ok(Box::new(ErrorSink::new(self::options(options)?, backend)?))
+            },
+        ),
+        new::<teleport::TeleportSink>(&Kind::TELEPORT_SINK),
+        new::<teleport::TeleportSource>(&Kind::TELEPORT_SOURCE),
+        entry(
+            &Kind::BIN,
+            Backend::ALL,
+            schemars::schema_for!(bin::Options),
+            |_, options| {
+                let bin = bin::Bin::from_options(&self::options(options)?)
+                    .map_err(|e| ConfigError {
+                        message: e.to_string(),
+                    })?;
+                Ok(Box::new(bin))
+            },
+        ),
+    ])
+}
+
+fn prompt(
+    backend: Backend,
+    #[allow(unused_variables)] options: serde_json::Value,
+) -> Result<Box<dyn Element>, NewError> {
+    match backend {
+        Backend::Independent => Err(UnavailableError {
+            kind: Kind::PROMPT,
+            backend,
+        }
+        .into()),
+        #[cfg(feature = "misanthropic")]
+        Backend::Misanthropic => {
+            let options: super::prompt::Options = self::options(options)?;
+            let prompt =
+                super::prompt::misanthropic::Misanthropic::try_from(options)
+                    .map_err(|e| ConfigError {
+                        message: e.to_string(),
+                    })?;
+            Ok(Box::new(prompt))
+        }
+    }
+}
+
+fn inference(
+    backend: Backend,
+    #[allow(unused_variables)] options: serde_json::Value,
+) -> Result<Box<dyn Element>, NewError> {
+    match backend {
+        Backend::Independent => Err(UnavailableError {
+            kind: Kind::INFERENCE,
+            backend,
+        }
+        .into()),
+        #[cfg(feature = "misanthropic")]
+        Backend::Misanthropic => {
+            let options: super::inference::Options = self::options(options)?;
+            let key = match options.key {
+                Some(key) => key,
+                None => std::env::var("ANTHROPIC_API_KEY").map_err(|e| {
+                    ConfigError {
+                        message: format!("`ANTHROPIC_API_KEY`: {e}"),
+                    }
+                })?,
+            };
+            let client =
+                ::misanthropic::Client::new(key).map_err(|e| ConfigError {
+                    message: e.to_string(),
+                })?;
+            Ok(Box::new(super::inference::misanthropic::Misanthropic::new(
+                client,
+                options.settings,
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pad::Caps;
+
+    #[test]
+    fn test_register() {
+        let kind = Kind::from("TestSplit");
+        assert!(!contains(&kind));
+        assert!(!kind.available(Backend::Independent));
+
+        let object = || schemars::json_schema!({ "type": "object" });
+        register(
+            kind.clone(),
+            &[Backend::Independent],
+            object(),
+            |_, options| {
+                use crate::element::tee;
+                let options: tee::Options = self::options(options)?;
+                Ok(Box::new(tee::Tee::new(options)))
+            },
+        )
+        .unwrap();
+
+        assert!(kinds().contains(&kind));
+        assert_eq!(backends(&kind).unwrap().len(), 1);
+        assert!(kind.available(Backend::Independent));
+        let element = kind
+            .new(
+                Backend::Independent,
+                serde_json::json!({ "caps": "ToolSchema", "pads": 3 }),
+            )
+            .unwrap();
+        assert_eq!(element.sources().count(), 3);
+        assert_eq!(element.sinks().next().unwrap().caps(), Caps::ToolSchema);
+
+        assert!(matches!(
+            register(kind.clone(), &[], object(), |_, _| unreachable!()),
+            Err(RegisterError::Duplicate { .. })
+        ));
+        assert!(matches!(
+            register(Kind::TEE, &[], object(), |_, _| unreachable!()),
+            Err(RegisterError::Duplicate { .. })
+        ));
+
+        // The schema lets anything through, so the factory reports it.
+        assert!(matches!(
+            kind.new(Backend::Independent, serde_json::json!({ "pads": "x" })),
+            Err(NewError::Config(_))
+        ));
+        assert!(matches!(
+            Kind::from("Nope")
+                .new(Backend::Independent, serde_json::Value::Null),
+            Err(NewError::Unavailable(_))
+        ));
+    }
+
+    #[test]
+    fn test_builtins() {
+        for kind in [
+            Kind::TEE,
+            Kind::QUEUE,
+            Kind::TELEPORT_SINK,
+            Kind::TELEPORT_SOURCE,
+            Kind::BIN,
+            Kind::ERROR_SINK,
+        ] {
+            assert!(kind.available(Backend::Independent), "{kind}");
+        }
+        assert!(!Kind::PROMPT.available(Backend::Independent));
+        assert!(!Kind::INFERENCE.available(Backend::Independent));
+        assert!(Kind::QUEUE
+            .new(Backend::Independent, serde_json::Value::Null)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = Kind::QUEUE.schema().unwrap();
+        let properties = schema.as_value()["properties"].as_object().unwrap();
+        assert!(properties.contains_key("max_buffers"));
+        assert!(properties.contains_key("leak"));
+
+        Kind::QUEUE.validate(&serde_json::Value::Null).unwrap();
+        Kind::QUEUE
+            .validate(&serde_json::json!({ "max_buffers": null }))
+            .unwrap();
+
+        let options = serde_json::json!({
+            "caps": "Nope",
+            "max_buffers": -1,
+        });
+        let Err(NewError::Schema(err)) = Kind::QUEUE.validate(&options) else {
+            panic!("expected a schema error");
+        };
+        let mut paths: Vec<&str> =
+            err.problems.iter().map(|p| p.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, ["/caps", "/max_buffers"]);
+        assert!(err.to_string().starts_with("SCHEMA: Invalid options"));
+
+        // Construction checks the schema first.
+        assert!(matches!(
+            Kind::QUEUE.new(Backend::Independent, options),
+            Err(NewError::Schema(_))
+        ));
+        assert!(Kind::from("Nope").schema().is_none());
+    }
+
+    #[test]
+    fn test_backend_schemas() {
+        let schema = Kind::PROMPT.schema().unwrap();
+        let properties = schema.as_value()["properties"].as_object().unwrap();
+        for key in ["system", "messages", "model", "max_tokens"] {
+            assert!(properties.contains_key(key), "{key}");
+        }
+        Kind::PROMPT
+            .validate(&serde_json::json!({
+                "system": "Be brief.",
+                "messages": [{ "role": "user", "content": "Hi" }],
+            }))
+            .unwrap();
+        assert!(matches!(
+            Kind::PROMPT.validate(&serde_json::json!({
+                "messages": [{ "role": "robot", "content": "Hi" }],
+            })),
+            Err(NewError::Schema(_))
+        ));
+
+        let schema = Kind::INFERENCE.schema().unwrap();
+        let properties = schema.as_value()["properties"].as_object().unwrap();
+        for key in ["key", "model", "temperature"] {
+            assert!(properties.contains_key(key), "{key}");
+        }
+        assert!(matches!(
+            Kind::INFERENCE.validate(&serde_json::json!({ "max_tokens": -1 })),
+            Err(NewError::Schema(_))
+        ));
+    }
+
+    #[test]
+    fn test_register_new() {
+        use crate::element::queue::Queue;
+
+        let kind = Kind::from("TestBuffer");
+        register_new::<Queue>(kind.clone(), &[Backend::Independent]).unwrap();
+        assert_eq!(kind.schema(), Kind::QUEUE.schema());
+        assert!(kind
+            .new(
+                Backend::Independent,
+                serde_json::json!({ "caps": "Prompt" })
+            )
+            .is_ok());
+
+        assert!(matches!(
+            register(
+                Kind::from("TestBadSchema"),
+                &[],
+                schemars::json_schema!({ "type": 5 }),
+                |_, _| unreachable!(),
+            ),
+            Err(RegisterError::Schema { .. })
+        ));
+        assert!(!contains(&Kind::from("TestBadSchema")));
+    }
+}
@@ -0,0 +1,385 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backends::Backend,
+    buffer::{self, any::Typed, sink, source, Error, ErrorStaticString},
+    element::Element,
+    info::Info,
+    pad::{Caps, Pull, Push},
+};
+
+/// What a [`Tee`] does with a [`Buffer`] that can't be cloned.
+///
+/// [`Buffer`]: crate::buffer::Buffer
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+pub enum Uncloneable {
+    /// Fail. The [`Tee`] stops.
+    #[default]
+    Error,
+    /// Send it to the first source pad only.
+    First,
+    /// Drop it. No source pad gets it.
+    Drop,
+}
+
+/// Options for a [`Tee`].
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Options {
+    /// [`Caps`] of the sink pad and every source pad.
+    pub caps: Caps,
+    /// Number of source pads to start with. More can be added with
+    /// [`Tee::request_pad`].
+    pub pads: usize,
+    /// What to do with [`Buffer`]s that can't be cloned.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub uncloneable: Uncloneable,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            caps: Caps::Message,
+            pads: 2,
+            uncloneable: Uncloneable::Error,
+        }
+    }
+}
+
+/// A `Tee` [`Element`]. Sends a copy of every [`Buffer`] pushed to its sink
+/// pad to each of its source pads, using [`Buffer::try_clone`]. Backend
+/// independent.
+///
+/// For example, one [`AgentMessage`] can go to a chat UI, an audit log and a
+/// metrics sink at the same time. Each source pad holds at most one
+/// [`Buffer`] until it is pulled, so link each one once.
+///
+/// [`Buffer`]: crate::buffer::Buffer
+/// [`Buffer::try_clone`]: crate::buffer::Buffer::try_clone
+/// [`AgentMessage`]: crate::buffer::AgentMessage
+pub struct Tee {
+    caps: Caps,
+    uncloneable: Uncloneable,
+    pads: Vec<TeePad>,
+}
+
+/// A source pad of a [`Tee`].
+pub struct TeePad {
+    caps: Caps,
+    pending: Option<Typed>,
+}
+
+impl Tee {
+    /// Create a new `Tee` from [`Options`].
+    pub fn new(options: Options) -> Self {
+        let mut tee = Self {
+            caps: options.caps,
+            uncloneable: options.uncloneable,
+            pads: Vec::with_capacity(options.pads),
+        };
+        for _ in 0..options.pads {
+            tee.request_pad();
+        }
+
+        tee
+    }
+
+    /// Add a source pad and return its index.
+    ///
+    /// Pads must be requested before the `Tee` is added to a [`Pipeline`],
+    /// which only knows the pads an [`Element`] has when it is added. Use
+    /// [`Options::pads`] for a `Tee` created from configuration.
+    ///
+    /// [`Pipeline`]: crate::pipeline::Pipeline
+    pub fn request_pad(&mut self) -> usize {
+        self.pads.push(TeePad {
+            caps: self.caps,
+            pending: None,
+        });
+        self.pads.len() - 1
+    }
+
+    /// Give every source pad a copy of the `buffer`.
+    ///
+    /// # Errors
+    /// - If the `buffer` doesn't match the [`Caps`] of the `Tee`.
+    /// - If the `buffer` can't be cloned and the policy is
+    ///   [`Uncloneable::Error`].
+    fn fan_out(&mut self, buffer: Typed) -> Result<(), Box<dyn Error>> {
+        if buffer.caps() != self.caps {
+            return Err(ErrorStaticString::from(
+                "Buffer does not match the caps of the tee.",
+            )
+            .into());
+        }
+
+        let copies = self.pads.len().saturating_sub(1);
+        let mut clones = Vec::with_capacity(copies);
+        while clones.len() < copies {
+            match buffer.try_clone() {
+                Some(clone) => clones.push(clone),
+                None => break,
+            }
+        }
+
+        if clones.len() < copies {
+            match self.uncloneable {
+                Uncloneable::Error => {
+                    return Err(ErrorStaticString::from(
+                        "Buffer can't be cloned by the tee.",
+                    )
+                    .into())
+                }
+                Uncloneable::First => self.pads[0].pending = Some(buffer),
+                Uncloneable::Drop => {}
+            }
+            return Ok(());
+        }
+
+        let buffers = clones.into_iter().chain(std::iter::once(buffer));
+        for (pad, buffer) in self.pads.iter_mut().zip(buffers) {
+            pad.pending = Some(buffer);
+        }
+
+        Ok(())
+    }
+}
+
+impl crate::new::New for Tee {
+    type Options = Options;
+
+    fn new(options: Options, _backend: Backend) -> Self {
+        Self::new(options)
+    }
+}
+
+impl crate::new::Options for Options {
+    type New = Tee;
+}
+
+impl TeePad {
+    fn as_source(&self) -> source::Any<'_> {
+        match self.caps {
+            Caps::Prompt => source::Any::Prompt(self),
+            Caps::Message => source::Any::Message(self),
+            Caps::AgentMessage => source::Any::AgentMessage(self),
+            Caps::UserMessage => source::Any::UserMessage(self),
+            Caps::ToolSchema => source::Any::ToolSchema(self),
+            Caps::ToolUse => source::Any::ToolUse(self),
+            Caps::ToolResult => source::Any::ToolResult(self),
+            Caps::Error => source::Any::Error(self),
+        }
+    }
+
+    fn as_source_mut(&mut self) -> source::AnyMut<'_> {
+        match self.caps {
+            Caps::Prompt => source::AnyMut::Prompt(self),
+            Caps::Message => source::AnyMut::Message(self),
+            Caps::AgentMessage => source::AnyMut::AgentMessage(self),
+            Caps::UserMessage => source::AnyMut::UserMessage(self),
+            Caps::ToolSchema => source::AnyMut::ToolSchema(self),
+            Caps::ToolUse => source::AnyMut::ToolUse(self),
+            Caps::ToolResult => source::AnyMut::ToolResult(self),
+            Caps::Error => source::AnyMut::Error(self),
+        }
+    }
+}
+
+// The same `Push` and `Pull` implementations for every kind of buffer.
+macro_rules! impl_pads {
+    ($($variant:ident($buffer:ty)),* $(,)?) => {
+        $(
+            #[async_trait::async_trait]
+            impl Push<Box<$buffer>> for Tee {
+                async fn push(
+                    &mut self,
+                    buffer: Box<$buffer>,
+                ) -> Result<(), Box<dyn Error>> {
+                    self.fan_out(Typed::$variant(buffer))
+                }
+            }
+
+            #[async_trait::async_trait]
+            impl Pull<Box<$buffer>> for TeePad {
+                async fn pull(
+                    &mut self,
+                ) -> Result<Box<$buffer>, Box<dyn Error>> {
+                    match self.pending.take() {
+                        Some(Typed::$variant(buffer)) => Ok(buffer),
+                        _ => Err(ErrorStaticString::from(
+                            "Nothing to pull from the tee pad.",
+                        )
+                        .into()),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_pads!(
+    Prompt(dyn buffer::Prompt),
+    Message(dyn buffer::Message),
+    AgentMessage(dyn buffer::AgentMessage),
+    UserMessage(dyn buffer::UserMessage),
+    ToolSchema(dyn buffer::tool::Schema),
+    ToolUse(dyn buffer::tool::Use),
+    ToolResult(dyn buffer::tool::Result),
+    Error(dyn buffer::Error),
+);
+
+impl Info for Tee {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "Tee".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Sends a copy of every buffer to each source pad.".into()
+    }
+}
+
+impl Info for TeePad {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "Tee Source".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Provides copies of the buffers pushed to a tee.".into()
+    }
+}
+
+#[async_trait::async_trait]
+impl Element for Tee {
+    fn backend(&self) -> Backend {
+        Backend::Independent
+    }
+
+    fn can_pull(&self, pad: usize) -> bool {
+        self.pads.get(pad).is_some_and(|pad| pad.pending.is_some())
+    }
+
+    fn required(&self, _pad: usize) -> bool {
+        true
+    }
+
+    fn sources<'a>(&'a self) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+        Box::new(self.pads.iter().map(TeePad::as_source))
+    }
+
+    fn sources_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+        Box::new(self.pads.iter_mut().map(TeePad::as_source_mut))
+    }
+
+    fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+        Box::new(std::iter::once(match self.caps {
+            Caps::Prompt => sink::Any::Prompt(self),
+            Caps::Message => sink::Any::Message(self),
+            Caps::AgentMessage => sink::Any::AgentMessage(self),
+            Caps::UserMessage => sink::Any::UserMessage(self),
+            Caps::ToolSchema => sink::Any::ToolSchema(self),
+            Caps::ToolUse => sink::Any::ToolUse(self),
+            Caps::ToolResult => sink::Any::ToolResult(self),
+            Caps::Error => sink::Any::Error(self),
+        }))
+    }
+
+    fn sinks_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::once(match self.caps {
+            Caps::Prompt => sink::AnyMut::Prompt(self),
+            Caps::Message => sink::AnyMut::Message(self),
+            Caps::AgentMessage => sink::AnyMut::AgentMessage(self),
+            Caps::UserMessage => sink::AnyMut::UserMessage(self),
+            Caps::ToolSchema => sink::AnyMut::ToolSchema(self),
+            Caps::ToolUse => sink::AnyMut::ToolUse(self),
+            Caps::ToolResult => sink::AnyMut::ToolResult(self),
+            Caps::Error => sink::AnyMut::Error(self),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::test;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tee_fan_out() {
+        let mut tee = Tee::new(Options {
+            pads: 2,
+            ..Default::default()
+        });
+        assert_eq!(tee.request_pad(), 2);
+        assert_eq!(tee.sources().count(), 3);
+        assert!(!tee.can_pull(0));
+
+        let buffer = test::buffer(Caps::Message);
+        let expected = test::text(&*buffer);
+        tee.sinks_mut().next().unwrap().push(buffer).await.unwrap();
+
+        for pad in 0..3 {
+            assert!(tee.can_pull(pad));
+            let mut source = tee.sources_mut().nth(pad).unwrap();
+            assert_eq!(test::text(&*source.pull().await.unwrap()), expected);
+            assert!(!tee.can_pull(pad));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tee_uncloneable() {
+        // Which pad gets a buffer that can't be cloned, if any.
+        for (uncloneable, expected) in [
+            (Uncloneable::Error, None),
+            (Uncloneable::First, Some(0)),
+            (Uncloneable::Drop, None),
+        ] {
+            let mut tee = Tee::new(Options {
+                caps: Caps::ToolSchema,
+                uncloneable,
+                ..Default::default()
+            });
+            let pushed = tee
+                .sinks_mut()
+                .next()
+                .unwrap()
+                .push(Box::new(test::Unique(
+                    serde_json::json!({ "name": "unique" }),
+                )))
+                .await;
+            assert_eq!(
+                pushed.is_err(),
+                uncloneable == Uncloneable::Error,
+                "{uncloneable:?}"
+            );
+
+            for pad in 0..2 {
+                assert_eq!(
+                    tee.can_pull(pad),
+                    expected == Some(pad),
+                    "{uncloneable:?}"
+                );
+            }
+            if let Some(pad) = expected {
+                let mut source = tee.sources_mut().nth(pad).unwrap();
+                let buffer = source.pull().await.unwrap();
+                assert_eq!(test::text(&*buffer), r#"{"name":"unique"}"#);
+            }
+        }
+    }
+}
@@ -0,0 +1,509 @@
+//! Paired [`TeleportSink`] and [`TeleportSource`] moving buffers between
+//! separately run [`Pipeline`]s, like GStreamer's `intersink` and `intersrc`.
+//!
+//! Both ends refer to a channel by name. The channel is created by whichever
+//! end comes first and removed once the last end using it is dropped. Any
+//! number of [`TeleportSink`]s may send to the same channel, so a long-lived
+//! inference [`Pipeline`] can serve many short-lived per-connection
+//! [`Pipeline`]s.
+//!
+//! The sources of a channel wait for its first sink. Once the last sink is
+//! dropped the channel closes, and the sources finish with the buffers still
+//! in it. Ends opened after that get a new channel.
+//!
+//! If several [`TeleportSource`]s receive from the same channel, each buffer
+//! goes to only one of them, whichever is ready first. Nothing routes it to a
+//! particular one, so to answer each of many [`Pipeline`]s give each its own
+//! channel for replies, for example named after its [`SessionId`].
+//!
+//! Only buffers are teleported. [`Event`]s stop at the [`TeleportSink`], so a
+//! [`Pipeline`] ending does not end the other side until its sink is dropped.
+//! A [`TeleportSource`] sends [`Event::Eos`] once its channel closes.
+//!
+//! [`Pipeline`]: crate::pipeline::Pipeline
+//! [`Event`]: crate::pad::Event
+//! [`SessionId`]: crate::buffer::meta::SessionId
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    backends::Backend,
+    buffer::{any::Typed, sink, source, Error, ErrorStaticString},
+    element::{impl_pull_typed, impl_push_typed, Element},
+    info::Info,
+    pad::{Caps, Event},
+};
+
+/// Default number of buffers a channel holds before [`TeleportSink`]s wait.
+pub const CHANNEL_CAPACITY: usize = 16;
+
+/// Options for a [`TeleportSink`] or [`TeleportSource`].
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Options {
+    /// Name of the channel. Ends with the same name are connected.
+    pub channel: String,
+    /// [`Caps`] of the pad. Should be the same on both ends.
+    pub caps: Caps,
+    /// Number of buffers the channel holds. Only used by the end creating
+    /// the channel.
+    pub capacity: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            channel: "default".into(),
+            caps: Caps::Message,
+            capacity: CHANNEL_CAPACITY,
+        }
+    }
+}
+
+/// A named channel, shared by the ends using it.
+struct Channel {
+    /// Kept until the first sink takes it, so the sources wait for one.
+    tx: Option<mpsc::Sender<Typed>>,
+    /// For the sinks after the first. The channel closes once they are all
+    /// dropped.
+    weak: mpsc::WeakSender<Typed>,
+    rx: Arc<tokio::sync::Mutex<mpsc::Receiver<Typed>>>,
+    /// Number of ends using it. It is removed once there are none.
+    ends: usize,
+}
+
+impl Channel {
+    fn new(capacity: usize) -> Self {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        Self {
+            weak: tx.downgrade(),
+            tx: Some(tx),
+            rx: Arc::new(tokio::sync::Mutex::new(rx)),
+            ends: 0,
+        }
+    }
+
+    /// The sender for a new sink.
+    fn sender(&mut self, capacity: usize) -> mpsc::Sender<Typed> {
+        match self.tx.take().or_else(|| self.weak.upgrade()) {
+            Some(tx) => tx,
+            // Every sink was dropped, which closed it. Start over.
+            None => {
+                self.reopen(capacity);
+                self.sender(capacity)
+            }
+        }
+    }
+
+    /// The receiver for a new source.
+    fn receiver(
+        &mut self,
+        capacity: usize,
+    ) -> Arc<tokio::sync::Mutex<mpsc::Receiver<Typed>>> {
+        if self.tx.is_none() && self.weak.upgrade().is_none() {
+            // Every sink was dropped, which closed it. Start over.
+            self.reopen(capacity);
+        }
+        Arc::clone(&self.rx)
+    }
+
+    fn reopen(&mut self, capacity: usize) {
+        *self = Self {
+            ends: self.ends,
+            ..Self::new(capacity)
+        };
+    }
+}
+
+/// The channels by name.
+fn channels() -> MutexGuard<'static, HashMap<String, Channel>> {
+    static CHANNELS: OnceLock<Mutex<HashMap<String, Channel>>> =
+        OnceLock::new();
+
+    CHANNELS
+        .get_or_init(Default::default)
+        .lock()
+        // The map is always left consistent, so a poisoned lock is fine.
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Open an end of the channel called `name`, creating it if needed. `end`
+/// takes what the end needs from the channel.
+fn open<T>(
+    name: &str,
+    capacity: usize,
+    end: impl FnOnce(&mut Channel) -> T,
+) -> T {
+    let mut channels = channels();
+    let channel = channels
+        .entry(name.to_owned())
+        .or_insert_with(|| Channel::new(capacity));
+    channel.ends += 1;
+    end(channel)
+}
+
+/// Close an end of the channel called `name`, removing the channel if it was
+/// the last.
+fn close(name: &str) {
+    let mut channels = channels();
+    if let Some(channel) = channels.get_mut(name) {
+        channel.ends -= 1;
+        if channel.ends == 0 {
+            channels.remove(name);
+        }
+    }
+}
+
+/// A `TeleportSink` [`Element`]. Sends every buffer pushed to it to the
+/// [`TeleportSource`]s with the same channel name. Backend independent.
+pub struct TeleportSink {
+    channel: String,
+    caps: Caps,
+    tx: mpsc::Sender<Typed>,
+}
+
+impl TeleportSink {
+    /// Create a new `TeleportSink` from [`Options`].
+    pub fn new(options: Options) -> Self {
+        let capacity = options.capacity;
+        let tx = open(&options.channel, capacity, |channel| {
+            channel.sender(capacity)
+        });
+        Self {
+            channel: options.channel,
+            caps: options.caps,
+            tx,
+        }
+    }
+
+    /// Name of the channel.
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// Send a buffer, waiting if the channel is full.
+    async fn send(&mut self, buffer: Typed) -> Result<(), Box<dyn Error>> {
+        if buffer.caps() != self.caps {
+            return Err(ErrorStaticString::from(
+                "Buffer does not match the caps of the teleport sink.",
+            )
+            .into());
+        }
+
+        // The channel keeps its receiver while this end is open, so sending
+        // can't fail.
+        self.tx.send(buffer).await.ok();
+
+        Ok(())
+    }
+}
+
+impl Drop for TeleportSink {
+    fn drop(&mut self) {
+        close(&self.channel);
+    }
+}
+
+impl crate::new::New for TeleportSink {
+    type Options = Options;
+
+    fn new(options: Options, _backend: Backend) -> Self {
+        Self::new(options)
+    }
+}
+
+/// A `TeleportSource` [`Element`]. Yields the buffers sent by
+/// [`TeleportSink`]s with the same channel name, waiting for them if
+/// necessary. Backend independent.
+pub struct TeleportSource {
+    channel: String,
+    caps: Caps,
+    rx: Arc<tokio::sync::Mutex<mpsc::Receiver<Typed>>>,
+    /// Received while [`ready`], not pulled yet.
+    ///
+    /// [`ready`]: Element::ready
+    received: Option<Typed>,
+    closed: bool,
+    /// The channel closed and [`Event::Eos`] is yet to be sent.
+    eos: bool,
+}
+
+impl TeleportSource {
+    /// Create a new `TeleportSource` from [`Options`].
+    pub fn new(options: Options) -> Self {
+        let capacity = options.capacity;
+        let rx = open(&options.channel, capacity, |channel| {
+            channel.receiver(capacity)
+        });
+        Self {
+            channel: options.channel,
+            caps: options.caps,
+            rx,
+            received: None,
+            closed: false,
+            eos: false,
+        }
+    }
+
+    /// Name of the channel.
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// Receive a buffer, waiting until one is sent.
+    async fn recv(&mut self) -> Result<Typed, Box<dyn Error>> {
+        if let Some(buffer) = self.received.take() {
+            return Ok(buffer);
+        }
+        self.rx.lock().await.recv().await.ok_or_else(|| {
+            ErrorStaticString::from("Teleport channel closed.").into()
+        })
+    }
+}
+
+impl Drop for TeleportSource {
+    fn drop(&mut self) {
+        close(&self.channel);
+    }
+}
+
+impl crate::new::New for TeleportSource {
+    type Options = Options;
+
+    fn new(options: Options, _backend: Backend) -> Self {
+        Self::new(options)
+    }
+}
+
+impl crate::new::Options for Options {
+    type New = TeleportSink;
+}
+
+impl_push_typed!(TeleportSink, send);
+impl_pull_typed!(TeleportSource, recv);
+
+impl Info for TeleportSink {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "Teleport Sink".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Sends buffers to another pipeline.".into()
+    }
+}
+
+impl Info for TeleportSource {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        "Teleport Source".into()
+    }
+
+    fn description(&self) -> std::borrow::Cow<'_, str> {
+        "Receives buffers from another pipeline.".into()
+    }
+}
+
+#[async_trait::async_trait]
+impl Element for TeleportSink {
+    fn backend(&self) -> Backend {
+        Backend::Independent
+    }
+
+    async fn event(
+        &mut self,
+        _pad: usize,
+        _event: Event,
+    ) -> Result<Option<Event>, Box<dyn Error>> {
+        // There is nothing downstream of a sink.
+        Ok(None)
+    }
+
+    fn required(&self, _pad: usize) -> bool {
+        true
+    }
+
+    fn sources<'a>(&'a self) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+        Box::new(std::iter::empty())
+    }
+
+    fn sources_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::empty())
+    }
+
+    fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+        Box::new(std::iter::once(match self.caps {
+            Caps::Prompt => sink::Any::Prompt(self),
+            Caps::Message => sink::Any::Message(self),
+            Caps::AgentMessage => sink::Any::AgentMessage(self),
+            Caps::UserMessage => sink::Any::UserMessage(self),
+            Caps::ToolSchema => sink::Any::ToolSchema(self),
+            Caps::ToolUse => sink::Any::ToolUse(self),
+            Caps::ToolResult => sink::Any::ToolResult(self),
+            Caps::Error => sink::Any::Error(self),
+        }))
+    }
+
+    fn sinks_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::once(match self.caps {
+            Caps::Prompt => sink::AnyMut::Prompt(self),
+            Caps::Message => sink::AnyMut::Message(self),
+            Caps::AgentMessage => sink::AnyMut::AgentMessage(self),
+            Caps::UserMessage => sink::AnyMut::UserMessage(self),
+            Caps::ToolSchema => sink::AnyMut::ToolSchema(self),
+            Caps::ToolUse => sink::AnyMut::ToolUse(self),
+            Caps::ToolResult => sink::AnyMut::ToolResult(self),
+            Caps::Error => sink::AnyMut::Error(self),
+        }))
+    }
+}
+
+#[async_trait::async_trait]
+impl Element for TeleportSource {
+    fn backend(&self) -> Backend {
+        Backend::Independent
+    }
+
+    fn take_event(&mut self) -> Option<Event> {
+        std::mem::take(&mut self.eos).then_some(Event::Eos)
+    }
+
+    fn can_pull(&self, _pad: usize) -> bool {
+        self.received.is_some()
+    }
+
+    async fn ready(&mut self) {
+        if self.received.is_some() {
+            return;
+        }
+        // Both are cancel safe, and nothing is received until both are done.
+        match self.rx.lock().await.recv().await {
+            Some(buffer) => self.received = Some(buffer),
+            None => {
+                self.closed = true;
+                self.eos = true;
+            }
+        }
+    }
+
+    fn busy(&self) -> bool {
+        // Waiting for buffers until the channel closes.
+        !self.closed
+    }
+
+    fn sources<'a>(&'a self) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+        Box::new(std::iter::once(match self.caps {
+            Caps::Prompt => source::Any::Prompt(self),
+            Caps::Message => source::Any::Message(self),
+            Caps::AgentMessage => source::Any::AgentMessage(self),
+            Caps::UserMessage => source::Any::UserMessage(self),
+            Caps::ToolSchema => source::Any::ToolSchema(self),
+            Caps::ToolUse => source::Any::ToolUse(self),
+            Caps::ToolResult => source::Any::ToolResult(self),
+            Caps::Error => source::Any::Error(self),
+        }))
+    }
+
+    fn sources_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::once(match self.caps {
+            Caps::Prompt => source::AnyMut::Prompt(self),
+            Caps::Message => source::AnyMut::Message(self),
+            Caps::AgentMessage => source::AnyMut::AgentMessage(self),
+            Caps::UserMessage => source::AnyMut::UserMessage(self),
+            Caps::ToolSchema => source::AnyMut::ToolSchema(self),
+            Caps::ToolUse => source::AnyMut::ToolUse(self),
+            Caps::ToolResult => source::AnyMut::ToolResult(self),
+            Caps::Error => source::AnyMut::Error(self),
+        }))
+    }
+
+    fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+        Box::new(std::iter::empty())
+    }
+
+    fn sinks_mut<'a>(
+        &'a mut self,
+    ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+        Box::new(std::iter::empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::test;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_teleport() {
+        let options = Options {
+            channel: "test_teleport".into(),
+            ..Default::default()
+        };
+        let mut sink = TeleportSink::new(options.clone());
+        let mut source = TeleportSource::new(options);
+
+        let buffer = test::buffer(Caps::Message);
+        let expected = test::text(&*buffer);
+        sink.sinks_mut().next().unwrap().push(buffer).await.unwrap();
+
+        let mut source = source.sources_mut().next().unwrap();
+        assert_eq!(test::text(&*source.pull().await.unwrap()), expected);
+    }
+
+    #[tokio::test]
+    async fn test_teleport_closes() {
+        let options = Options {
+            channel: "test_teleport_closes".into(),
+            ..Default::default()
+        };
+        /// Whether `source` is ready soon.
+        async fn wait(source: &mut TeleportSource) -> bool {
+            let ready = source.ready();
+            let soon = std::time::Duration::from_millis(50);
+            tokio::time::timeout(soon, ready).await.is_ok()
+        }
+
+        // Sources wait for the first sink.
+        let mut source = TeleportSource::new(options.clone());
+        assert!(!wait(&mut source).await);
+        assert!(source.busy());
+
+        // They finish once the last one is gone, after what it sent.
+        let mut sink = TeleportSink::new(options.clone());
+        let other = TeleportSink::new(options.clone());
+        let buffer = test::buffer(Caps::Message);
+        sink.sinks_mut().next().unwrap().push(buffer).await.unwrap();
+        drop(sink);
+        drop(other);
+        assert!(wait(&mut source).await);
+        assert!(source.can_pull(0));
+        source.sources_mut().next().unwrap().pull().await.unwrap();
+        assert!(wait(&mut source).await);
+        assert!(!source.busy());
+        assert_eq!(source.take_event(), Some(Event::Eos));
+        assert_eq!(source.take_event(), None);
+
+        // An end opened after that gets a new channel.
+        let mut late = TeleportSource::new(options.clone());
+        assert!(!wait(&mut late).await);
+        assert!(late.busy());
+
+        // The channel is removed with its last end.
+        drop(source);
+        assert!(channels().contains_key("test_teleport_closes"));
+        drop(late);
+        assert!(!channels().contains_key("test_teleport_closes"));
+    }
+}
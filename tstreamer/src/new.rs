@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::{de::DeserializeOwned, Serialize};
 
 use crate::backends::Backend;
@@ -6,8 +7,13 @@ use crate::backends::Backend;
 /// [`Element`]s in a [`Pipeline`].
 ///
 /// You should implement [`New`] instead of this trait. There is a blanket
-/// implementation for all [`New`] types.
-pub trait Options: Serialize + DeserializeOwned + Sized + 'static {
+/// implementation for all [`New`] types that are their own `Options`.
+///
+/// [`Element`]: crate::element::Element
+/// [`Pipeline`]: crate::pipeline::Pipeline
+pub trait Options:
+    Serialize + DeserializeOwned + JsonSchema + Sized + 'static
+{
     /// The [`New`] thing to construct.
     type New: New<Options = Self>;
 
@@ -15,15 +21,26 @@ pub trait Options: Serialize + DeserializeOwned + Sized + 'static {
     fn build(self, backend: Backend) -> Self::New {
         Self::New::new(self, backend)
     }
+
+    /// JSON Schema describing these `Options`, derived from the type. Used
+    /// to check configuration before anything is constructed and to render
+    /// forms for it.
+    fn schema() -> schemars::Schema {
+        schemars::schema_for!(Self)
+    }
 }
 
 /// A trait for constructing various objects such as [`Element`]s in a
 /// [`Pipeline`].
 ///
+/// [`Element`]: crate::element::Element
 /// [`Pipeline`]: crate::pipeline::Pipeline
-pub trait New: Serialize + DeserializeOwned + 'static {
-    /// The type of the options.
-    type Options: Options<New = Self>;
+pub trait New: 'static {
+    /// The type of the options. Several things may share one type, like the
+    /// two ends of a [`teleport`] channel.
+    ///
+    /// [`teleport`]: crate::element::teleport
+    type Options: Options;
 
     /// Creates a new instance of the element. This should not block. Use
     /// [`Element::init`] for any long-running initialization.
@@ -34,7 +51,7 @@ pub trait New: Serialize + DeserializeOwned + 'static {
 
 impl<T> Options for T
 where
-    T: New<Options = T>,
+    T: New<Options = T> + Serialize + DeserializeOwned + JsonSchema,
 {
     type New = T;
 }
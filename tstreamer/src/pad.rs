@@ -1,4 +1,5 @@
 use direction::{Direction, Pulls, Pushes};
+use serde::{Deserialize, Serialize};
 
 use crate::buffer::{Buffer, Error, Message};
 
@@ -17,26 +18,37 @@ pub mod direction {
 ///
 /// This is implemented for all [`Pull`]able sources.
 #[async_trait::async_trait]
-pub trait Source<Out: Buffer, D: Direction> {
+pub trait Source<Out: Buffer, D: Direction = Pulls> {
     // Source does not necessarily implement Pull because it may not be possible
     // to pull from the source. For example, an event-driven source may not have
     // a pull method.
 }
-impl<B: Buffer> Source<B, Pulls> for dyn Pull<B> {}
+impl<B: Buffer, T: Pull<B> + ?Sized> Source<B, Pulls> for T {}
 static_assertions::assert_obj_safe!(Source<Box<dyn Message>, Pulls>);
 
 /// A `Pull`able [`Source`]
 #[async_trait::async_trait]
 pub trait Pull<Out: Buffer> {
     /// [`Pull`]s a buffer from the source.
+    ///
+    /// A source waiting here is interrupted when a running [`Pipeline`] is
+    /// changed through a [`Control`], so it must be safe to drop the future
+    /// before it completes, without losing a buffer.
+    ///
+    /// [`Pipeline`]: crate::pipeline::Pipeline
+    /// [`Control`]: crate::pipeline::Control
     async fn pull(&mut self) -> Result<Out, Box<dyn Error>>;
 }
 static_assertions::assert_obj_safe!(Pull<Box<dyn Message>>);
 
-/// A [`Buffer`] `Sink`. Indicates either
+/// A [`Buffer`] `Sink`. Indicates either that it is possible to [`Push`] to
+/// this sink, or that it will pull this type of buffer from a compatible
+/// [`Source`].
+///
+/// This is implemented for all [`Push`]able sinks.
 #[async_trait::async_trait]
-pub trait Sink<In: Buffer, D: Direction> {}
-impl<B: Buffer> Sink<B, Pushes> for dyn Push<B> {}
+pub trait Sink<In: Buffer, D: Direction = Pushes> {}
+impl<B: Buffer, T: Push<B> + ?Sized> Sink<B, Pushes> for T {}
 static_assertions::assert_obj_safe!(Sink<Box<dyn Message>, Pulls>);
 
 /// A [`Push`]able [`Sink`]
@@ -46,3 +58,162 @@ pub trait Push<In: Buffer> {
     async fn push(&mut self, buffer: In) -> Result<(), Box<dyn Error>>;
 }
 static_assertions::assert_obj_safe!(Push<Box<dyn Message>>);
+
+/// `Caps` (capabilities) of a pad. The kind of [`Buffer`] a [`Source`] yields
+/// or a [`Sink`] accepts. Used to check links before a [`Pipeline`] runs.
+///
+/// [`Pipeline`]: crate::pipeline::Pipeline
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    schemars::JsonSchema,
+)]
+pub enum Caps {
+    /// [`Prompt`](crate::buffer::Prompt)s.
+    Prompt,
+    /// Any [`Message`].
+    Message,
+    /// [`AgentMessage`](crate::buffer::AgentMessage)s.
+    AgentMessage,
+    /// [`UserMessage`](crate::buffer::UserMessage)s.
+    UserMessage,
+    /// [`tool::Schema`](crate::buffer::tool::Schema)s.
+    ToolSchema,
+    /// [`tool::Use`](crate::buffer::tool::Use)s.
+    ToolUse,
+    /// [`tool::Result`](crate::buffer::tool::Result)s.
+    ToolResult,
+    /// [`Error`]s, such as those sent on the error pad every [`Node`] has.
+    ///
+    /// [`Node`]: crate::pipeline::Node
+    Error,
+}
+
+impl Caps {
+    /// All `Caps`.
+    pub const ALL: &'static [Caps] = &[
+        Caps::Prompt,
+        Caps::Message,
+        Caps::AgentMessage,
+        Caps::UserMessage,
+        Caps::ToolSchema,
+        Caps::ToolUse,
+        Caps::ToolResult,
+        Caps::Error,
+    ];
+
+    /// Whether a [`Sink`] with these `Caps` accepts every [`Buffer`] a
+    /// [`Source`] with the `source` `Caps` can yield. A [`Message`] [`Sink`]
+    /// accepts the more specific kinds of [`Message`] as well, except tool use,
+    /// which only some backends put in a [`Message`].
+    ///
+    /// This is exactly when [`Typed::new`] converts any such [`Buffer`] to
+    /// these `Caps`.
+    ///
+    /// [`Typed::new`]: crate::buffer::any::Typed::new
+    pub fn accepts(self, source: Caps) -> bool {
+        match self {
+            Caps::Message => matches!(
+                source,
+                Caps::Message
+                    | Caps::AgentMessage
+                    | Caps::UserMessage
+                    | Caps::ToolResult
+            ),
+            sink => sink == source,
+        }
+    }
+}
+
+impl std::fmt::Display for Caps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// An `Event` travelling alongside [`Buffer`]s. `Event`s keep their place in
+/// the stream, so everything pushed before an `Event` reaches a [`Sink`]
+/// before it does.
+///
+/// `Event`s are handled by [`Element::event`], which decides whether they go
+/// further downstream.
+///
+/// [`Element::event`]: crate::element::Element::event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    /// End of stream. Nothing follows on this pad. For example, a
+    /// conversation has ended.
+    Eos,
+    /// Discard queued [`Buffer`]s until [`Event::FlushStop`].
+    FlushStart,
+    /// Stop discarding [`Buffer`]s.
+    FlushStop,
+    /// Abandon whatever is in progress, such as generating a response,
+    /// without ending the stream.
+    Cancel,
+    /// An application defined `Event`.
+    Custom {
+        /// Name of the `Event`.
+        name: String,
+        /// Arbitrary data.
+        #[serde(default)]
+        data: serde_json::Value,
+    },
+}
+
+impl Event {
+    /// Create a [`Event::Custom`].
+    pub fn custom(name: impl Into<String>, data: serde_json::Value) -> Self {
+        Event::Custom {
+            name: name.into(),
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caps_accepts() {
+        for &caps in Caps::ALL {
+            assert!(caps.accepts(caps));
+        }
+
+        assert!(Caps::Message.accepts(Caps::AgentMessage));
+        assert!(Caps::Message.accepts(Caps::ToolResult));
+        assert!(!Caps::AgentMessage.accepts(Caps::Message));
+        assert!(!Caps::Prompt.accepts(Caps::AgentMessage));
+        assert!(!Caps::Message.accepts(Caps::ToolUse));
+    }
+
+    #[test]
+    fn test_caps_accepts_what_converts() {
+        use crate::buffer::{any::Typed, test};
+
+        for &source in Caps::ALL {
+            for &sink in Caps::ALL {
+                let converts =
+                    test::buffers(source).into_iter().all(|buffer| {
+                        // What the source yields, as it crosses the link.
+                        let buffer =
+                            Typed::new(source, buffer).unwrap().into_buffer();
+                        Typed::new(sink, buffer)
+                            .is_ok_and(|typed| typed.caps() == sink)
+                    });
+                assert_eq!(
+                    sink.accepts(source),
+                    converts,
+                    "{source} -> {sink}"
+                );
+            }
+        }
+    }
+}
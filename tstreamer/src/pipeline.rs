@@ -1,15 +1,2412 @@
-mod node;
+/// [`Node`]s of a [`Pipeline`] and their configuration.
+pub mod node;
 pub(crate) use node::Node;
 
+pub mod config;
+pub use config::Config;
+
+pub mod parse;
+pub use parse::{ParseError, ParseErrorKind};
+
+pub mod bus;
+pub use bus::Bus;
+
+pub mod control;
+pub use control::{Control, ControlError};
+
+pub mod dot;
+
+pub mod trace;
+pub use trace::Tracer;
+
+pub mod metrics;
+pub use metrics::Metrics;
+
+pub mod shutdown;
+
+pub mod supervisor;
+
+pub mod checkpoint;
+pub use checkpoint::{Checkpoint, CheckpointError};
+
+pub mod reload;
+
+pub mod validate;
+pub use validate::{Problem, ValidationError};
+
 mod edge;
 pub(crate) use edge::Edge;
 
+mod executor;
+pub use executor::CHANNEL_CAPACITY;
+
 mod state;
-use serde::{Deserialize, Serialize};
-pub use state::State;
+pub use state::{
+    BuildError, Builder, InitError, LinkError, New, Ready, RunError, Shutdown,
+    ShutdownError, State,
+};
+
+pub use petgraph::graph::{EdgeIndex, NodeIndex};
+
+use std::time::Instant;
+
+use petgraph::visit::EdgeRef;
+
+use crate::element::{property, Element};
 
 /// A `Pipeline` of [`Node`]s and [`Edge`]s connecting them.
-#[derive(Serialize, Deserialize)]
+// This is not `Serialize` because `Element`s are not. The configuration used
+// to create the pipeline is what gets serialized.
 pub struct Pipeline<S: State> {
     graph: petgraph::graph::Graph<Node<S>, Edge<S>>,
+    bus: Bus,
+    control: Control,
+    tracers: trace::Tracers,
+}
+
+impl Pipeline<Builder> {
+    /// Create a new, empty, `Pipeline`.
+    pub fn new() -> Self {
+        Self {
+            graph: petgraph::graph::Graph::new(),
+            bus: Bus::new(),
+            control: Control::default(),
+            tracers: trace::Tracers::default(),
+        }
+    }
+}
+
+impl Default for Pipeline<Builder> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: State> std::fmt::Debug for Pipeline<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("state", &S::NAME)
+            .field(
+                "nodes",
+                &self
+                    .graph
+                    .node_weights()
+                    .map(|node| &node.name)
+                    .collect::<Vec<_>>(),
+            )
+            .field("edges", &self.graph.edge_count())
+            .finish()
+    }
+}
+
+impl<S: State> Pipeline<S> {
+    /// Find a [`Node`] by name.
+    pub fn find(&self, name: &str) -> Option<NodeIndex> {
+        self.graph
+            .node_indices()
+            .find(|&index| self.graph[index].name == name)
+    }
+
+    /// The [`Bus`] the `Pipeline` posts [`bus::Message`]s to. Clone it to
+    /// keep listening after the `Pipeline` changes [`State`].
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    /// Add a [`Tracer`], called from now on. Add it before [`init`] to see
+    /// every hook. See [`trace`].
+    ///
+    /// [`init`]: Pipeline::init
+    pub fn add_tracer(&mut self, tracer: std::sync::Arc<dyn Tracer>) {
+        self.tracers.add(tracer);
+    }
+
+    /// Export the `Pipeline` as a Graphviz DOT graph. [`Node`]s are labeled
+    /// with their name, [`Info::name`], [`Backend`], options and read-only
+    /// [`Property`]s, like the fill level of a [`Queue`]. [`Edge`]s are
+    /// labeled with the [`Caps`] they carry. Use [`Control::to_dot`] for a
+    /// running `Pipeline`.
+    ///
+    /// [`Info::name`]: crate::info::Info::name
+    /// [`Backend`]: crate::backends::Backend
+    /// [`Property`]: property::Property
+    /// [`Queue`]: crate::element::queue::Queue
+    /// [`Caps`]: crate::pad::Caps
+    pub fn to_dot(&self) -> String {
+        let names: Vec<_> = self
+            .graph
+            .node_weights()
+            .map(|node| node.element.name())
+            .collect();
+
+        dot::render(
+            self.graph.node_weights().zip(&names).enumerate().map(
+                |(index, (node, element))| dot::Node {
+                    index,
+                    name: &node.name,
+                    element,
+                    backend: node.element.backend(),
+                    config: node.config.as_ref(),
+                    readings: property::readings(&*node.element),
+                    status: vec![],
+                },
+            ),
+            self.graph.edge_references().map(|edge| dot::Edge {
+                source: edge.source().index(),
+                sink: edge.target().index(),
+                source_pad: edge.weight().source,
+                sink_pad: edge.weight().sink,
+                caps: edge.weight().caps,
+                sent: None,
+            }),
+        )
+    }
+
+    /// Write [`to_dot`] to `<name>.dot` in the directory named by the
+    /// [`dot::DUMP_DIR_ENV`] environment variable, if set. Returns the path
+    /// written to, if any.
+    ///
+    /// # Errors
+    /// - If the file can't be written.
+    ///
+    /// [`to_dot`]: Pipeline::to_dot
+    pub fn dump_dot(
+        &self,
+        name: &str,
+    ) -> std::io::Result<Option<std::path::PathBuf>> {
+        dot::dump(name, &self.to_dot())
+    }
+
+    /// The [`Element`] of a [`Node`].
+    pub(crate) fn element(&self, node: NodeIndex) -> Option<&dyn Element> {
+        self.graph
+            .node_weight(node)
+            .map(|node| node.element.as_ref())
+    }
+
+    /// Index of the error pad of a [`Node`], or [`None`] if there is no such
+    /// [`Node`]. Every [`Node`] has one, after the source pads of its
+    /// [`Element`]. When pushing to or pulling from the [`Element`] fails
+    /// and the error pad is linked, the [`Error`] is sent there instead and
+    /// the [`Node`] carries on. Only the first [`Edge`] still listening gets
+    /// it, so link a [`Tee`] to send it to several places. An [`ErrorSink`]
+    /// makes a good destination.
+    ///
+    /// [`Error`]: crate::buffer::Error
+    /// [`Tee`]: crate::element::tee::Tee
+    /// [`ErrorSink`]: crate::element::error_sink::ErrorSink
+    pub fn error_pad(&self, node: NodeIndex) -> Option<usize> {
+        self.element(node).map(|element| element.sources().count())
+    }
+
+    /// The [`Property`]s of the [`Element`] of a [`Node`], or [`None`] if
+    /// there is no such [`Node`].
+    ///
+    /// [`Property`]: property::Property
+    pub fn properties(
+        &self,
+        node: NodeIndex,
+    ) -> Option<&'static [property::Property]> {
+        self.element(node).map(|element| element.properties())
+    }
+
+    /// Read a [`Property`] of a [`Node`] with [`property::get`]. Use
+    /// [`Control::property`] while the `Pipeline` runs.
+    ///
+    /// # Errors
+    /// - [`LinkError::NoSuchNode`] if the [`Node`] does not exist.
+    /// - Anything [`property::get`] can return.
+    ///
+    /// [`Property`]: property::Property
+    pub fn property(
+        &self,
+        node: NodeIndex,
+        name: &str,
+    ) -> Result<property::Value, ControlError> {
+        let element = self
+            .element(node)
+            .ok_or(LinkError::NoSuchNode { node: node.index() })?;
+
+        Ok(property::get(element, name)?)
+    }
+
+    /// Write a [`Property`] of a [`Node`] with [`property::set`], posting a
+    /// [`bus::Message::PropertyChanged`]. Returns the new value. Use
+    /// [`Control::set_property`] while the `Pipeline` runs.
+    ///
+    /// # Errors
+    /// - [`LinkError::NoSuchNode`] if the [`Node`] does not exist.
+    /// - Anything [`property::set`] can return.
+    ///
+    /// [`Property`]: property::Property
+    pub fn set_property(
+        &mut self,
+        node: NodeIndex,
+        name: &str,
+        value: impl Into<property::Value>,
+    ) -> Result<property::Value, ControlError> {
+        let weight = self
+            .graph
+            .node_weight_mut(node)
+            .ok_or(LinkError::NoSuchNode { node: node.index() })?;
+        let value = property::set(weight.element.as_mut(), name, value.into())?;
+        if let Some(config) = &mut weight.config {
+            config.set(name, &value);
+        }
+
+        self.bus.post(bus::Message::PropertyChanged {
+            node: node.index(),
+            name: weight.name.clone(),
+            property: name.to_owned(),
+            value: value.clone(),
+        });
+
+        Ok(value)
+    }
+
+    /// A [`Control`] to change the `Pipeline` while it [`run`]s. Requests
+    /// made while it is not running fail with [`ControlError::NotRunning`].
+    ///
+    /// [`run`]: Pipeline::run
+    pub fn control(&self) -> Control {
+        self.control.clone()
+    }
+
+    /// Call [`Element::stop`] on every [`Node`], in order, posting failures
+    /// on the [`Bus`]. Returns the first failure. Every [`Element`] is still
+    /// stopped.
+    async fn stop_all(&mut self) -> Option<ShutdownError> {
+        let mut failed = None;
+        for (index, node) in self.graph.node_weights_mut().enumerate() {
+            let start = Instant::now();
+            let result = node.element.stop().await.map_err(|e| e.to_string());
+            let elapsed = start.elapsed();
+            self.tracers
+                .stop(node.origin(index), elapsed, result.is_ok());
+            let Err(message) = result else {
+                continue;
+            };
+            self.bus.post(bus::Message::Error {
+                node: index,
+                name: node.name.clone(),
+                message: message.clone(),
+            });
+            self.tracers.error(node.origin(index), &message);
+            failed.get_or_insert(ShutdownError::Element {
+                node: index,
+                name: node.name.clone(),
+                message,
+            });
+        }
+
+        failed
+    }
+
+    /// Move the `Pipeline` and all its [`Node`]s and [`Edge`]s to another
+    /// [`State`], posting a [`bus::Message::StateChanged`]. Indices are
+    /// preserved.
+    fn into_state<T: State>(self) -> Pipeline<T> {
+        let (nodes, edges) = self.graph.into_nodes_edges();
+        let mut graph =
+            petgraph::graph::Graph::with_capacity(nodes.len(), edges.len());
+        for node in nodes {
+            graph.add_node(node.weight.into_state());
+        }
+        for edge in edges {
+            graph.add_edge(
+                edge.source(),
+                edge.target(),
+                edge.weight.into_state(),
+            );
+        }
+
+        self.bus.post(bus::Message::StateChanged {
+            from: S::NAME,
+            to: T::NAME,
+        });
+
+        Pipeline {
+            graph,
+            bus: self.bus,
+            control: self.control,
+            tracers: self.tracers,
+        }
+    }
+}
+
+impl Pipeline<Builder> {
+    /// Add an [`Element`] to the `Pipeline` as a [`Node`] with a `name`.
+    /// Returns the index of the new [`Node`].
+    ///
+    /// The `Pipeline` can't be serialized as a [`Config`] if it contains
+    /// [`Element`]s added this way. Prefer [`Pipeline::add_config`].
+    pub fn add(
+        &mut self,
+        name: impl Into<String>,
+        element: Box<dyn Element>,
+    ) -> NodeIndex {
+        self.graph.add_node(Node::new(name.into(), None, element))
+    }
+
+    /// Construct an [`Element`] from a [`node::Config`] and add it to the
+    /// `Pipeline` as a [`Node`] with a `name`. Returns the index of the new
+    /// [`Node`].
+    ///
+    /// # Errors
+    /// - [`BuildError::New`] if the [`Element`] can't be constructed.
+    pub fn add_config(
+        &mut self,
+        name: impl Into<String>,
+        config: node::Config,
+    ) -> Result<NodeIndex, BuildError> {
+        let name = name.into();
+        let element = match config.new_element() {
+            Ok(element) => element,
+            Err(source) => return Err(BuildError::New { name, source }),
+        };
+
+        Ok(self.graph.add_node(Node::new(name, Some(config), element)))
+    }
+
+    /// Set the restart [`Policy`] of a [`Node`]. See [`supervisor`].
+    ///
+    /// # Errors
+    /// - [`LinkError::NoSuchNode`] if the [`Node`] does not exist.
+    ///
+    /// [`Policy`]: supervisor::Policy
+    pub fn set_restart(
+        &mut self,
+        node: NodeIndex,
+        policy: supervisor::Policy,
+    ) -> Result<(), LinkError> {
+        self.graph
+            .node_weight_mut(node)
+            .ok_or(LinkError::NoSuchNode { node: node.index() })?
+            .restart = policy;
+
+        Ok(())
+    }
+
+    /// Link the source pad at `source_pad` of the `source` [`Node`] to the sink
+    /// pad at `sink_pad` of the `sink` [`Node`]. Pads are indexed in the order
+    /// they are yielded by [`Element::sources`] and [`Element::sinks`]. The
+    /// last source pad is the [`error_pad`].
+    ///
+    /// # Errors
+    /// - [`LinkError::NoSuchNode`] if either [`Node`] does not exist.
+    /// - [`LinkError::NoSuchSourcePad`] or [`LinkError::NoSuchSinkPad`] if a
+    ///   pad does not exist.
+    /// - [`LinkError::Incompatible`] if the sink pad does not accept the
+    ///   [`Caps`] of the source pad. For example, an [`AgentMessageSource`]
+    ///   can't be linked to a [`PromptSink`].
+    ///
+    /// [`Caps`]: crate::pad::Caps
+    /// [`AgentMessageSource`]: crate::buffer::source::AgentMessageSource
+    /// [`PromptSink`]: crate::buffer::sink::PromptSink
+    /// [`error_pad`]: Pipeline::error_pad
+    pub fn link(
+        &mut self,
+        source: NodeIndex,
+        source_pad: usize,
+        sink: NodeIndex,
+        sink_pad: usize,
+    ) -> Result<EdgeIndex, LinkError> {
+        let source_caps = self.source_caps(source, source_pad)?;
+        let sink_caps = self.sink_caps(sink, sink_pad)?;
+
+        if !sink_caps.accepts(source_caps) {
+            return Err(LinkError::Incompatible {
+                source_node: source.index(),
+                source_caps,
+                sink_node: sink.index(),
+                sink_caps,
+            });
+        }
+
+        Ok(self.graph.add_edge(
+            source,
+            sink,
+            Edge::new(source_pad, sink_pad, source_caps),
+        ))
+    }
+
+    /// [`Caps`] of the source pad at `pad` of the `node`.
+    ///
+    /// [`Caps`]: crate::pad::Caps
+    pub(crate) fn source_caps(
+        &self,
+        node: NodeIndex,
+        pad: usize,
+    ) -> Result<crate::pad::Caps, LinkError> {
+        let element = self
+            .element(node)
+            .ok_or(LinkError::NoSuchNode { node: node.index() })?;
+        node::source_caps(element).get(pad).copied().ok_or(
+            LinkError::NoSuchSourcePad {
+                node: node.index(),
+                pad,
+            },
+        )
+    }
+
+    /// [`Caps`] of the sink pad at `pad` of the `node`.
+    ///
+    /// [`Caps`]: crate::pad::Caps
+    pub(crate) fn sink_caps(
+        &self,
+        node: NodeIndex,
+        pad: usize,
+    ) -> Result<crate::pad::Caps, LinkError> {
+        self.element(node)
+            .ok_or(LinkError::NoSuchNode { node: node.index() })?
+            .sinks()
+            .nth(pad)
+            .map(|pad| pad.caps())
+            .ok_or(LinkError::NoSuchSinkPad {
+                node: node.index(),
+                pad,
+            })
+    }
+
+    /// Remove a [`Node`] and its [`Edge`]s, returning its [`Element`]. The
+    /// last [`Node`] takes its index, so remove from the end to keep the
+    /// others in place.
+    pub(crate) fn remove(
+        &mut self,
+        node: NodeIndex,
+    ) -> Option<Box<dyn Element>> {
+        self.graph.remove_node(node).map(|node| node.element)
+    }
+
+    /// Link two [`Node`]s like [`link`], marking the [`Edge`] as feedback: it
+    /// closes an intended cycle, such as [`tool::Result`]s going back to an
+    /// [`Inference`] [`Element`]. Cycles without a feedback [`Edge`] fail
+    /// [`validate`].
+    ///
+    /// # Errors
+    /// - Anything [`link`] can return.
+    ///
+    /// [`link`]: Pipeline::link
+    /// [`validate`]: Pipeline::validate
+    /// [`tool::Result`]: crate::buffer::tool::Result
+    /// [`Inference`]: crate::element::inference::Inference
+    pub fn link_feedback(
+        &mut self,
+        source: NodeIndex,
+        source_pad: usize,
+        sink: NodeIndex,
+        sink_pad: usize,
+    ) -> Result<EdgeIndex, LinkError> {
+        let edge = self.link(source, source_pad, sink, sink_pad)?;
+        self.graph[edge].feedback = true;
+
+        Ok(edge)
+    }
+
+    /// Link the `source` [`Node`] to the `sink` [`Node`] like [`link`], but
+    /// pick the pads automatically. Pads left as [`None`] are chosen so the
+    /// first compatible pair (in pad order) is linked. Source pads that are
+    /// not linked yet are preferred, so a [`Tee`] can be autolinked to
+    /// several sinks. Only an [`Error`] sink accepts the [`error_pad`], so
+    /// it is picked for an [`ErrorSink`].
+    ///
+    /// # Errors
+    /// - Anything [`link`] can return.
+    /// - [`LinkError::NoCompatiblePads`] if no pair of pads is compatible.
+    ///
+    /// [`link`]: Pipeline::link
+    /// [`Tee`]: crate::element::tee::Tee
+    /// [`Error`]: crate::buffer::Error
+    /// [`error_pad`]: Pipeline::error_pad
+    /// [`ErrorSink`]: crate::element::error_sink::ErrorSink
+    pub fn autolink(
+        &mut self,
+        source: NodeIndex,
+        source_pad: Option<usize>,
+        sink: NodeIndex,
+        sink_pad: Option<usize>,
+    ) -> Result<EdgeIndex, LinkError> {
+        if let (Some(source_pad), Some(sink_pad)) = (source_pad, sink_pad) {
+            return self.link(source, source_pad, sink, sink_pad);
+        }
+
+        let node = |index: NodeIndex| {
+            self.graph.node_weight(index).ok_or(LinkError::NoSuchNode {
+                node: index.index(),
+            })
+        };
+        let mut sources: Vec<_> = node::source_caps(&*node(source)?.element)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| source_pad.is_none_or(|pad| pad == *i))
+            .collect();
+        let linked: std::collections::HashSet<usize> = self
+            .graph
+            .edges(source)
+            .map(|edge| edge.weight().source)
+            .collect();
+        // Stable, so pad order is kept otherwise.
+        sources.sort_by_key(|(i, _)| linked.contains(i));
+        let sinks: Vec<_> = node(sink)?
+            .element
+            .sinks()
+            .map(|pad| pad.caps())
+            .enumerate()
+            .filter(|(i, _)| sink_pad.is_none_or(|pad| pad == *i))
+            .collect();
+
+        // Let `link` report pads that don't exist.
+        let pair = sources.iter().find_map(|&(i, source_caps)| {
+            sinks
+                .iter()
+                .find(|(_, sink_caps)| sink_caps.accepts(source_caps))
+                .map(|&(j, _)| (i, j))
+        });
+        match (pair, source_pad, sink_pad) {
+            (Some((i, j)), _, _) => self.link(source, i, sink, j),
+            (None, Some(pad), _) if sources.is_empty() => {
+                Err(LinkError::NoSuchSourcePad {
+                    node: source.index(),
+                    pad,
+                })
+            }
+            (None, _, Some(pad)) if sinks.is_empty() => {
+                Err(LinkError::NoSuchSinkPad {
+                    node: sink.index(),
+                    pad,
+                })
+            }
+            (None, _, _) => Err(LinkError::NoCompatiblePads {
+                source_node: source.index(),
+                sink_node: sink.index(),
+            }),
+        }
+    }
+
+    /// Finish building the `Pipeline`. No more [`Node`]s or [`Edge`]s can be
+    /// added until it is [`shutdown`] and back in the [`Builder`] state.
+    ///
+    /// # Errors
+    /// - [`BuildError::Invalid`] listing every problem found by [`validate`].
+    ///   The `Pipeline` is handed back, so it can be fixed and built again.
+    ///
+    /// [`shutdown`]: Pipeline::shutdown
+    /// [`validate`]: Pipeline::validate
+    // Same shape as `init` and `run`, which hand the pipeline back too.
+    #[allow(clippy::result_large_err)]
+    pub fn build(
+        self,
+    ) -> Result<Pipeline<New>, (Pipeline<Builder>, BuildError)> {
+        match self.validate() {
+            Ok(()) => Ok(self.into_state()),
+            Err(err) => Err((self, err.into())),
+        }
+    }
+}
+
+impl Pipeline<New> {
+    /// Call [`Element::init`] on every [`Node`], in order.
+    ///
+    /// # Errors
+    /// - [`InitError::Element`] for the first [`Element`] that fails to
+    ///   initialize. Every [`Element`] is stopped again and the `Pipeline` is
+    ///   handed back in the [`Builder`] state, so it can be fixed and tried
+    ///   again.
+    pub async fn init(
+        mut self,
+    ) -> Result<Pipeline<Ready>, (Pipeline<Builder>, InitError)> {
+        let mut failed = None;
+        for (index, node) in self.graph.node_weights_mut().enumerate() {
+            let start = Instant::now();
+            // The error is not `Send` so it can't be held across an `.await`.
+            let result = node.element.init().await.map_err(|e| e.to_string());
+            let elapsed = start.elapsed();
+            self.tracers
+                .init(node.origin(index), elapsed, result.is_ok());
+            let Err(message) = result else {
+                continue;
+            };
+            failed = Some(InitError::Element {
+                node: index,
+                name: node.name.clone(),
+                message,
+            });
+            break;
+        }
+
+        if let Some(err) = failed {
+            let InitError::Element {
+                node: failed,
+                name,
+                message,
+            } = &err;
+            self.bus.post(bus::Message::Error {
+                node: *failed,
+                name: name.clone(),
+                message: message.clone(),
+            });
+            let node = &self.graph[petgraph::graph::NodeIndex::new(*failed)];
+            self.tracers.error(node.origin(*failed), message);
+            // Failures to stop are on the bus. The first error is the cause.
+            self.stop_all().await;
+            return Err((self.into_state(), err));
+        }
+
+        Ok(self.into_state())
+    }
+}
+
+impl Pipeline<Ready> {
+    /// Run the `Pipeline` until every [`Node`] has finished. Each [`Node`]
+    /// runs in its own tokio task.
+    ///
+    /// Scheduling works like this:
+    /// - A [`Buffer`] arriving for a sink pad is given to the [`Element`] with
+    ///   [`Push::push`]. Then every linked source pad of the [`Element`] is
+    ///   [`Pull`]ed once per [`Edge`] and the results are sent downstream.
+    ///   Pads for which [`Element::can_pull`] is `false` are skipped.
+    /// - A [`decoupled`] [`Element`], such as a [`Queue`], is instead pushed
+    ///   while it has room and pulled while downstream has room, so both
+    ///   sides run at their own pace.
+    /// - A [`Node`] with no linked sink pads is a source. Its linked source
+    ///   pads are [`Pull`]ed repeatedly until every downstream [`Node`] hangs
+    ///   up, none of them can be pulled, or an error occurs.
+    /// - An [`Event`] arriving for a sink pad is given to [`Element::event`]
+    ///   and whatever it returns is sent on every linked source pad.
+    ///   [`Element::take_event`] is checked after every [`Buffer`].
+    /// - A [`Node`] finishes when all of its upstream [`Node`]s have sent
+    ///   [`Event::Eos`] or finished, or when its [`Element`] sends
+    ///   [`Event::Eos`]. This in turn finishes its downstream [`Node`]s.
+    /// - [`Node`]s and [`Edge`]s can be added and removed meanwhile through
+    ///   the [`Control`]. Changes are applied between two [`Buffer`]s.
+    /// - [`Control::shutdown`] ends the run early, letting the [`Node`]s
+    ///   drain or cancelling them. See [`shutdown`].
+    ///
+    /// Failing [`Node`]s are reported on the [`Bus`] as they happen, and
+    /// [`bus::Message::Eos`] is posted once every [`Node`] has finished
+    /// cleanly.
+    ///
+    /// # Errors
+    /// On error every [`Element`] is stopped and the `Pipeline` is handed
+    /// back in the [`Builder`] state, with the error:
+    /// - [`RunError::Element`] for the first [`Element`] that failed. The
+    ///   other [`Node`]s are still run to completion.
+    /// - [`RunError::Panicked`] if a task panicked. The [`Node`] is lost, so
+    ///   the [`Node`]s after it move down one index.
+    ///
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`Push::push`]: crate::pad::Push::push
+    /// [`Pull`]: crate::pad::Pull
+    /// [`Event`]: crate::pad::Event
+    /// [`Event::Eos`]: crate::pad::Event::Eos
+    /// [`decoupled`]: Element::decoupled
+    /// [`Queue`]: crate::element::queue::Queue
+    pub async fn run(
+        self,
+    ) -> Result<Pipeline<Shutdown>, (Pipeline<Builder>, RunError)> {
+        let (graph, failed) =
+            executor::run(self.graph, &self.bus, &self.control, &self.tracers)
+                .await;
+        let mut pipeline = Pipeline {
+            graph,
+            bus: self.bus,
+            control: self.control,
+            tracers: self.tracers,
+        };
+
+        if let Some(err) = failed {
+            // Failures to stop are on the bus. The first error is the cause.
+            pipeline.stop_all().await;
+            return Err((pipeline.into_state(), err));
+        }
+        pipeline.bus.post(bus::Message::Eos);
+
+        Ok(pipeline.into_state())
+    }
+}
+
+impl Pipeline<Shutdown> {
+    /// Call [`Element::stop`] on every [`Node`] and return to the [`Builder`]
+    /// state so the `Pipeline` can be reconfigured and run again.
+    ///
+    /// # Errors
+    /// - [`ShutdownError::Element`] for the first [`Element`] that fails to
+    ///   stop. Every [`Element`] is still stopped.
+    pub async fn shutdown(
+        mut self,
+    ) -> Result<Pipeline<Builder>, ShutdownError> {
+        match self.stop_all().await {
+            Some(err) => Err(err),
+            None => Ok(self.into_state()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::{
+        buffer::{self, any::Typed, sink, source, ErrorStaticString},
+        element::{
+            queue::{self, Queue},
+            teleport::{self, TeleportSink, TeleportSource},
+        },
+        info::Info,
+        pad::{Caps, Event},
+    };
+
+    #[tokio::test]
+    async fn test_empty_pipeline_round_trip() {
+        let pipeline = Pipeline::new()
+            .build()
+            .unwrap()
+            .init()
+            .await
+            .unwrap()
+            .run()
+            .await
+            .unwrap()
+            .shutdown()
+            .await
+            .unwrap();
+
+        assert_eq!(pipeline.graph.node_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_links_move_every_caps() {
+        use crate::buffer::test;
+
+        for &caps in Caps::ALL {
+            let options = |channel: &str| teleport::Options {
+                channel: format!("test_links_move_every_caps_{caps}_{channel}"),
+                caps,
+                ..Default::default()
+            };
+
+            let mut pipeline = Pipeline::new();
+            let source = pipeline
+                .add("source", Box::new(TeleportSource::new(options("in"))));
+            let sink = pipeline
+                .add("sink", Box::new(TeleportSink::new(options("out"))));
+            pipeline.link(source, 0, sink, 0).unwrap();
+            let pipeline = pipeline.build().unwrap().init().await.unwrap();
+            let control = pipeline.control();
+            let running = tokio::spawn(pipeline.run());
+
+            let mut input = TeleportSink::new(options("in"));
+            let mut output = TeleportSource::new(options("out"));
+            let buffer = test::buffer(caps);
+            let expected = test::text(&*buffer);
+            input
+                .sinks_mut()
+                .next()
+                .unwrap()
+                .push(buffer)
+                .await
+                .unwrap();
+            let arrived =
+                output.sources_mut().next().unwrap().pull().await.unwrap();
+            assert_eq!(test::text(&*arrived), expected, "{caps}");
+
+            // Removing the source finishes the sink.
+            let removed =
+                when_running(|| control.remove(source)).await.unwrap();
+            assert_eq!(removed.name(), "Teleport Source");
+            running.await.unwrap().unwrap().shutdown().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_meta_through_links() {
+        use crate::buffer::meta::{Sequence, TraceId};
+
+        let (source, mut input) = teleport("test_meta_through_links_in");
+        let (mut output, sink) = teleport("test_meta_through_links_out");
+        let mut pipeline = Pipeline::new();
+        let source = pipeline.add("source", Box::new(source));
+        let queue = pipeline.add("queue", Box::new(queue()));
+        let sink = pipeline.add("sink", Box::new(sink));
+        pipeline.link(source, 0, queue, 0).unwrap();
+        pipeline.link(queue, 0, sink, 0).unwrap();
+        let pipeline = pipeline.build().unwrap().init().await.unwrap();
+        let control = pipeline.control();
+        let running = tokio::spawn(pipeline.run());
+
+        let mut meta = buffer::Meta::now();
+        meta.insert(TraceId(7));
+        meta.insert(Sequence(0));
+        let message =
+            buffer::test::TextMessage::new(buffer::message::Role::User, "Hi.");
+        let tagged = buffer::Tagged::with_meta(message, meta);
+        let mut pad = input.sinks_mut().next().unwrap();
+        pad.push(Box::new(tagged)).await.unwrap();
+        let mut pad = output.sources_mut().next().unwrap();
+        let arrived = within("the message", pad.pull()).await.unwrap();
+
+        assert_eq!(buffer::test::text(&*arrived), "Hi.");
+        let meta = arrived.meta().unwrap();
+        assert_eq!(meta.get::<TraceId>(), Some(&TraceId(7)));
+        assert_eq!(meta.get::<Sequence>(), Some(&Sequence(0)));
+
+        when_running(|| control.remove(source)).await.unwrap();
+        running.await.unwrap().unwrap().shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_teleport_between_pipelines() {
+        let (source, mut input) = teleport("test_teleport_between_in");
+        let (middle, output) = teleport("test_teleport_between_middle");
+        let mut upstream = Pipeline::new();
+        let source = upstream.add("source", Box::new(source));
+        let output = upstream.add("sink", Box::new(output));
+        upstream.link(source, 0, output, 0).unwrap();
+
+        let probe = Probe::default();
+        let log = probe.log.clone();
+        let mut downstream = Pipeline::new();
+        let middle = downstream.add("source", Box::new(middle));
+        let sink = downstream.add("sink", Box::new(probe));
+        downstream.link(middle, 0, sink, 0).unwrap();
+
+        let upstream = upstream.build().unwrap().init().await.unwrap();
+        let upstream = tokio::spawn(upstream.run());
+        let downstream = downstream.build().unwrap().init().await.unwrap();
+        let downstream = tokio::spawn(downstream.run());
+
+        say(&mut input, "Hi.").await;
+        arrived(&log, "Hi.").await;
+
+        // Dropping the first sink ends the upstream pipeline. Dropping that
+        // drops the second sink, which ends the downstream one.
+        drop(input);
+        let upstream = within("the upstream pipeline", upstream).await;
+        drop(upstream.unwrap().unwrap().shutdown().await.unwrap());
+        let downstream = within("the downstream pipeline", downstream).await;
+        downstream.unwrap().unwrap().shutdown().await.unwrap();
+
+        let eos = format!("{:?}", Event::Eos);
+        assert_eq!(*log.lock().unwrap(), ["Hi.".to_owned(), eos]);
+    }
+
+    #[tokio::test]
+    async fn test_bin_data_path() {
+        use crate::element::bin::Bin;
+
+        let mut inner = Pipeline::new();
+        let queue = inner.add("queue", Box::new(queue()));
+        let mut bin = Bin::new(inner);
+        bin.ghost_sink(queue, 0).unwrap();
+        bin.ghost_source(queue, 0).unwrap();
+
+        let (source, mut input) = teleport("test_bin_data_path_in");
+        let (mut output, sink) = teleport("test_bin_data_path_out");
+        let mut outer = Pipeline::new();
+        let source = outer.add("source", Box::new(source));
+        let bin = outer.add("bin", Box::new(bin));
+        let sink = outer.add("sink", Box::new(sink));
+        outer.link(source, 0, bin, 0).unwrap();
+        outer.link(bin, 0, sink, 0).unwrap();
+        let outer = outer.build().unwrap().init().await.unwrap();
+        let running = tokio::spawn(outer.run());
+
+        say(&mut input, "Hi.").await;
+        let mut pad = output.sources_mut().next().unwrap();
+        let arrived = within("the message", pad.pull()).await.unwrap();
+        assert_eq!(buffer::test::text(&*arrived), "Hi.");
+
+        // Ending the outer pipeline ends the inner one.
+        drop(input);
+        let outer = within("the outer pipeline", running).await;
+        outer.unwrap().unwrap().shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bin_forwards_events() {
+        use crate::element::bin::Bin;
+
+        let probe = Probe::default();
+        let log = probe.log.clone();
+        let mut inner = Pipeline::new();
+        let probe = inner.add("probe", Box::new(probe));
+        let mut bin = Bin::new(inner);
+        bin.ghost_sink(probe, 0).unwrap();
+
+        let mut outer = Pipeline::new();
+        let source = outer.add(
+            "source",
+            Box::new(Script::new([
+                Step::Say("1"),
+                Step::Send(Event::FlushStart),
+                Step::Say("2"),
+                Step::Send(Event::FlushStop),
+                Step::Say("3"),
+                Step::Send(Event::Cancel),
+                Step::Send(Event::Eos),
+            ])),
+        );
+        let bin = outer.add("bin", Box::new(bin));
+        outer.link(source, 0, bin, 0).unwrap();
+        let outer = outer.build().unwrap().init().await.unwrap();
+        within("the outer pipeline", outer.run()).await.unwrap();
+
+        // In order with the buffers, and what arrived while flushing was
+        // discarded.
+        let expected = [
+            "1".to_owned(),
+            format!("{:?}", Event::FlushStart),
+            format!("{:?}", Event::FlushStop),
+            "3".to_owned(),
+            format!("{:?}", Event::Cancel),
+            format!("{:?}", Event::Eos),
+        ];
+        assert_eq!(*log.lock().unwrap(), expected);
+    }
+
+    /// Wait for `future`, so a test waiting on `what` fails rather than
+    /// hangs if it never happens.
+    async fn within<T>(
+        what: &str,
+        future: impl std::future::Future<Output = T>,
+    ) -> T {
+        tokio::time::timeout(std::time::Duration::from_secs(10), future)
+            .await
+            .unwrap_or_else(|_| panic!("Timed out waiting for {what}."))
+    }
+
+    /// Retry a [`Control`] call until the [`Pipeline`] is running.
+    async fn when_running<T, F>(
+        mut call: impl FnMut() -> F,
+    ) -> Result<T, ControlError>
+    where
+        F: std::future::Future<Output = Result<T, ControlError>>,
+    {
+        within("the pipeline to run", async {
+            loop {
+                match call().await {
+                    Err(ControlError::NotRunning) => {
+                        tokio::task::yield_now().await
+                    }
+                    result => return result,
+                }
+            }
+        })
+        .await
+    }
+
+    /// What a [`Script`] yields next.
+    enum Step {
+        Say(&'static str),
+        /// Say it, after a while.
+        Late(&'static str),
+        Send(Event),
+        /// Panic when pulled.
+        Panic,
+    }
+
+    /// A [`Message`] source yielding its `steps` in order, then finishing.
+    ///
+    /// [`Message`]: buffer::Message
+    struct Script {
+        steps: std::collections::VecDeque<Step>,
+    }
+
+    impl Script {
+        fn new(steps: impl IntoIterator<Item = Step>) -> Self {
+            Self {
+                steps: steps.into_iter().collect(),
+            }
+        }
+
+        async fn next(&mut self) -> Result<Typed, Box<dyn buffer::Error>> {
+            let text = match self.steps.pop_front() {
+                Some(Step::Say(text)) => text,
+                Some(Step::Late(text)) => {
+                    tokio::time::sleep(std::time::Duration::from_millis(20))
+                        .await;
+                    text
+                }
+                Some(Step::Panic) => panic!("as scripted"),
+                _ => unreachable!("only pulled when it has something to say"),
+            };
+            Ok(Typed::Message(Box::new(buffer::test::TextMessage::new(
+                buffer::message::Role::Agent,
+                text,
+            ))))
+        }
+    }
+
+    crate::element::impl_pull_typed!(Script, next);
+
+    impl Info for Script {
+        fn name(&self) -> std::borrow::Cow<'_, str> {
+            "Script".into()
+        }
+
+        fn description(&self) -> std::borrow::Cow<'_, str> {
+            "Says its lines and sends its events.".into()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Element for Script {
+        fn backend(&self) -> crate::backends::Backend {
+            crate::backends::Backend::Independent
+        }
+
+        fn take_event(&mut self) -> Option<Event> {
+            match self.steps.front() {
+                Some(Step::Send(_)) => match self.steps.pop_front() {
+                    Some(Step::Send(event)) => Some(event),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+
+        fn can_pull(&self, _pad: usize) -> bool {
+            matches!(
+                self.steps.front(),
+                Some(Step::Say(_) | Step::Late(_) | Step::Panic)
+            )
+        }
+
+        async fn ready(&mut self) {
+            // Events are taken once awake.
+            if !matches!(self.steps.front(), Some(Step::Send(_))) {
+                std::future::pending().await
+            }
+        }
+
+        fn busy(&self) -> bool {
+            !self.steps.is_empty()
+        }
+
+        fn sources<'a>(
+            &'a self,
+        ) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+            Box::new(std::iter::once(source::Any::Message(self)))
+        }
+
+        fn sources_mut<'a>(
+            &'a mut self,
+        ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+            Box::new(std::iter::once(source::AnyMut::Message(self)))
+        }
+
+        fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+            Box::new(std::iter::empty())
+        }
+
+        fn sinks_mut<'a>(
+            &'a mut self,
+        ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+            Box::new(std::iter::empty())
+        }
+    }
+
+    /// A [`Message`] sink writing down what arrives, [`Event`]s included.
+    ///
+    /// [`Message`]: buffer::Message
+    #[derive(Default)]
+    struct Probe {
+        log: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl Probe {
+        async fn record(
+            &mut self,
+            buffer: Typed,
+        ) -> Result<(), Box<dyn buffer::Error>> {
+            let text = buffer::test::text(&*buffer.into_buffer());
+            self.log.lock().unwrap().push(text);
+            Ok(())
+        }
+    }
+
+    crate::element::impl_push_typed!(Probe, record);
+
+    impl Info for Probe {
+        fn name(&self) -> std::borrow::Cow<'_, str> {
+            "Probe".into()
+        }
+
+        fn description(&self) -> std::borrow::Cow<'_, str> {
+            "Writes down what arrives.".into()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Element for Probe {
+        fn backend(&self) -> crate::backends::Backend {
+            crate::backends::Backend::Independent
+        }
+
+        async fn event(
+            &mut self,
+            _pad: usize,
+            event: Event,
+        ) -> Result<Option<Event>, Box<dyn buffer::Error>> {
+            self.log.lock().unwrap().push(format!("{event:?}"));
+            Ok(Some(event))
+        }
+
+        fn sources<'a>(
+            &'a self,
+        ) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+            Box::new(std::iter::empty())
+        }
+
+        fn sources_mut<'a>(
+            &'a mut self,
+        ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+            Box::new(std::iter::empty())
+        }
+
+        fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+            Box::new(std::iter::once(sink::Any::Message(self)))
+        }
+
+        fn sinks_mut<'a>(
+            &'a mut self,
+        ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+            Box::new(std::iter::once(sink::AnyMut::Message(self)))
+        }
+    }
+
+    /// Wait until the [`Property`] `name` of `node` is `value` in the
+    /// running [`Pipeline`].
+    ///
+    /// [`Property`]: property::Property
+    async fn until(
+        control: &Control,
+        node: NodeIndex,
+        name: &str,
+        value: impl Into<property::Value>,
+    ) {
+        let value = value.into();
+        within(&format!("`{name}` to be {value:?}"), async {
+            loop {
+                match control.property(node, name).await {
+                    Ok(current) if current == value => return,
+                    Ok(_) | Err(ControlError::NotRunning) => {
+                        tokio::task::yield_now().await
+                    }
+                    Err(err) => panic!("{err}"),
+                }
+            }
+        })
+        .await
+    }
+
+    /// A [`Queue`] of [`Message`]s.
+    ///
+    /// [`Message`]: buffer::Message
+    fn queue() -> Queue {
+        Queue::new(queue::Options {
+            caps: Caps::Message,
+            ..Default::default()
+        })
+    }
+
+    /// A [`TeleportSource`] of [`Message`]s on `channel`, and the
+    /// [`TeleportSink`] sending to it.
+    ///
+    /// [`Message`]: buffer::Message
+    fn teleport(channel: &str) -> (TeleportSource, TeleportSink) {
+        let options = teleport::Options {
+            channel: channel.into(),
+            caps: Caps::Message,
+            ..Default::default()
+        };
+        (
+            TeleportSource::new(options.clone()),
+            TeleportSink::new(options),
+        )
+    }
+
+    /// Send `text` with the `input` of a [`teleport`].
+    async fn say(input: &mut TeleportSink, text: &str) {
+        let message =
+            buffer::test::TextMessage::new(buffer::message::Role::User, text);
+        input
+            .sinks_mut()
+            .next()
+            .unwrap()
+            .push(Box::new(message))
+            .await
+            .unwrap();
+    }
+
+    /// Wait until `text` is in the `log` of a [`Probe`].
+    async fn arrived(log: &std::sync::Mutex<Vec<String>>, text: &str) {
+        within(&format!("{text:?} to arrive"), async {
+            while !log.lock().unwrap().iter().any(|line| line == text) {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+    }
+
+    /// A [`teleport`] source, a "queue" and a [`Probe`], in a line.
+    struct Chain {
+        pipeline: Pipeline<Builder>,
+        source: NodeIndex,
+        queue: NodeIndex,
+        input: TeleportSink,
+        log: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl Chain {
+        fn new(channel: &str) -> Self {
+            let (source, input) = teleport(channel);
+            let probe = Probe::default();
+            let log = probe.log.clone();
+            let mut pipeline = Pipeline::new();
+            let source = pipeline.add("source", Box::new(source));
+            let queue = pipeline.add("queue", Box::new(queue()));
+            let sink = pipeline.add("sink", Box::new(probe));
+            pipeline.link(source, 0, queue, 0).unwrap();
+            pipeline.link(queue, 0, sink, 0).unwrap();
+            Self {
+                pipeline,
+                source,
+                queue,
+                input,
+                log,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let probe = Probe::default();
+        let log = probe.log.clone();
+        let mut pipeline = Pipeline::new();
+        let source = pipeline.add(
+            "source",
+            Box::new(Script::new([
+                Step::Say("1"),
+                Step::Send(Event::FlushStart),
+                Step::Say("2"),
+                Step::Send(Event::FlushStop),
+                Step::Say("3"),
+                Step::Send(Event::custom("mark", serde_json::json!(3))),
+                Step::Send(Event::Eos),
+            ])),
+        );
+        let sink = pipeline.add("sink", Box::new(probe));
+        pipeline.link(source, 0, sink, 0).unwrap();
+        pipeline
+            .build()
+            .unwrap()
+            .init()
+            .await
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        // What arrived while flushing was discarded. The rest is in order.
+        let expected = [
+            "1".to_owned(),
+            format!("{:?}", Event::FlushStart),
+            format!("{:?}", Event::FlushStop),
+            "3".to_owned(),
+            format!("{:?}", Event::custom("mark", serde_json::json!(3))),
+            format!("{:?}", Event::Eos),
+        ];
+        assert_eq!(*log.lock().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_panic_loses_node() {
+        let mut pipeline = Pipeline::new();
+        let source = pipeline.add(
+            "source",
+            Box::new(Script::new([Step::Say("1"), Step::Panic])),
+        );
+        let sink = pipeline.add("sink", Box::new(Probe::default()));
+        pipeline.link(source, 0, sink, 0).unwrap();
+        let pipeline = pipeline.build().unwrap().init().await.unwrap();
+
+        let Err((pipeline, err)) = within("the panic", pipeline.run()).await
+        else {
+            panic!("expected a panic");
+        };
+        assert!(matches!(
+            err,
+            RunError::Panicked { node: 0, ref name } if name == "source"
+        ));
+        // The sink moved down in its place.
+        assert_eq!(pipeline.find("source"), None);
+        assert_eq!(pipeline.find("sink"), Some(source));
+    }
+
+    #[tokio::test]
+    async fn test_eos_with_removed_upstream() {
+        let (lasts, mut input) = teleport("test_eos_with_removed_upstream");
+        let probe = Probe::default();
+        let log = probe.log.clone();
+        let mut pipeline = Pipeline::new();
+        let sink = pipeline.add("sink", Box::new(probe));
+        let ends = pipeline.add(
+            "ends",
+            Box::new(Script::new([Step::Say("1"), Step::Send(Event::Eos)])),
+        );
+        let removed = pipeline
+            .add("removed", Box::new(Script::new([Step::Send(Event::Eos)])));
+        let lasts = pipeline.add("lasts", Box::new(lasts));
+        pipeline.link(ends, 0, sink, 0).unwrap();
+        let removed = pipeline.link(removed, 0, sink, 0).unwrap();
+        let lasts = pipeline.link(lasts, 0, sink, 0).unwrap();
+        let pipeline = pipeline.build().unwrap().init().await.unwrap();
+        let control = pipeline.control();
+        let running = tokio::spawn(pipeline.run());
+
+        // An ended upstream that is removed no longer counts as ended, so
+        // the sink waits for the one left.
+        when_running(|| control.unlink(removed)).await.unwrap();
+        say(&mut input, "2").await;
+        arrived(&log, "2").await;
+
+        // Removing the last one that has not ended ends the sink.
+        control.unlink(lasts).await.unwrap();
+        running.await.unwrap().unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["1".to_owned(), "2".to_owned(), format!("{:?}", Event::Eos)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_leaks() {
+        let lines = ["1", "2", "3", "4", "5"];
+        for leak in [queue::Leak::Block, queue::Leak::DropNewest] {
+            let probe = Probe::default();
+            let log = probe.log.clone();
+            let mut pipeline = Pipeline::new();
+            let steps = lines.map(Step::Say).into_iter();
+            let source = pipeline.add(
+                "source",
+                Box::new(Script::new(steps.chain([Step::Send(Event::Eos)]))),
+            );
+            let queue = pipeline.add(
+                "queue",
+                Box::new(Queue::new(queue::Options {
+                    max_buffers: std::num::NonZeroUsize::new(1),
+                    leak,
+                    ..Default::default()
+                })),
+            );
+            let sink = pipeline.add("sink", Box::new(probe));
+            pipeline.link(source, 0, queue, 0).unwrap();
+            pipeline.link(queue, 0, sink, 0).unwrap();
+            let pipeline = within(
+                "the lines to arrive",
+                pipeline.build().unwrap().init().await.unwrap().run(),
+            )
+            .await
+            .unwrap();
+
+            // Blocking holds the source back, so every line arrives.
+            // Otherwise those that arrived and those dropped add up.
+            let dropped = match pipeline.property(queue, "dropped").unwrap() {
+                property::Value::Int(dropped) => dropped as usize,
+                value => panic!("{value:?}"),
+            };
+            let log = log.lock().unwrap();
+            let (eos, arrived) = log.split_last().unwrap();
+            assert_eq!(*eos, format!("{:?}", Event::Eos));
+            assert!(arrived.iter().is_sorted(), "{leak:?}: {arrived:?}");
+            assert_eq!(arrived.len() + dropped, lines.len(), "{leak:?}");
+            if leak == queue::Leak::Block {
+                assert_eq!(arrived, lines);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commands_wait_for_pulls() {
+        let probe = Probe::default();
+        let log = probe.log.clone();
+        let mut pipeline = Pipeline::new();
+        let source = pipeline.add(
+            "source",
+            Box::new(Script::new([
+                Step::Late("1"),
+                Step::Late("2"),
+                Step::Send(Event::Eos),
+            ])),
+        );
+        let sink = pipeline.add("sink", Box::new(probe));
+        pipeline.link(source, 0, sink, 0).unwrap();
+        let pipeline = pipeline.build().unwrap().init().await.unwrap();
+        let control = pipeline.control();
+        let running = tokio::spawn(pipeline.run());
+
+        // Reading a property while a line is on its way does not lose it.
+        let eos = format!("{:?}", Event::Eos);
+        within("EOS to arrive", async {
+            while !log.lock().unwrap().contains(&eos) {
+                control.property(source, "lines").await.ok();
+                tokio::task::yield_now().await;
+            }
+        })
+        .await;
+        running.await.unwrap().unwrap();
+        assert_eq!(*log.lock().unwrap(), ["1".to_owned(), "2".to_owned(), eos]);
+    }
+
+    #[tokio::test]
+    async fn test_tracers() {
+        let Chain {
+            mut pipeline,
+            source,
+            mut input,
+            log,
+            ..
+        } = Chain::new("test_tracers");
+        let latency = Arc::new(trace::Latency::new());
+        pipeline.add_tracer(latency.clone());
+        let metrics = Arc::new(Metrics::new());
+        pipeline.add_tracer(metrics.clone());
+
+        let pipeline = pipeline.build().unwrap().init().await.unwrap();
+        let control = pipeline.control();
+        let running = tokio::spawn(pipeline.run());
+        say(&mut input, "Hi").await;
+        arrived(&log, "Hi").await;
+        // Stop it by removing the source.
+        let removed = when_running(|| control.remove(source)).await.unwrap();
+        assert_eq!(removed.name(), "Teleport Source");
+        let pipeline = running.await.unwrap().unwrap();
+        pipeline.shutdown().await.unwrap();
+        assert_eq!(*log.lock().unwrap(), ["Hi"]);
+
+        let stats = latency.stats();
+        assert!(stats["source"].init.is_some());
+        assert!(stats["queue"].init.is_some());
+        // Only the queue and the sink were left to stop.
+        assert!(stats["source"].stop.is_none());
+        assert!(stats["queue"].stop.is_some());
+        assert!(stats["sink"].stop.is_some());
+        assert_eq!(stats["queue"].push.count, 1);
+        assert_eq!(stats["sink"].push.count, 1);
+        // The queue passed it on.
+        let rendered = metrics.render();
+        let pushed = r#"tstreamer_property{node="queue",backend="Independent",property="pushed"} 1"#;
+        assert!(rendered.contains(pushed), "{rendered}");
+        let depth = r#"tstreamer_property{node="queue",backend="Independent",property="buffers"} 0"#;
+        assert!(rendered.contains(depth), "{rendered}");
+    }
+
+    #[tokio::test]
+    async fn test_control() {
+        use crate::element::tee;
+
+        let (source, mut input) = teleport("test_control");
+        let mut pipeline = Pipeline::new();
+        let source = pipeline.add("source", Box::new(source));
+        let tee = pipeline.add(
+            "tee",
+            Box::new(tee::Tee::new(tee::Options {
+                caps: Caps::Message,
+                pads: 1,
+                ..Default::default()
+            })),
+        );
+        pipeline.link(source, 0, tee, 0).unwrap();
+        let pipeline = pipeline.build().unwrap().init().await.unwrap();
+
+        let control = pipeline.control();
+        assert!(matches!(
+            control.unlink(EdgeIndex::new(0)).await,
+            Err(ControlError::NotRunning)
+        ));
+
+        let running = tokio::spawn(pipeline.run());
+        let first = when_running(|| control.add("first", Box::new(queue())))
+            .await
+            .unwrap();
+        assert!(matches!(
+            control.add("first", Box::new(queue())).await,
+            Err(ControlError::Build(BuildError::DuplicateName { .. }))
+        ));
+        let second = control.add("second", Box::new(queue())).await.unwrap();
+
+        let edge = control.link(tee, 0, first, 0).await.unwrap();
+        assert!(matches!(
+            control.link(first, 0, source, 0).await,
+            Err(ControlError::Link(LinkError::NoSuchSinkPad { .. }))
+        ));
+
+        control.block(tee, 0).await.unwrap();
+        assert!(matches!(
+            control.block(tee, 1).await,
+            Err(ControlError::Link(LinkError::NoSuchSourcePad { .. }))
+        ));
+        let moved = control.relink(edge, second, 0).await.unwrap();
+        control.unblock(tee, 0).await.unwrap();
+        assert!(matches!(
+            control.unlink(edge).await,
+            Err(ControlError::NoSuchEdge { .. })
+        ));
+        assert_ne!(moved, edge);
+
+        // What is sent now goes to `second` only.
+        say(&mut input, "Hi").await;
+        until(&control, second, "pushed", 1).await;
+        assert_eq!(
+            control.property(first, "pushed").await.unwrap(),
+            property::Value::Int(0)
+        );
+        let dot = control.to_dot().await.unwrap();
+        assert!(dot.contains("second\\nQueue"));
+        assert!(dot.contains("1 buffers"), "{dot}");
+
+        // `first` lost its only upstream edge, so it finishes.
+        control.remove(first).await.unwrap();
+        // Removing the source finishes everything downstream.
+        control.remove(source).await.unwrap();
+
+        let pipeline = running.await.unwrap().unwrap();
+        assert_eq!(pipeline.graph.node_count(), 2);
+        assert_eq!(pipeline.graph.edge_count(), 1);
+        assert_eq!(pipeline.find("tee"), Some(NodeIndex::new(0)));
+        let second = pipeline.find("second").unwrap();
+        assert_eq!(second, NodeIndex::new(1));
+        assert_eq!(
+            pipeline.property(second, "buffers").unwrap(),
+            property::Value::Int(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_properties() {
+        use futures::StreamExt;
+
+        use crate::element::property::PropertyError;
+
+        let Chain {
+            mut pipeline,
+            source,
+            queue,
+            mut input,
+            log,
+        } = Chain::new("test_properties");
+        let mut messages = Box::pin(pipeline.bus().subscribe());
+
+        assert_eq!(pipeline.properties(source).unwrap().len(), 0);
+        assert_eq!(
+            pipeline.set_property(queue, "max_buffers", 8).unwrap(),
+            property::Value::Int(8)
+        );
+        assert_eq!(
+            messages.next().await,
+            Some(bus::Message::PropertyChanged {
+                node: 1,
+                name: "queue".into(),
+                property: "max_buffers".into(),
+                value: property::Value::Int(8),
+            })
+        );
+
+        let pipeline = pipeline.build().unwrap().init().await.unwrap();
+        let control = pipeline.control();
+        let running = tokio::spawn(pipeline.run());
+        let value = when_running(|| control.property(queue, "max_buffers"))
+            .await
+            .unwrap();
+        assert_eq!(value, property::Value::Int(8));
+
+        assert_eq!(
+            control
+                .set_property(queue, "leak", "DropNewest")
+                .await
+                .unwrap(),
+            property::Value::String("DropNewest".into())
+        );
+        assert!(matches!(
+            control.set_property(queue, "leak", 1).await,
+            Err(ControlError::Property(PropertyError::Type { .. }))
+        ));
+        assert!(matches!(
+            control.property(NodeIndex::new(7), "leak").await,
+            Err(ControlError::Link(LinkError::NoSuchNode { node: 7 }))
+        ));
+        let changed = within("a property change", async {
+            loop {
+                match messages.next().await.unwrap() {
+                    bus::Message::PropertyChanged {
+                        property, value, ..
+                    } => break (property, value),
+                    _ => continue,
+                }
+            }
+        })
+        .await;
+        assert_eq!(changed.0, "leak");
+
+        // The changed queue still passes buffers on.
+        say(&mut input, "Hi").await;
+        arrived(&log, "Hi").await;
+
+        control.remove(source).await.unwrap();
+        let pipeline = running.await.unwrap().unwrap();
+        let queue = pipeline.find("queue").unwrap();
+        assert_eq!(
+            pipeline.property(queue, "leak").unwrap(),
+            property::Value::String("DropNewest".into())
+        );
+        assert_eq!(
+            pipeline.property(queue, "pushed").unwrap(),
+            property::Value::Int(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        use std::time::Duration;
+
+        use shutdown::{Mode, Outcome};
+
+        /// A [`Queue`] that never finishes by itself.
+        struct Stuck(Queue);
+
+        impl Info for Stuck {
+            fn name(&self) -> std::borrow::Cow<'_, str> {
+                "Stuck".into()
+            }
+
+            fn description(&self) -> std::borrow::Cow<'_, str> {
+                "Never finishes.".into()
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl Element for Stuck {
+            fn backend(&self) -> crate::backends::Backend {
+                self.0.backend()
+            }
+
+            fn decoupled(&self) -> bool {
+                true
+            }
+
+            fn busy(&self) -> bool {
+                true
+            }
+
+            fn sources<'a>(
+                &'a self,
+            ) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+                self.0.sources()
+            }
+
+            fn sources_mut<'a>(
+                &'a mut self,
+            ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+                self.0.sources_mut()
+            }
+
+            fn sinks<'a>(
+                &'a self,
+            ) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+                self.0.sinks()
+            }
+
+            fn sinks_mut<'a>(
+                &'a mut self,
+            ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+                self.0.sinks_mut()
+            }
+        }
+
+        let run = |channel: &str, sink: Box<dyn Element>| {
+            let (source, input) = teleport(channel);
+            let mut pipeline = Pipeline::new();
+            let source = pipeline.add("source", Box::new(source));
+            let sink = pipeline.add("sink", sink);
+            pipeline.link(source, 0, sink, 0).unwrap();
+            let control = pipeline.control();
+            let running = tokio::spawn(async move {
+                pipeline.build().unwrap().init().await.unwrap().run().await
+            });
+            (control, running, input, sink)
+        };
+        let shutdown = |control: Control, mode, deadline| async move {
+            when_running(|| control.shutdown(mode, deadline))
+                .await
+                .unwrap()
+        };
+
+        // Draining stops the source, then the queue finishes with what it
+        // was sent.
+        let (control, running, mut input, sink) =
+            run("test_shutdown_drain", Box::new(queue()));
+        say(&mut input, "Hi").await;
+        until(&control, sink, "pushed", 1).await;
+        let report =
+            shutdown(control, Mode::Drain, Duration::from_secs(10)).await;
+        assert!(report.is_clean());
+        assert_eq!(report.mode, Mode::Drain);
+        assert_eq!(report.names(Outcome::Clean).count(), 2);
+        let pipeline = running.await.unwrap().unwrap();
+        assert_eq!(
+            pipeline.property(sink, "buffers").unwrap(),
+            property::Value::Int(1)
+        );
+        pipeline.shutdown().await.unwrap();
+
+        // The stuck sink hits the deadline and is cancelled.
+        let (control, running, ..) =
+            run("test_shutdown_deadline", Box::new(Stuck(queue())));
+        let report =
+            shutdown(control, Mode::Drain, Duration::from_millis(10)).await;
+        assert!(!report.is_clean());
+        assert_eq!(
+            report.names(Outcome::Clean).collect::<Vec<_>>(),
+            ["source"]
+        );
+        assert_eq!(
+            report.names(Outcome::Cancelled).collect::<Vec<_>>(),
+            ["sink"]
+        );
+        // The cancelled element is handed back and can be stopped.
+        let pipeline = running.await.unwrap().unwrap();
+        assert_eq!(pipeline.graph.node_count(), 2);
+        pipeline.shutdown().await.unwrap();
+
+        // Aborting cuts a drain short. Nothing can be added meanwhile.
+        let (control, running, ..) =
+            run("test_shutdown_abort", Box::new(Stuck(queue())));
+        let draining = tokio::spawn(shutdown(
+            control.clone(),
+            Mode::Drain,
+            Duration::from_secs(60),
+        ));
+        within("the pipeline to shut down", async {
+            for i in 0.. {
+                let added = control.add(format!("added{i}"), Box::new(queue()));
+                match added.await {
+                    Err(ControlError::ShuttingDown) => break,
+                    _ => tokio::task::yield_now().await,
+                }
+            }
+        })
+        .await;
+        let report =
+            shutdown(control, Mode::Abort, Duration::from_secs(60)).await;
+        assert_eq!(report.mode, Mode::Abort);
+        assert!(report.elapsed < Duration::from_secs(60));
+        assert_eq!(
+            report.names(Outcome::Cancelled).collect::<Vec<_>>(),
+            ["sink"]
+        );
+        assert_eq!(draining.await.unwrap(), report);
+        running.await.unwrap().unwrap();
+    }
+
+    /// A [`Message`] source failing `fails` times, then saying "Recovered."
+    /// and waiting forever. Counts its `inits`, and fails to init if
+    /// `broken`.
+    ///
+    /// [`Message`]: buffer::Message
+    struct Flaky {
+        fails: usize,
+        said: bool,
+        inits: Arc<AtomicUsize>,
+        broken: bool,
+    }
+
+    impl Flaky {
+        fn new(fails: usize) -> Self {
+            Self {
+                fails,
+                said: false,
+                inits: Arc::default(),
+                broken: false,
+            }
+        }
+
+        async fn next(&mut self) -> Result<Typed, Box<dyn buffer::Error>> {
+            if self.fails > 0 {
+                self.fails -= 1;
+                return Err(ErrorStaticString::from("Overloaded.").into());
+            }
+            self.said = true;
+            Ok(Typed::Message(Box::new(buffer::test::TextMessage::new(
+                buffer::message::Role::Agent,
+                "Recovered.",
+            ))))
+        }
+    }
+
+    crate::element::impl_pull_typed!(Flaky, next);
+
+    impl Info for Flaky {
+        fn name(&self) -> std::borrow::Cow<'_, str> {
+            "Flaky".into()
+        }
+
+        fn description(&self) -> std::borrow::Cow<'_, str> {
+            "Fails a few times, then recovers.".into()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Element for Flaky {
+        async fn init(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+            self.inits.fetch_add(1, Ordering::Relaxed);
+            if self.broken {
+                return Err("Broken.".into());
+            }
+            Ok(())
+        }
+
+        fn backend(&self) -> crate::backends::Backend {
+            crate::backends::Backend::Independent
+        }
+
+        fn can_pull(&self, _pad: usize) -> bool {
+            self.fails > 0 || !self.said
+        }
+
+        fn busy(&self) -> bool {
+            // Then waits forever.
+            true
+        }
+
+        fn sources<'a>(
+            &'a self,
+        ) -> Box<dyn Iterator<Item = source::Any<'a>> + 'a> {
+            Box::new(std::iter::once(source::Any::Message(self)))
+        }
+
+        fn sources_mut<'a>(
+            &'a mut self,
+        ) -> Box<dyn Iterator<Item = source::AnyMut<'a>> + 'a> {
+            Box::new(std::iter::once(source::AnyMut::Message(self)))
+        }
+
+        fn sinks<'a>(&'a self) -> Box<dyn Iterator<Item = sink::Any<'a>> + 'a> {
+            Box::new(std::iter::empty())
+        }
+
+        fn sinks_mut<'a>(
+            &'a mut self,
+        ) -> Box<dyn Iterator<Item = sink::AnyMut<'a>> + 'a> {
+            Box::new(std::iter::empty())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restart() {
+        use futures::StreamExt;
+
+        use supervisor::{Backoff, Policy};
+
+        let run = |fails, policy| {
+            let source = Flaky::new(fails);
+            let inits = source.inits.clone();
+            let mut pipeline = Pipeline::new();
+            let source = pipeline.add("source", Box::new(source));
+            let queue = pipeline.add("queue", Box::new(queue()));
+            pipeline.link(source, 0, queue, 0).unwrap();
+            pipeline.set_restart(source, policy).unwrap();
+            let restarts =
+                pipeline.bus().subscribe().filter_map(|m| async move {
+                    match m {
+                        bus::Message::Restarting { attempt, .. } => {
+                            Some(attempt)
+                        }
+                        _ => None,
+                    }
+                });
+            let control = pipeline.control();
+            let running = tokio::spawn(async move {
+                pipeline.build().unwrap().init().await.unwrap().run().await
+            });
+            (control, running, Box::pin(restarts), inits)
+        };
+        let backoff = Backoff {
+            initial_ms: 1,
+            ..Default::default()
+        };
+
+        // Restarted twice, then it recovers with its link still in place.
+        let policy = Policy::on_failure(3).with_backoff(backoff);
+        let (control, running, mut restarts, inits) = run(2, policy);
+        let attempts: Vec<_> =
+            within("two restarts", restarts.by_ref().take(2).collect()).await;
+        assert_eq!(attempts, [1, 2]);
+        until(&control, NodeIndex::new(1), "pushed", 1).await;
+        // Answered once it runs again.
+        assert!(matches!(
+            control.property(NodeIndex::new(0), "missing").await,
+            Err(ControlError::Property(_))
+        ));
+        let report = control
+            .shutdown(shutdown::Mode::Drain, std::time::Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert!(report.is_clean());
+        assert_eq!(inits.load(Ordering::Relaxed), 3);
+        let pipeline = running.await.unwrap().unwrap();
+        assert_eq!(pipeline.graph.edge_count(), 1);
+        assert_eq!(pipeline.graph[NodeIndex::new(0)].restart, policy);
+        assert_eq!(
+            pipeline.property(NodeIndex::new(1), "buffers").unwrap(),
+            property::Value::Int(1)
+        );
+
+        // It gives up after one restart.
+        let policy = Policy::on_failure(1).with_backoff(backoff);
+        let (_, running, mut restarts, inits) = run(3, policy);
+        let Err((pipeline, RunError::Element { node: 0, .. })) =
+            running.await.unwrap()
+        else {
+            panic!("the source should fail");
+        };
+        assert_eq!(restarts.next().await, Some(1));
+        assert_eq!(inits.load(Ordering::Relaxed), 2);
+        // Handed back with its links, ready to be fixed and run again.
+        assert_eq!(pipeline.graph.node_count(), 2);
+        assert_eq!(pipeline.graph.edge_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_errors_stop_every_element() {
+        let build = |broken, fails| {
+            let mut pipeline = Pipeline::new();
+            let queue = pipeline.add("queue", Box::new(queue()));
+            let source = pipeline.add(
+                "source",
+                Box::new(Flaky {
+                    broken,
+                    ..Flaky::new(fails)
+                }),
+            );
+            pipeline.link(source, 0, queue, 0).unwrap();
+            let latency = Arc::new(trace::Latency::new());
+            pipeline.add_tracer(latency.clone());
+            (pipeline.build().unwrap(), latency)
+        };
+
+        // The queue was initialized before the source failed to.
+        let (pipeline, latency) = build(true, 0);
+        let (pipeline, err) = pipeline.init().await.unwrap_err();
+        assert!(matches!(err, InitError::Element { node: 1, .. }));
+        assert!(latency.stats()["queue"].stop.is_some());
+        assert_eq!(pipeline.graph.edge_count(), 1);
+
+        // The queue was running when the source failed.
+        let (pipeline, latency) = build(false, 1);
+        let pipeline = pipeline.init().await.unwrap();
+        let (pipeline, err) = pipeline.run().await.unwrap_err();
+        assert!(matches!(err, RunError::Element { node: 1, .. }));
+        assert!(latency.stats()["queue"].stop.is_some());
+        assert!(latency.stats()["source"].stop.is_some());
+        assert_eq!(pipeline.graph.edge_count(), 1);
+        // And it can run again.
+        pipeline.build().unwrap().init().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_error_pad() {
+        use crate::element::error_sink;
+
+        let mut pipeline = Pipeline::new();
+        let source = pipeline.add("source", Box::new(Flaky::new(2)));
+        let queue = pipeline.add("queue", Box::new(queue()));
+        let errors = pipeline.add(
+            "errors",
+            Box::new(
+                error_sink::ErrorSink::new(
+                    error_sink::Options {
+                        action: error_sink::Action::Count,
+                        ..Default::default()
+                    },
+                    crate::backends::Backend::Independent,
+                )
+                .unwrap(),
+            ),
+        );
+        pipeline.link(source, 0, queue, 0).unwrap();
+        assert_eq!(pipeline.error_pad(source), Some(1));
+        assert!(matches!(
+            pipeline.link(source, 1, queue, 0),
+            Err(LinkError::Incompatible {
+                source_caps: Caps::Error,
+                ..
+            })
+        ));
+        // The only pad an `ErrorSink` accepts.
+        let edge = pipeline.autolink(source, None, errors, None).unwrap();
+        assert_eq!(pipeline.graph[edge].source, 1);
+
+        let control = pipeline.control();
+        let running = tokio::spawn(async move {
+            pipeline.build().unwrap().init().await.unwrap().run().await
+        });
+        // Both errors end up in the sink and the source carries on.
+        until(&control, errors, "errors", 2).await;
+        until(&control, queue, "pushed", 1).await;
+        assert_eq!(
+            control.property(errors, "last").await.unwrap(),
+            property::Value::String("Overloaded.".into())
+        );
+        let report = control
+            .shutdown(shutdown::Mode::Drain, std::time::Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert!(report.is_clean());
+        running.await.unwrap().unwrap();
+    }
+
+    /// Register [`Flaky`] as `TestFlaky`, once for every test.
+    fn register_flaky() {
+        use crate::element::{any::Kind, registry};
+
+        static REGISTER: std::sync::Once = std::sync::Once::new();
+        REGISTER.call_once(|| {
+            registry::register(
+                Kind::from("TestFlaky"),
+                &[crate::backends::Backend::Independent],
+                schemars::json_schema!({ "type": "object" }),
+                |_, options| {
+                    let fails = options["fails"].as_u64().unwrap_or(0);
+                    Ok(Box::new(Flaky::new(fails as usize)))
+                },
+            )
+            .unwrap()
+        });
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint() {
+        register_flaky();
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "nodes": [
+                {
+                    "name": "source",
+                    "element": "TestFlaky",
+                    "backend": "Independent",
+                    "config": { "fails": 2 }
+                },
+                {
+                    "name": "queue",
+                    "element": "Queue",
+                    "backend": "Independent",
+                    "config": { "caps": "Message" }
+                },
+                {
+                    "name": "errors",
+                    "element": "ErrorSink",
+                    "backend": "Independent",
+                    "config": { "action": "Count" }
+                }
+            ],
+            "edges": [
+                { "source": "source", "sink": "queue" },
+                { "source": "source", "sink": "errors" }
+            ]
+        }))
+        .unwrap();
+
+        let pipeline = Pipeline::from_config(&config).unwrap();
+        let errors = pipeline.find("errors").unwrap();
+        let queue = pipeline.find("queue").unwrap();
+        let control = pipeline.control();
+        let running = tokio::spawn(async move {
+            pipeline.build().unwrap().init().await.unwrap().run().await
+        });
+        until(&control, errors, "errors", 2).await;
+        control.set_property(queue, "max_buffers", 8).await.unwrap();
+        let checkpoint = control.checkpoint().await.unwrap();
+        assert_eq!(checkpoint.config.nodes.len(), 3);
+        assert_eq!(checkpoint.config.edges[1].source_pad, Some(1));
+        assert_eq!(checkpoint.state.keys().collect::<Vec<_>>(), ["errors"]);
+        control
+            .shutdown(shutdown::Mode::Abort, std::time::Duration::ZERO)
+            .await
+            .unwrap();
+        running.await.unwrap().unwrap();
+
+        // As if the process had restarted.
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        let mut checkpoint: Checkpoint = serde_json::from_str(&json).unwrap();
+        let pipeline = Pipeline::restore(&checkpoint).await.unwrap();
+        assert_eq!(
+            pipeline.property(errors, "errors").unwrap(),
+            property::Value::Int(2)
+        );
+        assert_eq!(
+            pipeline.property(errors, "last").unwrap(),
+            property::Value::String("Overloaded.".into())
+        );
+        assert_eq!(pipeline.checkpoint().unwrap().state, checkpoint.state);
+        // Properties set while running are kept as options.
+        assert_eq!(
+            pipeline.property(queue, "max_buffers").unwrap(),
+            property::Value::Int(8)
+        );
+
+        checkpoint
+            .state
+            .insert("queue".into(), serde_json::json!({}));
+        assert!(matches!(
+            Pipeline::restore(&checkpoint).await,
+            Err(CheckpointError::Restore { .. })
+        ));
+        checkpoint.state.remove("queue");
+        checkpoint
+            .state
+            .insert("missing".into(), serde_json::json!({}));
+        assert!(matches!(
+            Pipeline::restore(&checkpoint).await,
+            Err(CheckpointError::Build(BuildError::UnknownNode { .. }))
+        ));
+
+        let mut pipeline = Pipeline::new();
+        pipeline.add("source", Box::new(Flaky::new(0)));
+        assert!(matches!(
+            pipeline.checkpoint(),
+            Err(CheckpointError::NoConfig { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        use futures::StreamExt;
+
+        register_flaky();
+        let config = |text: &str, restart: serde_json::Value| {
+            serde_json::json!({
+                "nodes": [
+                    {
+                        "name": "source",
+                        "element": "TestFlaky",
+                        "backend": "Independent",
+                        "config": { "fails": 2 }
+                    },
+                    {
+                        "name": "queue",
+                        "element": "Queue",
+                        "backend": "Independent",
+                        "config": { "caps": "Message" },
+                        "restart": restart
+                    },
+                    {
+                        "name": "errors",
+                        "element": "ErrorSink",
+                        "backend": "Independent",
+                        "config": { "action": "Count", "text": text }
+                    }
+                ],
+                "edges": [
+                    { "source": "source", "sink": "queue" },
+                    { "source": "source", "sink": "errors" }
+                ]
+            })
+        };
+        let never = serde_json::json!({ "policy": "never" });
+        let path = std::env::temp_dir()
+            .join(format!("tstreamer-reload-{}.json", std::process::id()));
+        std::fs::write(&path, config("Sorry.", never.clone()).to_string())
+            .unwrap();
+
+        let pipeline = Pipeline::from_config(
+            &serde_json::from_str(&std::fs::read_to_string(&path).unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        let errors = pipeline.find("errors").unwrap();
+        let control = pipeline.control();
+        let mut messages = Box::pin(pipeline.bus().subscribe());
+        let watcher = pipeline
+            .watch(&path, std::time::Duration::from_millis(10))
+            .unwrap();
+        let running = tokio::spawn(async move {
+            pipeline.build().unwrap().init().await.unwrap().run().await
+        });
+        until(&control, errors, "errors", 2).await;
+
+        // Only a property changes, so the count is kept.
+        std::fs::write(&path, config("Oops.", never.clone()).to_string())
+            .unwrap();
+        let changes = within("a reload", async {
+            loop {
+                match messages.next().await.unwrap() {
+                    bus::Message::Reloaded { changes } => break changes,
+                    bus::Message::ReloadFailed { message } => {
+                        panic!("{message}")
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .await;
+        assert_eq!(changes.updated, ["errors"]);
+        assert!(changes.replaced.is_empty() && changes.linked.is_empty());
+        assert_eq!(
+            control.property(errors, "text").await.unwrap(),
+            property::Value::String("Oops.".into())
+        );
+        assert_eq!(
+            control.property(errors, "errors").await.unwrap(),
+            property::Value::Int(2)
+        );
+
+        std::fs::write(&path, "{").unwrap();
+        within("a failed reload", async {
+            loop {
+                if let bus::Message::ReloadFailed { .. } =
+                    messages.next().await.unwrap()
+                {
+                    break;
+                }
+            }
+        })
+        .await;
+        drop(watcher);
+        std::fs::remove_file(&path).unwrap();
+
+        // A new restart policy replaces the queue. A node is added after it.
+        let mut config: Config = serde_json::from_value(config(
+            "Oops.",
+            serde_json::json!({ "policy": "on_failure", "max_retries": 1 }),
+        ))
+        .unwrap();
+        config.nodes.push(config::Node {
+            name: "more".into(),
+            config: node::Config {
+                element: crate::element::any::Kind::QUEUE,
+                backend: crate::backends::Backend::Independent,
+                config: serde_json::json!({ "caps": "Message" }),
+            },
+            restart: Default::default(),
+        });
+        config.edges.push(config::Edge {
+            source: "queue".into(),
+            source_pad: None,
+            sink: "more".into(),
+            sink_pad: None,
+            feedback: false,
+        });
+        let changes = control.reload(&config).await.unwrap();
+        assert_eq!(changes.replaced, ["queue"]);
+        assert_eq!(changes.added, ["more"]);
+        assert_eq!(changes.linked.len(), 2);
+        assert_eq!(changes.unlinked.len(), 1);
+        let running_config = control.config().await.unwrap();
+        assert_eq!(running_config.nodes.len(), 4);
+        assert_eq!(running_config.edges.len(), 3);
+        assert_eq!(
+            control.property(errors, "errors").await.unwrap(),
+            property::Value::Int(2)
+        );
+        assert!(control.reload(&config).await.unwrap().is_empty());
+
+        // A property set meanwhile is kept in the config, so reloading the
+        // old value sets it back.
+        control.set_property(errors, "text", "Hm.").await.unwrap();
+        let running_config = control.config().await.unwrap();
+        let node = running_config.nodes.iter().find(|n| n.name == "errors");
+        assert_eq!(node.unwrap().config.config["text"], "Hm.");
+        assert_eq!(control.reload(&config).await.unwrap().updated, ["errors"]);
+        assert_eq!(
+            control.property(errors, "text").await.unwrap(),
+            property::Value::String("Oops.".into())
+        );
+        assert!(control.reload(&config).await.unwrap().is_empty());
+
+        // A bad edge leaves everything as it was, even the nodes and
+        // properties changed along with it. One the config can't have is
+        // slipped into the plan, as it would get past validation.
+        let before = control.config().await.unwrap();
+        let mut bad = config.clone();
+        bad.nodes[2].config.config["text"] = "Changed.".into();
+        bad.nodes.push(config::Node {
+            name: "extra".into(),
+            ..bad.nodes[3].clone()
+        });
+        bad.edges.push(config::Edge {
+            source: "more".into(),
+            sink: "extra".into(),
+            ..bad.edges[2].clone()
+        });
+        let mut plan =
+            reload::plan(&before, Pipeline::from_config(&bad).unwrap());
+        assert_eq!(plan.changes.added, ["extra"]);
+        assert_eq!(plan.changes.updated, ["errors"]);
+        plan.changes.linked.push(config::Edge {
+            source: "more".into(),
+            source_pad: Some(0),
+            sink: "extra".into(),
+            sink_pad: Some(1),
+            feedback: false,
+        });
+        let reloaded = control
+            .request(|reply| control::Request::Reload { plan, reply })
+            .await;
+        let Err(err) = reloaded else {
+            panic!("the bad edge was linked")
+        };
+        assert!(matches!(
+            err,
+            ControlError::Link(LinkError::NoSuchSinkPad { pad: 1, .. })
+        ));
+        assert_eq!(
+            serde_json::to_value(control.config().await.unwrap()).unwrap(),
+            serde_json::to_value(before).unwrap()
+        );
+        assert_eq!(
+            control.property(errors, "text").await.unwrap(),
+            property::Value::String("Oops.".into())
+        );
+
+        let report = control
+            .shutdown(shutdown::Mode::Drain, std::time::Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert!(report.is_clean());
+        running.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_to_dot() {
+        use crate::{element::tee, pad::Caps};
+
+        let mut pipeline = Pipeline::new();
+        let first = pipeline.add(
+            "first",
+            Box::new(tee::Tee::new(tee::Options {
+                caps: Caps::ToolSchema,
+                pads: 2,
+                ..Default::default()
+            })),
+        );
+        let second = pipeline.add(
+            "second",
+            Box::new(tee::Tee::new(tee::Options {
+                caps: Caps::ToolSchema,
+                pads: 1,
+                ..Default::default()
+            })),
+        );
+        pipeline.link(first, 1, second, 0).unwrap();
+
+        let dot = pipeline.to_dot();
+        assert!(dot.contains("n0 [label=\"first\\nTee (Independent)\"];"));
+        assert!(dot.contains("n0 -> n1 [label=\"ToolSchema\""));
+        assert!(dot.contains("taillabel=\"1\", headlabel=\"0\""));
+        assert!(!dot.contains("buffers"));
+    }
 }
@@ -0,0 +1,235 @@
+//! Message [`Bus`] of a [`Pipeline`], like `GstBus`.
+//!
+//! [`Pipeline`]: super::Pipeline
+
+use futures::Stream;
+use tokio::sync::broadcast;
+
+use crate::element::property::Value;
+
+use super::reload::Changes;
+
+/// How many [`Message`]s a slow subscriber may fall behind before it starts
+/// missing them. See [`Message::Lagged`].
+pub const BUS_CAPACITY: usize = 64;
+
+/// A `Message` posted on a [`Bus`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// A [`Node`] failed. It has stopped processing [`Buffer`]s.
+    ///
+    /// [`Node`]: super::Node
+    /// [`Buffer`]: crate::buffer::Buffer
+    Error {
+        /// Index of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        node: usize,
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+        /// The error message.
+        message: String,
+    },
+    /// Something worth knowing about happened in a [`Node`], but it is still
+    /// running.
+    ///
+    /// [`Node`]: super::Node
+    Warning {
+        /// Index of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        node: usize,
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+        /// The warning message.
+        message: String,
+    },
+    /// Every [`Node`] has finished without error. No more [`Buffer`]s will
+    /// flow.
+    ///
+    /// [`Node`]: super::Node
+    /// [`Buffer`]: crate::buffer::Buffer
+    Eos,
+    /// The [`Pipeline`] moved from one [`State`] to another. The names are
+    /// [`State::NAME`]s.
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    /// [`State`]: super::State
+    /// [`State::NAME`]: super::State::NAME
+    StateChanged {
+        /// The old state.
+        from: &'static str,
+        /// The new state.
+        to: &'static str,
+    },
+    /// A [`Property`] of a [`Node`] was set, either directly on the
+    /// [`Pipeline`] or through its [`Control`] while running.
+    ///
+    /// [`Property`]: crate::element::property::Property
+    /// [`Node`]: super::Node
+    /// [`Pipeline`]: super::Pipeline
+    /// [`Control`]: super::Control
+    PropertyChanged {
+        /// Index of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        node: usize,
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+        /// Name of the [`Property`].
+        ///
+        /// [`Property`]: crate::element::property::Property
+        property: String,
+        /// The new value.
+        value: Value,
+    },
+    /// A [`Node`] failed or finished and is restarted after `delay` by its
+    /// [`Policy`].
+    ///
+    /// [`Node`]: super::Node
+    /// [`Policy`]: super::supervisor::Policy
+    Restarting {
+        /// Index of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        node: usize,
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+        /// Number of restarts in a row, counting this one.
+        attempt: u32,
+        /// How long until it is initialized again.
+        delay: std::time::Duration,
+    },
+    /// The running [`Pipeline`] was reloaded from a changed [`Config`] with
+    /// [`Control::reload`], possibly by a [`Watcher`].
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    /// [`Config`]: super::Config
+    /// [`Control::reload`]: super::Control::reload
+    /// [`Watcher`]: super::reload::Watcher
+    Reloaded {
+        /// What changed.
+        changes: Changes,
+    },
+    /// A [`Watcher`] failed to reload the [`Pipeline`]. See [`ReloadError`]
+    /// for what may have been applied anyway.
+    ///
+    /// [`Watcher`]: super::reload::Watcher
+    /// [`Pipeline`]: super::Pipeline
+    /// [`ReloadError`]: super::reload::ReloadError
+    ReloadFailed {
+        /// The error message.
+        message: String,
+    },
+    /// The subscriber fell behind and missed some `Message`s.
+    Lagged {
+        /// How many `Message`s were missed.
+        missed: u64,
+    },
+}
+
+/// A `Bus` carrying [`Message`]s from a [`Pipeline`] to the application.
+/// Cloning a `Bus` is cheap and all clones post to and receive from the same
+/// subscribers.
+///
+/// [`Pipeline`]: super::Pipeline
+#[derive(Debug, Clone)]
+pub struct Bus {
+    sender: broadcast::Sender<Message>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    /// Create a new `Bus` with no subscribers.
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(BUS_CAPACITY).0,
+        }
+    }
+
+    /// Post a [`Message`] to all current subscribers. [`Message`]s posted
+    /// while nobody is subscribed are dropped.
+    pub fn post(&self, message: Message) {
+        // An error only means there are no subscribers.
+        self.sender.send(message).ok();
+    }
+
+    /// Post a [`Message::Warning`] for a [`Node`].
+    ///
+    /// [`Node`]: super::Node
+    pub fn warn(
+        &self,
+        node: usize,
+        name: impl Into<String>,
+        message: impl Into<String>,
+    ) {
+        self.post(Message::Warning {
+            node,
+            name: name.into(),
+            message: message.into(),
+        })
+    }
+
+    /// Subscribe to [`Message`]s posted from now on. The stream ends when the
+    /// [`Pipeline`] and every clone of the `Bus` have been dropped.
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    pub fn subscribe(&self) -> impl Stream<Item = Message> + Send + 'static {
+        futures::stream::unfold(
+            self.sender.subscribe(),
+            |mut receiver| async move {
+                let message = match receiver.recv().await {
+                    Ok(message) => message,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        Message::Lagged { missed }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                };
+                Some((message, receiver))
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bus() {
+        let bus = Bus::new();
+        // Nobody is listening. This is dropped.
+        bus.post(Message::Eos);
+
+        let mut messages = Box::pin(bus.subscribe());
+        bus.warn(0, "prompt", "Careful.");
+        bus.post(Message::Eos);
+        drop(bus);
+
+        assert_eq!(
+            messages.next().await,
+            Some(Message::Warning {
+                node: 0,
+                name: "prompt".into(),
+                message: "Careful.".into(),
+            })
+        );
+        assert_eq!(messages.next().await, Some(Message::Eos));
+        assert_eq!(messages.next().await, None);
+    }
+}
@@ -0,0 +1,175 @@
+//! [`Checkpoint`]s of a [`Pipeline`], so a conversation survives a restart of
+//! the process.
+//!
+//! A [`Checkpoint`] is the [`Config`] of the [`Pipeline`] along with the state
+//! of every stateful [`Element`], like the history of a [`Prompt`] and the
+//! tool calls in it still waiting for a result. Take one with
+//! [`Pipeline::checkpoint`] or, while running, [`Control::checkpoint`], and
+//! continue from it with [`Pipeline::restore`]. In JSON it looks like this:
+//!
+//! ```json
+//! {
+//!   "config": { "nodes": [], "edges": [] },
+//!   "state": {
+//!     "prompt": { "system": "Be helpful.", "messages": [] }
+//!   }
+//! }
+//! ```
+//!
+//! Only [`Node`]s created from a [`node::Config`] can be saved, since the
+//! [`Element`]s themselves are not serializable. [`Buffer`]s in flight, such
+//! as those held by a [`Queue`], are not saved either.
+//!
+//! [`Element`]: crate::element::Element
+//! [`Prompt`]: crate::buffer::Prompt
+//! [`Control::checkpoint`]: super::Control::checkpoint
+//! [`Node`]: super::Node
+//! [`node::Config`]: super::node::Config
+//! [`Buffer`]: crate::buffer::Buffer
+//! [`Queue`]: crate::element::queue::Queue
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{BuildError, Config, InitError, Pipeline, Ready, State};
+
+/// A `Checkpoint` of a [`Pipeline`]. See the [module](self) documentation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// How to construct the [`Pipeline`] again.
+    pub config: Config,
+    /// State of each stateful [`Node`] by name, from
+    /// [`Element::checkpoint`].
+    ///
+    /// [`Node`]: super::Node
+    /// [`Element::checkpoint`]: crate::element::Element::checkpoint
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub state: BTreeMap<String, serde_json::Value>,
+}
+
+/// Error when taking or restoring a [`Checkpoint`].
+#[derive(Debug, thiserror::Error)]
+pub enum CheckpointError {
+    /// The [`Node`] was not created from a [`node::Config`], so it can't be
+    /// constructed again.
+    ///
+    /// [`Node`]: super::Node
+    /// [`node::Config`]: super::node::Config
+    #[error("Node `{name}` has no configuration to save.")]
+    NoConfig {
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+    },
+    /// [`Element::checkpoint`] failed.
+    ///
+    /// [`Element::checkpoint`]: crate::element::Element::checkpoint
+    #[error("Node `{name}` failed to save its state: {message}")]
+    Save {
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+        /// What went wrong.
+        message: String,
+    },
+    /// [`Element::restore`] failed.
+    ///
+    /// [`Element::restore`]: crate::element::Element::restore
+    #[error("Node `{name}` failed to restore its state: {message}")]
+    Restore {
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+        /// What went wrong.
+        message: String,
+    },
+    /// The [`Pipeline`] could not be built from the [`Checkpoint`]'s
+    /// config.
+    #[error(transparent)]
+    Build(#[from] BuildError),
+    /// The restored [`Pipeline`] failed to initialize.
+    #[error(transparent)]
+    Init(#[from] InitError),
+}
+impl super::state::Error for CheckpointError {}
+
+impl<S: State> Pipeline<S> {
+    /// Take a [`Checkpoint`] of the `Pipeline`. Use [`Control::checkpoint`]
+    /// while it runs.
+    ///
+    /// # Errors
+    /// - [`CheckpointError::NoConfig`] if a [`Node`] was not created from a
+    ///   [`node::Config`].
+    /// - [`CheckpointError::Save`] if an [`Element`] fails to save its state.
+    ///
+    /// [`Control::checkpoint`]: super::Control::checkpoint
+    /// [`Node`]: super::Node
+    /// [`node::Config`]: super::node::Config
+    /// [`Element`]: crate::element::Element
+    pub fn checkpoint(&self) -> Result<Checkpoint, CheckpointError> {
+        let mut state = BTreeMap::new();
+        for node in self.graph.node_weights() {
+            if node.config.is_none() {
+                return Err(CheckpointError::NoConfig {
+                    name: node.name.clone(),
+                });
+            }
+            let saved = node.element.checkpoint().map_err(|e| {
+                CheckpointError::Save {
+                    name: node.name.clone(),
+                    message: e.to_string(),
+                }
+            })?;
+            if let Some(saved) = saved {
+                state.insert(node.name.clone(), saved);
+            }
+        }
+
+        Ok(Checkpoint {
+            // Every node has a config, checked above.
+            config: self.config().unwrap_or_default(),
+            state,
+        })
+    }
+}
+
+impl Pipeline<Ready> {
+    /// Construct a `Pipeline` from a [`Checkpoint`], restore the state of its
+    /// [`Element`]s and initialize it, ready to continue where the
+    /// [`Checkpoint`] was taken.
+    ///
+    /// # Errors
+    /// - [`CheckpointError::Build`] for anything [`Pipeline::from_config`] or
+    ///   [`Pipeline::build`] can return, or [`BuildError::UnknownNode`] if
+    ///   there is state for a missing [`Node`].
+    /// - [`CheckpointError::Restore`] if an [`Element`] rejects its state.
+    /// - [`CheckpointError::Init`] if an [`Element`] fails to initialize.
+    ///
+    /// [`Element`]: crate::element::Element
+    /// [`Node`]: super::Node
+    pub async fn restore(
+        checkpoint: &Checkpoint,
+    ) -> Result<Self, CheckpointError> {
+        let mut pipeline = Pipeline::from_config(&checkpoint.config)?;
+        for (name, state) in checkpoint.state.iter() {
+            let node = pipeline.find(name).ok_or_else(|| {
+                BuildError::UnknownNode { name: name.clone() }
+            })?;
+            pipeline.graph[node]
+                .element
+                .restore(state.clone())
+                .map_err(|e| CheckpointError::Restore {
+                    name: name.clone(),
+                    message: e.to_string(),
+                })?;
+        }
+
+        // Nothing to hand back, since the pipeline was made here.
+        let pipeline = pipeline.build().map_err(|(_, err)| err)?;
+        Ok(pipeline.init().await.map_err(|(_, err)| err)?)
+    }
+}
@@ -0,0 +1,419 @@
+//! Serializable description of a [`Pipeline`].
+//!
+//! A [`Pipeline`] is serialized as the configuration used to create it, not
+//! the [`Element`]s themselves. In JSON it looks like this:
+//!
+//! ```json
+//! {
+//!   "nodes": [
+//!     {
+//!       "name": "prompt",
+//!       "element": "Prompt",
+//!       "backend": "Misanthropic",
+//!       "config": { "system": "You are a helpful assistant." }
+//!     },
+//!     {
+//!       "name": "inference",
+//!       "element": "Inference",
+//!       "backend": "Misanthropic",
+//!       "restart": { "policy": "on_failure", "max_retries": 3 }
+//!     }
+//!   ],
+//!   "edges": [
+//!     {
+//!       "source": "prompt",
+//!       "source_pad": 0,
+//!       "sink": "inference",
+//!       "sink_pad": 3
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! - `nodes` are constructed in order with [`Kind::new`]. `name`s must be
+//!   unique. `config` is optional and depends on the `element` and `backend`.
+//!   `restart` is an optional [`supervisor::Policy`].
+//! - `edges` are linked in order with [`Pipeline::link`], referring to
+//!   `nodes` by `name`. Pads are indexed in the order they are yielded by
+//!   [`Element::sources`] and [`Element::sinks`], followed by the
+//!   [`Pipeline::error_pad`] of the source. Either pad may be omitted,
+//!   in which case the first compatible one is picked by
+//!   [`Pipeline::autolink`]. `feedback` marks an edge closing an intended
+//!   cycle, like [`Pipeline::link_feedback`].
+//!
+//! [`Element`]: crate::element::Element
+//! [`Element::sources`]: crate::element::Element::sources
+//! [`Element::sinks`]: crate::element::Element::sinks
+//! [`Kind::new`]: crate::element::any::Kind::new
+//! [`Pipeline::error_pad`]: super::Pipeline::error_pad
+//! [`supervisor::Policy`]: super::supervisor::Policy
+
+use std::collections::HashSet;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::element::any::{NewError, UnavailableError};
+
+use super::{
+    node, supervisor, BuildError, Builder, Pipeline, Problem, State,
+    ValidationError,
+};
+
+/// `Config` for a whole [`Pipeline`]. See the [module](self) documentation
+/// for the format.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Config {
+    /// [`Node`] descriptions, in order.
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+    /// [`Edge`] descriptions, in order.
+    #[serde(default)]
+    pub edges: Vec<Edge>,
+}
+
+/// Description of a named node in a [`Pipeline`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Node {
+    /// Name of the node. Must be unique within the [`Pipeline`].
+    pub name: String,
+    /// What to construct.
+    #[serde(flatten)]
+    pub config: node::Config,
+    /// When to restart it while running.
+    #[serde(default, skip_serializing_if = "supervisor::Policy::is_never")]
+    pub restart: supervisor::Policy,
+}
+
+/// Description of a link between two named nodes in a [`Pipeline`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Edge {
+    /// Name of the upstream [`Node`].
+    pub source: String,
+    /// Index of the source pad on the upstream [`Node`]. Picked
+    /// automatically if [`None`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_pad: Option<usize>,
+    /// Name of the downstream [`Node`].
+    pub sink: String,
+    /// Index of the sink pad on the downstream [`Node`]. Picked automatically
+    /// if [`None`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sink_pad: Option<usize>,
+    /// Whether the edge closes an intended cycle. See
+    /// [`Pipeline::link_feedback`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub feedback: bool,
+}
+
+impl Pipeline<Builder> {
+    /// Construct a `Pipeline` from a [`Config`].
+    ///
+    /// # Errors
+    /// - [`BuildError::Invalid`] if two nodes share a name, an [`Element`]
+    ///   is unavailable for its [`Backend`] or its options do not match the
+    ///   [`Kind::schema`]. Nothing is constructed for those. Every other
+    ///   problem found by [`Pipeline::validate`] is listed as well.
+    /// - [`BuildError::New`] if an [`Element`] can't be constructed.
+    /// - [`BuildError::UnknownNode`] if an edge refers to a missing node.
+    /// - [`BuildError::Link`] if an edge can't be linked.
+    ///
+    /// [`Element`]: crate::element::Element
+    /// [`Backend`]: crate::backends::Backend
+    /// [`Kind::schema`]: crate::element::any::Kind::schema
+    pub fn from_config(config: &Config) -> Result<Self, BuildError> {
+        let mut names = HashSet::new();
+        let duplicates =
+            !config.nodes.iter().all(|node| names.insert(&node.name));
+
+        // Unavailable nodes and nodes with invalid options are left out so
+        // everything else can be checked.
+        let mut problems = vec![];
+        let mut missing = HashSet::new();
+        let mut pipeline = Self::new();
+        for Node {
+            name,
+            config,
+            restart,
+        } in config.nodes.iter()
+        {
+            if !config.element.available(config.backend) {
+                missing.insert(name);
+                problems.push(Problem::Unavailable {
+                    name: name.clone(),
+                    source: UnavailableError {
+                        kind: config.element.clone(),
+                        backend: config.backend,
+                    },
+                });
+                continue;
+            }
+            if let Err(NewError::Schema(source)) =
+                config.element.validate(&config.config)
+            {
+                missing.insert(name);
+                problems.push(Problem::Options {
+                    name: name.clone(),
+                    source,
+                });
+                continue;
+            }
+            let node = pipeline.add_config(name.clone(), config.clone())?;
+            pipeline.graph[node].restart = *restart;
+        }
+
+        // Nodes fed by a missing node are not reported as unconnected.
+        let mut starved = HashSet::new();
+        for edge in config.edges.iter() {
+            if missing.contains(&edge.source) || missing.contains(&edge.sink) {
+                starved.insert(&edge.sink);
+                continue;
+            }
+
+            let source = pipeline.find(&edge.source).ok_or_else(|| {
+                BuildError::UnknownNode {
+                    name: edge.source.clone(),
+                }
+            })?;
+            let sink = pipeline.find(&edge.sink).ok_or_else(|| {
+                BuildError::UnknownNode {
+                    name: edge.sink.clone(),
+                }
+            })?;
+            let index = pipeline.autolink(
+                source,
+                edge.source_pad,
+                sink,
+                edge.sink_pad,
+            )?;
+            pipeline.graph[index].feedback = edge.feedback;
+        }
+
+        if duplicates || !problems.is_empty() {
+            if let Err(err) = pipeline.validate() {
+                problems.extend(err.problems.into_iter().filter(|problem| {
+                    !matches!(
+                        problem,
+                        Problem::Unconnected { name, .. }
+                            if starved.contains(name)
+                    )
+                }));
+            }
+            return Err(ValidationError { problems }.into());
+        }
+
+        Ok(pipeline)
+    }
+
+    /// Construct a `Pipeline` from a JSON [`Config`].
+    ///
+    /// # Errors
+    /// - [`BuildError::SerdeJson`] if the JSON is not a valid [`Config`].
+    /// - Anything [`Pipeline::from_config`] can return.
+    pub fn from_json(json: &str) -> Result<Self, BuildError> {
+        Self::from_config(&serde_json::from_str(json)?)
+    }
+}
+
+impl<S: State> Pipeline<S> {
+    /// The [`Config`] this `Pipeline` can be recreated from. Returns [`None`]
+    /// if any [`Element`] was added directly rather than from a
+    /// [`node::Config`], since those can't be serialized.
+    ///
+    /// [`Element`]: crate::element::Element
+    pub fn config(&self) -> Option<Config> {
+        let nodes = self
+            .graph
+            .node_weights()
+            .map(|node| {
+                Some(Node {
+                    name: node.name.clone(),
+                    config: node.config.clone()?,
+                    restart: node.restart,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let edges = self
+            .graph
+            .raw_edges()
+            .iter()
+            .map(|edge| Edge {
+                source: self.graph[edge.source()].name.clone(),
+                source_pad: Some(edge.weight.source),
+                sink: self.graph[edge.target()].name.clone(),
+                sink_pad: Some(edge.weight.sink),
+                feedback: edge.weight.feedback,
+            })
+            .collect();
+
+        Some(Config { nodes, edges })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_round_trip() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "name": "prompt",
+                    "element": "Prompt",
+                    "backend": "Independent",
+                    "config": { "system": "Be helpful." }
+                },
+                {
+                    "name": "inference",
+                    "element": "Inference",
+                    "backend": "Independent"
+                }
+            ],
+            "edges": [
+                {
+                    "source": "prompt",
+                    "source_pad": 0,
+                    "sink": "inference",
+                    "sink_pad": 3
+                }
+            ]
+        }"#;
+
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.nodes.len(), 2);
+        assert_eq!(config.nodes[0].name, "prompt");
+        assert_eq!(
+            config.nodes[0].config.config,
+            serde_json::json!({ "system": "Be helpful." })
+        );
+        assert!(config.nodes[1].config.config.is_null());
+        assert_eq!(
+            config.edges[0],
+            Edge {
+                source: "prompt".into(),
+                source_pad: Some(0),
+                sink: "inference".into(),
+                sink_pad: Some(3),
+                feedback: false,
+            }
+        );
+
+        let again: Config =
+            serde_json::from_value(serde_json::to_value(&config).unwrap())
+                .unwrap();
+        assert_eq!(again.edges, config.edges);
+    }
+
+    #[test]
+    fn test_from_config_unavailable() {
+        // Prompts don't exist for the independent backend yet.
+        let json = r#"{
+            "nodes": [
+                {
+                    "name": "prompt",
+                    "element": "Prompt",
+                    "backend": "Independent"
+                },
+                {
+                    "name": "tee",
+                    "element": "Tee",
+                    "backend": "Independent"
+                },
+                {
+                    "name": "tee",
+                    "element": "Tee",
+                    "backend": "Independent"
+                },
+                {
+                    "name": "queue",
+                    "element": "Queue",
+                    "backend": "Independent"
+                }
+            ],
+            "edges": [
+                { "source": "prompt", "sink": "queue" },
+                { "source": "queue", "sink": "tee" }
+            ]
+        }"#;
+
+        let Err(BuildError::Invalid(ValidationError { problems })) =
+            Pipeline::from_json(json)
+        else {
+            panic!("expected validation errors");
+        };
+        // The queue is fed by the prompt, so it is not reported.
+        assert!(
+            matches!(
+                problems.as_slice(),
+                [
+                    Problem::Unavailable { name, .. },
+                    Problem::DuplicateName { .. },
+                    Problem::Unconnected { node: 1, .. },
+                ] if name == "prompt"
+            ),
+            "{problems:?}"
+        );
+    }
+
+    #[test]
+    fn test_from_config_options() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "name": "queue",
+                    "element": "Queue",
+                    "backend": "Independent",
+                    "config": { "leak": "Sideways", "max_bytes": "lots" }
+                },
+                {
+                    "name": "tee",
+                    "element": "Tee",
+                    "backend": "Independent"
+                }
+            ],
+            "edges": [{ "source": "queue", "sink": "tee" }]
+        }"#;
+
+        let Err(BuildError::Invalid(ValidationError { problems })) =
+            Pipeline::from_json(json)
+        else {
+            panic!("expected validation errors");
+        };
+        // The tee is fed by the queue, so it is not reported.
+        let [Problem::Options { name, source }] = problems.as_slice() else {
+            panic!("{problems:?}");
+        };
+        assert_eq!(name, "queue");
+        assert_eq!(source.problems.len(), 2);
+    }
+
+    #[test]
+    fn test_feedback_round_trip() {
+        let json = r#"{
+            "nodes": [
+                {
+                    "name": "tee",
+                    "element": "Tee",
+                    "backend": "Independent"
+                },
+                {
+                    "name": "queue",
+                    "element": "Queue",
+                    "backend": "Independent"
+                }
+            ],
+            "edges": [
+                { "source": "tee", "sink": "queue" },
+                { "source": "queue", "sink": "tee", "feedback": true }
+            ]
+        }"#;
+
+        let pipeline = Pipeline::from_json(json).unwrap();
+        let config = pipeline.config().unwrap();
+        assert!(!config.edges[0].feedback);
+        assert!(config.edges[1].feedback);
+        pipeline.build().unwrap();
+    }
+}
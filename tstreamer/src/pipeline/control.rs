@@ -0,0 +1,559 @@
+//! [`Control`] handle to change a running [`Pipeline`].
+//!
+//! [`Pipeline`]: super::Pipeline
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::element::{
+    property::{PropertyError, Value},
+    Element,
+};
+
+use super::{
+    node,
+    reload::{Plan, Reloaded},
+    shutdown::{Mode, Report},
+    BuildError, Checkpoint, CheckpointError, Config, EdgeIndex, LinkError,
+    NodeIndex,
+};
+
+/// Error when changing a running [`Pipeline`] through a [`Control`].
+///
+/// [`Pipeline`]: super::Pipeline
+#[derive(Debug, thiserror::Error)]
+pub enum ControlError {
+    /// The [`Pipeline`] is not running, or finished before the request could
+    /// be handled.
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    #[error("The pipeline is not running.")]
+    NotRunning,
+    /// The [`Pipeline`] is shutting down, so no [`Node`]s can be added.
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    /// [`Node`]: super::Node
+    #[error("The pipeline is shutting down.")]
+    ShuttingDown,
+    /// Linking, unlinking or relinking failed.
+    #[error(transparent)]
+    Link(#[from] LinkError),
+    /// A [`Node`] could not be added.
+    ///
+    /// [`Node`]: super::Node
+    #[error(transparent)]
+    Build(#[from] BuildError),
+    /// A [`Property`] could not be read or set.
+    ///
+    /// [`Property`]: crate::element::property::Property
+    #[error(transparent)]
+    Property(#[from] PropertyError),
+    /// A [`Checkpoint`] could not be taken.
+    ///
+    /// [`Checkpoint`]: super::Checkpoint
+    #[error(transparent)]
+    Checkpoint(#[from] CheckpointError),
+    /// The [`Node`] has already finished, so nothing can be linked to or
+    /// from it.
+    ///
+    /// [`Node`]: super::Node
+    #[error("Node {node} has finished.")]
+    Finished {
+        /// Index of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        node: usize,
+    },
+    /// The [`Node`] is being pulled as a source, so it can't be linked to.
+    ///
+    /// [`Node`]: super::Node
+    #[error("Node {node} is running as a source and can't be linked to.")]
+    Source {
+        /// Index of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        node: usize,
+    },
+    /// There is no [`Edge`] at this index.
+    ///
+    /// [`Edge`]: super::Edge
+    #[error("No edge at index {edge}.")]
+    NoSuchEdge {
+        /// Index of the missing [`Edge`].
+        ///
+        /// [`Edge`]: super::Edge
+        edge: usize,
+    },
+}
+impl super::state::Error for ControlError {}
+
+/// Reply to a [`Request`].
+pub(super) type Reply<T> = oneshot::Sender<Result<T, ControlError>>;
+
+/// A change to a running [`Pipeline`], handled by the executor.
+///
+/// [`Pipeline`]: super::Pipeline
+pub(super) enum Request {
+    Add {
+        name: String,
+        config: Option<node::Config>,
+        element: Box<dyn Element>,
+        reply: Reply<NodeIndex>,
+    },
+    Remove {
+        node: NodeIndex,
+        reply: Reply<Box<dyn Element>>,
+    },
+    Link {
+        source: NodeIndex,
+        source_pad: usize,
+        sink: NodeIndex,
+        sink_pad: usize,
+        reply: Reply<EdgeIndex>,
+    },
+    Unlink {
+        edge: EdgeIndex,
+        reply: Reply<()>,
+    },
+    Relink {
+        edge: EdgeIndex,
+        sink: NodeIndex,
+        sink_pad: usize,
+        reply: Reply<EdgeIndex>,
+    },
+    Dot {
+        reply: Reply<String>,
+    },
+    Block {
+        node: NodeIndex,
+        pad: usize,
+        reply: Reply<()>,
+    },
+    Unblock {
+        node: NodeIndex,
+        pad: usize,
+        reply: Reply<()>,
+    },
+    Property {
+        node: NodeIndex,
+        name: String,
+        reply: Reply<Value>,
+    },
+    SetProperty {
+        node: NodeIndex,
+        name: String,
+        value: Value,
+        reply: Reply<Value>,
+    },
+    Checkpoint {
+        reply: Reply<Checkpoint>,
+    },
+    Config {
+        reply: Reply<Config>,
+    },
+    Reload {
+        plan: Plan,
+        reply: Reply<Reloaded>,
+    },
+    Shutdown {
+        mode: Mode,
+        deadline: Duration,
+        reply: Reply<Report>,
+    },
+}
+
+/// A `Control` changes the [`Node`]s and [`Edge`]s of a [`Pipeline`] while
+/// it [`run`]s, so tools can be added, observers attached and models swapped
+/// in the middle of a session. Get one with [`Pipeline::control`] before
+/// running. Cloning a `Control` is cheap and all clones act on the same
+/// [`Pipeline`].
+///
+/// Changes are applied by the tasks running the affected [`Node`]s between
+/// two [`Buffer`]s, so no [`Buffer`] is lost or split:
+/// - Buffers already sent on an unlinked [`Edge`] are still delivered.
+/// - A [`Node`] that is [`remove`]d first finishes what it was sent.
+/// - A source pad that is [`block`]ed stops being pulled. Its [`Buffer`]s
+///   stay in the [`Element`] until it is [`unblock`]ed. Block a pad, make
+///   several changes, then unblock it to apply them all at once.
+///
+/// A source [`Node`] waiting for a [`Buffer`] to [`Pull`] stops waiting
+/// to apply changes, then waits again.
+///
+/// A [`Node`] added while running does nothing until linked. Link its sink
+/// pads first. A [`Node`] that is only linked downstream is pulled as a
+/// source, and can't be linked to afterwards.
+///
+/// The [`Pipeline`] can be stopped early with a deadline by [`shutdown`].
+///
+/// [`Node`]: super::Node
+/// [`Edge`]: super::Edge
+/// [`Pipeline`]: super::Pipeline
+/// [`run`]: super::Pipeline::run
+/// [`Pipeline::control`]: super::Pipeline::control
+/// [`Buffer`]: crate::buffer::Buffer
+/// [`Pull`]: crate::pad::Pull
+/// [`remove`]: Control::remove
+/// [`block`]: Control::block
+/// [`unblock`]: Control::unblock
+/// [`shutdown`]: Control::shutdown
+#[derive(Clone, Default)]
+pub struct Control {
+    requests: Arc<Mutex<Option<mpsc::UnboundedSender<Request>>>>,
+}
+
+impl Control {
+    /// Start taking [`Request`]s on behalf of a running [`Pipeline`].
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    pub(super) fn attach(&self) -> mpsc::UnboundedReceiver<Request> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        *self.lock() = Some(tx);
+        rx
+    }
+
+    /// Stop taking [`Request`]s. Pending ones fail with
+    /// [`ControlError::NotRunning`].
+    pub(super) fn detach(&self) {
+        self.lock().take();
+    }
+
+    fn lock(
+        &self,
+    ) -> std::sync::MutexGuard<'_, Option<mpsc::UnboundedSender<Request>>> {
+        self.requests
+            .lock()
+            // An `Option` is always consistent, so a poisoned lock is fine.
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Send a [`Request`] and wait for the reply.
+    pub(super) async fn request<T>(
+        &self,
+        request: impl FnOnce(Reply<T>) -> Request,
+    ) -> Result<T, ControlError> {
+        let (tx, rx) = oneshot::channel();
+        let sender = self.lock().clone().ok_or(ControlError::NotRunning)?;
+        sender
+            .send(request(tx))
+            .map_err(|_| ControlError::NotRunning)?;
+
+        rx.await.map_err(|_| ControlError::NotRunning)?
+    }
+
+    /// Add an [`Element`] as a [`Node`] with a `name`. Returns the index of
+    /// the new [`Node`].
+    ///
+    /// # Errors
+    /// - [`BuildError::DuplicateName`] if a [`Node`] already has this name.
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Node`]: super::Node
+    /// [`Pipeline`]: super::Pipeline
+    pub async fn add(
+        &self,
+        name: impl Into<String>,
+        element: Box<dyn Element>,
+    ) -> Result<NodeIndex, ControlError> {
+        let name = name.into();
+        self.request(|reply| Request::Add {
+            name,
+            config: None,
+            element,
+            reply,
+        })
+        .await
+    }
+
+    /// Construct an [`Element`] from a [`node::Config`] and [`add`] it.
+    ///
+    /// # Errors
+    /// - [`BuildError::New`] if the [`Element`] can't be constructed.
+    /// - Anything [`add`] can return.
+    ///
+    /// [`add`]: Control::add
+    pub async fn add_config(
+        &self,
+        name: impl Into<String>,
+        config: node::Config,
+    ) -> Result<NodeIndex, ControlError> {
+        let name = name.into();
+        let element = match config.new_element() {
+            Ok(element) => element,
+            Err(source) => return Err(BuildError::New { name, source }.into()),
+        };
+
+        self.request(|reply| Request::Add {
+            name,
+            config: Some(config),
+            element,
+            reply,
+        })
+        .await
+    }
+
+    /// Unlink a [`Node`] and remove it once it has finished what it was
+    /// sent. Returns its [`Element`], which is not stopped.
+    ///
+    /// When the [`Pipeline`] is done, the remaining [`Node`]s are compacted,
+    /// so [`Node`]s added after this one get a lower index.
+    ///
+    /// # Errors
+    /// - [`LinkError::NoSuchNode`] if the [`Node`] does not exist.
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Node`]: super::Node
+    /// [`Pipeline`]: super::Pipeline
+    pub async fn remove(
+        &self,
+        node: NodeIndex,
+    ) -> Result<Box<dyn Element>, ControlError> {
+        self.request(|reply| Request::Remove { node, reply }).await
+    }
+
+    /// Link two [`Node`]s like [`Pipeline::link`].
+    ///
+    /// # Errors
+    /// - Anything [`Pipeline::link`] can return.
+    /// - [`ControlError::Finished`] if either [`Node`] has finished.
+    /// - [`ControlError::Source`] if the `sink` [`Node`] is running as a
+    ///   source.
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Node`]: super::Node
+    /// [`Pipeline`]: super::Pipeline
+    /// [`Pipeline::link`]: super::Pipeline::link
+    pub async fn link(
+        &self,
+        source: NodeIndex,
+        source_pad: usize,
+        sink: NodeIndex,
+        sink_pad: usize,
+    ) -> Result<EdgeIndex, ControlError> {
+        self.request(|reply| Request::Link {
+            source,
+            source_pad,
+            sink,
+            sink_pad,
+            reply,
+        })
+        .await
+    }
+
+    /// Remove an [`Edge`]. [`Buffer`]s already sent on it are delivered.
+    ///
+    /// # Errors
+    /// - [`ControlError::NoSuchEdge`] if the [`Edge`] does not exist.
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Edge`]: super::Edge
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`Pipeline`]: super::Pipeline
+    pub async fn unlink(&self, edge: EdgeIndex) -> Result<(), ControlError> {
+        self.request(|reply| Request::Unlink { edge, reply }).await
+    }
+
+    /// Move the downstream end of an [`Edge`] to the sink pad at `sink_pad`
+    /// of the `sink` [`Node`]. Every [`Buffer`] goes either to the old or the
+    /// new sink. Returns the index of the new [`Edge`].
+    ///
+    /// # Errors
+    /// - [`ControlError::NoSuchEdge`] if the [`Edge`] does not exist.
+    /// - Anything [`link`] can return. The old [`Edge`] is kept.
+    ///
+    /// [`Edge`]: super::Edge
+    /// [`Node`]: super::Node
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`link`]: Control::link
+    pub async fn relink(
+        &self,
+        edge: EdgeIndex,
+        sink: NodeIndex,
+        sink_pad: usize,
+    ) -> Result<EdgeIndex, ControlError> {
+        self.request(|reply| Request::Relink {
+            edge,
+            sink,
+            sink_pad,
+            reply,
+        })
+        .await
+    }
+
+    /// Export the running [`Pipeline`] as a Graphviz DOT graph, like
+    /// [`Pipeline::to_dot`]. [`Node`]s also show whether they are running
+    /// and how full their inbox is. [`Edge`]s also show how many
+    /// [`Buffer`]s they carried. See [`dot::dump`] to write it to a file.
+    ///
+    /// # Errors
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    /// [`Pipeline::to_dot`]: super::Pipeline::to_dot
+    /// [`Node`]: super::Node
+    /// [`Edge`]: super::Edge
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`dot::dump`]: super::dot::dump
+    pub async fn to_dot(&self) -> Result<String, ControlError> {
+        self.request(|reply| Request::Dot { reply }).await
+    }
+
+    /// Stop pulling the source pad at `pad` of a [`Node`], like a blocking
+    /// pad probe. Returns once the [`Node`] is between two [`Buffer`]s.
+    /// [`Buffer`]s the pad would have yielded in the meantime are pulled when
+    /// it is [`unblock`]ed.
+    ///
+    /// # Errors
+    /// - [`LinkError::NoSuchNode`] or [`LinkError::NoSuchSourcePad`] if the
+    ///   [`Node`] or pad does not exist. The error pad is never pulled, so it
+    ///   can't be blocked.
+    /// - [`ControlError::Finished`] if the [`Node`] has finished.
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Node`]: super::Node
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`Pipeline`]: super::Pipeline
+    /// [`unblock`]: Control::unblock
+    pub async fn block(
+        &self,
+        node: NodeIndex,
+        pad: usize,
+    ) -> Result<(), ControlError> {
+        self.request(|reply| Request::Block { node, pad, reply })
+            .await
+    }
+
+    /// Resume pulling a source pad [`block`]ed before. Unblocking a pad that
+    /// is not blocked does nothing.
+    ///
+    /// # Errors
+    /// - Anything [`block`] can return.
+    ///
+    /// [`block`]: Control::block
+    pub async fn unblock(
+        &self,
+        node: NodeIndex,
+        pad: usize,
+    ) -> Result<(), ControlError> {
+        self.request(|reply| Request::Unblock { node, pad, reply })
+            .await
+    }
+
+    /// Read a [`Property`] of a [`Node`] like [`Pipeline::property`]. The
+    /// [`Node`] answers between two [`Buffer`]s.
+    ///
+    /// # Errors
+    /// - [`LinkError::NoSuchNode`] if the [`Node`] does not exist.
+    /// - [`PropertyError::Unknown`] if it has no such [`Property`].
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Property`]: crate::element::property::Property
+    /// [`Node`]: super::Node
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`Pipeline`]: super::Pipeline
+    /// [`Pipeline::property`]: super::Pipeline::property
+    pub async fn property(
+        &self,
+        node: NodeIndex,
+        name: impl Into<String>,
+    ) -> Result<Value, ControlError> {
+        let name = name.into();
+        self.request(|reply| Request::Property { node, name, reply })
+            .await
+    }
+
+    /// Write a [`Property`] of a [`Node`] like [`Pipeline::set_property`],
+    /// between two [`Buffer`]s. Returns the new value once it is set. A
+    /// [`bus::Message::PropertyChanged`] is posted.
+    ///
+    /// # Errors
+    /// - [`LinkError::NoSuchNode`] if the [`Node`] does not exist.
+    /// - Anything [`property::set`] can return.
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Property`]: crate::element::property::Property
+    /// [`Node`]: super::Node
+    /// [`Buffer`]: crate::buffer::Buffer
+    /// [`Pipeline::set_property`]: super::Pipeline::set_property
+    /// [`bus::Message::PropertyChanged`]: super::bus::Message::PropertyChanged
+    /// [`property::set`]: crate::element::property::set
+    pub async fn set_property(
+        &self,
+        node: NodeIndex,
+        name: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Result<Value, ControlError> {
+        let (name, value) = (name.into(), value.into());
+        self.request(|reply| Request::SetProperty {
+            node,
+            name,
+            value,
+            reply,
+        })
+        .await
+    }
+
+    /// Take a [`Checkpoint`] of the running [`Pipeline`] like
+    /// [`Pipeline::checkpoint`]. Each running [`Node`] saves its state
+    /// between two [`Buffer`]s, so a turn with a model in flight is finished
+    /// first. [`Node`]s added through the `Control` are included, as long as
+    /// they were added with [`Control::add_config`].
+    ///
+    /// # Errors
+    /// - [`CheckpointError`] as for [`Pipeline::checkpoint`].
+    /// - [`ControlError::Finished`] if a [`Node`] finished before saving its
+    ///   state. Trying again works.
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    /// [`Pipeline::checkpoint`]: super::Pipeline::checkpoint
+    /// [`Node`]: super::Node
+    /// [`Buffer`]: crate::buffer::Buffer
+    pub async fn checkpoint(&self) -> Result<Checkpoint, ControlError> {
+        self.request(|reply| Request::Checkpoint { reply }).await
+    }
+
+    /// The [`Config`] of the running [`Pipeline`] like [`Pipeline::config`],
+    /// with every pad picked. [`Node`]s being removed are left out.
+    ///
+    /// # Errors
+    /// - [`CheckpointError::NoConfig`] if a [`Node`] was not created from a
+    ///   [`node::Config`], such as one added with [`Control::add`].
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    /// [`Pipeline::config`]: super::Pipeline::config
+    /// [`Node`]: super::Node
+    pub async fn config(&self) -> Result<Config, ControlError> {
+        self.request(|reply| Request::Config { reply }).await
+    }
+
+    /// Shut down the running [`Pipeline`] in some [`Mode`], cancelling
+    /// whatever is still running after `deadline`. Returns a [`Report`] once
+    /// every [`Node`] has stopped, when [`Pipeline::run`] is about to return.
+    /// Calling it again can only make the deadline earlier or switch to
+    /// [`Mode::Abort`]. Adding [`Node`]s meanwhile fails with
+    /// [`ControlError::ShuttingDown`].
+    ///
+    /// # Errors
+    /// - [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    /// [`Pipeline::run`]: super::Pipeline::run
+    /// [`Node`]: super::Node
+    pub async fn shutdown(
+        &self,
+        mode: Mode,
+        deadline: Duration,
+    ) -> Result<Report, ControlError> {
+        self.request(|reply| Request::Shutdown {
+            mode,
+            deadline,
+            reply,
+        })
+        .await
+    }
+}
@@ -0,0 +1,177 @@
+//! Graphviz DOT export of a [`Pipeline`], like `GST_DEBUG_DUMP_DOT_DIR` in
+//! GStreamer. Render the output with `dot -Tsvg pipeline.dot -o pipeline.svg`.
+//!
+//! [`Pipeline`]: super::Pipeline
+
+use std::{fmt::Write, path::PathBuf};
+
+use crate::{backends::Backend, element::property::Value, pad::Caps};
+
+use super::node;
+
+/// Environment variable naming a directory to write DOT files to. See
+/// [`dump`].
+pub const DUMP_DIR_ENV: &str = "TSTREAMER_DEBUG_DUMP_DOT_DIR";
+
+/// A [`Node`] as drawn.
+///
+/// [`Node`]: super::Node
+pub(super) struct Node<'a> {
+    pub index: usize,
+    /// Name of the [`Node`] in the [`Pipeline`].
+    ///
+    /// [`Node`]: super::Node
+    /// [`Pipeline`]: super::Pipeline
+    pub name: &'a str,
+    /// [`Info::name`] of the [`Element`].
+    ///
+    /// [`Info::name`]: crate::info::Info::name
+    /// [`Element`]: crate::element::Element
+    pub element: &'a str,
+    pub backend: Backend,
+    pub config: Option<&'a node::Config>,
+    /// Read-only properties of the [`Element`], like the fill level of a
+    /// [`Queue`].
+    ///
+    /// [`Element`]: crate::element::Element
+    /// [`Queue`]: crate::element::queue::Queue
+    pub readings: Vec<(&'static str, Value)>,
+    /// Extra lines, such as the state of a running [`Node`].
+    ///
+    /// [`Node`]: super::Node
+    pub status: Vec<String>,
+}
+
+/// An [`Edge`] as drawn.
+///
+/// [`Edge`]: super::Edge
+pub(super) struct Edge {
+    pub source: usize,
+    pub sink: usize,
+    pub source_pad: usize,
+    pub sink_pad: usize,
+    pub caps: Caps,
+    /// Number of buffers sent so far, if running.
+    pub sent: Option<u64>,
+}
+
+/// Render a graph in the DOT language.
+pub(super) fn render<'a>(
+    nodes: impl IntoIterator<Item = Node<'a>>,
+    edges: impl IntoIterator<Item = Edge>,
+) -> String {
+    let mut dot = String::from(
+        "digraph pipeline {\n  rankdir=LR;\n  node [shape=box, \
+        style=\"rounded,filled\", fillcolor=\"#eeeeee\", \
+        fontname=\"monospace\"];\n  edge [fontname=\"monospace\"];\n",
+    );
+
+    // Writing to a `String` can't fail.
+    for node in nodes {
+        let mut lines = vec![
+            node.name.to_owned(),
+            format!("{} ({:?})", node.element, node.backend),
+        ];
+        if let Some(options) = node
+            .config
+            .map(|config| &config.config)
+            .filter(|options| !options.is_null())
+        {
+            lines.push(options.to_string());
+        }
+        if !node.readings.is_empty() {
+            let readings: Vec<_> = node
+                .readings
+                .iter()
+                .map(|(name, value)| format!("{name}: {value}"))
+                .collect();
+            lines.push(readings.join(", "));
+        }
+        lines.extend(node.status);
+
+        writeln!(dot, "  n{} [label=\"{}\"];", node.index, label(&lines))
+            .unwrap();
+    }
+
+    for edge in edges {
+        let mut lines = vec![edge.caps.to_string()];
+        if let Some(sent) = edge.sent {
+            lines.push(format!("{sent} buffers"));
+        }
+
+        writeln!(
+            dot,
+            "  n{} -> n{} [label=\"{}\", taillabel=\"{}\", headlabel=\"{}\"];",
+            edge.source,
+            edge.sink,
+            label(&lines),
+            edge.source_pad,
+            edge.sink_pad
+        )
+        .unwrap();
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Join `lines` into the contents of a quoted DOT label.
+fn label(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|line| line.replace('\\', "\\\\").replace('"', "\\\""))
+        .collect::<Vec<_>>()
+        .join("\\n")
+}
+
+/// Write `dot` to `<dir>/<name>.dot` if the [`DUMP_DIR_ENV`] environment
+/// variable names a directory `dir`. Returns the path written to, if any.
+///
+/// # Errors
+/// - If the file can't be written.
+pub fn dump(name: &str, dot: &str) -> std::io::Result<Option<PathBuf>> {
+    let Some(dir) = std::env::var_os(DUMP_DIR_ENV) else {
+        return Ok(None);
+    };
+
+    let path = PathBuf::from(dir).join(format!("{name}.dot"));
+    std::fs::write(&path, dot)?;
+
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let dot = render(
+            [Node {
+                index: 0,
+                name: "say \"hi\"",
+                element: "Tee",
+                backend: Backend::Independent,
+                config: None,
+                readings: vec![("buffers", Value::Int(2))],
+                status: vec!["running".into()],
+            }],
+            [Edge {
+                source: 0,
+                sink: 0,
+                source_pad: 1,
+                sink_pad: 0,
+                caps: Caps::Message,
+                sent: Some(3),
+            }],
+        );
+
+        assert!(dot.starts_with("digraph pipeline {"));
+        assert!(dot.contains(
+            "n0 [label=\"say \\\"hi\\\"\\nTee (Independent)\\nbuffers: 2\\nrunning\"];"
+        ));
+        assert!(dot.contains("n0 -> n0 [label=\""));
+        assert!(dot.contains("\\n3 buffers\", taillabel=\"1\""));
+        assert!(dot.ends_with("}\n"));
+    }
+}
@@ -1,5 +1,53 @@
+use crate::pad::Caps;
+
 use super::State;
 
+/// An `Edge` in a [`Pipeline`]. Links a [`Source`] pad of one [`Node`] to a
+/// [`Sink`] pad of another. Pads are identified by their position in
+/// [`Element::sources`] and [`Element::sinks`] respectively.
+///
+/// [`Pipeline`]: super::Pipeline
+/// [`Node`]: super::Node
+/// [`Source`]: crate::pad::Source
+/// [`Sink`]: crate::pad::Sink
+/// [`Element::sources`]: crate::element::Element::sources
+/// [`Element::sinks`]: crate::element::Element::sinks
 pub struct Edge<S: State> {
+    /// Index of the source pad on the upstream [`Node`].
+    ///
+    /// [`Node`]: super::Node
+    pub(crate) source: usize,
+    /// Index of the sink pad on the downstream [`Node`].
+    ///
+    /// [`Node`]: super::Node
+    pub(crate) sink: usize,
+    /// [`Caps`] of the source pad. What the `Edge` carries.
+    pub(crate) caps: Caps,
+    /// Whether the `Edge` closes an intended cycle, such as a tool use loop.
+    /// Cycles made only of other `Edge`s fail [`Pipeline::validate`].
+    ///
+    /// [`Pipeline::validate`]: super::Pipeline::validate
+    pub(crate) feedback: bool,
     state: std::marker::PhantomData<S>,
 }
+
+impl<S: State> Edge<S> {
+    /// Create a new `Edge` from a source pad index to a sink pad index.
+    pub(crate) fn new(source: usize, sink: usize, caps: Caps) -> Self {
+        Self {
+            source,
+            sink,
+            caps,
+            feedback: false,
+            state: std::marker::PhantomData,
+        }
+    }
+
+    /// Move the `Edge` to another [`State`].
+    pub(crate) fn into_state<T: State>(self) -> Edge<T> {
+        Edge {
+            feedback: self.feedback,
+            ..Edge::new(self.source, self.sink, self.caps)
+        }
+    }
+}
@@ -0,0 +1,2192 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use petgraph::graph::{EdgeIndex, Graph, NodeIndex};
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    task::JoinError,
+};
+
+use crate::{
+    backends::Backend,
+    buffer::{Buffer, Error, ErrorStaticString},
+    element::{
+        property::{self, Value},
+        Element,
+    },
+    pad::{Caps, Event},
+};
+
+use super::{
+    bus, config,
+    control::{ControlError, Reply, Request},
+    dot, node,
+    reload::{Plan, Reloaded},
+    shutdown::{Mode, Outcome, Report, Stopped},
+    supervisor::Policy,
+    trace::{BufferInfo, Origin, Tracer, Tracers, Transfer},
+    BuildError, Bus, Checkpoint, CheckpointError, Control, Edge, LinkError,
+    Node, RunError, State,
+};
+
+/// Capacity of the channel feeding the sink pads of each [`Node`]. When full,
+/// upstream [`Node`]s wait before pulling more [`Buffer`]s.
+pub const CHANNEL_CAPACITY: usize = 16;
+
+/// A [`Buffer`] or [`Event`] on its way to a sink pad of a [`Node`].
+struct Delivery {
+    /// Index of the sink pad.
+    pad: usize,
+    /// Index of the [`Edge`] it arrived on.
+    edge: usize,
+    item: Item,
+}
+
+/// What a [`Delivery`] carries.
+enum Item {
+    Buffer(Box<dyn Buffer>),
+    Event(Event),
+}
+
+/// A linked source pad of a [`Node`] and where its [`Buffer`]s go.
+struct Outlet {
+    /// Index of the [`Edge`] this is for.
+    edge: usize,
+    /// Index of the source pad on this [`Node`].
+    pad: usize,
+    /// Index of the sink pad on the downstream [`Node`].
+    sink: usize,
+    tx: mpsc::Sender<Delivery>,
+    /// Number of [`Buffer`]s sent, shared with the executor.
+    sent: Arc<AtomicU64>,
+}
+
+impl Outlet {
+    /// Count a [`Buffer`] as sent.
+    fn count(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A change to a running [`Node`], applied between two [`Buffer`]s.
+enum Command {
+    /// Start sending on a new [`Outlet`].
+    Link(Outlet),
+    /// Drop the [`Outlet`] for an [`Edge`].
+    Unlink { edge: usize },
+    /// An [`Edge`] into the [`Node`] was added.
+    AddUpstream { edge: usize },
+    /// An [`Edge`] into a sink pad of the [`Node`] was removed.
+    RemoveUpstream { edge: usize, pad: usize },
+    /// Stop waiting for [`Delivery`]s and pull the [`Node`] as a source.
+    Source,
+    /// Finish now.
+    Stop,
+    /// Stop pulling a source pad.
+    Block { pad: usize, reply: Reply<()> },
+    /// Resume pulling a source pad.
+    Unblock { pad: usize, reply: Reply<()> },
+    /// Read a [`Property`].
+    ///
+    /// [`Property`]: crate::element::property::Property
+    Property { name: String, reply: Reply<Value> },
+    /// Write a [`Property`] and post the change.
+    ///
+    /// [`Property`]: crate::element::property::Property
+    SetProperty {
+        name: String,
+        value: Value,
+        reply: Reply<Value>,
+    },
+    /// Save the state of the [`Element`] for a [`Checkpoint`].
+    Checkpoint { reply: oneshot::Sender<Saved> },
+}
+
+/// State saved by [`Element::checkpoint`], or why it could not be.
+type Saved = Result<Option<serde_json::Value>, String>;
+
+/// Save the state of an [`Element`] for a [`Checkpoint`].
+fn save(element: &dyn Element) -> Saved {
+    element.checkpoint().map_err(|e| e.to_string())
+}
+
+/// How a [`Node`] is driven. Decided by how it is first linked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    /// Pulled repeatedly. Can't be linked to.
+    Source,
+    /// Pushed to, then pulled.
+    Filter,
+    /// Added while running and not linked yet.
+    Idle,
+}
+
+/// A [`Node`] as it would be after some of the [`Edge`]s of a reload were
+/// linked. See [`Executor::check`].
+struct Planned {
+    index: usize,
+    sources: Vec<Caps>,
+    sinks: Vec<Caps>,
+    role: Role,
+}
+
+/// How a [`Task`] ended.
+enum Exit {
+    /// [`Task::drive`] returned.
+    Done(Result<(), Box<dyn Error>>),
+    /// It was cancelled by a shutdown.
+    Cancelled,
+}
+
+/// What a [`Task`] hands back when done, and which [`Node`] it ran.
+type Joined = (usize, Result<(Task, Exit), JoinError>);
+
+/// A [`Task`] back from waiting to be restarted.
+struct Restarted {
+    index: usize,
+    task: Task,
+    /// Why it was restarted, if it failed.
+    failure: Option<String>,
+    /// How long [`Element::init`] took and how it went. [`None`] if the
+    /// [`Pipeline`] started shutting down first.
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    init: Option<(Duration, Result<(), String>)>,
+}
+
+/// A [`Command::SetProperty`] sent to a running [`Task`], recorded in the
+/// [`node::Config`] of its [`Node`] once applied.
+struct Set {
+    index: usize,
+    name: String,
+    result: Result<Value, ControlError>,
+    reply: Reply<Value>,
+}
+
+/// The executor's view of a [`Node`] while its [`Task`] runs.
+struct Slot {
+    name: String,
+    config: Option<node::Config>,
+    /// [`Info::name`] and [`Backend`] of the [`Element`], taken before the
+    /// [`Task`] started, and its latest [`Readings`]. Used for [`dot`]
+    /// export.
+    ///
+    /// [`Info::name`]: crate::info::Info::name
+    element_name: String,
+    backend: Backend,
+    readings: Readings,
+    /// [`Caps`] of the source pads, including the error pad, and of the sink
+    /// pads, taken before the [`Task`] started. Used to check new links.
+    sources: Vec<Caps>,
+    sinks: Vec<Caps>,
+    role: Role,
+    control: mpsc::UnboundedSender<Command>,
+    /// Sender for the inbox of the [`Node`]. Only the [`Outlet`]s keep it
+    /// open, otherwise the [`Node`] would never finish.
+    inbox: mpsc::WeakSender<Delivery>,
+    /// Keeps the inbox of an [`Role::Idle`] [`Node`] open until it is
+    /// linked.
+    held: Option<mpsc::Sender<Delivery>>,
+    /// The [`Element`], once the [`Task`] has finished for good.
+    element: Option<Box<dyn Element>>,
+    /// Who to give the [`Element`] to when a removed [`Node`] finishes.
+    removal: Option<Reply<Box<dyn Element>>>,
+    /// When to restart the [`Task`], how many times it was in a row, and
+    /// when it last started.
+    restart: Policy,
+    restarts: u32,
+    started: Instant,
+}
+
+/// The executor's view of an [`Edge`].
+struct Link<S: State> {
+    /// Index of the upstream [`Node`].
+    source: usize,
+    /// Index of the downstream [`Node`].
+    sink: usize,
+    weight: Edge<S>,
+    /// Number of [`Buffer`]s sent, shared with the [`Outlet`].
+    sent: Arc<AtomicU64>,
+}
+
+/// Runs the [`Task`]s of a graph of [`Node`]s and applies [`Request`]s to it.
+struct Executor<S: State> {
+    slots: Vec<Option<Slot>>,
+    /// Removed [`Edge`]s are [`None`] so indices stay valid.
+    edges: Vec<Option<Link<S>>>,
+    tasks: FuturesUnordered<Pin<Box<dyn Future<Output = Joined> + Send>>>,
+    /// [`Task`]s waiting to be restarted.
+    restarts: FuturesUnordered<Pin<Box<dyn Future<Output = Restarted> + Send>>>,
+    /// [`Command::SetProperty`]s sent to running [`Task`]s.
+    sets: FuturesUnordered<Pin<Box<dyn Future<Output = Set> + Send>>>,
+    bus: Bus,
+    tracers: Tracers,
+    first_error: Option<RunError>,
+    /// Set to `true` to cancel every [`Task`].
+    cancel: watch::Sender<bool>,
+    /// Set once a shutdown is requested.
+    stopping: Option<Stopping>,
+    /// Set to `true` with `stopping` to give up pending restarts.
+    halt: watch::Sender<bool>,
+    /// [`Node`]s that finished, except removed ones.
+    stopped: Vec<Stopped>,
+}
+
+/// A shutdown in progress.
+struct Stopping {
+    mode: Mode,
+    started: Instant,
+    /// When to cancel whatever is still running.
+    deadline: Instant,
+    replies: Vec<Reply<Report>>,
+}
+
+/// Run a graph of [`Node`]s to completion, spawning one tokio task per
+/// [`Node`] and applying [`Request`]s sent through the [`Control`] meanwhile.
+/// The graph is handed back once every task has finished, with the first
+/// error if any. Indices are preserved unless [`Node`]s were removed or lost
+/// to a panic. A [`bus::Message::Error`] is posted on the [`Bus`] as soon as a
+/// [`Node`] fails. See [`Pipeline::run`] for the scheduling rules.
+///
+/// [`Pipeline::run`]: super::Pipeline::run
+pub(super) async fn run<S: State>(
+    graph: Graph<Node<S>, Edge<S>>,
+    bus: &Bus,
+    control: &Control,
+    tracers: &Tracers,
+) -> (Graph<Node<S>, Edge<S>>, Option<RunError>) {
+    let mut requests = control.attach();
+    let mut executor = Executor::new(graph, bus.clone(), tracers.clone());
+
+    while !executor.tasks.is_empty()
+        || !executor.restarts.is_empty()
+        || !executor.sets.is_empty()
+    {
+        let deadline = executor.deadline();
+        let expired = async move {
+            match deadline {
+                Some(deadline) => {
+                    tokio::time::sleep_until(deadline.into()).await
+                }
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            Some(joined) = executor.tasks.next() => executor.joined(joined),
+            Some(restarted) = executor.restarts.next() => {
+                executor.restarted(restarted)
+            }
+            Some(set) = executor.sets.next() => executor.set(set),
+            Some(request) = requests.recv() => executor.request(request),
+            _ = expired => {
+                executor.cancel.send_replace(true);
+            }
+            else => break,
+        }
+    }
+
+    // Pending requests fail as the receiver is dropped.
+    control.detach();
+    executor.report();
+    executor.into_graph()
+}
+
+impl<S: State> Executor<S> {
+    /// Spawn a [`Task`] for every [`Node`] of the `graph`.
+    fn new(graph: Graph<Node<S>, Edge<S>>, bus: Bus, tracers: Tracers) -> Self {
+        let (nodes, edges) = graph.into_nodes_edges();
+
+        let mut executor = Self {
+            slots: Vec::with_capacity(nodes.len()),
+            edges: Vec::with_capacity(edges.len()),
+            tasks: FuturesUnordered::new(),
+            restarts: FuturesUnordered::new(),
+            sets: FuturesUnordered::new(),
+            bus,
+            tracers,
+            first_error: None,
+            cancel: watch::Sender::new(false),
+            stopping: None,
+            halt: watch::Sender::new(false),
+            stopped: Vec::new(),
+        };
+
+        // One inbox per node. Only the outlets may keep the channels open.
+        let (senders, inboxes): (Vec<_>, Vec<_>) = nodes
+            .iter()
+            .map(|_| mpsc::channel(CHANNEL_CAPACITY))
+            .unzip();
+        // Edges into each node, to know when all of them reached EOS.
+        let mut upstream = vec![HashSet::new(); nodes.len()];
+        let mut outlets: Vec<Vec<Outlet>> =
+            nodes.iter().map(|_| vec![]).collect();
+        for (index, edge) in edges.into_iter().enumerate() {
+            let (source, target) =
+                (edge.source().index(), edge.target().index());
+            let sent = Arc::new(AtomicU64::new(0));
+            upstream[target].insert(index);
+            outlets[source].push(Outlet {
+                edge: index,
+                pad: edge.weight.source,
+                sink: edge.weight.sink,
+                tx: senders[target].clone(),
+                sent: sent.clone(),
+            });
+            executor.edges.push(Some(Link {
+                source,
+                sink: target,
+                weight: edge.weight,
+                sent,
+            }));
+        }
+
+        for ((((node, tx), inbox), upstream), outlets) in nodes
+            .into_iter()
+            .zip(senders)
+            .zip(inboxes)
+            .zip(upstream)
+            .zip(outlets)
+        {
+            let role = if !upstream.is_empty() {
+                Role::Filter
+            } else {
+                Role::Source
+            };
+            let Node {
+                name,
+                config,
+                element,
+                restart,
+                ..
+            } = node.weight;
+            executor.spawn(
+                name,
+                config,
+                element,
+                restart,
+                tx.downgrade(),
+                None,
+                inbox,
+                outlets,
+                role,
+                upstream,
+            );
+        }
+
+        executor
+    }
+
+    /// Start a [`Task`] for a [`Node`] and give it the next [`Slot`].
+    #[allow(clippy::too_many_arguments)]
+    fn spawn(
+        &mut self,
+        name: String,
+        config: Option<node::Config>,
+        element: Box<dyn Element>,
+        restart: Policy,
+        inbox_tx: mpsc::WeakSender<Delivery>,
+        held: Option<mpsc::Sender<Delivery>>,
+        inbox: mpsc::Receiver<Delivery>,
+        outlets: Vec<Outlet>,
+        role: Role,
+        upstream: HashSet<usize>,
+    ) -> usize {
+        let index = self.slots.len();
+        let (control_tx, control) = mpsc::unbounded_channel();
+        let element_name = element.name().into_owned();
+        let backend = element.backend();
+        let origin = Origin {
+            node: index,
+            name: &name,
+            backend,
+        };
+        self.tracers.spawn(origin, &*element);
+        let readings = property::readings(&*element);
+        if !readings.is_empty() {
+            self.tracers.readings(origin, &readings);
+        }
+        let counters = element.counters();
+        if !counters.is_empty() {
+            self.tracers.counters(origin, &counters);
+        }
+        let readings = Arc::new(Mutex::new(readings));
+        let sources = node::source_caps(&*element);
+        let sinks = element.sinks().map(|pad| pad.caps()).collect();
+        let error_pad = sources.len() - 1;
+        let (errors, outlets) = outlets
+            .into_iter()
+            .partition(|o: &Outlet| o.pad == error_pad);
+
+        let task = Task {
+            element,
+            inbox,
+            control,
+            outlets,
+            errors,
+            error_pad,
+            source: role == Role::Source,
+            receiving: role != Role::Source,
+            upstream,
+            ended: HashSet::new(),
+            eos: false,
+            flushing: false,
+            stopped: false,
+            blocked: HashSet::new(),
+            owed: HashMap::new(),
+            interrupted: VecDeque::new(),
+            cancel: self.cancel.subscribe(),
+            tag: Tag {
+                bus: self.bus.clone(),
+                tracers: self.tracers.clone(),
+                node: index,
+                name: name.clone(),
+                backend,
+                readings: readings.clone(),
+            },
+        };
+        self.tasks.push(Box::pin(
+            tokio::spawn(task.run()).map(move |joined| (index, joined)),
+        ));
+
+        self.slots.push(Some(Slot {
+            name,
+            config,
+            element_name,
+            backend,
+            readings,
+            sources,
+            sinks,
+            role,
+            control: control_tx,
+            inbox: inbox_tx,
+            held,
+            element: None,
+            removal: None,
+            restart,
+            restarts: 0,
+            started: Instant::now(),
+        }));
+
+        index
+    }
+
+    /// Take back the [`Element`] of a finished [`Task`].
+    fn joined(&mut self, (index, joined): Joined) {
+        let Some(slot) = self.slots[index].as_mut() else {
+            return;
+        };
+
+        let (task, exit) = match joined {
+            Ok(joined) => joined,
+            Err(_) => {
+                self.first_error.get_or_insert(RunError::Panicked {
+                    node: index,
+                    name: slot.name.clone(),
+                });
+                match slot.removal.take() {
+                    Some(reply) => {
+                        reply
+                            .send(Err(ControlError::Finished { node: index }))
+                            .ok();
+                    }
+                    None => self.stopped.push(Stopped {
+                        node: index,
+                        name: slot.name.clone(),
+                        outcome: Outcome::Failed,
+                    }),
+                }
+                self.slots[index] = None;
+                return;
+            }
+        };
+
+        let failure = match exit {
+            Exit::Done(Ok(())) => None,
+            Exit::Done(Err(err)) => Some(err.to_string()),
+            Exit::Cancelled => {
+                return self.finish(index, task.element, None, true);
+            }
+        };
+        if slot.started.elapsed() >= slot.restart.backoff.reset() {
+            slot.restarts = 0;
+        }
+        let restart = slot.removal.is_none()
+            && self.stopping.is_none()
+            && slot.restart.restarts(
+                failure.is_some(),
+                task.resumable(),
+                slot.restarts,
+            );
+
+        if restart {
+            self.restart(index, task, failure);
+        } else {
+            self.finish(index, task.element, failure, false);
+        }
+    }
+
+    /// Keep the [`Element`] of a [`Node`] that is done for good, or hand it
+    /// to whoever removed it. `failure` is reported as a [`RunError`].
+    fn finish(
+        &mut self,
+        index: usize,
+        element: Box<dyn Element>,
+        failure: Option<String>,
+        cancelled: bool,
+    ) {
+        let Some(slot) = self.slots[index].as_mut() else {
+            return;
+        };
+
+        let outcome = match (&failure, cancelled) {
+            (_, true) => Outcome::Cancelled,
+            (Some(_), false) => Outcome::Failed,
+            (None, false) => Outcome::Clean,
+        };
+        if let (Some(message), None) = (failure, &self.first_error) {
+            self.first_error = Some(RunError::Element {
+                node: index,
+                name: slot.name.clone(),
+                message,
+            });
+        }
+
+        match slot.removal.take() {
+            Some(reply) => {
+                reply.send(Ok(element)).ok();
+                self.slots[index] = None;
+            }
+            None => {
+                self.stopped.push(Stopped {
+                    node: index,
+                    name: slot.name.clone(),
+                    outcome,
+                });
+                slot.element = Some(element);
+            }
+        }
+    }
+
+    /// Stop and initialize the [`Element`] of a [`Node`] again after the
+    /// [`Backoff`] of its [`Policy`]. The [`Task`] is kept, and so are its
+    /// links and anything sent to it meanwhile.
+    ///
+    /// [`Backoff`]: super::supervisor::Backoff
+    fn restart(
+        &mut self,
+        index: usize,
+        mut task: Task,
+        failure: Option<String>,
+    ) {
+        // Only running nodes are restarted.
+        let slot = self.slots[index].as_mut().unwrap();
+        slot.restarts += 1;
+        let attempt = slot.restarts;
+        let delay = slot.restart.backoff.delay(attempt);
+        self.bus.post(bus::Message::Restarting {
+            node: index,
+            name: slot.name.clone(),
+            attempt,
+            delay,
+        });
+
+        let mut halt = self.halt.subscribe();
+        self.restarts.push(Box::pin(async move {
+            let halted = tokio::select! {
+                _ = tokio::time::sleep(delay) => false,
+                Ok(_) = halt.wait_for(|&halt| halt) => true,
+            };
+            let init = if halted {
+                None
+            } else {
+                // The errors are not `Send` so they can't be held across an
+                // `.await`.
+                task.element.stop().await.ok();
+                let start = Instant::now();
+                let result =
+                    task.element.init().await.map_err(|e| e.to_string());
+                Some((start.elapsed(), result))
+            };
+
+            Restarted {
+                index,
+                task,
+                failure,
+                init,
+            }
+        }));
+    }
+
+    /// Run a [`Task`] back from [`Executor::restart`] again, unless its
+    /// [`Element`] failed to initialize or it is no longer wanted.
+    fn restarted(&mut self, restarted: Restarted) {
+        let Restarted {
+            index,
+            mut task,
+            failure,
+            init,
+        } = restarted;
+        let Some(slot) = self.slots[index].as_mut() else {
+            return;
+        };
+
+        let Some((elapsed, result)) = init else {
+            return self.finish(index, task.element, failure, false);
+        };
+        self.tracers
+            .init(task.tag.origin(), elapsed, result.is_ok());
+        if slot.removal.is_some() || self.stopping.is_some() {
+            return self.finish(index, task.element, failure, false);
+        }
+
+        if let Err(message) = result {
+            self.tracers.error(task.tag.origin(), &message);
+            self.bus.post(bus::Message::Error {
+                node: index,
+                name: slot.name.clone(),
+                message: message.clone(),
+            });
+            if slot.restart.restarts(true, false, slot.restarts) {
+                self.restart(index, task, Some(message));
+            } else {
+                self.finish(index, task.element, Some(message), false);
+            }
+            return;
+        }
+
+        slot.started = Instant::now();
+        task.stopped = false;
+        task.eos = false;
+        self.tasks.push(Box::pin(
+            tokio::spawn(task.run()).map(move |joined| (index, joined)),
+        ));
+    }
+
+    /// Handle a [`Request`] from a [`Control`], replying right away unless
+    /// a [`Node`] has to act on it first.
+    fn request(&mut self, request: Request) {
+        match request {
+            Request::Add { reply, .. } if self.stopping.is_some() => {
+                reply.send(Err(ControlError::ShuttingDown)).ok();
+            }
+            Request::Add {
+                name,
+                config,
+                element,
+                reply,
+            } => {
+                reply.send(self.add(name, config, element)).ok();
+            }
+            Request::Remove { node, reply } => self.remove(node.index(), reply),
+            Request::Link {
+                source,
+                source_pad,
+                sink,
+                sink_pad,
+                reply,
+            } => {
+                let result = self
+                    .link(source.index(), source_pad, sink.index(), sink_pad)
+                    .map(EdgeIndex::new);
+                reply.send(result).ok();
+            }
+            Request::Unlink { edge, reply } => {
+                reply.send(self.unlink(edge.index())).ok();
+            }
+            Request::Relink {
+                edge,
+                sink,
+                sink_pad,
+                reply,
+            } => {
+                reply
+                    .send(self.relink(edge.index(), sink.index(), sink_pad))
+                    .ok();
+            }
+            Request::Dot { reply } => {
+                reply.send(Ok(self.to_dot())).ok();
+            }
+            Request::Block { node, pad, reply } => {
+                self.probe(node.index(), pad, reply, |pad, reply| {
+                    Command::Block { pad, reply }
+                })
+            }
+            Request::Unblock { node, pad, reply } => {
+                self.probe(node.index(), pad, reply, |pad, reply| {
+                    Command::Unblock { pad, reply }
+                })
+            }
+            Request::Property { node, name, reply } => {
+                self.property(node.index(), Command::Property { name, reply })
+            }
+            Request::SetProperty {
+                node,
+                name,
+                value,
+                reply,
+            } => self.property(
+                node.index(),
+                Command::SetProperty { name, value, reply },
+            ),
+            Request::Checkpoint { reply } => self.checkpoint(reply),
+            Request::Config { reply } => {
+                reply.send(self.config().map_err(Into::into)).ok();
+            }
+            Request::Reload { plan, reply } => {
+                reply.send(self.reload(plan)).ok();
+            }
+            Request::Shutdown {
+                mode,
+                deadline,
+                reply,
+            } => self.shutdown(mode, deadline, reply),
+        }
+    }
+
+    /// Start or escalate a shutdown. The [`Report`] is sent once every
+    /// [`Task`] has finished.
+    fn shutdown(
+        &mut self,
+        mode: Mode,
+        deadline: Duration,
+        reply: Reply<Report>,
+    ) {
+        let now = Instant::now();
+        let deadline = now + deadline;
+        self.halt.send_replace(true);
+        let stopping = self.stopping.get_or_insert_with(|| Stopping {
+            mode,
+            started: now,
+            deadline,
+            replies: vec![],
+        });
+        stopping.deadline = stopping.deadline.min(deadline);
+        stopping.replies.push(reply);
+
+        if mode == Mode::Abort {
+            stopping.mode = Mode::Abort;
+            self.cancel.send_replace(true);
+            return;
+        }
+
+        for slot in self.slots.iter_mut().flatten() {
+            // Filters finish by themselves once everything upstream has.
+            if slot.element.is_none() && slot.role != Role::Filter {
+                slot.control.send(Command::Stop).ok();
+            }
+            slot.held = None;
+        }
+    }
+
+    /// When to cancel every [`Task`], if shutting down and not cancelled
+    /// yet.
+    fn deadline(&self) -> Option<Instant> {
+        self.stopping
+            .as_ref()
+            .filter(|_| !*self.cancel.borrow())
+            .map(|stopping| stopping.deadline)
+    }
+
+    /// Send the [`Report`] to whoever asked for a shutdown.
+    fn report(&mut self) {
+        let Some(stopping) = self.stopping.take() else {
+            return;
+        };
+        self.stopped.sort_by_key(|stopped| stopped.node);
+        let report = Report {
+            mode: stopping.mode,
+            elapsed: stopping.started.elapsed(),
+            nodes: self.stopped.clone(),
+        };
+        for reply in stopping.replies {
+            reply.send(Ok(report.clone())).ok();
+        }
+    }
+
+    /// Apply a [`Command::Property`] or [`Command::SetProperty`]. A running
+    /// [`Node`] does so between two [`Buffer`]s. A finished one has its
+    /// [`Element`] here.
+    fn property(&mut self, node: usize, command: Command) {
+        let fail = |command, err| match command {
+            Command::Property { reply, .. }
+            | Command::SetProperty { reply, .. } => {
+                reply.send(Err(err)).ok();
+            }
+            _ => unreachable!("only property commands are applied here"),
+        };
+        let Some(Some(slot)) = self.slots.get_mut(node) else {
+            fail(command, LinkError::NoSuchNode { node }.into());
+            return;
+        };
+
+        let Some(element) = slot.element.as_deref_mut() else {
+            // Sets are recorded in the config once the task replies.
+            let command =
+                match command {
+                    Command::SetProperty { name, value, reply } => {
+                        let (tx, rx) = oneshot::channel();
+                        let set = name.clone();
+                        self.sets.push(Box::pin(rx.map(move |result| Set {
+                            index: node,
+                            name: set,
+                            result: result.unwrap_or(Err(
+                                ControlError::Finished { node },
+                            )),
+                            reply,
+                        })));
+                        Command::SetProperty {
+                            name,
+                            value,
+                            reply: tx,
+                        }
+                    }
+                    command => command,
+                };
+            // The task is gone but has not been joined yet.
+            if let Err(mpsc::error::SendError(command)) =
+                slot.control.send(command)
+            {
+                fail(command, ControlError::Finished { node });
+            }
+            return;
+        };
+
+        match command {
+            Command::Property { name, reply } => {
+                let result = property::get(element, &name);
+                reply.send(result.map_err(Into::into)).ok();
+            }
+            Command::SetProperty { name, value, reply } => {
+                let result = property::set(element, &name, value);
+                if let Ok(value) = &result {
+                    if let Some(config) = &mut slot.config {
+                        config.set(&name, value);
+                    }
+                    self.bus.post(bus::Message::PropertyChanged {
+                        node,
+                        name: slot.name.clone(),
+                        property: name,
+                        value: value.clone(),
+                    });
+                }
+                reply.send(result.map_err(Into::into)).ok();
+            }
+            _ => unreachable!("only property commands are applied here"),
+        }
+    }
+
+    /// Record a [`Set`] applied by a running [`Task`] in the config of its
+    /// [`Node`], then pass the result on.
+    fn set(&mut self, set: Set) {
+        let Set {
+            index,
+            name,
+            result,
+            reply,
+        } = set;
+        if let (Ok(value), Some(Some(slot))) =
+            (&result, self.slots.get_mut(index))
+        {
+            if let Some(config) = &mut slot.config {
+                config.set(&name, value);
+            }
+        }
+        reply.send(result).ok();
+    }
+
+    /// Take a [`Checkpoint`]. A running [`Node`] saves its state between two
+    /// [`Buffer`]s, so the reply is sent from another task once all of them
+    /// have.
+    fn checkpoint(&mut self, reply: Reply<Checkpoint>) {
+        let config = match self.config() {
+            Ok(config) => config,
+            Err(err) => {
+                reply.send(Err(err.into())).ok();
+                return;
+            }
+        };
+
+        let mut saves = vec![];
+        for (index, slot) in self.slots.iter().enumerate() {
+            let Some(slot) = slot.as_ref().filter(|s| s.removal.is_none())
+            else {
+                continue;
+            };
+            let (tx, rx) = oneshot::channel();
+            match slot.element.as_deref() {
+                Some(element) => {
+                    tx.send(save(element)).ok();
+                }
+                // If the task is gone but not joined yet, `rx` fails.
+                None => {
+                    slot.control.send(Command::Checkpoint { reply: tx }).ok();
+                }
+            }
+            saves.push((index, slot.name.clone(), rx));
+        }
+
+        tokio::spawn(async move {
+            let mut state = BTreeMap::new();
+            for (node, name, rx) in saves {
+                let err = match rx.await {
+                    Ok(Ok(Some(saved))) => {
+                        state.insert(name, saved);
+                        continue;
+                    }
+                    Ok(Ok(None)) => continue,
+                    Ok(Err(message)) => {
+                        CheckpointError::Save { name, message }.into()
+                    }
+                    Err(_) => ControlError::Finished { node },
+                };
+                reply.send(Err(err)).ok();
+                return;
+            }
+
+            reply.send(Ok(Checkpoint { config, state })).ok();
+        });
+    }
+
+    /// The [`Config`] of the running graph. [`Node`]s being removed are left
+    /// out, and so are their [`Edge`]s, which are already unlinked.
+    ///
+    /// [`Config`]: config::Config
+    fn config(&self) -> Result<config::Config, CheckpointError> {
+        let mut nodes = vec![];
+        for slot in self.slots.iter().flatten() {
+            if slot.removal.is_some() {
+                continue;
+            }
+            let Some(config) = slot.config.clone() else {
+                let name = slot.name.clone();
+                return Err(CheckpointError::NoConfig { name });
+            };
+            nodes.push(config::Node {
+                name: slot.name.clone(),
+                config,
+                restart: slot.restart,
+            });
+        }
+
+        let edges = self
+            .edges
+            .iter()
+            .flatten()
+            .filter_map(|link| {
+                Some(config::Edge {
+                    source: self.slots.get(link.source)?.as_ref()?.name.clone(),
+                    source_pad: Some(link.weight.source),
+                    sink: self.slots.get(link.sink)?.as_ref()?.name.clone(),
+                    sink_pad: Some(link.weight.sink),
+                    feedback: link.weight.feedback,
+                })
+            })
+            .collect();
+
+        Ok(config::Config { nodes, edges })
+    }
+
+    /// Index of the [`Node`] called `name`, unless it is being removed.
+    fn find(&self, name: &str) -> Result<usize, ControlError> {
+        self.slots
+            .iter()
+            .position(|slot| {
+                slot.as_ref().is_some_and(|slot| {
+                    slot.name == name && slot.removal.is_none()
+                })
+            })
+            .ok_or_else(|| {
+                BuildError::UnknownNode {
+                    name: name.to_owned(),
+                }
+                .into()
+            })
+    }
+
+    /// Apply a reload [`Plan`]. New [`Edge`]s are linked before old ones are
+    /// unlinked and [`Node`]s removed, so the [`Node`]s in between never run
+    /// out of upstream [`Node`]s and finish. Every [`Edge`] is checked before
+    /// anything changes, so a bad one leaves the graph as it was.
+    fn reload(&mut self, plan: Plan) -> Result<Reloaded, ControlError> {
+        if self.stopping.is_some() {
+            return Err(ControlError::ShuttingDown);
+        }
+        let Plan {
+            changes,
+            updates,
+            nodes,
+        } = plan;
+
+        // Checked first, so nothing changes if the graph changed since the
+        // plan was made or an edge can't be linked.
+        let gone: HashSet<_> =
+            changes.removed.iter().chain(&changes.replaced).collect();
+        let removed = gone
+            .iter()
+            .map(|name| self.find(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let updated = updates
+            .iter()
+            .map(|(node, _)| self.find(&node.name))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.check(&changes.linked, &nodes)?;
+        let unlinked = changes
+            .unlinked
+            .iter()
+            .filter(|edge| {
+                !gone.contains(&edge.source) && !gone.contains(&edge.sink)
+            })
+            .map(|edge| {
+                Ok((edge, self.find(&edge.source)?, self.find(&edge.sink)?))
+            })
+            .collect::<Result<Vec<_>, ControlError>>()?;
+
+        let mut set = vec![];
+        // Each property is recorded in the config once it is set.
+        for ((_, values), index) in updates.into_iter().zip(updated) {
+            for (name, value) in values {
+                let (reply, rx) = oneshot::channel();
+                self.property(
+                    index,
+                    Command::SetProperty { name, value, reply },
+                );
+                set.push(rx);
+            }
+        }
+
+        // Replaced nodes share their name with the old ones until those are
+        // removed below.
+        let mut added = HashMap::new();
+        for (node, element) in nodes {
+            let index = self.idle(
+                node.name.clone(),
+                Some(node.config),
+                element,
+                node.restart,
+            );
+            added.insert(node.name, index);
+        }
+        let find = |executor: &Self, name: &String| match added.get(name) {
+            Some(&index) => Ok(index),
+            None => executor.find(name),
+        };
+
+        // Only a node finishing meanwhile makes a checked edge fail.
+        let mut failed = None;
+        for edge in changes.linked.iter() {
+            // The plan takes edges from `Pipeline::config`, with every pad.
+            let (source_pad, sink_pad) =
+                edge.source_pad.zip(edge.sink_pad).unwrap_or_default();
+            let result = find(self, &edge.source)
+                .and_then(|source| Ok((source, find(self, &edge.sink)?)))
+                .and_then(|(source, sink)| {
+                    self.link(source, source_pad, sink, sink_pad)
+                });
+            match result {
+                Ok(index) => {
+                    if let Some(Some(link)) = self.edges.get_mut(index) {
+                        link.weight.feedback = edge.feedback;
+                    }
+                }
+                Err(err) => {
+                    failed.get_or_insert(err);
+                }
+            }
+        }
+
+        // Those of removed and replaced nodes go with them.
+        for (edge, source, sink) in unlinked {
+            let index = self.edges.iter().position(|link| {
+                link.as_ref().is_some_and(|link| {
+                    link.source == source
+                        && link.sink == sink
+                        && Some(link.weight.source) == edge.source_pad
+                        && Some(link.weight.sink) == edge.sink_pad
+                })
+            });
+            if let Some(Err(err)) = index.map(|index| self.unlink(index)) {
+                failed.get_or_insert(err);
+            }
+        }
+
+        let removed = removed
+            .into_iter()
+            .map(|index| {
+                let (reply, rx) = oneshot::channel();
+                self.remove(index, reply);
+                rx
+            })
+            .collect();
+
+        if failed.is_none() {
+            self.bus.post(bus::Message::Reloaded { changes });
+        }
+
+        Ok(Reloaded {
+            set,
+            removed,
+            failed,
+        })
+    }
+
+    /// Check that every [`Edge`] in `linked` can be linked in order, as by
+    /// [`link`], once the `fresh` [`Node`]s of a reload are added.
+    ///
+    /// [`link`]: Executor::link
+    fn check(
+        &mut self,
+        linked: &[config::Edge],
+        fresh: &[(config::Node, Box<dyn Element>)],
+    ) -> Result<(), ControlError> {
+        let mut nodes: HashMap<&str, Planned> = HashMap::new();
+        for (i, (node, element)) in fresh.iter().enumerate() {
+            let planned = Planned {
+                index: self.slots.len() + i,
+                sources: node::source_caps(element.as_ref()),
+                sinks: element.sinks().map(|pad| pad.caps()).collect(),
+                role: Role::Idle,
+            };
+            nodes.insert(&node.name, planned);
+        }
+        for edge in linked {
+            for name in [&edge.source, &edge.sink] {
+                if !nodes.contains_key(name.as_str()) {
+                    let index = self.find(name)?;
+                    let slot = self.running(index)?;
+                    let planned = Planned {
+                        index,
+                        sources: slot.sources.clone(),
+                        sinks: slot.sinks.clone(),
+                        role: slot.role,
+                    };
+                    nodes.insert(name, planned);
+                }
+            }
+
+            let (source_pad, sink_pad) =
+                edge.source_pad.zip(edge.sink_pad).unwrap_or_default();
+            let (source, sink) =
+                (&nodes[edge.source.as_str()], &nodes[edge.sink.as_str()]);
+            let source_caps = *source.sources.get(source_pad).ok_or(
+                LinkError::NoSuchSourcePad {
+                    node: source.index,
+                    pad: source_pad,
+                },
+            )?;
+            let sink_caps =
+                *sink.sinks.get(sink_pad).ok_or(LinkError::NoSuchSinkPad {
+                    node: sink.index,
+                    pad: sink_pad,
+                })?;
+            if !sink_caps.accepts(source_caps) {
+                return Err(LinkError::Incompatible {
+                    source_node: source.index,
+                    source_caps,
+                    sink_node: sink.index,
+                    sink_caps,
+                }
+                .into());
+            }
+            if sink.role == Role::Source {
+                return Err(ControlError::Source { node: sink.index });
+            }
+
+            for (name, role) in
+                [(&edge.sink, Role::Filter), (&edge.source, Role::Source)]
+            {
+                let node = nodes.get_mut(name.as_str()).expect("added above");
+                if node.role == Role::Idle {
+                    node.role = role;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add an idle [`Node`].
+    fn add(
+        &mut self,
+        name: String,
+        config: Option<node::Config>,
+        element: Box<dyn Element>,
+    ) -> Result<NodeIndex, ControlError> {
+        if self.slots.iter().flatten().any(|slot| slot.name == name) {
+            return Err(BuildError::DuplicateName { name }.into());
+        }
+
+        let index = self.idle(name, config, element, Policy::default());
+
+        Ok(NodeIndex::new(index))
+    }
+
+    /// Spawn a [`Node`] that does nothing until it is linked.
+    fn idle(
+        &mut self,
+        name: String,
+        config: Option<node::Config>,
+        element: Box<dyn Element>,
+        restart: Policy,
+    ) -> usize {
+        // The executor keeps the inbox open until the first link.
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        self.spawn(
+            name,
+            config,
+            element,
+            restart,
+            tx.downgrade(),
+            Some(tx),
+            rx,
+            vec![],
+            Role::Idle,
+            HashSet::new(),
+        )
+    }
+
+    /// The [`Slot`] of a [`Node`] whose [`Task`] is still running.
+    fn running(&mut self, node: usize) -> Result<&mut Slot, ControlError> {
+        match self.slots.get_mut(node) {
+            Some(Some(slot)) if slot.element.is_none() => Ok(slot),
+            Some(Some(_)) => Err(ControlError::Finished { node }),
+            _ => Err(LinkError::NoSuchNode { node }.into()),
+        }
+    }
+
+    /// Link two running [`Node`]s. Returns the index of the new [`Edge`].
+    fn link(
+        &mut self,
+        source: usize,
+        source_pad: usize,
+        sink: usize,
+        sink_pad: usize,
+    ) -> Result<usize, ControlError> {
+        let source_caps =
+            *self.running(source)?.sources.get(source_pad).ok_or(
+                LinkError::NoSuchSourcePad {
+                    node: source,
+                    pad: source_pad,
+                },
+            )?;
+        let edge = self.edges.len();
+        let slot = self.running(sink)?;
+        let sink_caps =
+            *slot.sinks.get(sink_pad).ok_or(LinkError::NoSuchSinkPad {
+                node: sink,
+                pad: sink_pad,
+            })?;
+        if !sink_caps.accepts(source_caps) {
+            return Err(LinkError::Incompatible {
+                source_node: source,
+                source_caps,
+                sink_node: sink,
+                sink_caps,
+            }
+            .into());
+        }
+
+        let tx = match slot.role {
+            Role::Source => return Err(ControlError::Source { node: sink }),
+            Role::Idle => {
+                slot.role = Role::Filter;
+                // The outlet keeps the inbox open from now on.
+                slot.held.take()
+            }
+            _ => slot.inbox.upgrade(),
+        }
+        .ok_or(ControlError::Finished { node: sink })?;
+        // Sent before the outlet exists, so it is handled before any
+        // delivery on it.
+        slot.control.send(Command::AddUpstream { edge }).ok();
+
+        let sent = Arc::new(AtomicU64::new(0));
+        let slot = self.running(source)?;
+        slot.control
+            .send(Command::Link(Outlet {
+                edge,
+                pad: source_pad,
+                sink: sink_pad,
+                tx,
+                sent: sent.clone(),
+            }))
+            .ok();
+        if slot.role == Role::Idle {
+            slot.role = Role::Source;
+            slot.held = None;
+            slot.control.send(Command::Source).ok();
+        }
+
+        self.edges.push(Some(Link {
+            source,
+            sink,
+            weight: Edge::new(source_pad, sink_pad, source_caps),
+            sent,
+        }));
+
+        Ok(edge)
+    }
+
+    /// Remove an [`Edge`]. What was sent on it is still delivered.
+    fn unlink(&mut self, edge: usize) -> Result<(), ControlError> {
+        let Link {
+            source,
+            sink,
+            weight,
+            ..
+        } = self
+            .edges
+            .get_mut(edge)
+            .and_then(Option::take)
+            .ok_or(ControlError::NoSuchEdge { edge })?;
+
+        // Finished nodes don't care.
+        if let Some(Some(slot)) = self.slots.get(source) {
+            slot.control.send(Command::Unlink { edge }).ok();
+        }
+        if let Some(Some(slot)) = self.slots.get(sink) {
+            slot.control
+                .send(Command::RemoveUpstream {
+                    edge,
+                    pad: weight.sink,
+                })
+                .ok();
+        }
+
+        Ok(())
+    }
+
+    /// Link the upstream end of an [`Edge`] to another sink pad, then unlink
+    /// it. The upstream [`Node`] handles both between the same two
+    /// [`Buffer`]s.
+    fn relink(
+        &mut self,
+        edge: usize,
+        sink: usize,
+        sink_pad: usize,
+    ) -> Result<EdgeIndex, ControlError> {
+        let (source, source_pad, feedback) = match self.edges.get(edge) {
+            Some(Some(link)) => {
+                (link.source, link.weight.source, link.weight.feedback)
+            }
+            _ => return Err(ControlError::NoSuchEdge { edge }),
+        };
+        let new = self.link(source, source_pad, sink, sink_pad)?;
+        self.unlink(edge)?;
+        if let Some(Some(link)) = self.edges.get_mut(new) {
+            link.weight.feedback = feedback;
+        }
+
+        Ok(EdgeIndex::new(new))
+    }
+
+    /// Unlink a [`Node`] and have its [`Element`] sent to `reply` once it
+    /// has finished.
+    fn remove(&mut self, node: usize, reply: Reply<Box<dyn Element>>) {
+        let linked: Vec<usize> = self
+            .edges
+            .iter()
+            .enumerate()
+            .filter_map(|(index, edge)| {
+                edge.as_ref()
+                    .filter(|link| link.source == node || link.sink == node)
+                    .map(|_| index)
+            })
+            .collect();
+
+        let Some(Some(slot)) = self.slots.get_mut(node) else {
+            reply.send(Err(LinkError::NoSuchNode { node }.into())).ok();
+            return;
+        };
+        if let Some(element) = slot.element.take() {
+            reply.send(Ok(element)).ok();
+            self.slots[node] = None;
+        } else {
+            // A filter finishes by itself once its inbox is drained.
+            if slot.role != Role::Filter {
+                slot.control.send(Command::Stop).ok();
+            }
+            slot.held = None;
+            slot.removal = Some(reply);
+        }
+
+        for edge in linked {
+            // The edge exists, it was just listed.
+            self.unlink(edge).ok();
+        }
+    }
+
+    /// Forward a [`Command::Block`] or [`Command::Unblock`] to a [`Node`],
+    /// which replies once it is between two [`Buffer`]s.
+    fn probe(
+        &mut self,
+        node: usize,
+        pad: usize,
+        reply: Reply<()>,
+        command: impl FnOnce(usize, Reply<()>) -> Command,
+    ) {
+        // The error pad, last, is never pulled.
+        let slot = match self.running(node) {
+            Ok(slot) if pad + 1 < slot.sources.len() => slot,
+            Ok(_) => {
+                let err = LinkError::NoSuchSourcePad { node, pad };
+                reply.send(Err(err.into())).ok();
+                return;
+            }
+            Err(err) => {
+                reply.send(Err(err)).ok();
+                return;
+            }
+        };
+
+        // The task is gone but has not been joined yet.
+        if let Err(mpsc::error::SendError(
+            Command::Block { reply, .. } | Command::Unblock { reply, .. },
+        )) = slot.control.send(command(pad, reply))
+        {
+            reply.send(Err(ControlError::Finished { node })).ok();
+        }
+    }
+
+    /// Export the graph as it is now, like [`Pipeline::to_dot`], with the
+    /// state of each [`Node`], the fill of its inbox and the number of
+    /// [`Buffer`]s sent on each [`Edge`].
+    ///
+    /// [`Pipeline::to_dot`]: super::Pipeline::to_dot
+    fn to_dot(&self) -> String {
+        let nodes =
+            self.slots.iter().enumerate().filter_map(|(index, slot)| {
+                let slot = slot.as_ref()?;
+                let state = match (slot.role, &slot.element, &slot.removal) {
+                    (_, Some(_), _) => "finished",
+                    (_, None, Some(_)) => "removing",
+                    (Role::Source, ..) => "source",
+                    (Role::Filter, ..) => "filter",
+                    (Role::Idle, ..) => "idle",
+                };
+                let mut status = vec![state.to_owned()];
+                if let Some(tx) = slot.inbox.upgrade() {
+                    status.push(format!(
+                        "inbox: {}/{}",
+                        tx.max_capacity() - tx.capacity(),
+                        tx.max_capacity()
+                    ));
+                }
+
+                Some(dot::Node {
+                    index,
+                    name: &slot.name,
+                    element: &slot.element_name,
+                    backend: slot.backend,
+                    config: slot.config.as_ref(),
+                    readings: lock(&slot.readings).clone(),
+                    status,
+                })
+            });
+        let edges = self.edges.iter().flatten().map(|link| dot::Edge {
+            source: link.source,
+            sink: link.sink,
+            source_pad: link.weight.source,
+            sink_pad: link.weight.sink,
+            caps: link.weight.caps,
+            sent: Some(link.sent.load(Ordering::Relaxed)),
+        });
+
+        dot::render(nodes, edges)
+    }
+
+    /// Rebuild the graph from the finished [`Node`]s, with the first error.
+    /// [`Node`]s whose task panicked are left out, since their [`Element`]
+    /// is lost.
+    fn into_graph(self) -> (Graph<Node<S>, Edge<S>>, Option<RunError>) {
+        let mut graph =
+            Graph::with_capacity(self.slots.len(), self.edges.len());
+        let mut indices = Vec::with_capacity(self.slots.len());
+        for slot in self.slots {
+            indices.push(slot.and_then(|slot| {
+                // Every other task has finished and handed its element back.
+                let element = slot.element?;
+                Some(
+                    graph.add_node(
+                        Node::new(slot.name, slot.config, element)
+                            .with_restart(slot.restart),
+                    ),
+                )
+            }));
+        }
+
+        for link in self.edges.into_iter().flatten() {
+            if let (Some(source), Some(sink)) =
+                (indices[link.source], indices[link.sink])
+            {
+                graph.add_edge(source, sink, link.weight);
+            }
+        }
+
+        (graph, self.first_error)
+    }
+}
+
+/// Where a [`Node`] task reports to, and who it is.
+struct Tag {
+    bus: Bus,
+    tracers: Tracers,
+    node: usize,
+    name: String,
+    backend: Backend,
+    readings: Readings,
+}
+
+/// The latest [`property::readings`] of an [`Element`], shared by its
+/// [`Task`] and [`Slot`].
+type Readings = Arc<Mutex<Vec<(&'static str, Value)>>>;
+
+/// Lock [`Readings`]. They are only ever replaced whole, so a poisoned lock
+/// is fine.
+fn lock(
+    readings: &Readings,
+) -> std::sync::MutexGuard<'_, Vec<(&'static str, Value)>> {
+    readings
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Tag {
+    /// Who is reporting, for [`Tracer`]s.
+    fn origin(&self) -> Origin<'_> {
+        Origin {
+            node: self.node,
+            name: &self.name,
+            backend: self.backend,
+        }
+    }
+
+    /// When a traced push or pull started, if there are [`Tracer`]s.
+    fn start(&self) -> Option<Instant> {
+        (!self.tracers.is_empty()).then(Instant::now)
+    }
+
+    /// Describe a [`Buffer`] about to be pushed and start timing, if there
+    /// are [`Tracer`]s.
+    fn pushing(&self, buffer: &dyn Buffer) -> Option<(BufferInfo, Instant)> {
+        (!self.tracers.is_empty())
+            .then(|| (BufferInfo::of(buffer), Instant::now()))
+    }
+
+    /// Trace a push started with [`pushing`].
+    ///
+    /// [`pushing`]: Tag::pushing
+    fn pushed(
+        &self,
+        pad: usize,
+        edge: usize,
+        traced: Option<(BufferInfo, Instant)>,
+        ok: bool,
+    ) {
+        if let Some((buffer, start)) = traced {
+            self.tracers.push(&Transfer {
+                origin: self.origin(),
+                pad,
+                edge,
+                buffer: Some(&buffer),
+                elapsed: start.elapsed(),
+                ok,
+            });
+        }
+    }
+
+    /// Take the [`Readings`] and [`Counter`]s of `element`, if it has any,
+    /// after it was pushed or pulled.
+    ///
+    /// [`Counter`]: property::Counter
+    fn read(&self, element: &dyn Element) {
+        if !self.tracers.is_empty() {
+            let counters = element.counters();
+            if !counters.is_empty() {
+                self.tracers.counters(self.origin(), &counters);
+            }
+        }
+        let readings = property::readings(element);
+        if readings.is_empty() {
+            return;
+        }
+        self.tracers.readings(self.origin(), &readings);
+        *lock(&self.readings) = readings;
+    }
+
+    /// Trace a pull started at `start`.
+    fn pulled(
+        &self,
+        pad: usize,
+        edge: usize,
+        start: Option<Instant>,
+        result: &Result<Box<dyn Buffer>, Box<dyn Error>>,
+    ) {
+        if let Some(start) = start {
+            let elapsed = start.elapsed();
+            let buffer = result.as_ref().ok().map(|b| BufferInfo::of(&**b));
+            self.tracers.pull(&Transfer {
+                origin: self.origin(),
+                pad,
+                edge,
+                buffer: buffer.as_ref(),
+                elapsed,
+                ok: result.is_ok(),
+            });
+        }
+    }
+}
+
+/// Why a [`Task`] woke up.
+enum Wake {
+    Command(Command),
+    Delivery(Delivery),
+    /// Every sender of the inbox is gone.
+    Hangup,
+    /// Downstream has room.
+    Room,
+    /// The [`Element`] may have something new to pull, or room.
+    Ready,
+    /// Nothing can ever wake the [`Task`] again.
+    Idle,
+}
+
+/// The task running a single [`Node`].
+struct Task {
+    element: Box<dyn Element>,
+    inbox: mpsc::Receiver<Delivery>,
+    control: mpsc::UnboundedReceiver<Command>,
+    outlets: Vec<Outlet>,
+    /// [`Outlet`]s of the error pad, at index `error_pad`. Never pulled.
+    errors: Vec<Outlet>,
+    error_pad: usize,
+    /// Pulled without being pushed to.
+    source: bool,
+    /// Still taking [`Delivery`]s from the inbox.
+    receiving: bool,
+    /// [`Edge`]s feeding the inbox, and those of them that reached
+    /// [`Event::Eos`].
+    upstream: HashSet<usize>,
+    ended: HashSet<usize>,
+    /// [`Event::Eos`] is to be sent once drained. Only for [`decoupled`]
+    /// [`Element`]s.
+    ///
+    /// [`decoupled`]: Element::decoupled
+    eos: bool,
+    flushing: bool,
+    stopped: bool,
+    /// Source pads that must not be pulled.
+    blocked: HashSet<usize>,
+    /// Pulls skipped per blocked pad, made up for when it is unblocked.
+    owed: HashMap<usize, usize>,
+    /// [`Command`]s that arrived while a source was being pulled.
+    interrupted: VecDeque<Command>,
+    /// Becomes `true` when the [`Task`] is to be cancelled.
+    cancel: watch::Receiver<bool>,
+    tag: Tag,
+}
+
+impl Task {
+    /// Body of the task. Hands the [`Element`] back when done.
+    async fn run(mut self) -> (Self, Exit) {
+        let mut cancel = self.cancel.clone();
+        let cancelled = async move {
+            // Never cancelled if the executor is gone.
+            if cancel.wait_for(|&cancel| cancel).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        };
+        // Cancelling drops whatever the `Element` was doing.
+        let result = tokio::select! {
+            result = self.drive() => result,
+            _ = cancelled => return (self, Exit::Cancelled),
+        };
+
+        if let Err(err) = &result {
+            let message = err.to_string();
+            self.tag.tracers.error(self.tag.origin(), &message);
+            self.tag.bus.post(bus::Message::Error {
+                node: self.tag.node,
+                name: self.tag.name.clone(),
+                message,
+            });
+        }
+
+        (self, Exit::Done(result))
+    }
+
+    /// Whether the [`Task`] would have more to do after finishing by itself:
+    /// someone downstream is listening, and it is a source or upstream has
+    /// not ended.
+    fn resumable(&self) -> bool {
+        let listened = self.outlets.iter().any(|o| !o.tx.is_closed());
+        listened
+            && (self.source
+                || (self.receiving && self.ended.len() < self.upstream.len()))
+    }
+
+    /// Push, pull and apply [`Command`]s until [`done`].
+    ///
+    /// [`done`]: Task::done
+    async fn drive(&mut self) -> Result<(), Box<dyn Error>> {
+        loop {
+            if self.pulled() && !self.stopped {
+                self.drain().await?;
+            }
+            if !self.interrupted.is_empty() {
+                for command in std::mem::take(&mut self.interrupted) {
+                    self.command(command).await?;
+                }
+                continue;
+            }
+            if self.done() {
+                break;
+            }
+
+            match self.wait().await {
+                Wake::Command(command) => self.commands(command).await?,
+                Wake::Delivery(delivery) => self.deliver(delivery).await?,
+                Wake::Hangup => self.receiving = false,
+                // The permit is dropped and `drain` takes it.
+                Wake::Room | Wake::Ready => {}
+                Wake::Idle => break,
+            }
+
+            if self.emit_events().await {
+                return Ok(());
+            }
+        }
+
+        if self.eos {
+            self.send_event(Event::Eos).await;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the [`Element`] is pulled whenever downstream has room,
+    /// rather than once per push.
+    fn pulled(&self) -> bool {
+        self.source || self.element.decoupled()
+    }
+
+    /// Whether the [`Node`] has nothing left to do.
+    fn done(&self) -> bool {
+        if self.stopped {
+            return true;
+        }
+
+        // Blocked pads still count, they will be unblocked.
+        let pending = self
+            .outlets
+            .iter()
+            .any(|o| !o.tx.is_closed() && self.element.can_pull(o.pad));
+        let hung_up = !self.outlets.is_empty()
+            && self.outlets.iter().all(|o| o.tx.is_closed());
+
+        if self.source {
+            // Nothing left to pull or nobody listening.
+            !pending && (hung_up || !self.element.busy())
+        } else if self.receiving {
+            // Everybody downstream is gone. There is no point continuing.
+            hung_up
+        } else if self.element.decoupled() {
+            !pending && (hung_up || !self.element.busy())
+        } else {
+            self.owed.is_empty() || hung_up
+        }
+    }
+
+    /// Wait for a [`Command`], a [`Delivery`] or room downstream. Commands
+    /// come first.
+    async fn wait(&mut self) -> Wake {
+        let receiving = self.receiving
+            && (!self.element.decoupled() || self.element.can_push());
+        let ready: Vec<&mpsc::Sender<Delivery>> = if self.pulled() {
+            self.outlets
+                .iter()
+                .filter(|o| {
+                    !o.tx.is_closed()
+                        && !self.blocked.contains(&o.pad)
+                        && self.element.can_pull(o.pad)
+                })
+                .map(|o| &o.tx)
+                .collect()
+        } else {
+            vec![]
+        };
+        let waiting = !ready.is_empty();
+        let busy = self.pulled() && self.element.busy();
+        // Lazy, because `select_all` panics on nothing.
+        let room = async move {
+            futures::future::select_all(
+                ready.into_iter().map(|tx| Box::pin(tx.reserve())),
+            )
+            .await
+        };
+
+        tokio::select! {
+            biased;
+            Some(command) = self.control.recv() => Wake::Command(command),
+            delivery = self.inbox.recv(), if receiving => match delivery {
+                Some(delivery) => Wake::Delivery(delivery),
+                None => Wake::Hangup,
+            },
+            _ = room, if waiting => Wake::Room,
+            _ = self.element.ready(), if busy => Wake::Ready,
+            else => Wake::Idle,
+        }
+    }
+
+    /// Apply a [`Command`] and any sent along with it, so they take effect
+    /// together.
+    async fn commands(
+        &mut self,
+        command: Command,
+    ) -> Result<(), Box<dyn Error>> {
+        self.command(command).await?;
+        while let Ok(command) = self.control.try_recv() {
+            self.command(command).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply a [`Command`]. Nothing is being pushed or pulled meanwhile.
+    async fn command(
+        &mut self,
+        command: Command,
+    ) -> Result<(), Box<dyn Error>> {
+        match command {
+            Command::Link(outlet) if outlet.pad == self.error_pad => {
+                self.errors.push(outlet)
+            }
+            Command::Link(outlet) => self.outlets.push(outlet),
+            Command::Unlink { edge } => {
+                self.outlets.retain(|outlet| outlet.edge != edge);
+                self.errors.retain(|outlet| outlet.edge != edge);
+                // Nobody listens any more, as if every outlet was closed.
+                if self.source && self.outlets.is_empty() {
+                    self.stopped = true;
+                }
+            }
+            Command::AddUpstream { edge } => {
+                self.upstream.insert(edge);
+            }
+            Command::RemoveUpstream { edge, pad } => {
+                self.upstream.remove(&edge);
+                self.ended.remove(&edge);
+                // The others may all have ended already.
+                if self.receiving
+                    && !self.ended.is_empty()
+                    && self.ended.len() == self.upstream.len()
+                {
+                    self.end(pad).await?;
+                }
+            }
+            Command::Source => {
+                self.source = true;
+                self.receiving = false;
+            }
+            Command::Stop => self.stopped = true,
+            Command::Block { pad, reply } => {
+                self.blocked.insert(pad);
+                reply.send(Ok(())).ok();
+            }
+            Command::Unblock { pad, reply } => {
+                self.blocked.remove(&pad);
+                for _ in 0..self.owed.remove(&pad).unwrap_or(0) {
+                    for index in 0..self.outlets.len() {
+                        if self.outlets[index].pad == pad {
+                            self.send_pulled(index).await?;
+                        }
+                    }
+                }
+                reply.send(Ok(())).ok();
+            }
+            Command::Property { name, reply } => {
+                let result = property::get(self.element.as_ref(), &name);
+                reply.send(result.map_err(Into::into)).ok();
+            }
+            Command::SetProperty { name, value, reply } => {
+                let result = property::set(self.element.as_mut(), &name, value);
+                if let Ok(value) = &result {
+                    self.tag.bus.post(bus::Message::PropertyChanged {
+                        node: self.tag.node,
+                        name: self.tag.name.clone(),
+                        property: name,
+                        value: value.clone(),
+                    });
+                }
+                reply.send(result.map_err(Into::into)).ok();
+            }
+            Command::Checkpoint { reply } => {
+                reply.send(save(self.element.as_ref())).ok();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Give a [`Delivery`] to the [`Element`] and, unless it is pulled on its
+    /// own, forward what it yields.
+    async fn deliver(
+        &mut self,
+        Delivery { pad, edge, item }: Delivery,
+    ) -> Result<(), Box<dyn Error>> {
+        match item {
+            Item::Buffer(_) if self.flushing => {}
+            Item::Buffer(buffer) => {
+                let traced = self.tag.pushing(buffer.as_ref());
+                let result = push(self.element.as_mut(), pad, buffer).await;
+                self.tag.pushed(pad, edge, traced, result.is_ok());
+                if let Err(error) = result {
+                    return route(&self.errors, error).await;
+                }
+                if !self.pulled() {
+                    self.forward().await?;
+                }
+            }
+            Item::Event(Event::Eos) => {
+                // A removed edge no longer counts.
+                if !self.upstream.contains(&edge) {
+                    return Ok(());
+                }
+                self.ended.insert(edge);
+                if self.ended.len() == self.upstream.len() {
+                    self.end(pad).await?;
+                }
+            }
+            Item::Event(event) => {
+                match event {
+                    Event::FlushStart => self.flushing = true,
+                    Event::FlushStop => self.flushing = false,
+                    _ => {}
+                }
+                if let Some(event) = self.element.event(pad, event).await? {
+                    self.send_event(event).await;
+                }
+            }
+        }
+
+        self.tag.read(self.element.as_ref());
+
+        Ok(())
+    }
+
+    /// Every upstream [`Edge`] has ended, the last on `pad`. Give the
+    /// [`Element`] [`Event::Eos`] and stop receiving.
+    async fn end(&mut self, pad: usize) -> Result<(), Box<dyn Error>> {
+        self.element.event(pad, Event::Eos).await?;
+        self.receiving = false;
+        if self.element.decoupled() {
+            self.eos = true;
+        } else {
+            self.send_event(Event::Eos).await;
+            self.stopped = true;
+        }
+
+        Ok(())
+    }
+
+    /// Pull once per [`Outlet`] and send the [`Buffer`]s downstream. Blocked
+    /// pads are pulled later instead.
+    async fn forward(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut owed = HashSet::new();
+        for index in 0..self.outlets.len() {
+            let pad = self.outlets[index].pad;
+            if self.blocked.contains(&pad) {
+                owed.insert(pad);
+            } else {
+                self.send_pulled(index).await?;
+            }
+        }
+        for pad in owed {
+            *self.owed.entry(pad).or_default() += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Pull a [`Buffer`] for the [`Outlet`] at `index`, if its pad can be
+    /// pulled, and send it, waiting for room.
+    async fn send_pulled(
+        &mut self,
+        index: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let outlet = &self.outlets[index];
+        if outlet.tx.is_closed() || !self.element.can_pull(outlet.pad) {
+            return Ok(());
+        }
+
+        let start = self.tag.start();
+        let result = pull(self.element.as_mut(), outlet.pad).await;
+        self.tag.pulled(outlet.pad, outlet.edge, start, &result);
+        let buffer = match result {
+            Ok(buffer) => buffer,
+            Err(error) => return route(&self.errors, error).await,
+        };
+        let delivery = Delivery {
+            pad: outlet.sink,
+            edge: outlet.edge,
+            item: Item::Buffer(buffer),
+        };
+        // A closed outlet is not an error. Its node is done.
+        if outlet.tx.send(delivery).await.is_ok() {
+            outlet.count();
+        }
+
+        Ok(())
+    }
+
+    /// Pull from every unblocked [`Outlet`] whose pad [`can_pull`] for as long
+    /// as downstream has room, without waiting for room. A source stops
+    /// between two pulls for a [`Command`]. One that arrives while a source
+    /// is being pulled waits for the [`Buffer`], which would be lost
+    /// otherwise, unless it is [`Command::Stop`].
+    ///
+    /// [`can_pull`]: Element::can_pull
+    async fn drain(&mut self) -> Result<(), Box<dyn Error>> {
+        for outlet in &self.outlets {
+            if self.blocked.contains(&outlet.pad) {
+                continue;
+            }
+            while self.element.can_pull(outlet.pad) {
+                if self.source {
+                    if let Ok(command) = self.control.try_recv() {
+                        self.interrupted.push_back(command);
+                        return Ok(());
+                    }
+                }
+                let Ok(permit) = outlet.tx.try_reserve() else {
+                    break;
+                };
+                let start = self.tag.start();
+                let mut pulled =
+                    std::pin::pin!(pull(self.element.as_mut(), outlet.pad));
+                let result = loop {
+                    tokio::select! {
+                        biased;
+                        result = &mut pulled => break result,
+                        Some(command) = self.control.recv(), if self.source => {
+                            let stop = matches!(command, Command::Stop);
+                            self.interrupted.push_back(command);
+                            if stop {
+                                return Ok(());
+                            }
+                        }
+                    }
+                };
+                self.tag.pulled(outlet.pad, outlet.edge, start, &result);
+                let buffer = match result {
+                    Ok(buffer) => buffer,
+                    Err(error) => {
+                        drop(permit);
+                        route(&self.errors, error).await?;
+                        continue;
+                    }
+                };
+                permit.send(Delivery {
+                    pad: outlet.sink,
+                    edge: outlet.edge,
+                    item: Item::Buffer(buffer),
+                });
+                outlet.count();
+            }
+        }
+
+        self.tag.read(self.element.as_ref());
+
+        Ok(())
+    }
+
+    /// Send an [`Event`] on every [`Outlet`], including those of the error
+    /// pad. Takes `&mut self` only so the future is [`Send`].
+    async fn send_event(&mut self, event: Event) {
+        send_event(&self.outlets, event.clone()).await;
+        send_event(&self.errors, event).await;
+    }
+
+    /// Send the [`Event`]s the [`Element`] has to offer downstream. Returns
+    /// `true` if one of them was [`Event::Eos`].
+    async fn emit_events(&mut self) -> bool {
+        while let Some(event) = self.element.take_event() {
+            let eos = event == Event::Eos;
+            self.send_event(event).await;
+            if eos {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// Send an [`Event`] on every [`Outlet`] that is still listening.
+async fn send_event(outlets: &[Outlet], event: Event) {
+    for outlet in outlets {
+        let delivery = Delivery {
+            pad: outlet.sink,
+            edge: outlet.edge,
+            item: Item::Event(event.clone()),
+        };
+        // A closed outlet is not an error. Its node is done.
+        outlet.tx.send(delivery).await.ok();
+    }
+}
+
+/// Send an [`Error`] from the [`Element`] on the first [`Outlet`] of the error
+/// pad still listening, instead of failing. Hands it back if there is none.
+async fn route(
+    errors: &[Outlet],
+    error: Box<dyn Error>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(outlet) = errors.iter().find(|o| !o.tx.is_closed()) else {
+        return Err(error);
+    };
+    let delivery = Delivery {
+        pad: outlet.sink,
+        edge: outlet.edge,
+        item: Item::Buffer(Box::new(error)),
+    };
+    if outlet.tx.send(delivery).await.is_ok() {
+        outlet.count();
+    }
+
+    Ok(())
+}
+
+/// Push a [`Buffer`] to the sink pad at `pad`.
+async fn push(
+    element: &mut dyn Element,
+    pad: usize,
+    buffer: Box<dyn Buffer>,
+) -> Result<(), Box<dyn Error>> {
+    let mut sink = element
+        .sinks_mut()
+        .nth(pad)
+        .ok_or(ErrorStaticString::from("No sink pad at this index."))?;
+
+    sink.push(buffer).await
+}
+
+/// Pull a [`Buffer`] from the source pad at `pad`.
+async fn pull(
+    element: &mut dyn Element,
+    pad: usize,
+) -> Result<Box<dyn Buffer>, Box<dyn Error>> {
+    let mut source = element
+        .sources_mut()
+        .nth(pad)
+        .ok_or(ErrorStaticString::from("No source pad at this index."))?;
+
+    source.pull().await
+}
@@ -0,0 +1,585 @@
+//! Prometheus [`Metrics`] for a [`Pipeline`], per [`Node`] and per [`Edge`].
+//!
+//! [`Metrics`] is a [`Tracer`], so add it with [`Pipeline::add_tracer`]. It
+//! can be read in the Prometheus text format with [`Metrics::render`], served
+//! over HTTP with [`Metrics::serve`] or handed to a callback every so often
+//! with [`Metrics::report`]. [`Node`]s are labeled by `node` name and
+//! `backend`. Collected are:
+//!
+//! - `tstreamer_buffers_pushed_total`, `tstreamer_buffers_pulled_total` and
+//!   `tstreamer_bytes_pushed_total` per [`Node`].
+//! - `tstreamer_errors_total` per [`Node`].
+//! - `tstreamer_push_duration_seconds`, a histogram of push latency per
+//!   [`Node`], with [`BUCKETS`].
+//! - `tstreamer_edge_buffers_total` and `tstreamer_edge_bytes_total` per
+//!   [`Edge`], labeled by `edge` index and `source` and `sink` [`Node`] name.
+//! - `tstreamer_property`, labeled by `property` name, for every numeric
+//!   read-only [`Property`] of a [`Node`], such as the `buffers` and
+//!   `dropped` of a [`Queue`].
+//! - `tstreamer_<name>_total` for every [`Counter`] of a [`Node`], with its
+//!   own labels, such as `tstreamer_inference_input_tokens_total` and
+//!   `tstreamer_inference_output_tokens_total` of an [`Inference`]
+//!   [`Element`], labeled by `model`.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use tstreamer::pipeline::{metrics::Metrics, Pipeline};
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let metrics = Arc::new(Metrics::new());
+//! let mut pipeline = Pipeline::new();
+//! pipeline.add_tracer(metrics.clone());
+//! let (addr, _server) = metrics.serve("127.0.0.1:9898").await?;
+//! println!("Scrape http://{addr}/metrics");
+//! // Build, init and run the pipeline.
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Pipeline`]: super::Pipeline
+//! [`Pipeline::add_tracer`]: super::Pipeline::add_tracer
+//! [`Node`]: super::Node
+//! [`Edge`]: super::Edge
+//! [`Element`]: crate::element::Element
+//! [`Property`]: crate::element::property::Property
+//! [`Counter`]: crate::element::property::Counter
+//! [`Queue`]: crate::element::queue::Queue
+//! [`Inference`]: crate::element::inference::Inference
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    task::JoinHandle,
+};
+
+use crate::{
+    backends::Backend,
+    element::property::{Counter, Value},
+};
+
+use super::trace::{Origin, Tracer, Transfer};
+
+/// Upper bounds of the push latency histogram buckets, in seconds.
+pub const BUCKETS: &[f64] = &[
+    0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 60.0,
+];
+
+/// How long [`Metrics::serve`] waits before accepting again after failing to.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Content type of [`Metrics::render`].
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// A [`Tracer`] collecting Prometheus metrics. See the [module] docs.
+///
+/// [module]: self
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// By [`Node`] name.
+    ///
+    /// [`Node`]: super::Node
+    nodes: BTreeMap<String, NodeMetrics>,
+    /// By [`Edge`] index.
+    ///
+    /// [`Edge`]: super::Edge
+    edges: BTreeMap<usize, EdgeMetrics>,
+}
+
+#[derive(Debug)]
+struct NodeMetrics {
+    backend: Backend,
+    pushed: u64,
+    pulled: u64,
+    bytes: u64,
+    errors: u64,
+    push: Histogram,
+    /// Latest numeric read-only properties, by name.
+    readings: Vec<(String, f64)>,
+    /// Latest [`Counter`]s.
+    counters: Vec<Counter>,
+}
+
+impl NodeMetrics {
+    fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            pushed: 0,
+            pulled: 0,
+            bytes: 0,
+            errors: 0,
+            push: Histogram::default(),
+            readings: Vec::new(),
+            counters: Vec::new(),
+        }
+    }
+}
+
+/// Reads a counter of [`NodeMetrics`].
+type Read = fn(&NodeMetrics) -> u64;
+
+#[derive(Debug, Default)]
+struct EdgeMetrics {
+    source: String,
+    sink: String,
+    buffers: u64,
+    bytes: u64,
+}
+
+/// Observations per bucket of [`BUCKETS`], not cumulative.
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|&le| seconds <= le) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+impl Metrics {
+    /// Create new, empty, `Metrics`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            // Only counters are updated, so a poisoned lock is fine.
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Render the `Metrics` in the Prometheus text format, as
+    /// [`CONTENT_TYPE`].
+    pub fn render(&self) -> String {
+        let inner = self.lock();
+        let mut out = String::new();
+        let nodes = || {
+            inner.nodes.iter().map(|(name, node)| {
+                let backend = format!("{:?}", node.backend);
+                (labels(&[("node", name), ("backend", &backend)]), node)
+            })
+        };
+
+        let counters: [(&str, &str, Read); 4] = [
+            (
+                "tstreamer_buffers_pushed_total",
+                "Buffers pushed to the sink pads of a node.",
+                |node| node.pushed,
+            ),
+            (
+                "tstreamer_buffers_pulled_total",
+                "Buffers pulled from the source pads of a node.",
+                |node| node.pulled,
+            ),
+            (
+                "tstreamer_bytes_pushed_total",
+                "Bytes pushed to the sink pads of a node.",
+                |node| node.bytes,
+            ),
+            ("tstreamer_errors_total", "Errors of a node.", |node| {
+                node.errors
+            }),
+        ];
+        for (name, help, value) in counters {
+            header(&mut out, name, "counter", help);
+            for (labels, node) in nodes() {
+                let _ = writeln!(out, "{name}{{{labels}}} {}", value(node));
+            }
+        }
+
+        let name = "tstreamer_push_duration_seconds";
+        header(&mut out, name, "histogram", "Time taken by a push.");
+        for (labels, node) in nodes() {
+            let mut cumulative = 0;
+            for (le, count) in BUCKETS.iter().zip(node.push.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "{name}_bucket{{{labels},le=\"{le}\"}} {cumulative}"
+                );
+            }
+            let count = node.push.count;
+            let _ =
+                writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {count}");
+            let _ = writeln!(out, "{name}_sum{{{labels}}} {}", node.push.sum);
+            let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+        }
+
+        let edges = || {
+            inner.edges.iter().map(|(index, edge)| {
+                let labels = labels(&[
+                    ("edge", &index.to_string()),
+                    ("source", &edge.source),
+                    ("sink", &edge.sink),
+                ]);
+                (labels, edge)
+            })
+        };
+        let name = "tstreamer_edge_buffers_total";
+        header(&mut out, name, "counter", "Buffers sent over an edge.");
+        for (labels, edge) in edges() {
+            let _ = writeln!(out, "{name}{{{labels}}} {}", edge.buffers);
+        }
+        let name = "tstreamer_edge_bytes_total";
+        header(&mut out, name, "counter", "Bytes sent over an edge.");
+        for (labels, edge) in edges() {
+            let _ = writeln!(out, "{name}{{{labels}}} {}", edge.bytes);
+        }
+
+        let name = "tstreamer_property";
+        header(&mut out, name, "gauge", "Read-only property of a node.");
+        for (labels, node) in nodes() {
+            for (property, value) in &node.readings {
+                let property = self::labels(&[("property", property)]);
+                let _ = writeln!(out, "{name}{{{labels},{property}}} {value}");
+            }
+        }
+
+        // Every `Counter` of a name together, in the order first seen.
+        let mut names: Vec<&Counter> = Vec::new();
+        for counter in inner.nodes.values().flat_map(|node| &node.counters) {
+            if names.iter().all(|seen| seen.name != counter.name) {
+                names.push(counter);
+            }
+        }
+        for first in names {
+            let name = format!("tstreamer_{}_total", first.name);
+            header(&mut out, &name, "counter", first.description);
+            for (labels, node) in nodes() {
+                for counter in &node.counters {
+                    if counter.name != first.name {
+                        continue;
+                    }
+                    let own: Vec<_> = counter
+                        .labels
+                        .iter()
+                        .map(|(label, value)| (*label, value.as_str()))
+                        .collect();
+                    let own = self::labels(&own);
+                    let value = counter.value;
+                    let _ = writeln!(out, "{name}{{{labels},{own}}} {value}");
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Serve [`Metrics::render`] over HTTP at `/metrics` on `addr`, until the
+    /// returned task is aborted. Returns the address actually bound, which
+    /// differs from `addr` for port 0.
+    ///
+    /// # Errors
+    /// - If `addr` can't be bound.
+    pub async fn serve(
+        self: Arc<Self>,
+        addr: impl ToSocketAddrs,
+    ) -> std::io::Result<(SocketAddr, JoinHandle<()>)> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    // A failed scrape only concerns that client.
+                    Ok((stream, _)) => {
+                        tokio::spawn(respond(self.clone(), stream));
+                    }
+                    // Such as running out of file descriptors, which takes
+                    // a while to resolve. Retrying at once would spin.
+                    Err(err) => {
+                        eprintln!("Metrics server failed to accept: {err}");
+                        tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    }
+                }
+            }
+        });
+
+        Ok((addr, task))
+    }
+
+    /// Call `f` with [`Metrics::render`] every `period`, starting now, until
+    /// the returned task is aborted.
+    pub fn report<F>(
+        self: Arc<Self>,
+        period: Duration,
+        mut f: F,
+    ) -> JoinHandle<()>
+    where
+        F: FnMut(String) + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                f(self.render());
+            }
+        })
+    }
+}
+
+impl Tracer for Metrics {
+    fn push(&self, transfer: &Transfer<'_>) {
+        let mut inner = self.lock();
+        let size = transfer.buffer.map_or(0, |b| b.size) as u64;
+        let node = node(&mut inner, transfer.origin);
+        node.push.observe(transfer.elapsed);
+        if !transfer.ok {
+            return;
+        }
+        node.pushed += 1;
+        node.bytes += size;
+
+        let edge = inner.edges.entry(transfer.edge).or_default();
+        transfer.origin.name.clone_into(&mut edge.sink);
+        edge.buffers += 1;
+        edge.bytes += size;
+    }
+
+    fn pull(&self, transfer: &Transfer<'_>) {
+        let mut inner = self.lock();
+        if transfer.ok {
+            node(&mut inner, transfer.origin).pulled += 1;
+        }
+        let edge = inner.edges.entry(transfer.edge).or_default();
+        transfer.origin.name.clone_into(&mut edge.source);
+    }
+
+    fn error(&self, origin: Origin<'_>, _: &str) {
+        node(&mut self.lock(), origin).errors += 1;
+    }
+
+    fn readings(&self, origin: Origin<'_>, readings: &[(&str, Value)]) {
+        node(&mut self.lock(), origin).readings = readings
+            .iter()
+            .filter_map(|(name, value)| {
+                Some(((*name).to_owned(), value.as_float()?))
+            })
+            .collect();
+    }
+
+    fn counters(&self, origin: Origin<'_>, counters: &[Counter]) {
+        node(&mut self.lock(), origin).counters = counters.to_vec();
+    }
+}
+
+/// The [`NodeMetrics`] of `origin`.
+fn node<'a>(inner: &'a mut Inner, origin: Origin<'_>) -> &'a mut NodeMetrics {
+    let node = inner
+        .nodes
+        .entry(origin.name.to_owned())
+        .or_insert_with(|| NodeMetrics::new(origin.backend));
+    // A new `Node` may reuse the name of a removed one.
+    node.backend = origin.backend;
+    node
+}
+
+/// Write the `# HELP` and `# TYPE` lines of a metric.
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Format label pairs, without the braces, escaping the values.
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let mut out = String::new();
+    for (i, (name, value)) in pairs.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{name}=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    out
+}
+
+/// Answer one HTTP/1.1 request on `stream`. Anything other than
+/// `GET /metrics` gets a 404.
+async fn respond(
+    metrics: Arc<Metrics>,
+    mut stream: TcpStream,
+) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut chunk = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 || request.len() > 8192 {
+            return Ok(());
+        }
+        request.extend_from_slice(&chunk[..n]);
+    }
+
+    let line = request.split(|&b| b == b'\r').next().unwrap_or_default();
+    let mut parts = line.split(|&b| b == b' ');
+    let method = parts.next();
+    let path = parts.next().and_then(|p| p.split(|&b| b == b'?').next());
+    let (status, content_type, body) = match (method, path) {
+        (Some(b"GET"), Some(b"/metrics")) => {
+            ("200 OK", CONTENT_TYPE, metrics.render())
+        }
+        _ => ("404 Not Found", "text/plain", "Not Found\n".to_owned()),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::trace::BufferInfo;
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Metrics::new();
+        let source = Origin {
+            node: 0,
+            name: "source",
+            backend: Backend::Independent,
+        };
+        let queue = Origin {
+            node: 1,
+            name: "queue \"q\"",
+            ..source
+        };
+        metrics.readings(source, &[("output_tokens", Value::Int(3))]);
+        metrics.readings(
+            queue,
+            &[("buffers", Value::Int(4)), ("leak", Value::from("Block"))],
+        );
+
+        let buffer = BufferInfo {
+            name: "Message".into(),
+            size: 10,
+        };
+        let pull = Transfer {
+            origin: source,
+            pad: 0,
+            edge: 0,
+            buffer: Some(&buffer),
+            elapsed: Duration::from_millis(3),
+            ok: true,
+        };
+        metrics.pull(&pull);
+        metrics.push(&Transfer {
+            origin: queue,
+            ..pull
+        });
+        metrics.push(&Transfer {
+            origin: queue,
+            elapsed: Duration::from_secs(120),
+            ..pull
+        });
+        metrics.error(queue, "Oops.");
+        let tokens = |model: &str, value| Counter {
+            name: "inference_input_tokens",
+            description: "Input tokens used, per model.",
+            labels: vec![("model", model.to_owned())],
+            value,
+        };
+        metrics.counters(source, &[tokens("claude", 7), tokens("other", 2)]);
+        metrics.counters(source, &[tokens("claude", 9), tokens("other", 2)]);
+
+        let text = metrics.render();
+        let s = r#"node="source",backend="Independent""#;
+        let q = r#"node="queue \"q\"",backend="Independent""#;
+        let h = "tstreamer_push_duration_seconds";
+        let edge = r#"edge="0",source="source",sink="queue \"q\"""#;
+        for line in [
+            format!("tstreamer_buffers_pushed_total{{{q}}} 2"),
+            format!("tstreamer_bytes_pushed_total{{{q}}} 20"),
+            format!("tstreamer_errors_total{{{q}}} 1"),
+            format!("tstreamer_buffers_pulled_total{{{s}}} 1"),
+            format!("{h}_bucket{{{q},le=\"0.001\"}} 0"),
+            format!("{h}_bucket{{{q},le=\"0.005\"}} 1"),
+            format!("{h}_bucket{{{q},le=\"60\"}} 1"),
+            format!("{h}_bucket{{{q},le=\"+Inf\"}} 2"),
+            format!("{h}_count{{{q}}} 2"),
+            format!("tstreamer_edge_buffers_total{{{edge}}} 2"),
+            format!("tstreamer_property{{{q},property=\"buffers\"}} 4"),
+            format!("tstreamer_property{{{s},property=\"output_tokens\"}} 3"),
+            "# TYPE tstreamer_inference_input_tokens_total counter".into(),
+            format!(
+                "tstreamer_inference_input_tokens_total{{{s},model=\"claude\"}} 9"
+            ),
+            format!(
+                "tstreamer_inference_input_tokens_total{{{s},model=\"other\"}} 2"
+            ),
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line} not in:\n{text}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.error(
+            Origin {
+                node: 0,
+                name: "node",
+                backend: Backend::Independent,
+            },
+            "Oops.",
+        );
+        let (addr, server) =
+            metrics.clone().serve("127.0.0.1:0").await.unwrap();
+
+        let get = |path: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let request = format!("GET {path} HTTP/1.1\r\nHost: test\r\n\r\n");
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = get("/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&metrics.render()));
+        assert!(response.contains("tstreamer_errors_total{node=\"node\""));
+        assert!(get("/").await.starts_with("HTTP/1.1 404"));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let reporter = metrics.report(Duration::from_millis(1), move |text| {
+            let _ = tx.send(text);
+        });
+        assert!(rx.recv().await.unwrap().contains("tstreamer_errors_total"));
+        reporter.abort();
+        server.abort();
+    }
+}
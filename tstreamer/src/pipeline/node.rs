@@ -1,17 +1,119 @@
-use crate::element::Element;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-use super::State;
+use crate::{
+    backends::Backend,
+    element::{
+        any::{Kind, NewError},
+        property, Element,
+    },
+    pad::Caps,
+};
+
+use super::{supervisor::Policy, State};
 
 /// [`Config`] for a [`Node`] specifying the type of element and it's
 /// configuration in the form of a JSON object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
-    element: crate::element::any::Kind,
-    config: serde_json::Value,
+    /// The [`Kind`] of [`Element`], looked up in the [`registry`].
+    ///
+    /// [`registry`]: crate::element::registry
+    pub element: Kind,
+    /// The [`Backend`] providing the [`Element`].
+    pub backend: Backend,
+    /// Options passed to [`Kind::new`]. What is accepted depends on the
+    /// [`Kind`] and [`Backend`].
+    #[serde(default)]
+    pub config: serde_json::Value,
+}
+
+impl Config {
+    /// Construct the [`Element`] described by this `Config`.
+    pub fn new_element(&self) -> Result<Box<dyn Element>, NewError> {
+        self.element.new(self.backend, self.config.clone())
+    }
+
+    /// Record a [`Property`] set on the [`Element`] in the options, where
+    /// writable properties go by the same name.
+    ///
+    /// [`Property`]: crate::element::property::Property
+    pub(crate) fn set(&mut self, name: &str, value: &property::Value) {
+        if !self.config.is_object() {
+            self.config = serde_json::Value::Object(Default::default());
+        }
+        if let (Some(options), Ok(value)) =
+            (self.config.as_object_mut(), serde_json::to_value(value))
+        {
+            options.insert(name.to_owned(), value);
+        }
+    }
 }
 
 /// A `Node` in a [`Pipeline`]. Wraps an [`Element`] and its children.
+///
+/// [`Pipeline`]: super::Pipeline
 pub(crate) struct Node<S: State> {
-    config: serde_json::Value,
-    element: Box<dyn Element>,
-    state: S,
+    /// Name of the `Node`, unique within a [`Pipeline`].
+    ///
+    /// [`Pipeline`]: super::Pipeline
+    pub(crate) name: String,
+    /// [`Config`] the [`Element`] was created from, if any. [`Element`]s added
+    /// directly from Rust code have none.
+    pub(crate) config: Option<Config>,
+    pub(crate) element: Box<dyn Element>,
+    /// When to restart the [`Element`] while running.
+    pub(crate) restart: Policy,
+    state: std::marker::PhantomData<S>,
+}
+
+impl<S: State> Node<S> {
+    /// Create a new `Node` wrapping an [`Element`].
+    pub(crate) fn new(
+        name: String,
+        config: Option<Config>,
+        element: Box<dyn Element>,
+    ) -> Self {
+        Self {
+            name,
+            config,
+            element,
+            restart: Policy::default(),
+            state: std::marker::PhantomData,
+        }
+    }
+
+    /// Who this `Node` is, given its index, for [`Tracer`]s.
+    ///
+    /// [`Tracer`]: super::Tracer
+    pub(crate) fn origin(&self, index: usize) -> super::trace::Origin<'_> {
+        super::trace::Origin {
+            node: index,
+            name: &self.name,
+            backend: self.element.backend(),
+        }
+    }
+
+    /// Use a restart [`Policy`].
+    pub(crate) fn with_restart(self, restart: Policy) -> Self {
+        Self { restart, ..self }
+    }
+
+    /// Move the `Node` to another [`State`].
+    pub(crate) fn into_state<T: State>(self) -> Node<T> {
+        Node::new(self.name, self.config, self.element)
+            .with_restart(self.restart)
+    }
+}
+
+/// [`Caps`] of the source pads of a [`Node`]: those of its [`Element`], then
+/// its error pad. See [`Pipeline::error_pad`].
+///
+/// [`Pipeline::error_pad`]: super::Pipeline::error_pad
+pub(crate) fn source_caps(element: &dyn Element) -> Vec<Caps> {
+    element
+        .sources()
+        .map(|pad| pad.caps())
+        .chain(std::iter::once(Caps::Error))
+        .collect()
 }
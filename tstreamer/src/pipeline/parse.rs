@@ -0,0 +1,623 @@
+//! Parser for `gst-launch` style [`Pipeline`] descriptions.
+//!
+//! ```text
+//! prompt system="You are a pirate." ! inference max_tokens=1024
+//! ```
+//!
+//! - Elements are written as any registered [`Kind`] (case insensitive) then
+//!   `key=value` options. The options become the [`node::Config::config`]
+//!   object passed to [`Kind::new`].
+//! - `name` and `backend` are special options. `name` names the node,
+//!   otherwise it is named after its [`Kind`] and a counter (`prompt0`,
+//!   `prompt1`, ...). `backend` picks the [`Backend`] (case insensitive),
+//!   otherwise [`Backend::default`] is used.
+//! - `!` links two elements. The pads are picked by [`Pipeline::autolink`].
+//! - `name.` refers to a named element and `name.N` to its pad `N`. This is
+//!   how graphs that aren't a straight line are written, for example a loop:
+//!   `prompt name=p ! inference ! p.`. Whitespace without a `!` starts a new
+//!   chain. A link to a `name.` that closes a loop is marked as feedback,
+//!   like [`Pipeline::link_feedback`], so it passes [`Pipeline::validate`].
+//! - Values may be quoted with `"` or `'`, with `\` escapes. Unquoted values
+//!   that are JSON numbers, booleans or `null` become those. Anything else is
+//!   a string.
+//!
+//! The result is the same [`Config`] the serde loader uses.
+
+use std::{collections::HashSet, ops::Range};
+
+use serde_json::Value;
+
+use crate::{backends::Backend, element::any::Kind};
+
+use super::{config, node, BuildError, Builder, Config, Pipeline};
+
+/// What went wrong while parsing. See [`ParseError`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseErrorKind {
+    /// Something else was expected here.
+    #[error("Expected {0}.")]
+    Expected(&'static str),
+    /// A quoted value is missing its closing quote.
+    #[error("Unterminated string.")]
+    UnterminatedString,
+    /// There is no [`Kind`] of element with this name.
+    #[error("Unknown element `{0}`.")]
+    UnknownKind(String),
+    /// There is no [`Backend`] with this name.
+    #[error("Unknown backend `{0}`.")]
+    UnknownBackend(String),
+    /// A reference to an element that does not exist.
+    #[error("No element named `{0}`.")]
+    UnknownName(String),
+    /// Two elements have the same name.
+    #[error("Duplicate element name `{0}`.")]
+    DuplicateName(String),
+    /// A pad reference that is not a number.
+    #[error("Invalid pad `{0}`. Pads are numbers.")]
+    InvalidPad(String),
+}
+
+/// Error parsing a [`Pipeline`] description. The `span` is the byte range of
+/// the offending input. Use [`ParseError::report`] for a human readable
+/// message pointing at it.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{kind} (at {}..{})", .span.start, .span.end)]
+pub struct ParseError {
+    /// Byte range of the offending input.
+    pub span: Range<usize>,
+    /// What went wrong.
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    fn new(span: Range<usize>, kind: ParseErrorKind) -> Self {
+        Self { span, kind }
+    }
+
+    /// Render the error with the offending line of `input` and the span
+    /// underlined, like this:
+    ///
+    /// ```text
+    /// error: Unknown element `stdout`.
+    ///  --> 1:22
+    ///   |
+    ///   | prompt ! inference ! stdout
+    ///   |                      ^^^^^^
+    /// ```
+    pub fn report(&self, input: &str) -> String {
+        let start = self.span.start.min(input.len());
+        let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end =
+            input[start..].find('\n').map_or(input.len(), |i| start + i);
+        let line = input[..start].matches('\n').count() + 1;
+        let column = input[line_start..start].chars().count();
+        let width = input[start..self.span.end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        format!(
+            "error: {}\n --> {}:{}\n  |\n  | {}\n  | {}{}\n",
+            self.kind,
+            line,
+            column + 1,
+            &input[line_start..line_end],
+            " ".repeat(column),
+            "^".repeat(width),
+        )
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = ParseError;
+
+    /// Parse a `gst-launch` style description. See the [module](self)
+    /// documentation for the syntax.
+    fn from_str(description: &str) -> Result<Self, Self::Err> {
+        parse(description)
+    }
+}
+
+impl Pipeline<Builder> {
+    /// Construct a `Pipeline` from a `gst-launch` style description. See the
+    /// [module](self) documentation for the syntax.
+    ///
+    /// # Errors
+    /// - [`BuildError::Parse`] if the description can't be parsed.
+    /// - Anything [`Pipeline::from_config`] can return.
+    pub fn parse_launch(description: &str) -> Result<Self, BuildError> {
+        Self::from_config(&parse(description)?)
+    }
+}
+
+/// Parse a `gst-launch` style description into a [`Config`]. See the
+/// [module](self) documentation for the syntax.
+pub fn parse(description: &str) -> Result<Config, ParseError> {
+    Parser {
+        input: description,
+        pos: 0,
+    }
+    .parse()
+}
+
+/// An element being parsed.
+struct Element {
+    kind: Kind,
+    backend: Backend,
+    /// Explicit name and the span of its value.
+    name: Option<(String, Range<usize>)>,
+    options: serde_json::Map<String, Value>,
+}
+
+/// Either end of a link.
+#[derive(Clone)]
+enum Item {
+    /// Index into the parsed elements.
+    Element(usize),
+    /// `name.` or `name.N`.
+    Ref {
+        name: String,
+        pad: Option<usize>,
+        span: Range<usize>,
+    },
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consume a word, stopping at whitespace, `!`, `=` or a quote.
+    fn word(&mut self) -> Range<usize> {
+        let start = self.pos;
+        let rest = &self.input[start..];
+        let len = rest
+            .find(|c: char| {
+                c.is_whitespace() || matches!(c, '!' | '=' | '"' | '\'')
+            })
+            .unwrap_or(rest.len());
+        self.pos += len;
+
+        start..self.pos
+    }
+
+    /// Consume a quoted or unquoted value.
+    fn value(&mut self) -> Result<(Value, Range<usize>), ParseError> {
+        let start = self.pos;
+        let quote = match self.peek() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => {
+                let span = self.word();
+                if span.is_empty() {
+                    return Err(ParseError::new(
+                        span,
+                        ParseErrorKind::Expected("a value"),
+                    ));
+                }
+                let word = &self.input[span.clone()];
+                let value = match serde_json::from_str(word) {
+                    Ok(
+                        value @ (Value::Number(_)
+                        | Value::Bool(_)
+                        | Value::Null),
+                    ) => value,
+                    _ => Value::String(word.to_owned()),
+                };
+                return Ok((value, span));
+            }
+        };
+
+        let unterminated = || {
+            ParseError::new(
+                start..self.input.len(),
+                ParseErrorKind::UnterminatedString,
+            )
+        };
+        let mut string = String::new();
+        let mut chars = self.input[start + 1..].char_indices();
+        loop {
+            match chars.next().ok_or_else(unterminated)? {
+                (i, c) if c == quote => {
+                    self.pos = start + 1 + i + 1;
+                    return Ok((Value::String(string), start..self.pos));
+                }
+                (_, '\\') => match chars.next().ok_or_else(unterminated)?.1 {
+                    'n' => string.push('\n'),
+                    't' => string.push('\t'),
+                    c => string.push(c),
+                },
+                (_, c) => string.push(c),
+            }
+        }
+    }
+
+    fn parse(mut self) -> Result<Config, ParseError> {
+        let mut elements: Vec<Element> = vec![];
+        let mut links: Vec<(Item, Item)> = vec![];
+        // Upstream end of the next link.
+        let mut prev: Option<Item> = None;
+        // Span of a `!` waiting for its downstream end.
+        let mut bang: Option<Range<usize>> = None;
+        // Element accepting options.
+        let mut current: Option<usize> = None;
+
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            match self.peek() {
+                None => break,
+                Some('!') => {
+                    self.pos += 1;
+                    if prev.is_none() || bang.is_some() {
+                        return Err(ParseError::new(
+                            start..self.pos,
+                            ParseErrorKind::Expected("an element before `!`"),
+                        ));
+                    }
+                    bang = Some(start..self.pos);
+                    current = None;
+                }
+                Some(c @ ('=' | '"' | '\'')) => {
+                    return Err(ParseError::new(
+                        start..start + c.len_utf8(),
+                        ParseErrorKind::Expected("an element"),
+                    ));
+                }
+                Some(_) => {
+                    let span = self.word();
+                    let word = &self.input[span.clone()];
+
+                    if self.peek() == Some('=') {
+                        let Some(index) = current else {
+                            return Err(ParseError::new(
+                                span,
+                                ParseErrorKind::Expected(
+                                    "an element before this option",
+                                ),
+                            ));
+                        };
+                        self.pos += 1;
+                        let (value, value_span) = self.value()?;
+                        set_option(
+                            &mut elements[index],
+                            word,
+                            value,
+                            value_span,
+                        )?;
+                        continue;
+                    }
+
+                    let item = match word.split_once('.') {
+                        Some((name, pad)) => {
+                            let pad = match pad {
+                                "" => None,
+                                pad => Some(pad.parse().map_err(|_| {
+                                    ParseError::new(
+                                        span.clone(),
+                                        ParseErrorKind::InvalidPad(pad.into()),
+                                    )
+                                })?),
+                            };
+                            current = None;
+                            Item::Ref {
+                                name: name.to_owned(),
+                                pad,
+                                span,
+                            }
+                        }
+                        None => {
+                            let kind = Kind::all()
+                                .into_iter()
+                                .find(|kind| {
+                                    kind.as_str().eq_ignore_ascii_case(word)
+                                })
+                                .ok_or_else(|| {
+                                    ParseError::new(
+                                        span,
+                                        ParseErrorKind::UnknownKind(
+                                            word.to_owned(),
+                                        ),
+                                    )
+                                })?;
+                            elements.push(Element {
+                                kind,
+                                backend: Backend::default(),
+                                name: None,
+                                options: serde_json::Map::new(),
+                            });
+                            current = Some(elements.len() - 1);
+                            Item::Element(elements.len() - 1)
+                        }
+                    };
+
+                    if bang.take().is_some() {
+                        // `prev` is always set when there is a `!`.
+                        links.push((prev.take().unwrap(), item.clone()));
+                    }
+                    prev = Some(item);
+                }
+            }
+        }
+
+        if let Some(span) = bang {
+            return Err(ParseError::new(
+                span,
+                ParseErrorKind::Expected("an element after `!`"),
+            ));
+        }
+
+        let names = name_elements(&elements)?;
+        let resolve = |item: &Item| match item {
+            Item::Element(index) => Ok((names[*index].clone(), None)),
+            Item::Ref { name, pad, span } => {
+                if names.contains(name) {
+                    Ok((name.clone(), *pad))
+                } else {
+                    Err(ParseError::new(
+                        span.clone(),
+                        ParseErrorKind::UnknownName(name.clone()),
+                    ))
+                }
+            }
+        };
+
+        let mut edges = Vec::with_capacity(links.len());
+        for (from, to) in links.iter() {
+            let (source, source_pad) = resolve(from)?;
+            let (sink, sink_pad) = resolve(to)?;
+            edges.push(config::Edge {
+                source,
+                source_pad,
+                sink,
+                sink_pad,
+                feedback: false,
+            });
+        }
+        for (index, (_, to)) in links.iter().enumerate() {
+            if matches!(to, Item::Ref { .. }) && closes_loop(&edges, index) {
+                edges[index].feedback = true;
+            }
+        }
+
+        let nodes = elements
+            .into_iter()
+            .zip(names)
+            .map(|(element, name)| config::Node {
+                name,
+                restart: Default::default(),
+                config: node::Config {
+                    element: element.kind,
+                    backend: element.backend,
+                    config: if element.options.is_empty() {
+                        Value::Null
+                    } else {
+                        Value::Object(element.options)
+                    },
+                },
+            })
+            .collect();
+
+        Ok(Config { nodes, edges })
+    }
+}
+
+/// Apply a `key=value` option to an [`Element`].
+fn set_option(
+    element: &mut Element,
+    key: &str,
+    value: Value,
+    span: Range<usize>,
+) -> Result<(), ParseError> {
+    let string = |value: Value| match value {
+        Value::String(string) => Ok(string),
+        _ => Err(ParseError::new(
+            span.clone(),
+            ParseErrorKind::Expected("a string"),
+        )),
+    };
+
+    match key {
+        "name" => element.name = Some((string(value)?, span.clone())),
+        "backend" => {
+            let name = string(value)?;
+            element.backend = Backend::ALL
+                .iter()
+                .copied()
+                .find(|backend| {
+                    format!("{backend:?}").eq_ignore_ascii_case(&name)
+                })
+                .ok_or_else(|| {
+                    ParseError::new(
+                        span.clone(),
+                        ParseErrorKind::UnknownBackend(name),
+                    )
+                })?;
+        }
+        key => {
+            element.options.insert(key.to_owned(), value);
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the sink of `edges[index]` leads back to its source through the
+/// other edges, ignoring those already marked as feedback.
+fn closes_loop(edges: &[config::Edge], index: usize) -> bool {
+    let target = &edges[index].source;
+    let mut seen = HashSet::new();
+    let mut stack = vec![&edges[index].sink];
+    while let Some(name) = stack.pop() {
+        if name == target {
+            return true;
+        }
+        if seen.insert(name) {
+            stack.extend(
+                edges
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, edge)| {
+                        *i != index && !edge.feedback && &edge.source == name
+                    })
+                    .map(|(_, edge)| &edge.sink),
+            );
+        }
+    }
+
+    false
+}
+
+/// Pick a unique name for every [`Element`]. Explicit names win. The rest are
+/// named after their [`Kind`] and a counter.
+fn name_elements(elements: &[Element]) -> Result<Vec<String>, ParseError> {
+    let mut taken = HashSet::new();
+    for (name, span) in elements.iter().filter_map(|e| e.name.as_ref()) {
+        if !taken.insert(name.clone()) {
+            return Err(ParseError::new(
+                span.clone(),
+                ParseErrorKind::DuplicateName(name.clone()),
+            ));
+        }
+    }
+
+    let mut counters = std::collections::HashMap::new();
+    let names = elements
+        .iter()
+        .map(|element| match &element.name {
+            Some((name, _)) => name.clone(),
+            None => {
+                let kind = element.kind.as_str().to_lowercase();
+                let counter = counters.entry(kind.clone()).or_insert(0);
+                loop {
+                    let name = format!("{kind}{counter}");
+                    *counter += 1;
+                    if taken.insert(name.clone()) {
+                        break name;
+                    }
+                }
+            }
+        })
+        .collect();
+
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chain() {
+        let config = parse(
+            r#"prompt system="Be \"nice\"." ! inference max_tokens=1024 stream=true"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.nodes.len(), 2);
+        assert_eq!(config.nodes[0].name, "prompt0");
+        assert_eq!(config.nodes[0].config.element, Kind::PROMPT);
+        assert_eq!(
+            config.nodes[0].config.config,
+            serde_json::json!({ "system": "Be \"nice\"." })
+        );
+        assert_eq!(config.nodes[1].name, "inference0");
+        assert_eq!(
+            config.nodes[1].config.config,
+            serde_json::json!({ "max_tokens": 1024, "stream": true })
+        );
+        assert_eq!(
+            config.edges,
+            vec![config::Edge {
+                source: "prompt0".into(),
+                source_pad: None,
+                sink: "inference0".into(),
+                sink_pad: None,
+                feedback: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_references() {
+        let config: Config = "prompt name=p backend=independent ! inference \
+            inference0.1 ! p.0 \
+            prompt"
+            .parse()
+            .unwrap();
+
+        assert!(config.nodes[0].config.backend.is_independent());
+        assert_eq!(config.nodes[2].name, "prompt0");
+        assert_eq!(config.edges.len(), 2);
+        assert_eq!(
+            config.edges[1],
+            config::Edge {
+                source: "inference0".into(),
+                source_pad: Some(1),
+                sink: "p".into(),
+                sink_pad: Some(0),
+                feedback: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |description: &str| parse(description).unwrap_err();
+
+        assert_eq!(
+            error("prompt ! stdout"),
+            ParseError::new(
+                9..15,
+                ParseErrorKind::UnknownKind("stdout".into())
+            )
+        );
+        assert_eq!(
+            error("prompt !").kind,
+            ParseErrorKind::Expected("an element after `!`")
+        );
+        assert_eq!(error("! prompt").span, 0..1);
+        assert_eq!(
+            error("prompt ! ! inference").kind,
+            ParseErrorKind::Expected("an element before `!`")
+        );
+        assert_eq!(
+            error("prompt system=\"oops").kind,
+            ParseErrorKind::UnterminatedString
+        );
+        assert_eq!(
+            error("prompt ! nope.").kind,
+            ParseErrorKind::UnknownName("nope".into())
+        );
+        assert_eq!(error("prompt name=a inference name=a").span, 29..30);
+        assert_eq!(
+            error("prompt.x").kind,
+            ParseErrorKind::InvalidPad("x".into())
+        );
+        assert_eq!(
+            error("prompt backend=openai").kind,
+            ParseErrorKind::UnknownBackend("openai".into())
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let input = "prompt\n  ! inference ! stdout";
+        let report = parse(input).unwrap_err().report(input);
+
+        assert_eq!(
+            report,
+            "error: Unknown element `stdout`.\n --> 2:17\n  |\n  |   ! \
+             inference ! stdout\n  |                 ^^^^^^\n"
+        );
+    }
+}
@@ -0,0 +1,524 @@
+//! Hot reload of a running [`Pipeline`] from a changed [`Config`].
+//!
+//! [`Control::reload`] compares a [`Config`] with the running graph and
+//! applies only what changed:
+//! - [`Node`]s missing from the [`Config`] are removed and new ones added.
+//! - A [`Node`] whose options changed has them [`set`] as [`Property`]s, as
+//!   long as its [`Element`] has a writable [`Property`] for each of them.
+//!   It keeps its state, like the history of a [`Prompt`] when only the
+//!   system prompt changed. Otherwise, or if its `element`, `backend` or
+//!   `restart` changed, it is replaced and its state is lost.
+//! - [`Edge`]s are linked and unlinked to match. New ones are linked before
+//!   old ones are unlinked, so the [`Node`]s downstream keep running.
+//!
+//! Every other [`Node`] is left alone. [`Pipeline::watch`] reloads whenever a
+//! config file changes.
+//!
+//! [`Node`]: super::Node
+//! [`Edge`]: super::Edge
+//! [`set`]: property::set
+//! [`Property`]: property::Property
+//! [`Prompt`]: crate::buffer::Prompt
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::PathBuf,
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::element::{
+    property::{self, Value},
+    Element,
+};
+
+use super::{
+    bus, config,
+    control::{ControlError, Request},
+    BuildError, Builder, Config, Control, Pipeline, State,
+};
+
+/// What a reload changed, by [`Node`] name.
+///
+/// [`Node`]: super::Node
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Changes {
+    /// [`Node`]s added.
+    ///
+    /// [`Node`]: super::Node
+    pub added: Vec<String>,
+    /// [`Node`]s removed.
+    ///
+    /// [`Node`]: super::Node
+    pub removed: Vec<String>,
+    /// [`Node`]s whose options were set as [`Property`]s, keeping their
+    /// state.
+    ///
+    /// [`Node`]: super::Node
+    /// [`Property`]: property::Property
+    pub updated: Vec<String>,
+    /// [`Node`]s removed and added again, losing their state.
+    ///
+    /// [`Node`]: super::Node
+    pub replaced: Vec<String>,
+    /// [`Edge`]s linked, in order.
+    ///
+    /// [`Edge`]: super::Edge
+    pub linked: Vec<config::Edge>,
+    /// [`Edge`]s unlinked, including those of removed and replaced
+    /// [`Node`]s.
+    ///
+    /// [`Edge`]: super::Edge
+    /// [`Node`]: super::Node
+    pub unlinked: Vec<config::Edge>,
+}
+
+impl Changes {
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Error when reloading a running [`Pipeline`].
+#[derive(Debug, thiserror::Error)]
+pub enum ReloadError {
+    /// The new [`Config`] is invalid. Nothing was changed.
+    #[error(transparent)]
+    Build(#[from] BuildError),
+    /// An added [`Element`] failed to initialize. Nothing was changed.
+    #[error("Node `{name}` failed to initialize: {message}")]
+    Init {
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+        /// What went wrong.
+        message: String,
+    },
+    /// A change could not be applied. Nothing was changed if it was an
+    /// [`Edge`] or a [`Node`] to replace or remove. A rejected [`Property`]
+    /// value is only found once the others were applied.
+    ///
+    /// [`Edge`]: super::Edge
+    /// [`Node`]: super::Node
+    /// [`Property`]: property::Property
+    #[error(transparent)]
+    Control(#[from] ControlError),
+}
+impl super::state::Error for ReloadError {}
+
+/// How to get from the running graph to a new [`Config`]. Made by [`plan`]
+/// and applied by the executor.
+pub(super) struct Plan {
+    pub changes: Changes,
+    /// New configuration of each updated [`Node`] and the [`Property`]
+    /// values to set.
+    ///
+    /// [`Node`]: super::Node
+    /// [`Property`]: property::Property
+    pub updates: Vec<(config::Node, Vec<(String, Value)>)>,
+    /// Added and replaced [`Node`]s, with their [`Element`]s.
+    ///
+    /// [`Node`]: super::Node
+    pub nodes: Vec<(config::Node, Box<dyn Element>)>,
+}
+
+/// What the executor did with a [`Plan`].
+pub(super) struct Reloaded {
+    /// Replies to the [`Property`] changes.
+    ///
+    /// [`Property`]: property::Property
+    pub set: Vec<oneshot::Receiver<Result<Value, ControlError>>>,
+    /// The [`Element`]s of removed [`Node`]s, once they have finished.
+    ///
+    /// [`Node`]: super::Node
+    pub removed: Vec<oneshot::Receiver<Result<Box<dyn Element>, ControlError>>>,
+    /// The first change that failed.
+    pub failed: Option<ControlError>,
+}
+
+/// Compare the `running` [`Config`] with a `target` [`Pipeline`] built from
+/// the new one. Its [`Element`]s are used for added and replaced [`Node`]s.
+///
+/// [`Node`]: super::Node
+pub(super) fn plan(running: &Config, target: Pipeline<Builder>) -> Plan {
+    // Every node was made from a config, with every pad picked.
+    let wanted = target.config().unwrap_or_default();
+    let mut elements: HashMap<_, _> = target
+        .graph
+        .into_nodes_edges()
+        .0
+        .into_iter()
+        .map(|node| (node.weight.name, node.weight.element))
+        .collect();
+    let old: HashMap<_, _> = running
+        .nodes
+        .iter()
+        .map(|node| (node.name.as_str(), node))
+        .collect();
+
+    let (mut added, mut updated, mut replaced) = (vec![], vec![], vec![]);
+    let (mut updates, mut nodes) = (vec![], vec![]);
+    for node in wanted.nodes.iter() {
+        let Some(element) = elements.remove(&node.name) else {
+            continue;
+        };
+        let Some(&old) = old.get(node.name.as_str()) else {
+            added.push(node.name.clone());
+            nodes.push((node.clone(), element));
+            continue;
+        };
+        if old == node {
+            continue;
+        }
+        match properties(old, node, element.as_ref()) {
+            Some(values) => {
+                updated.push(node.name.clone());
+                updates.push((node.clone(), values));
+            }
+            None => {
+                replaced.push(node.name.clone());
+                nodes.push((node.clone(), element));
+            }
+        }
+    }
+    let names: HashSet<_> =
+        wanted.nodes.iter().map(|node| &node.name).collect();
+    let removed: Vec<_> = running
+        .nodes
+        .iter()
+        .filter(|node| !names.contains(&node.name))
+        .map(|node| node.name.clone())
+        .collect();
+
+    let gone: HashSet<_> = removed.iter().chain(&replaced).collect();
+    let fresh: HashSet<_> = added.iter().chain(&replaced).collect();
+    let unlinked = running
+        .edges
+        .iter()
+        .filter(|edge| {
+            !wanted.edges.contains(edge)
+                || gone.contains(&edge.source)
+                || gone.contains(&edge.sink)
+        })
+        .cloned()
+        .collect();
+    let linked = wanted
+        .edges
+        .iter()
+        .filter(|edge| {
+            !running.edges.contains(edge)
+                || fresh.contains(&edge.source)
+                || fresh.contains(&edge.sink)
+        })
+        .cloned()
+        .collect();
+
+    Plan {
+        changes: Changes {
+            added,
+            removed,
+            updated,
+            replaced,
+            linked: order(linked),
+            unlinked,
+        },
+        updates,
+        nodes,
+    }
+}
+
+/// The [`Property`] values turning the options of `old` into those of `new`,
+/// read from an `element` made from `new`. [`None`] if the [`Node`] has to be
+/// replaced instead.
+///
+/// [`Property`]: property::Property
+/// [`Node`]: super::Node
+fn properties(
+    old: &config::Node,
+    new: &config::Node,
+    element: &dyn Element,
+) -> Option<Vec<(String, Value)>> {
+    if old.config.element != new.config.element
+        || old.config.backend != new.config.backend
+        || old.restart != new.restart
+    {
+        return None;
+    }
+
+    // Options may be left out entirely.
+    let options = |options: &serde_json::Value| match options {
+        serde_json::Value::Null => Some(serde_json::Map::new()),
+        options => options.as_object().cloned(),
+    };
+    let (old, new) =
+        (options(&old.config.config)?, options(&new.config.config)?);
+    old.keys()
+        .chain(new.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| {
+            if !property::find(element, key).ok()?.writable {
+                return None;
+            }
+            Some((key.clone(), property::get(element, key).ok()?))
+        })
+        .collect()
+}
+
+/// Order `edges` so those into a [`Node`] are linked before those out of it,
+/// where there is no cycle. A [`Node`] linked downstream first runs as a
+/// source and can't be linked to anymore.
+///
+/// [`Node`]: super::Node
+fn order(mut edges: Vec<config::Edge>) -> Vec<config::Edge> {
+    let mut ordered = Vec::with_capacity(edges.len());
+    while !edges.is_empty() {
+        // Feedback edges close cycles, so they don't count.
+        let next = edges
+            .iter()
+            .position(|edge| {
+                !edges
+                    .iter()
+                    .any(|other| !other.feedback && other.sink == edge.source)
+            })
+            .unwrap_or(0);
+        ordered.push(edges.remove(next));
+    }
+
+    ordered
+}
+
+impl Control {
+    /// Reload the running [`Pipeline`] from a [`Config`], applying only what
+    /// changed. See the [module](self) documentation. A
+    /// [`bus::Message::Reloaded`] is posted once everything is applied.
+    ///
+    /// # Errors
+    /// - [`ReloadError::Build`] if `config` is invalid, as for
+    ///   [`Pipeline::from_config`] and [`Pipeline::validate`].
+    /// - [`ReloadError::Init`] if an added [`Element`] fails to initialize.
+    /// - [`ReloadError::Control`] if a change can't be applied, such as a
+    ///   link to a [`Node`] running as a source, or with
+    ///   [`ControlError::NotRunning`] if the [`Pipeline`] is not running.
+    ///
+    /// [`Node`]: super::Node
+    pub async fn reload(
+        &self,
+        config: &Config,
+    ) -> Result<Changes, ReloadError> {
+        let target = Pipeline::from_config(config)?;
+        target.validate().map_err(BuildError::from)?;
+        let mut plan = plan(&self.config().await?, target);
+        if plan.changes.is_empty() {
+            return Ok(plan.changes);
+        }
+
+        for index in 0..plan.nodes.len() {
+            // The error is not `Send` so it can't be held across an `.await`.
+            let result =
+                plan.nodes[index].1.init().await.map_err(|e| e.to_string());
+            let Err(message) = result else {
+                continue;
+            };
+            for (_, element) in plan.nodes.iter_mut().take(index) {
+                element.stop().await.ok();
+            }
+            let name = plan.nodes[index].0.name.clone();
+            return Err(ReloadError::Init { name, message });
+        }
+
+        let changes = plan.changes.clone();
+        let reloaded = self.request(|reply| Request::Reload { plan, reply });
+        let Reloaded {
+            set,
+            removed,
+            mut failed,
+        } = reloaded.await?;
+        for reply in set {
+            if let Ok(Err(err)) = reply.await {
+                failed.get_or_insert(err);
+            }
+        }
+        for reply in removed {
+            if let Ok(Ok(mut element)) = reply.await {
+                // Nobody else is left to report it to.
+                element.stop().await.ok();
+            }
+        }
+
+        match failed {
+            Some(err) => Err(err.into()),
+            None => Ok(changes),
+        }
+    }
+}
+
+/// A `Watcher` reloads a running [`Pipeline`] whenever its config file
+/// changes. See [`Pipeline::watch`]. It stops when dropped.
+pub struct Watcher {
+    task: JoinHandle<()>,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl<S: State> Pipeline<S> {
+    /// Watch the JSON [`Config`] file at `path`, checking it every
+    /// `interval`, and [`Control::reload`] the `Pipeline` whenever it
+    /// changes. Changes made while the `Pipeline` is not running are applied
+    /// once it runs. A [`bus::Message::ReloadFailed`] is posted if the file
+    /// can't be parsed or the reload fails.
+    ///
+    /// # Errors
+    /// - If `path` can't be read. Later read errors, such as while the file
+    ///   is being replaced, are ignored.
+    ///
+    /// # Panics
+    /// - If not called from within a tokio runtime.
+    pub fn watch(
+        &self,
+        path: impl Into<PathBuf>,
+        interval: Duration,
+    ) -> std::io::Result<Watcher> {
+        let path = path.into();
+        let mut seen = std::fs::read_to_string(&path)?;
+        let (control, bus) = (self.control(), self.bus.clone());
+        let task = tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(
+                tokio::time::MissedTickBehavior::Delay,
+            );
+            loop {
+                ticks.tick().await;
+                let Ok(text) = tokio::fs::read_to_string(&path).await else {
+                    continue;
+                };
+                if text == seen {
+                    continue;
+                }
+
+                let failed = match serde_json::from_str::<Config>(&text) {
+                    Ok(config) => match control.reload(&config).await {
+                        Ok(_) => None,
+                        // Tried again on the next tick.
+                        Err(ReloadError::Control(ControlError::NotRunning)) => {
+                            continue
+                        }
+                        Err(err) => Some(err.to_string()),
+                    },
+                    Err(err) => Some(err.to_string()),
+                };
+                if let Some(message) = failed {
+                    bus.post(bus::Message::ReloadFailed { message });
+                }
+                seen = text;
+            }
+        });
+
+        Ok(Watcher { task })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, element: &str, options: serde_json::Value) -> String {
+        serde_json::json!({
+            "name": name,
+            "element": element,
+            "backend": "Independent",
+            "config": options,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_plan() {
+        let config = |nodes: &[String], edges: &str| -> Config {
+            let json = format!(
+                r#"{{ "nodes": [{}], "edges": {edges} }}"#,
+                nodes.join(",")
+            );
+            serde_json::from_str(&json).unwrap()
+        };
+        let tee = |pads| {
+            node(
+                "tee",
+                "Tee",
+                serde_json::json!({ "caps": "ToolSchema", "pads": pads }),
+            )
+        };
+        let queue = |name, max| {
+            node(
+                name,
+                "Queue",
+                serde_json::json!({ "caps": "ToolSchema", "max_buffers": max }),
+            )
+        };
+        let errors = node("errors", "ErrorSink", serde_json::json!(null));
+
+        let running = config(
+            &[tee(1), queue("first", 8), errors.clone()],
+            r#"[
+                { "source": "tee", "sink": "first" },
+                { "source": "tee", "sink": "errors" }
+            ]"#,
+        );
+        let running =
+            Pipeline::from_config(&running).unwrap().config().unwrap();
+        let target = config(
+            &[tee(2), queue("first", 16), queue("second", 8), errors],
+            r#"[
+                { "source": "tee", "sink": "first" },
+                { "source": "first", "sink": "second" },
+                { "source": "tee", "sink": "second" },
+                { "source": "tee", "sink": "errors" }
+            ]"#,
+        );
+        let plan = plan(&running, Pipeline::from_config(&target).unwrap());
+
+        let changes = plan.changes;
+        assert_eq!(changes.added, ["second"]);
+        assert!(changes.removed.is_empty());
+        // `max_buffers` is a property, the number of pads isn't.
+        assert_eq!(changes.updated, ["first"]);
+        assert_eq!(changes.replaced, ["tee"]);
+        let edge = |edge: &config::Edge| {
+            (edge.source.clone(), edge.sink.clone(), edge.source_pad)
+        };
+        assert_eq!(
+            changes.linked.iter().map(edge).collect::<Vec<_>>(),
+            [
+                ("tee".into(), "first".into(), Some(0)),
+                ("first".into(), "second".into(), Some(0)),
+                ("tee".into(), "second".into(), Some(1)),
+                ("tee".into(), "errors".into(), Some(2)),
+            ]
+        );
+        assert_eq!(changes.unlinked, running.edges);
+        assert_eq!(
+            plan.updates[0].1,
+            [("max_buffers".to_owned(), Value::Int(16))]
+        );
+        assert_eq!(plan.nodes.len(), 2);
+
+        let plan = self::plan(
+            &running,
+            Pipeline::from_config(&Config {
+                nodes: running.nodes.clone(),
+                edges: running.edges.clone(),
+            })
+            .unwrap(),
+        );
+        assert!(plan.changes.is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+//! Graceful shutdown of a running [`Pipeline`] with [`Control::shutdown`].
+//!
+//! In [`Mode::Drain`] the sources stop being pulled and every other [`Node`]
+//! finishes what it was already sent, such as a turn with a model. Whatever is
+//! still running at the deadline is cancelled. In [`Mode::Abort`] everything
+//! is cancelled right away. Cancelling a [`Node`] drops whatever its
+//! [`Element`] was doing, like a request in flight, and its [`Element`] is
+//! handed back as usual, so [`Pipeline::shutdown`] can still stop it.
+//!
+//! The [`Report`] tells which [`Node`]s stopped cleanly and which were
+//! cancelled.
+//!
+//! [`Pipeline`]: super::Pipeline
+//! [`Pipeline::shutdown`]: super::Pipeline::shutdown
+//! [`Control::shutdown`]: super::Control::shutdown
+//! [`Node`]: super::Node
+//! [`Element`]: crate::element::Element
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// How to shut down a running [`Pipeline`].
+///
+/// [`Pipeline`]: super::Pipeline
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Mode {
+    /// Stop pulling sources and let every [`Node`] finish what it was sent,
+    /// until the deadline.
+    ///
+    /// [`Node`]: super::Node
+    #[default]
+    Drain,
+    /// Cancel every [`Node`] now.
+    ///
+    /// [`Node`]: super::Node
+    Abort,
+}
+
+/// How a [`Node`] stopped.
+///
+/// [`Node`]: super::Node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Outcome {
+    /// It finished by itself.
+    Clean,
+    /// It failed or panicked. See [`Pipeline::run`] for the error.
+    ///
+    /// [`Pipeline::run`]: super::Pipeline::run
+    Failed,
+    /// It was still running at the deadline, or in [`Mode::Abort`], and was
+    /// cancelled.
+    Cancelled,
+}
+
+/// A [`Node`] and how it stopped.
+///
+/// [`Node`]: super::Node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stopped {
+    /// Index of the [`Node`] while it was running.
+    ///
+    /// [`Node`]: super::Node
+    pub node: usize,
+    /// Name of the [`Node`].
+    ///
+    /// [`Node`]: super::Node
+    pub name: String,
+    /// How it stopped.
+    pub outcome: Outcome,
+}
+
+/// What happened during a shutdown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report {
+    /// [`Mode::Abort`] if any shutdown asked for it.
+    pub mode: Mode,
+    /// Time from the first shutdown request until every [`Node`] stopped.
+    ///
+    /// [`Node`]: super::Node
+    pub elapsed: Duration,
+    /// Every [`Node`] in the [`Pipeline`], by index. Those removed while
+    /// running are not included.
+    ///
+    /// [`Node`]: super::Node
+    /// [`Pipeline`]: super::Pipeline
+    pub nodes: Vec<Stopped>,
+}
+
+impl Report {
+    /// Whether every [`Node`] stopped cleanly.
+    ///
+    /// [`Node`]: super::Node
+    pub fn is_clean(&self) -> bool {
+        self.nodes.iter().all(|n| n.outcome == Outcome::Clean)
+    }
+
+    /// Names of the [`Node`]s with this [`Outcome`].
+    ///
+    /// [`Node`]: super::Node
+    pub fn names(&self, outcome: Outcome) -> impl Iterator<Item = &str> {
+        self.nodes
+            .iter()
+            .filter(move |n| n.outcome == outcome)
+            .map(|n| n.name.as_str())
+    }
+}
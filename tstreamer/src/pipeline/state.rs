@@ -1,10 +1,12 @@
-use super::Error;
-
 pub trait Error: std::error::Error + Send + Sync + 'static {}
 
 /// Marks a state struct as a [`Pipeline`] state where all [`Node`]s are in the
 /// same state.
 pub trait State {
+    /// Name of the state, as posted in [`Message::StateChanged`].
+    ///
+    /// [`Message::StateChanged`]: super::bus::Message::StateChanged
+    const NAME: &'static str;
     /// Next state for the [`Pipeline`] and all [`Node`]s.
     type Next: State;
     /// Error type for transitioning to the next state.
@@ -14,6 +16,7 @@ pub trait State {
 /// Builder state for a [`Pipeline`]. All [`Node`]s are in this state.
 pub struct Builder;
 impl State for Builder {
+    const NAME: &'static str = "Builder";
     type Next = New;
     type Error = BuildError;
 }
@@ -24,33 +27,134 @@ pub enum BuildError {
     SerdeJson(#[from] serde_json::Error),
     #[error("{0}")]
     Serde(#[from] serde::de::value::Error),
+    /// Two [`Node`]s could not be linked.
+    #[error(transparent)]
+    Link(#[from] LinkError),
+    /// A [`Pipeline::parse_launch`] description could not be parsed.
+    #[error(transparent)]
+    Parse(#[from] super::ParseError),
+    /// An [`Element`] could not be constructed from its [`Config`].
+    ///
+    /// [`Element`]: crate::element::Element
+    /// [`Config`]: super::node::Config
+    #[error("Node `{name}`: {source}")]
+    New {
+        /// Name of the [`Node`].
+        name: String,
+        /// Why construction failed.
+        source: crate::element::any::NewError,
+    },
+    /// The [`Pipeline`] has structural problems. All of them are listed.
+    #[error(transparent)]
+    Invalid(#[from] super::ValidationError),
+    /// Two [`Node`]s have the same name.
+    #[error("Duplicate node name `{name}`.")]
+    DuplicateName {
+        /// The duplicated name.
+        name: String,
+    },
+    /// An [`Edge`] refers to a [`Node`] that does not exist.
+    ///
+    /// [`Edge`]: super::Edge
+    #[error("No node named `{name}`.")]
+    UnknownNode {
+        /// The missing name.
+        name: String,
+    },
 }
 impl Error for BuildError {}
 
+/// Error when linking two [`Node`]s in a [`Pipeline`] in the [`Builder`]
+/// state.
+#[derive(Debug, thiserror::Error)]
+pub enum LinkError {
+    /// There is no [`Node`] at this index.
+    #[error("No node at index {node}.")]
+    NoSuchNode {
+        /// Index of the missing [`Node`].
+        node: usize,
+    },
+    /// The [`Node`] has no source pad at this index.
+    #[error("Node {node} has no source pad at index {pad}.")]
+    NoSuchSourcePad {
+        /// Index of the [`Node`].
+        node: usize,
+        /// Index of the missing source pad.
+        pad: usize,
+    },
+    /// The [`Node`] has no sink pad at this index.
+    #[error("Node {node} has no sink pad at index {pad}.")]
+    NoSuchSinkPad {
+        /// Index of the [`Node`].
+        node: usize,
+        /// Index of the missing sink pad.
+        pad: usize,
+    },
+    /// The sink pad does not accept what the source pad yields.
+    #[error(
+        "Cannot link a `{source_caps}` source (node {source_node}) to a \
+        `{sink_caps}` sink (node {sink_node})."
+    )]
+    Incompatible {
+        /// Index of the upstream [`Node`].
+        source_node: usize,
+        /// [`Caps`] of the source pad.
+        ///
+        /// [`Caps`]: crate::pad::Caps
+        source_caps: crate::pad::Caps,
+        /// Index of the downstream [`Node`].
+        sink_node: usize,
+        /// [`Caps`] of the sink pad.
+        ///
+        /// [`Caps`]: crate::pad::Caps
+        sink_caps: crate::pad::Caps,
+    },
+    /// No source pad of one [`Node`] is compatible with any sink pad of the
+    /// other.
+    #[error(
+        "No compatible pads between node {source_node} and node {sink_node}."
+    )]
+    NoCompatiblePads {
+        /// Index of the upstream [`Node`].
+        source_node: usize,
+        /// Index of the downstream [`Node`].
+        sink_node: usize,
+    },
+}
+impl Error for LinkError {}
+
 /// New state for a [`Pipeline`] after it has been built, but not necessarily
 /// initialized.
 pub struct New;
 impl State for New {
+    const NAME: &'static str = "New";
     type Next = Ready;
     type Error = InitError;
 }
 #[derive(Debug, thiserror::Error)]
 pub enum InitError {
-    #[error("{0}")]
-    // TODO: variants
-    Element(String),
+    /// [`Element::init`] failed. [`Element`]s that were already initialized
+    /// have been stopped again.
+    ///
+    /// [`Element::init`]: crate::element::Element::init
+    /// [`Element`]: crate::element::Element
+    #[error("Node `{name}` ({node}) failed to initialize: {message}")]
+    Element {
+        /// Index of the failing [`Node`].
+        node: usize,
+        /// Name of the failing [`Node`].
+        name: String,
+        /// The error message.
+        message: String,
+    },
 }
 impl Error for InitError {}
-impl From<Box<dyn std::error::Error>> for InitError {
-    fn from(err: Box<dyn std::error::Error>) -> Self {
-        InitError::Element(err.to_string())
-    }
-}
 
 /// Initialized state for a [`Pipeline`]. All [`Node`]s are in this state. The
 /// Pipeline is ready to be run.
 pub struct Ready;
 impl State for Ready {
+    const NAME: &'static str = "Ready";
     type Next = Shutdown;
     type Error = RunError;
 }
@@ -58,6 +162,29 @@ impl State for Ready {
 pub enum RunError {
     #[error("{0}")]
     Custom(String),
+    /// An [`Element`] returned an error from a [`Push`] or [`Pull`].
+    ///
+    /// [`Element`]: crate::element::Element
+    /// [`Push`]: crate::pad::Push
+    /// [`Pull`]: crate::pad::Pull
+    #[error("Node `{name}` ({node}) failed: {message}")]
+    Element {
+        /// Index of the failing [`Node`].
+        node: usize,
+        /// Name of the failing [`Node`].
+        name: String,
+        /// The error message.
+        message: String,
+    },
+    /// The task running a [`Node`] panicked. The [`Node`] is lost, so the
+    /// ones after it move down one index.
+    #[error("Task for node `{name}` ({node}) panicked. The node is lost.")]
+    Panicked {
+        /// Index the [`Node`] that panicked had.
+        node: usize,
+        /// Name of the [`Node`] that panicked.
+        name: String,
+    },
 }
 
 impl Error for RunError {}
@@ -65,6 +192,7 @@ impl Error for RunError {}
 /// Shutdown state for a [`Pipeline`]. All [`Node`]s are in this state.
 pub struct Shutdown;
 impl State for Shutdown {
+    const NAME: &'static str = "Shutdown";
     type Next = Builder;
     type Error = ShutdownError;
 }
@@ -72,5 +200,18 @@ impl State for Shutdown {
 pub enum ShutdownError {
     #[error("{0}")]
     Custom(String),
+    /// [`Element::stop`] failed. The other [`Element`]s were still stopped.
+    ///
+    /// [`Element::stop`]: crate::element::Element::stop
+    /// [`Element`]: crate::element::Element
+    #[error("Node `{name}` ({node}) failed to stop: {message}")]
+    Element {
+        /// Index of the failing [`Node`].
+        node: usize,
+        /// Name of the failing [`Node`].
+        name: String,
+        /// The error message.
+        message: String,
+    },
 }
 impl Error for ShutdownError {}
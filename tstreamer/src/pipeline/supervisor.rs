@@ -0,0 +1,220 @@
+//! Restart [`Policy`]s for [`Node`]s, applied by the [`Pipeline`] while it
+//! runs, like a supervisor.
+//!
+//! When a [`Node`] with a [`Policy`] fails, its links are kept open and its
+//! [`Element`] is stopped and initialized again after a [`Backoff`], then it
+//! carries on with whatever it was sent meanwhile. Its error is still posted
+//! on the [`Bus`], followed by a [`bus::Message::Restarting`]. Only once it
+//! gives up does [`Pipeline::run`] return the error. Panics are never
+//! restarted, nor is anything while the [`Pipeline`] is shutting down.
+//!
+//! Set a [`Policy`] with [`Pipeline::set_restart`], or `restart` in a
+//! [`Config`]:
+//!
+//! ```json
+//! {
+//!   "name": "inference",
+//!   "element": "Inference",
+//!   "backend": "Misanthropic",
+//!   "restart": {
+//!     "policy": "on_failure",
+//!     "max_retries": 5,
+//!     "backoff": { "initial_ms": 500 }
+//!   }
+//! }
+//! ```
+//!
+//! [`Pipeline`]: super::Pipeline
+//! [`Pipeline::run`]: super::Pipeline::run
+//! [`Pipeline::set_restart`]: super::Pipeline::set_restart
+//! [`Node`]: super::Node
+//! [`Element`]: crate::element::Element
+//! [`Bus`]: super::Bus
+//! [`bus::Message::Restarting`]: super::bus::Message::Restarting
+//! [`Config`]: super::config::Node
+
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// When to restart a [`Node`].
+///
+/// [`Node`]: super::Node
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum Restart {
+    /// Let it fail.
+    #[default]
+    Never,
+    /// Restart it when it fails, up to `max_retries` times in a row.
+    OnFailure {
+        /// How many restarts in a row before giving up.
+        max_retries: u32,
+    },
+    /// Restart it whenever it fails, and whenever it finishes by itself while
+    /// there is still something upstream and someone listening downstream.
+    Always,
+}
+
+/// How long to wait before restarting a [`Node`]. The delay starts at
+/// `initial_ms` and is multiplied by `factor` with every restart in a row, up
+/// to `max_ms`.
+///
+/// [`Node`]: super::Node
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Backoff {
+    /// Delay before the first restart, in milliseconds.
+    pub initial_ms: u64,
+    /// Longest delay, in milliseconds.
+    pub max_ms: u64,
+    /// How much the delay grows with every restart in a row.
+    pub factor: f64,
+    /// A [`Node`] that ran this long, in milliseconds, is no longer failing
+    /// in a row.
+    ///
+    /// [`Node`]: super::Node
+    pub reset_ms: u64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial_ms: 100,
+            max_ms: 30_000,
+            factor: 2.0,
+            reset_ms: 60_000,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before restart number `attempt` in a row, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let ms = self.initial_ms as f64 * self.factor.max(1.0).powi(exponent);
+        Duration::from_millis(ms.min(self.max_ms as f64) as u64)
+    }
+
+    /// How long a [`Node`] must run for its restarts to no longer count as
+    /// in a row.
+    ///
+    /// [`Node`]: super::Node
+    pub fn reset(&self) -> Duration {
+        Duration::from_millis(self.reset_ms)
+    }
+}
+
+/// Restart `Policy` of a [`Node`]. See the [module](self) documentation.
+///
+/// [`Node`]: super::Node
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema,
+)]
+pub struct Policy {
+    /// When to restart.
+    #[serde(flatten)]
+    pub restart: Restart,
+    /// How long to wait first.
+    #[serde(default)]
+    pub backoff: Backoff,
+}
+
+impl Policy {
+    /// Restart on failure, up to `max_retries` times in a row.
+    pub fn on_failure(max_retries: u32) -> Self {
+        Self {
+            restart: Restart::OnFailure { max_retries },
+            ..Default::default()
+        }
+    }
+
+    /// Always restart.
+    pub fn always() -> Self {
+        Self {
+            restart: Restart::Always,
+            ..Default::default()
+        }
+    }
+
+    /// Use another [`Backoff`].
+    pub fn with_backoff(self, backoff: Backoff) -> Self {
+        Self { backoff, ..self }
+    }
+
+    /// Whether this is [`Restart::Never`].
+    pub fn is_never(&self) -> bool {
+        self.restart == Restart::Never
+    }
+
+    /// Whether to restart a [`Node`] that `failed` or finished, after
+    /// `restarts` restarts in a row. A [`Node`] that finished is only
+    /// restarted if it is `resumable`.
+    ///
+    /// [`Node`]: super::Node
+    pub(crate) fn restarts(
+        &self,
+        failed: bool,
+        resumable: bool,
+        restarts: u32,
+    ) -> bool {
+        match self.restart {
+            Restart::Never => false,
+            Restart::OnFailure { max_retries } => {
+                failed && restarts < max_retries
+            }
+            Restart::Always => failed || resumable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy::on_failure(2);
+        assert!(policy.restarts(true, false, 1));
+        assert!(!policy.restarts(true, false, 2));
+        assert!(!policy.restarts(false, true, 0));
+        assert!(Policy::always().restarts(false, true, 100));
+        assert!(!Policy::always().restarts(false, false, 0));
+        assert!(Policy::default().is_never());
+
+        let backoff = Backoff {
+            initial_ms: 100,
+            max_ms: 1_000,
+            ..Default::default()
+        };
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(3), Duration::from_millis(400));
+        assert_eq!(backoff.delay(10), Duration::from_millis(1_000));
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_millis(1_000));
+
+        let json = serde_json::json!({
+            "policy": "on_failure",
+            "max_retries": 5,
+            "backoff": { "initial_ms": 500 },
+        });
+        let policy: Policy = serde_json::from_value(json).unwrap();
+        assert_eq!(policy.restart, Restart::OnFailure { max_retries: 5 });
+        assert_eq!(policy.backoff.initial_ms, 500);
+        assert_eq!(policy.backoff.max_ms, 30_000);
+        assert_eq!(
+            serde_json::to_value(Policy::always()).unwrap()["policy"],
+            "always"
+        );
+    }
+}
@@ -0,0 +1,592 @@
+//! [`Tracer`]s following every [`Buffer`] through a [`Pipeline`], like the
+//! tracer subsystem of GStreamer.
+//!
+//! Add them with [`Pipeline::add_tracer`] before the [`Pipeline`] is
+//! initialized. They are called from every [`Node`] task at once, so they
+//! must be quick and do their own locking. Built in are [`Latency`] per
+//! [`Element`], buffer [`Counts`] per [`Edge`] and a [`JsonLines`] log.
+//!
+//! ```
+//! use std::sync::Arc;
+//! use tstreamer::pipeline::{trace, Pipeline};
+//!
+//! let latency = Arc::new(trace::Latency::new());
+//! let mut pipeline = Pipeline::new();
+//! pipeline.add_tracer(latency.clone());
+//! pipeline.add_tracer(Arc::new(trace::JsonLines::new(std::io::stderr())));
+//! // Build, init and run the pipeline, then:
+//! for (name, stats) in latency.stats() {
+//!     println!("{name}: {:?} per push", stats.push.mean());
+//! }
+//! ```
+//!
+//! [`Buffer`]: crate::buffer::Buffer
+//! [`Pipeline`]: super::Pipeline
+//! [`Pipeline::add_tracer`]: super::Pipeline::add_tracer
+//! [`Node`]: super::Node
+//! [`Element`]: crate::element::Element
+//! [`Edge`]: super::Edge
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{
+    backends::Backend,
+    buffer::Buffer,
+    element::{
+        property::{Counter, Value},
+        Element,
+    },
+};
+
+/// [`Info`] of a [`Buffer`], taken before it changed hands.
+///
+/// [`Info`]: crate::info::Info
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BufferInfo {
+    /// [`Info::name`] of the [`Buffer`].
+    ///
+    /// [`Info::name`]: crate::info::Info::name
+    pub name: String,
+    /// [`Buffer::size`] of the [`Buffer`].
+    pub size: usize,
+}
+
+impl BufferInfo {
+    /// Describe `buffer`.
+    pub fn of(buffer: &dyn Buffer) -> Self {
+        Self {
+            name: buffer.name().into_owned(),
+            size: buffer.size(),
+        }
+    }
+}
+
+/// The [`Node`] a hook is called for.
+///
+/// [`Node`]: super::Node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Origin<'a> {
+    /// Index of the [`Node`].
+    ///
+    /// [`Node`]: super::Node
+    pub node: usize,
+    /// Name of the [`Node`].
+    ///
+    /// [`Node`]: super::Node
+    pub name: &'a str,
+    /// [`Backend`] of its [`Element`].
+    ///
+    /// [`Element`]: crate::element::Element
+    pub backend: Backend,
+}
+
+/// A [`Buffer`] pushed to a sink pad or pulled from a source pad.
+#[derive(Debug, Clone, Copy)]
+pub struct Transfer<'a> {
+    /// Where it happened.
+    pub origin: Origin<'a>,
+    /// Index of the pad.
+    pub pad: usize,
+    /// Index of the [`Edge`] the [`Buffer`] arrived on or is sent on.
+    ///
+    /// [`Edge`]: super::Edge
+    pub edge: usize,
+    /// The [`Buffer`]. [`None`] if a pull failed.
+    pub buffer: Option<&'a BufferInfo>,
+    /// How long the [`Push::push`] or [`Pull::pull`] took. A pull includes
+    /// waiting for the [`Element`] to have something.
+    ///
+    /// [`Push::push`]: crate::pad::Push::push
+    /// [`Pull::pull`]: crate::pad::Pull::pull
+    /// [`Element`]: crate::element::Element
+    pub elapsed: Duration,
+    /// Whether it succeeded.
+    pub ok: bool,
+}
+
+/// A `Tracer` is called by the [`Pipeline`] as [`Element`]s are initialized
+/// and stopped, on every push and pull, and on errors. Every hook does
+/// nothing by default.
+///
+/// [`Pipeline`]: super::Pipeline
+/// [`Element`]: crate::element::Element
+pub trait Tracer: Send + Sync {
+    /// [`Element::init`] returned after `elapsed`.
+    ///
+    /// [`Element::init`]: crate::element::Element::init
+    #[allow(unused_variables)]
+    fn init(&self, origin: Origin<'_>, elapsed: Duration, ok: bool) {}
+    /// The task of a [`Node`] is about to run `element`. Called for every
+    /// [`Node`] when the [`Pipeline`] runs, and for [`Node`]s added while it
+    /// does.
+    ///
+    /// [`Node`]: super::Node
+    #[allow(unused_variables)]
+    fn spawn(&self, origin: Origin<'_>, element: &dyn Element) {}
+    /// [`Element::stop`] returned after `elapsed`.
+    ///
+    /// [`Element::stop`]: crate::element::Element::stop
+    #[allow(unused_variables)]
+    fn stop(&self, origin: Origin<'_>, elapsed: Duration, ok: bool) {}
+    /// A [`Buffer`] was pushed to a sink pad.
+    #[allow(unused_variables)]
+    fn push(&self, transfer: &Transfer<'_>) {}
+    /// A [`Buffer`] was pulled from a source pad.
+    #[allow(unused_variables)]
+    fn pull(&self, transfer: &Transfer<'_>) {}
+    /// A [`Node`] failed with `message`, the same as posted on the [`Bus`].
+    ///
+    /// [`Node`]: super::Node
+    /// [`Bus`]: super::Bus
+    #[allow(unused_variables)]
+    fn error(&self, origin: Origin<'_>, message: &str) {}
+    /// The read-only [`Property`]s of an [`Element`] that has some, when
+    /// its task is spawned and after every push or pull. See
+    /// [`property::readings`].
+    ///
+    /// [`Property`]: crate::element::property::Property
+    /// [`property::readings`]: crate::element::property::readings
+    #[allow(unused_variables)]
+    fn readings(&self, origin: Origin<'_>, readings: &[(&str, Value)]) {}
+    /// The [`Counter`]s of an [`Element`] that has some, read along with its
+    /// [`readings`]. See [`Element::counters`].
+    ///
+    /// [`Counter`]: crate::element::property::Counter
+    /// [`readings`]: Tracer::readings
+    /// [`Element::counters`]: crate::element::Element::counters
+    #[allow(unused_variables)]
+    fn counters(&self, origin: Origin<'_>, counters: &[Counter]) {}
+}
+static_assertions::assert_obj_safe!(Tracer);
+
+/// The [`Tracer`]s of a [`Pipeline`], called in the order they were added.
+///
+/// [`Pipeline`]: super::Pipeline
+#[derive(Clone, Default)]
+pub(crate) struct Tracers(Arc<Vec<Arc<dyn Tracer>>>);
+
+impl Tracers {
+    /// Add a [`Tracer`].
+    pub fn add(&mut self, tracer: Arc<dyn Tracer>) {
+        Arc::make_mut(&mut self.0).push(tracer);
+    }
+
+    /// Whether there are none, so there is nothing to measure.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Tracer for Tracers {
+    fn init(&self, origin: Origin<'_>, elapsed: Duration, ok: bool) {
+        for tracer in self.0.iter() {
+            tracer.init(origin, elapsed, ok);
+        }
+    }
+
+    fn spawn(&self, origin: Origin<'_>, element: &dyn Element) {
+        for tracer in self.0.iter() {
+            tracer.spawn(origin, element);
+        }
+    }
+
+    fn stop(&self, origin: Origin<'_>, elapsed: Duration, ok: bool) {
+        for tracer in self.0.iter() {
+            tracer.stop(origin, elapsed, ok);
+        }
+    }
+
+    fn push(&self, transfer: &Transfer<'_>) {
+        for tracer in self.0.iter() {
+            tracer.push(transfer);
+        }
+    }
+
+    fn pull(&self, transfer: &Transfer<'_>) {
+        for tracer in self.0.iter() {
+            tracer.pull(transfer);
+        }
+    }
+
+    fn error(&self, origin: Origin<'_>, message: &str) {
+        for tracer in self.0.iter() {
+            tracer.error(origin, message);
+        }
+    }
+
+    fn readings(&self, origin: Origin<'_>, readings: &[(&str, Value)]) {
+        for tracer in self.0.iter() {
+            tracer.readings(origin, readings);
+        }
+    }
+
+    fn counters(&self, origin: Origin<'_>, counters: &[Counter]) {
+        for tracer in self.0.iter() {
+            tracer.counters(origin, counters);
+        }
+    }
+}
+
+/// How many times something took how long.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timing {
+    /// Number of times.
+    pub count: u64,
+    /// Total time.
+    pub total: Duration,
+    /// Longest time.
+    pub max: Duration,
+}
+
+impl Timing {
+    fn add(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    /// Average time, or zero if never.
+    pub fn mean(&self) -> Duration {
+        match u32::try_from(self.count) {
+            Ok(0) => Duration::ZERO,
+            Ok(count) => self.total / count,
+            Err(_) => self.total.div_f64(self.count as f64),
+        }
+    }
+}
+
+/// [`Latency`] of one [`Element`].
+///
+/// [`Element`]: crate::element::Element
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElementLatency {
+    /// How long [`Element::init`] took.
+    ///
+    /// [`Element::init`]: crate::element::Element::init
+    pub init: Option<Duration>,
+    /// How long [`Element::stop`] took.
+    ///
+    /// [`Element::stop`]: crate::element::Element::stop
+    pub stop: Option<Duration>,
+    /// Pushes to any sink pad.
+    pub push: Timing,
+    /// Pulls from any source pad.
+    pub pull: Timing,
+}
+
+/// [`Tracer`] measuring the time spent in each [`Element`]. Kept by
+/// [`Node`] name, as indices change when [`Node`]s are removed.
+///
+/// [`Element`]: crate::element::Element
+/// [`Node`]: super::Node
+#[derive(Debug, Default)]
+pub struct Latency {
+    nodes: Mutex<BTreeMap<String, ElementLatency>>,
+}
+
+impl Latency {
+    /// Nothing measured yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What was measured so far, by [`Node`] name.
+    ///
+    /// [`Node`]: super::Node
+    pub fn stats(&self) -> BTreeMap<String, ElementLatency> {
+        self.lock().clone()
+    }
+
+    fn lock(
+        &self,
+    ) -> std::sync::MutexGuard<'_, BTreeMap<String, ElementLatency>> {
+        self.nodes
+            .lock()
+            // Only counters are updated, so a poisoned lock is fine.
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn update(&self, name: &str, f: impl FnOnce(&mut ElementLatency)) {
+        let mut nodes = self.lock();
+        match nodes.get_mut(name) {
+            Some(latency) => f(latency),
+            None => f(nodes.entry(name.to_owned()).or_default()),
+        }
+    }
+}
+
+impl Tracer for Latency {
+    fn init(&self, origin: Origin<'_>, elapsed: Duration, _: bool) {
+        self.update(origin.name, |latency| latency.init = Some(elapsed));
+    }
+
+    fn stop(&self, origin: Origin<'_>, elapsed: Duration, _: bool) {
+        self.update(origin.name, |latency| latency.stop = Some(elapsed));
+    }
+
+    fn push(&self, transfer: &Transfer<'_>) {
+        self.update(transfer.origin.name, |latency| {
+            latency.push.add(transfer.elapsed)
+        });
+    }
+
+    fn pull(&self, transfer: &Transfer<'_>) {
+        self.update(transfer.origin.name, |latency| {
+            latency.pull.add(transfer.elapsed)
+        });
+    }
+}
+
+/// Buffers on one [`Edge`], as counted by [`Counts`].
+///
+/// [`Edge`]: super::Edge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EdgeCount {
+    /// Pulled upstream to be sent on the [`Edge`].
+    ///
+    /// [`Edge`]: super::Edge
+    pub pulled: u64,
+    /// Pushed downstream after arriving on the [`Edge`].
+    ///
+    /// [`Edge`]: super::Edge
+    pub pushed: u64,
+    /// Total [`Buffer::size`] of those pushed.
+    pub bytes: u64,
+}
+
+/// [`Tracer`] counting the buffers on each [`Edge`].
+///
+/// [`Edge`]: super::Edge
+#[derive(Debug, Default)]
+pub struct Counts {
+    edges: Mutex<BTreeMap<usize, EdgeCount>>,
+}
+
+impl Counts {
+    /// Nothing counted yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What was counted so far, by [`Edge`] index.
+    ///
+    /// [`Edge`]: super::Edge
+    pub fn stats(&self) -> BTreeMap<usize, EdgeCount> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<usize, EdgeCount>> {
+        self.edges
+            .lock()
+            // Only counters are updated, so a poisoned lock is fine.
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Tracer for Counts {
+    fn push(&self, transfer: &Transfer<'_>) {
+        if transfer.ok {
+            let mut edges = self.lock();
+            let count = edges.entry(transfer.edge).or_default();
+            count.pushed += 1;
+            count.bytes += transfer.buffer.map_or(0, |b| b.size as u64);
+        }
+    }
+
+    fn pull(&self, transfer: &Transfer<'_>) {
+        if transfer.ok {
+            self.lock().entry(transfer.edge).or_default().pulled += 1;
+        }
+    }
+}
+
+/// [`Tracer`] writing one JSON object per line for every hook, with the
+/// `event` name, the time in microseconds since the Unix epoch and the
+/// arguments. Write errors are ignored.
+#[derive(Debug)]
+pub struct JsonLines<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLines<W> {
+    /// Log to `writer`. Consider buffering it.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// The `writer`.
+    pub fn into_inner(self) -> W {
+        self.writer
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self, event: &str, mut line: serde_json::Value) {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        if let Some(line) = line.as_object_mut() {
+            line.insert("event".into(), event.into());
+            line.insert("time_us".into(), time.into());
+        }
+
+        let mut writer = self
+            .writer
+            .lock()
+            // At worst a line is cut short.
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        serde_json::to_writer(&mut *writer, &line).ok();
+        writer.write_all(b"\n").ok();
+    }
+
+    fn transfer(&self, event: &str, transfer: &Transfer<'_>) {
+        self.write(
+            event,
+            serde_json::json!({
+                "node": transfer.origin.node,
+                "name": transfer.origin.name,
+                "backend": transfer.origin.backend,
+                "pad": transfer.pad,
+                "edge": transfer.edge,
+                "buffer": transfer.buffer,
+                "elapsed_us": transfer.elapsed.as_micros() as u64,
+                "ok": transfer.ok,
+            }),
+        );
+    }
+}
+
+impl<W: Write + Send> Tracer for JsonLines<W> {
+    fn init(&self, origin: Origin<'_>, elapsed: Duration, ok: bool) {
+        self.write(
+            "init",
+            serde_json::json!({
+                "node": origin.node,
+                "name": origin.name,
+                "backend": origin.backend,
+                "elapsed_us": elapsed.as_micros() as u64,
+                "ok": ok,
+            }),
+        );
+    }
+
+    fn stop(&self, origin: Origin<'_>, elapsed: Duration, ok: bool) {
+        self.write(
+            "stop",
+            serde_json::json!({
+                "node": origin.node,
+                "name": origin.name,
+                "backend": origin.backend,
+                "elapsed_us": elapsed.as_micros() as u64,
+                "ok": ok,
+            }),
+        );
+    }
+
+    fn push(&self, transfer: &Transfer<'_>) {
+        self.transfer("push", transfer);
+    }
+
+    fn pull(&self, transfer: &Transfer<'_>) {
+        self.transfer("pull", transfer);
+    }
+
+    fn error(&self, origin: Origin<'_>, message: &str) {
+        self.write(
+            "error",
+            serde_json::json!({
+                "node": origin.node,
+                "name": origin.name,
+                "backend": origin.backend,
+                "message": message,
+            }),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracers() {
+        let latency = Arc::new(Latency::new());
+        let counts = Arc::new(Counts::new());
+        let log = Arc::new(JsonLines::new(Vec::new()));
+        let mut tracers = Tracers::default();
+        tracers.add(latency.clone());
+        tracers.add(counts.clone());
+        tracers.add(log.clone());
+
+        let buffer = BufferInfo {
+            name: "Message".into(),
+            size: 10,
+        };
+        let origin = Origin {
+            node: 1,
+            name: "queue",
+            backend: Backend::Independent,
+        };
+        let transfer = Transfer {
+            origin,
+            pad: 0,
+            edge: 0,
+            buffer: Some(&buffer),
+            elapsed: Duration::from_millis(2),
+            ok: true,
+        };
+        tracers.init(origin, Duration::from_millis(1), true);
+        tracers.push(&transfer);
+        tracers.push(&Transfer {
+            elapsed: Duration::from_millis(4),
+            ..transfer
+        });
+        tracers.pull(&Transfer {
+            buffer: None,
+            ok: false,
+            ..transfer
+        });
+        tracers.error(origin, "Oops.");
+
+        let stats = &latency.stats()["queue"];
+        assert_eq!(stats.init, Some(Duration::from_millis(1)));
+        assert_eq!(stats.push.count, 2);
+        assert_eq!(stats.push.max, Duration::from_millis(4));
+        assert_eq!(stats.push.mean(), Duration::from_millis(3));
+        assert_eq!(stats.pull.count, 1);
+
+        assert_eq!(
+            counts.stats()[&0],
+            EdgeCount {
+                pulled: 0,
+                pushed: 2,
+                bytes: 20,
+            }
+        );
+
+        drop(tracers);
+        let log = Arc::into_inner(log).unwrap().into_inner();
+        let lines: Vec<serde_json::Value> = String::from_utf8(log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0]["event"], "init");
+        assert_eq!(lines[0]["backend"], "Independent");
+        assert_eq!(lines[1]["buffer"]["name"], "Message");
+        assert_eq!(lines[1]["elapsed_us"], 2000);
+        assert_eq!(lines[3]["buffer"], serde_json::Value::Null);
+        assert_eq!(lines[4]["message"], "Oops.");
+    }
+}
@@ -0,0 +1,327 @@
+//! Structural checks run on a [`Pipeline`] before it is initialized. Every
+//! problem is reported at once, rather than the first one surfacing as a
+//! runtime failure.
+
+use petgraph::visit::EdgeFiltered;
+
+use crate::{
+    element::any::{SchemaError, UnavailableError},
+    pad::Caps,
+};
+
+use super::{Pipeline, State};
+
+/// A structural `Problem` with a [`Pipeline`]. See [`Pipeline::validate`].
+#[derive(Debug, thiserror::Error)]
+pub enum Problem {
+    /// Two or more [`Node`]s have the same name.
+    ///
+    /// [`Node`]: super::Node
+    #[error("Duplicate node name `{name}` (nodes {nodes:?}).")]
+    DuplicateName {
+        /// The duplicated name.
+        name: String,
+        /// Indices of the [`Node`]s sharing it.
+        ///
+        /// [`Node`]: super::Node
+        nodes: Vec<usize>,
+    },
+    /// A sink pad the [`Element`] requires is not linked.
+    ///
+    /// [`Element`]: crate::element::Element
+    #[error(
+        "Node `{name}` ({node}) has nothing linked to its required `{caps}` \
+        sink pad {pad}."
+    )]
+    Unconnected {
+        /// Index of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        node: usize,
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+        /// Index of the sink pad.
+        pad: usize,
+        /// [`Caps`] of the sink pad.
+        caps: Caps,
+    },
+    /// [`Edge`]s form a cycle and none of them is marked as feedback with
+    /// [`Pipeline::link_feedback`].
+    ///
+    /// [`Edge`]: super::Edge
+    #[error(
+        "Nodes `{}` form a cycle. Link one of them with `link_feedback` if \
+        the loop is intended.",
+        .names.join("`, `")
+    )]
+    Cycle {
+        /// Names of the [`Node`]s in the cycle, in index order.
+        ///
+        /// [`Node`]: super::Node
+        names: Vec<String>,
+    },
+    /// The [`Backend`] of a [`Node`] does not provide its [`Kind`].
+    ///
+    /// [`Backend`]: crate::backends::Backend
+    /// [`Node`]: super::Node
+    /// [`Kind`]: crate::element::any::Kind
+    #[error("Node `{name}`: {source}")]
+    Unavailable {
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+        /// Which [`Kind`] is missing for which [`Backend`].
+        ///
+        /// [`Backend`]: crate::backends::Backend
+        /// [`Kind`]: crate::element::any::Kind
+        source: UnavailableError,
+    },
+    /// The options of a [`Node`] do not match the [`Kind::schema`].
+    ///
+    /// [`Node`]: super::Node
+    /// [`Kind::schema`]: crate::element::any::Kind::schema
+    #[error("Node `{name}`: {source}")]
+    Options {
+        /// Name of the [`Node`].
+        ///
+        /// [`Node`]: super::Node
+        name: String,
+        /// Every problem with the options.
+        source: SchemaError,
+    },
+}
+
+/// Error listing every [`Problem`] found by [`Pipeline::validate`].
+#[derive(Debug)]
+pub struct ValidationError {
+    /// The [`Problem`]s, grouped by kind.
+    pub problems: Vec<Problem>,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid pipeline ({} problems):", self.problems.len())?;
+        for problem in self.problems.iter() {
+            write!(f, "\n- {problem}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+impl super::state::Error for ValidationError {}
+
+impl<S: State> Pipeline<S> {
+    /// Check the `Pipeline` for structural problems. [`Pipeline::build`]
+    /// does this, so it is only needed to check a `Pipeline` that is still
+    /// being built. Looks for:
+    /// - [`Node`]s sharing a name.
+    /// - Sink pads that are [`required`] but not linked, such as the
+    ///   [`Prompt`] sink of an [`Inference`] [`Element`].
+    /// - Cycles, unless one of their [`Edge`]s is marked as feedback with
+    ///   [`Pipeline::link_feedback`]. Tool use loops are intended cycles.
+    /// - [`Node`]s whose [`Backend`] does not provide their [`Kind`].
+    ///
+    /// # Errors
+    /// - [`ValidationError`] listing every [`Problem`] found.
+    ///
+    /// [`Node`]: super::Node
+    /// [`Edge`]: super::Edge
+    /// [`required`]: crate::element::Element::required
+    /// [`Prompt`]: crate::buffer::Prompt
+    /// [`Inference`]: crate::element::inference::Inference
+    /// [`Element`]: crate::element::Element
+    /// [`Backend`]: crate::backends::Backend
+    /// [`Kind`]: crate::element::any::Kind
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut problems = self.duplicate_names();
+        problems.extend(self.unconnected());
+        problems.extend(self.cycles());
+        problems.extend(self.unavailable());
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { problems })
+        }
+    }
+
+    fn duplicate_names(&self) -> Vec<Problem> {
+        let mut nodes: Vec<(&str, Vec<usize>)> = vec![];
+        for (index, node) in self.graph.node_weights().enumerate() {
+            match nodes.iter_mut().find(|(name, _)| *name == node.name) {
+                Some((_, indices)) => indices.push(index),
+                None => nodes.push((&node.name, vec![index])),
+            }
+        }
+
+        nodes
+            .into_iter()
+            .filter(|(_, nodes)| nodes.len() > 1)
+            .map(|(name, nodes)| Problem::DuplicateName {
+                name: name.to_owned(),
+                nodes,
+            })
+            .collect()
+    }
+
+    fn unconnected(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+        for index in self.graph.node_indices() {
+            let node = &self.graph[index];
+            let linked: std::collections::HashSet<usize> = self
+                .graph
+                .edges_directed(index, petgraph::Direction::Incoming)
+                .map(|edge| edge.weight().sink)
+                .collect();
+            problems.extend(
+                node.element
+                    .sinks()
+                    .map(|pad| pad.caps())
+                    .enumerate()
+                    .filter(|(pad, _)| {
+                        node.element.required(*pad) && !linked.contains(pad)
+                    })
+                    .map(|(pad, caps)| Problem::Unconnected {
+                        node: index.index(),
+                        name: node.name.clone(),
+                        pad,
+                        caps,
+                    }),
+            );
+        }
+
+        problems
+    }
+
+    fn cycles(&self) -> Vec<Problem> {
+        let graph =
+            EdgeFiltered::from_fn(&self.graph, |edge| !edge.weight().feedback);
+
+        petgraph::algo::tarjan_scc(&graph)
+            .into_iter()
+            .filter(|component| match component.as_slice() {
+                // A node linked to itself is a cycle too.
+                [node] => self
+                    .graph
+                    .edges_connecting(*node, *node)
+                    .any(|edge| !edge.weight().feedback),
+                _ => true,
+            })
+            .map(|mut component| {
+                component.sort();
+                Problem::Cycle {
+                    names: component
+                        .into_iter()
+                        .map(|node| self.graph[node].name.clone())
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    fn unavailable(&self) -> Vec<Problem> {
+        self.graph
+            .node_weights()
+            .filter_map(|node| {
+                let config = node.config.as_ref()?;
+                (!config.element.available(config.backend)).then(|| {
+                    Problem::Unavailable {
+                        name: node.name.clone(),
+                        source: UnavailableError {
+                            kind: config.element.clone(),
+                            backend: config.backend,
+                        },
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::{queue, tee};
+
+    fn tee(pads: usize) -> Box<tee::Tee> {
+        Box::new(tee::Tee::new(tee::Options {
+            caps: Caps::ToolSchema,
+            pads,
+            ..Default::default()
+        }))
+    }
+
+    fn queue() -> Box<queue::Queue> {
+        Box::new(queue::Queue::new(queue::Options {
+            caps: Caps::ToolSchema,
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut pipeline = Pipeline::new();
+        let first = pipeline.add("first", tee(2));
+        let second = pipeline.add("second", queue());
+        pipeline.add("second", queue());
+        pipeline.link(first, 0, second, 0).unwrap();
+        pipeline.link(second, 0, first, 0).unwrap();
+
+        let problems = pipeline.validate().unwrap_err().problems;
+        assert_eq!(problems.len(), 3, "{problems:?}");
+        assert!(matches!(
+            &problems[0],
+            Problem::DuplicateName { name, nodes }
+                if name == "second" && nodes == &[1, 2]
+        ));
+        assert!(matches!(
+            &problems[1],
+            Problem::Unconnected {
+                node: 2,
+                pad: 0,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &problems[2],
+            Problem::Cycle { names } if names == &["first", "second"]
+        ));
+
+        // Renamed, fed, and with the loop marked as intended.
+        let mut pipeline = Pipeline::new();
+        let first = pipeline.add("first", tee(2));
+        let second = pipeline.add("second", queue());
+        let third = pipeline.add("third", queue());
+        pipeline.link(first, 0, second, 0).unwrap();
+        pipeline.link_feedback(second, 0, first, 0).unwrap();
+        pipeline.link(first, 1, third, 0).unwrap();
+        pipeline.validate().unwrap();
+
+        let pipeline = pipeline.build().unwrap();
+        assert!(pipeline.graph.raw_edges()[1].weight.feedback);
+    }
+
+    #[test]
+    fn test_validate_self_loop() {
+        let mut pipeline = Pipeline::new();
+        let node = pipeline.add("tee", tee(1));
+        pipeline.link(node, 0, node, 0).unwrap();
+
+        let Err((pipeline, err)) = pipeline.build() else {
+            panic!("expected a cycle");
+        };
+        // Handed back to be fixed.
+        assert_eq!(pipeline.find("tee"), Some(node));
+        assert!(matches!(
+            err,
+            super::super::BuildError::Invalid(ValidationError { ref problems })
+                if matches!(problems.as_slice(), [Problem::Cycle { .. }])
+        ));
+        assert!(err.to_string().contains("Nodes `tee` form a cycle."));
+    }
+}